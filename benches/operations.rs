@@ -0,0 +1,138 @@
+//! Benchmarks of the main operations of `AVLTree`, each against the same on `BTreeSet`.
+
+use std::collections::BTreeSet;
+
+use avl_tree::AVLTree;
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+
+const SIZES: [u64; 3] = [1_000, 10_000, 100_000];
+
+/// Distinct keys in a scrambled order, the same on every run: i * 0x9e3779b97f4a7c15 is a
+/// permutation of the u64s, odd multipliers being invertible.
+fn random_keys(n: u64) -> Vec<u64> {
+    (0..n).map(|i| i.wrapping_mul(0x9e37_79b9_7f4a_7c15) >> 1).collect()
+}
+
+/// Sums elements, wrapping around, so that they are all read.
+fn checksum<'a>(elements: impl Iterator<Item = &'a u64>) -> u64 {
+    elements.fold(0, |sum, &k| sum.wrapping_add(k))
+}
+
+fn insert(c: &mut Criterion) {
+    let mut group = c.benchmark_group("insert");
+    for n in SIZES {
+        group.throughput(Throughput::Elements(n));
+        let orders = [
+            ("random", random_keys(n)),
+            ("sorted", (0..n).collect()),
+            ("reversed", (0..n).rev().collect()),
+        ];
+        for (order, keys) in &orders {
+            group.bench_with_input(BenchmarkId::new(format!("AVLTree/{order}"), n), keys, |b, keys| {
+                b.iter(|| {
+                    let mut tree = AVLTree::new();
+                    for &k in keys {
+                        tree.insert(k);
+                    }
+                    tree
+                })
+            });
+            group.bench_with_input(BenchmarkId::new(format!("BTreeSet/{order}"), n), keys, |b, keys| {
+                b.iter(|| {
+                    let mut set = BTreeSet::new();
+                    for &k in keys {
+                        set.insert(k);
+                    }
+                    set
+                })
+            });
+        }
+    }
+    group.finish();
+}
+
+fn lookup(c: &mut Criterion) {
+    let mut group = c.benchmark_group("lookup");
+    for n in SIZES {
+        let keys = random_keys(n);
+        let tree: AVLTree<u64> = keys.iter().copied().collect();
+        let set: BTreeSet<u64> = keys.iter().copied().collect();
+        // flipping a high bit of a key mostly gives one that isn't in the set
+        let misses: Vec<u64> = keys.iter().map(|k| k ^ (1 << 62)).filter(|k| !set.contains(k)).collect();
+        group.throughput(Throughput::Elements(n));
+        for (outcome, probes) in [("hit", &keys), ("miss", &misses)] {
+            group.bench_with_input(BenchmarkId::new(format!("AVLTree/{outcome}"), n), probes, |b, probes| {
+                b.iter(|| probes.iter().filter(|k| tree.contains(*k)).count())
+            });
+            group.bench_with_input(BenchmarkId::new(format!("BTreeSet/{outcome}"), n), probes, |b, probes| {
+                b.iter(|| probes.iter().filter(|k| set.contains(*k)).count())
+            });
+        }
+    }
+    group.finish();
+}
+
+fn remove(c: &mut Criterion) {
+    let mut group = c.benchmark_group("remove");
+    for n in SIZES {
+        let keys = random_keys(n);
+        let mut removals = keys.clone();
+        removals.reverse();
+        let tree: AVLTree<u64> = keys.iter().copied().collect();
+        let set: BTreeSet<u64> = keys.iter().copied().collect();
+        group.throughput(Throughput::Elements(n));
+        group.bench_with_input(BenchmarkId::new("AVLTree", n), &removals, |b, removals| {
+            b.iter_batched(|| tree.clone(), |mut tree| {
+                for k in removals {
+                    tree.remove(k);
+                }
+                tree
+            }, BatchSize::LargeInput)
+        });
+        group.bench_with_input(BenchmarkId::new("BTreeSet", n), &removals, |b, removals| {
+            b.iter_batched(|| set.clone(), |mut set| {
+                for k in removals {
+                    set.remove(k);
+                }
+                set
+            }, BatchSize::LargeInput)
+        });
+    }
+    group.finish();
+}
+
+fn iterate(c: &mut Criterion) {
+    let mut group = c.benchmark_group("iterate");
+    for n in SIZES {
+        let tree: AVLTree<u64> = random_keys(n).into_iter().collect();
+        let set: BTreeSet<u64> = tree.iter().copied().collect();
+        group.throughput(Throughput::Elements(n));
+        group.bench_function(BenchmarkId::new("AVLTree", n), |b| b.iter(|| checksum(tree.iter())));
+        group.bench_function(BenchmarkId::new("AVLTree/stack", n), |b| b.iter(|| checksum(tree.stack_iter())));
+        group.bench_function(BenchmarkId::new("BTreeSet", n), |b| b.iter(|| checksum(set.iter())));
+    }
+    group.finish();
+}
+
+/// Reads 100 elements from each of 100 starting points spread over the keys.
+fn range_scan(c: &mut Criterion) {
+    let mut group = c.benchmark_group("range_scan");
+    for n in SIZES {
+        let keys = random_keys(n);
+        let tree: AVLTree<u64> = keys.iter().copied().collect();
+        let set: BTreeSet<u64> = keys.iter().copied().collect();
+        let starts: Vec<u64> = keys.iter().step_by(keys.len() / 100).copied().collect();
+        group.throughput(Throughput::Elements(100 * starts.len() as u64));
+        group.bench_function(BenchmarkId::new("AVLTree", n), |b| {
+            b.iter(|| starts.iter().map(|s| checksum(tree.iter_from(s).take(100))).fold(0, u64::wrapping_add))
+        });
+        group.bench_function(BenchmarkId::new("BTreeSet", n), |b| {
+            b.iter(|| starts.iter().map(|s| checksum(set.range(s..).take(100))).fold(0, u64::wrapping_add))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, insert, lookup, remove, iterate, range_scan);
+criterion_main!(benches);
+
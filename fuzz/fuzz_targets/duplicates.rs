@@ -0,0 +1,33 @@
+//! Decodes the input into operations, two bytes each, run on an `AVLTree` keeping duplicates
+//! and on a sorted `Vec` alike, checking the invariants of the tree and that both agree after
+//! every one of them.
+
+#![no_main]
+
+use avl_tree::{AVLTree, DuplicatePolicy};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let mut tree = AVLTree::new();
+    let mut model: Vec<u8> = Vec::new();
+    for op in data.chunks_exact(2) {
+        // few distinct values, for long runs of equal ones
+        let v = op[1] % 16;
+        match op[0] % 5 {
+            0 | 1 => {
+                tree.insert_with_policy(v, DuplicatePolicy::KeepBoth);
+                let at = model.partition_point(|x| *x <= v);
+                model.insert(at, v);
+            }
+            2 | 3 => {
+                let removed = model.iter().position(|x| *x == v).map(|at| model.remove(at));
+                assert_eq!(tree.take(&v), removed);
+            }
+            _ => tree.rebuild(),
+        }
+        tree.check_invariants().unwrap();
+        assert_eq!(tree.len(), model.len());
+        assert!(tree.iter().eq(&model));
+        assert_eq!(tree.count_range(v..=v), model.iter().filter(|x| **x == v).count());
+    }
+});
@@ -0,0 +1,42 @@
+//! Decodes the input into operations, two bytes each, run on an `AVLTree` and on a `BTreeSet`
+//! alike, checking the invariants of the tree and that both agree after every one of them.
+
+#![no_main]
+
+use std::collections::BTreeSet;
+
+use avl_tree::AVLTree;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let mut tree = AVLTree::new();
+    let mut model = BTreeSet::new();
+    for op in data.chunks_exact(2) {
+        let v = op[1];
+        match op[0] % 9 {
+            0 | 1 => assert_eq!(tree.insert(v), model.insert(v)),
+            2 | 3 => assert_eq!(tree.remove(&v), model.remove(&v)),
+            4 => assert_eq!(tree.take(&v), model.take(&v)),
+            5 => assert_eq!(tree.get_by_rank(v as usize), model.iter().nth(v as usize)),
+            6 => assert!(tree.iter_from(&v).eq(model.range(v..))),
+            7 => {
+                let mut high = tree.split_off(&v);
+                let model_high = model.split_off(&v);
+                assert!(high.iter().eq(&model_high));
+                high.check_invariants().unwrap();
+                tree.check_invariants().unwrap();
+                tree.append(&mut high);
+                model.extend(model_high);
+            }
+            _ => {
+                let removed = tree.extract_if(|x| x % (v | 1) == 0).count();
+                let before = model.len();
+                model.retain(|x| x % (v | 1) != 0);
+                assert_eq!(removed, before - model.len());
+            }
+        }
+        tree.check_invariants().unwrap();
+        assert_eq!(tree.len(), model.len());
+        assert!(tree.iter().eq(&model));
+    }
+});
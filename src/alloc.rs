@@ -0,0 +1,84 @@
+//! The allocators the nodes of `AVLTreeArena` and `AVLTreeBoxed` come from.
+//!
+//! With the `allocator_api2` feature, these are the allocators of the `allocator-api2` crate,
+//! and the vectors and boxes holding the nodes are its own. Without it, a stand-in for its
+//! `Allocator` trait is implemented by the global allocator only, and the vectors and boxes of
+//! the standard library are wrapped behind the same interface.
+
+#[cfg(feature = "allocator_api2")]
+pub(crate) use allocator_api2::{alloc::{Allocator, Global}, boxed::Box, vec::Vec};
+
+#[cfg(not(feature = "allocator_api2"))]
+pub(crate) use stand_in::{Allocator, Box, Global, Vec};
+
+#[cfg(not(feature = "allocator_api2"))]
+mod stand_in {
+    use std::ops::{Deref, DerefMut};
+
+    /// Implemented by [`Global`] only.
+    pub trait Allocator {}
+
+    /// The global allocator.
+    #[derive(Debug, Clone, Copy, Default)]
+    pub struct Global;
+
+    impl Allocator for Global {}
+
+    pub struct Vec<T, A: Allocator>(std::vec::Vec<T>, A);
+
+    impl<T, A: Allocator> Vec<T, A> {
+
+        pub const fn new_in(alloc: A) -> Self {
+            Vec(std::vec::Vec::new(), alloc)
+        }
+
+        pub fn with_capacity_in(capacity: usize, alloc: A) -> Self {
+            Vec(std::vec::Vec::with_capacity(capacity), alloc)
+        }
+
+        pub fn allocator(&self) -> &A {
+            &self.1
+        }
+    }
+
+    impl<T, A: Allocator> Deref for Vec<T, A> {
+        type Target = std::vec::Vec<T>;
+
+        fn deref(&self) -> &Self::Target {
+            &self.0
+        }
+    }
+
+    impl<T, A: Allocator> DerefMut for Vec<T, A> {
+        fn deref_mut(&mut self) -> &mut Self::Target {
+            &mut self.0
+        }
+    }
+
+    pub struct Box<T, A: Allocator>(std::boxed::Box<T>, A);
+
+    impl<T, A: Allocator> Box<T, A> {
+
+        pub fn new_in(x: T, alloc: A) -> Self {
+            Box(std::boxed::Box::new(x), alloc)
+        }
+
+        pub fn into_inner(boxed: Self) -> T {
+            *boxed.0
+        }
+    }
+
+    impl<T, A: Allocator> Deref for Box<T, A> {
+        type Target = T;
+
+        fn deref(&self) -> &T {
+            &self.0
+        }
+    }
+
+    impl<T, A: Allocator> DerefMut for Box<T, A> {
+        fn deref_mut(&mut self) -> &mut T {
+            &mut self.0
+        }
+    }
+}
@@ -0,0 +1,579 @@
+//! An ordered set keeping all of its nodes in one vector.
+
+use std::borrow::Borrow;
+use std::cmp::{max, Ordering};
+use std::mem;
+
+use crate::alloc::{self, Allocator, Global};
+use crate::Side;
+
+/// Stands for a missing node in the links.
+const NIL: u32 = u32::MAX;
+
+struct Node<T> {
+    value: T,
+    height: u8,
+    size: u32,
+    parent: u32,
+    left: u32,
+    right: u32,
+}
+
+impl<T> Node<T> {
+
+    fn child(&self, side: Side) -> u32 {
+        match side {
+            Side::Left => self.left,
+            Side::Right => self.right,
+        }
+    }
+
+    fn child_mut(&mut self, side: Side) -> &mut u32 {
+        match side {
+            Side::Left => &mut self.left,
+            Side::Right => &mut self.right,
+        }
+    }
+}
+
+/// An ordered set based on an AVL tree whose nodes are stored contiguously in a `Vec` and
+/// linked by `u32` indices.
+///
+/// Inserting only allocates when the vector grows, and no reference count or borrow flag is
+/// touched along the way. Removing moves the last node of the vector into the freed slot, so
+/// the nodes stay packed. The set holds at most `u32::MAX - 1` elements.
+///
+/// The vector is allocated from `A`, which can be any `allocator_api2::alloc::Allocator`, such
+/// as a reference to a bump arena, with the `allocator_api2` feature, and is the global
+/// allocator otherwise.
+pub struct AVLTreeArena<T: Ord, A: Allocator = Global> {
+    nodes: alloc::Vec<Node<T>, A>,
+    root: u32,
+}
+
+impl<T: Ord> AVLTreeArena<T> {
+
+    /// Creates an empty set.
+    pub fn new() -> Self {
+        AVLTreeArena::new_in(Global)
+    }
+
+    /// Creates an empty set with room for `capacity` elements before reallocating.
+    pub fn with_capacity(capacity: usize) -> Self {
+        AVLTreeArena::with_capacity_in(capacity, Global)
+    }
+}
+
+impl<T: Ord, A: Allocator> AVLTreeArena<T, A> {
+
+    /// Creates an empty set allocating from `alloc`.
+    pub fn new_in(alloc: A) -> Self {
+        AVLTreeArena { nodes: alloc::Vec::new_in(alloc), root: NIL }
+    }
+
+    /// Creates an empty set allocating from `alloc`, with room for `capacity` elements before
+    /// reallocating.
+    pub fn with_capacity_in(capacity: usize, alloc: A) -> Self {
+        AVLTreeArena { nodes: alloc::Vec::with_capacity_in(capacity, alloc), root: NIL }
+    }
+
+    /// Returns the allocator the set allocates from.
+    pub fn allocator(&self) -> &A {
+        self.nodes.allocator()
+    }
+
+    /// Returns the number of elements in the set.
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Returns the number of elements the set can hold without reallocating.
+    pub fn capacity(&self) -> usize {
+        self.nodes.capacity()
+    }
+
+    /// Removes all elements, keeping the allocated capacity.
+    pub fn clear(&mut self) {
+        self.nodes.clear();
+        self.root = NIL;
+    }
+
+    /// Returns `true` if the set contains `value`.
+    pub fn contains<Q: ?Sized + Ord>(&self, value: &Q) -> bool where T: Borrow<Q> {
+        self.find(value) != NIL
+    }
+
+    /// Returns the element equal to `value`.
+    pub fn get<Q: ?Sized + Ord>(&self, value: &Q) -> Option<&T> where T: Borrow<Q> {
+        match self.find(value) {
+            NIL => None,
+            i => Some(&self.node(i).value),
+        }
+    }
+
+    fn find<Q: ?Sized + Ord>(&self, value: &Q) -> u32 where T: Borrow<Q> {
+        let mut i = self.root;
+        while i != NIL {
+            let n = self.node(i);
+            i = match value.cmp(n.value.borrow()) {
+                Ordering::Equal => return i,
+                Ordering::Less => n.left,
+                Ordering::Greater => n.right,
+            };
+        }
+        NIL
+    }
+
+    /// Adds `value` to the set. Returns `false` if an equal element was already present, in
+    /// which case the set is left untouched.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the set already holds `u32::MAX - 1` elements.
+    pub fn insert(&mut self, value: T) -> bool {
+        let mut parent = NIL;
+        let mut side = Side::Left;
+        let mut i = self.root;
+        while i != NIL {
+            let n = self.node(i);
+            side = match value.cmp(&n.value) {
+                Ordering::Equal => return false,
+                Ordering::Less => Side::Left,
+                Ordering::Greater => Side::Right,
+            };
+            parent = i;
+            i = n.child(side);
+        }
+
+        let new = u32::try_from(self.nodes.len()).ok().filter(|&i| i != NIL).expect("arena tree is full");
+        self.nodes.push(Node { value, height: 1, size: 1, parent, left: NIL, right: NIL });
+        if parent == NIL {
+            self.root = new;
+        }
+        else {
+            *self.node_mut(parent).child_mut(side) = new;
+        }
+        self.rebalance(parent);
+        true
+    }
+
+    /// Removes `value` from the set. Returns `false` if it wasn't present.
+    pub fn remove<Q: ?Sized + Ord>(&mut self, value: &Q) -> bool where T: Borrow<Q> {
+        self.take(value).is_some()
+    }
+
+    /// Removes the element equal to `value` and returns it, or `None` if there was none.
+    pub fn take<Q: ?Sized + Ord>(&mut self, value: &Q) -> Option<T> where T: Borrow<Q> {
+        let mut i = self.find(value);
+        if i == NIL {
+            return None;
+        }
+        let n = self.node(i);
+        if n.left != NIL && n.right != NIL {
+            // trade elements with the successor, which has no left child
+            let mut s = n.right;
+            while self.node(s).left != NIL {
+                s = self.node(s).left;
+            }
+            let (a, b) = self.pair_mut(i, s);
+            mem::swap(&mut a.value, &mut b.value);
+            i = s;
+        }
+
+        let n = self.node(i);
+        let (parent, child) = (n.parent, if n.left != NIL { n.left } else { n.right });
+        if child != NIL {
+            self.node_mut(child).parent = parent;
+        }
+        self.replace_child(parent, i, child);
+        self.rebalance(parent);
+        Some(self.free(i))
+    }
+
+    /// Returns the element at position `rank` in sorted order (starting from 0).
+    pub fn get_by_rank(&self, rank: usize) -> Option<&T> {
+        let mut i = self.root;
+        let mut rank = rank;
+        while i != NIL {
+            let n = self.node(i);
+            let left = self.size(n.left);
+            i = match rank.cmp(&left) {
+                Ordering::Less => n.left,
+                Ordering::Equal => return Some(&n.value),
+                Ordering::Greater => {
+                    rank -= left + 1;
+                    n.right
+                }
+            };
+        }
+        None
+    }
+
+    /// Returns an iterator over the elements in sorted order.
+    pub fn iter(&self) -> Iter<'_, T, A> {
+        Iter {
+            tree: self,
+            front: self.outermost(self.root, Side::Left),
+            back: self.outermost(self.root, Side::Right),
+            remaining: self.len(),
+        }
+    }
+
+    /// Returns an iterator over the elements in sorted order which keeps a stack of the nodes
+    /// still to visit instead of following parent indices.
+    pub fn stack_iter(&self) -> StackIter<'_, T, A> {
+        let mut iter = StackIter { tree: self, front: Vec::new(), back: Vec::new(), remaining: self.len() };
+        iter.descend(self.root, Side::Left);
+        iter.descend(self.root, Side::Right);
+        iter
+    }
+
+    fn node(&self, i: u32) -> &Node<T> {
+        &self.nodes[i as usize]
+    }
+
+    fn node_mut(&mut self, i: u32) -> &mut Node<T> {
+        &mut self.nodes[i as usize]
+    }
+
+    /// Borrows two distinct nodes at once.
+    fn pair_mut(&mut self, a: u32, b: u32) -> (&mut Node<T>, &mut Node<T>) {
+        let (a, b) = (a as usize, b as usize);
+        if a < b {
+            let (low, high) = self.nodes.split_at_mut(b);
+            (&mut low[a], &mut high[0])
+        }
+        else {
+            let (low, high) = self.nodes.split_at_mut(a);
+            (&mut high[0], &mut low[b])
+        }
+    }
+
+    fn height(&self, i: u32) -> u8 {
+        if i == NIL { 0 } else { self.node(i).height }
+    }
+
+    fn size(&self, i: u32) -> usize {
+        if i == NIL { 0 } else { self.node(i).size as usize }
+    }
+
+    /// Recomputes the height and size of node `i`.
+    fn update(&mut self, i: u32) {
+        let n = self.node(i);
+        let height = 1 + max(self.height(n.left), self.height(n.right));
+        let size = 1 + self.size(n.left) + self.size(n.right);
+        let n = self.node_mut(i);
+        n.height = height;
+        n.size = size as u32;
+    }
+
+    fn balance_factor(&self, i: u32) -> i8 {
+        let n = self.node(i);
+        self.height(n.right) as i8 - self.height(n.left) as i8
+    }
+
+    /// Makes `parent` (the root if `NIL`) point at `new` where it pointed at `old`.
+    fn replace_child(&mut self, parent: u32, old: u32, new: u32) {
+        if parent == NIL {
+            self.root = new;
+        }
+        else if self.node(parent).left == old {
+            self.node_mut(parent).left = new;
+        }
+        else {
+            self.node_mut(parent).right = new;
+        }
+    }
+
+    /// Rotates the subtree rooted at `x` towards `side`, returning the new root of the subtree.
+    fn rotate(&mut self, x: u32, side: Side) -> u32 {
+        let z = self.node(x).child(!side);
+        let inner = self.node(z).child(side);
+        *self.node_mut(x).child_mut(!side) = inner;
+        if inner != NIL {
+            self.node_mut(inner).parent = x;
+        }
+        let parent = self.node(x).parent;
+        self.node_mut(z).parent = parent;
+        self.replace_child(parent, x, z);
+        *self.node_mut(z).child_mut(side) = x;
+        self.node_mut(x).parent = z;
+        self.update(x);
+        self.update(z);
+        z
+    }
+
+    /// Retraces from node `i` up to the root, refreshing heights and sizes and rotating
+    /// wherever a balance factor has reached +2 or -2.
+    fn rebalance(&mut self, mut i: u32) {
+        while i != NIL {
+            self.update(i);
+            let b = self.balance_factor(i);
+            if b.abs() > 1 {
+                let side = if b > 1 { Side::Left } else { Side::Right };
+                let z = self.node(i).child(!side);
+                let zb = self.balance_factor(z);
+                if (b > 1 && zb < 0) || (b < -1 && zb > 0) {
+                    self.rotate(z, !side);
+                }
+                i = self.rotate(i, side);
+            }
+            i = self.node(i).parent;
+        }
+    }
+
+    /// Removes the unlinked node `i` from the vector, moving the last node into its slot.
+    fn free(&mut self, i: u32) -> T {
+        let last = (self.nodes.len() - 1) as u32;
+        if i != last {
+            let n = self.node(last);
+            let (parent, left, right) = (n.parent, n.left, n.right);
+            self.replace_child(parent, last, i);
+            for child in [left, right] {
+                if child != NIL {
+                    self.node_mut(child).parent = i;
+                }
+            }
+        }
+        self.nodes.swap_remove(i as usize).value
+    }
+
+    /// Follows `side` children from `i` as far as they go.
+    fn outermost(&self, mut i: u32, side: Side) -> u32 {
+        if i == NIL {
+            return NIL;
+        }
+        while self.node(i).child(side) != NIL {
+            i = self.node(i).child(side);
+        }
+        i
+    }
+
+    /// Returns the in-order neighbour of node `i` on `side`.
+    fn neighbour(&self, i: u32, side: Side) -> u32 {
+        let child = self.node(i).child(side);
+        if child != NIL {
+            return self.outermost(child, !side);
+        }
+        let mut i = i;
+        let mut p = self.node(i).parent;
+        while p != NIL && self.node(p).child(side) == i {
+            i = p;
+            p = self.node(p).parent;
+        }
+        p
+    }
+}
+
+impl<T: Ord> Default for AVLTreeArena<T> {
+    fn default() -> Self {
+        AVLTreeArena::new()
+    }
+}
+
+impl<T: Ord> FromIterator<T> for AVLTreeArena<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut tree = AVLTreeArena::new();
+        tree.extend(iter);
+        tree
+    }
+}
+
+impl<T: Ord, A: Allocator> Extend<T> for AVLTreeArena<T, A> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for value in iter {
+            self.insert(value);
+        }
+    }
+}
+
+/// An iterator over the elements of an `AVLTreeArena`, in sorted order.
+///
+/// Created by [`AVLTreeArena::iter`].
+pub struct Iter<'a, T: Ord, A: Allocator = Global> {
+    tree: &'a AVLTreeArena<T, A>,
+    front: u32,
+    back: u32,
+    remaining: usize,
+}
+
+impl<'a, T: Ord, A: Allocator> Iterator for Iter<'a, T, A> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        let i = self.front;
+        self.front = self.tree.neighbour(i, Side::Right);
+        Some(&self.tree.node(i).value)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, T: Ord, A: Allocator> DoubleEndedIterator for Iter<'a, T, A> {
+    fn next_back(&mut self) -> Option<&'a T> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        let i = self.back;
+        self.back = self.tree.neighbour(i, Side::Left);
+        Some(&self.tree.node(i).value)
+    }
+}
+
+impl<T: Ord, A: Allocator> ExactSizeIterator for Iter<'_, T, A> {}
+
+/// An iterator over the elements of an `AVLTreeArena`, in sorted order, which ignores parent
+/// indices.
+///
+/// Created by [`AVLTreeArena::stack_iter`]. It keeps a stack of the nodes still to visit at
+/// each end.
+pub struct StackIter<'a, T: Ord, A: Allocator = Global> {
+    tree: &'a AVLTreeArena<T, A>,
+    front: Vec<u32>,
+    back: Vec<u32>,
+    remaining: usize,
+}
+
+impl<'a, T: Ord, A: Allocator> StackIter<'a, T, A> {
+
+    /// Stacks node `i` and its `side` children as far as they go.
+    fn descend(&mut self, mut i: u32, side: Side) {
+        let stack = match side {
+            Side::Left => &mut self.front,
+            Side::Right => &mut self.back,
+        };
+        while i != NIL {
+            stack.push(i);
+            i = self.tree.node(i).child(side);
+        }
+    }
+
+    fn step(&mut self, side: Side) -> Option<&'a T> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        let stack = match side {
+            Side::Left => &mut self.front,
+            Side::Right => &mut self.back,
+        };
+        let i = stack.pop()?;
+        let n = self.tree.node(i);
+        self.descend(n.child(!side), side);
+        Some(&n.value)
+    }
+}
+
+impl<'a, T: Ord, A: Allocator> Iterator for StackIter<'a, T, A> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        self.step(Side::Left)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, T: Ord, A: Allocator> DoubleEndedIterator for StackIter<'a, T, A> {
+    fn next_back(&mut self) -> Option<&'a T> {
+        self.step(Side::Right)
+    }
+}
+
+impl<T: Ord, A: Allocator> ExactSizeIterator for StackIter<'_, T, A> {}
+
+impl<'a, T: Ord, A: Allocator> IntoIterator for &'a AVLTreeArena<T, A> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T, A>;
+
+    fn into_iter(self) -> Iter<'a, T, A> {
+        self.iter()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Checks links, heights, sizes and balance of the whole tree.
+    fn check<T: Ord, A: Allocator>(tree: &AVLTreeArena<T, A>) {
+        fn walk<T: Ord, A: Allocator>(tree: &AVLTreeArena<T, A>, i: u32, parent: u32) -> (u8, usize) {
+            if i == NIL {
+                return (0, 0);
+            }
+            let n = tree.node(i);
+            assert_eq!(n.parent, parent);
+            let (hl, sl) = walk(tree, n.left, i);
+            let (hr, sr) = walk(tree, n.right, i);
+            assert!(hl.abs_diff(hr) <= 1);
+            assert_eq!(n.height, 1 + max(hl, hr));
+            assert_eq!(n.size as usize, 1 + sl + sr);
+            (n.height, n.size as usize)
+        }
+        assert_eq!(walk(tree, tree.root, NIL).1, tree.len());
+        assert!(tree.iter().zip(tree.iter().skip(1)).all(|(a, b)| a < b));
+    }
+
+    #[test]
+    fn test_arena() {
+        let mut tree = AVLTreeArena::with_capacity(100);
+        for i in 0..100 {
+            assert!(tree.insert((i * 37) % 100));
+        }
+        assert!(!tree.insert(5));
+        check(&tree);
+        assert_eq!(tree.len(), 100);
+        assert!(tree.iter().copied().eq(0..100));
+        assert!(tree.iter().rev().copied().eq((0..100).rev()));
+        assert_eq!(tree.get_by_rank(42), Some(&42));
+
+        for i in (0..100).filter(|i| i % 3 != 0) {
+            assert_eq!(tree.take(&i), Some(i));
+            check(&tree);
+        }
+        assert!(!tree.remove(&1));
+        assert!(tree.contains(&99));
+        assert!(tree.iter().copied().eq((0..100).step_by(3)));
+        assert!(tree.stack_iter().eq(tree.iter()));
+        assert!(tree.stack_iter().rev().eq(tree.iter().rev()));
+        assert_eq!(tree.len(), 34);
+
+        tree.clear();
+        assert!(tree.is_empty());
+        assert_eq!(tree.iter().next(), None);
+        assert_eq!(tree.stack_iter().next(), None);
+        assert!(tree.capacity() >= 100);
+    }
+
+    #[cfg(feature = "allocator_api2")]
+    #[test]
+    fn test_arena_allocator() {
+        let counting = crate::tests::Counting::default();
+        let mut tree = AVLTreeArena::with_capacity_in(64, &counting);
+        for i in 0..64 {
+            tree.insert((i * 37) % 64);
+        }
+        check(&tree);
+        // the room made up front holds all the nodes
+        assert_eq!(counting.allocations.get(), 1);
+        tree.insert(64);
+        assert_eq!(counting.allocations.get(), 2);
+        assert_eq!(counting.live.get(), 1);
+        drop(tree);
+        assert_eq!(counting.live.get(), 0);
+    }
+}
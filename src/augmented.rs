@@ -0,0 +1,596 @@
+//! An ordered map whose nodes each keep a summary of their subtree, such as the sum or the
+//! maximum of the values in it, maintained through every insertion, removal and rotation.
+//!
+//! What gets summarized is up to an [`Augment`] impl, chosen as a type parameter of the map.
+//! The subtree sizes and heights which the other trees keep are summaries of this kind, with
+//! counting and taking the maximum as combining steps; here they are kept alongside.
+
+use std::borrow::Borrow;
+use std::cmp::{max, Ordering};
+use std::marker::PhantomData;
+use std::mem;
+use std::ops::{Add, RangeBounds};
+
+use crate::{out_of_bounds, Side};
+
+/// Defines the summary an [`AVLAugmentedMap`] keeps for each of its subtrees.
+///
+/// Summaries must form a monoid: `combine` has to be associative, with `empty` as its identity,
+/// so that the summary of a subtree doesn't depend on its shape. The summary of a node is
+/// `combine(combine(left, summarize(key, value)), right)`, `left` and `right` being the
+/// summaries of its subtrees.
+pub trait Augment<K, V> {
+    type Summary: Clone;
+
+    /// Returns the summary of an empty subtree.
+    fn empty() -> Self::Summary;
+
+    /// Returns the summary of a single entry.
+    fn summarize(key: &K, value: &V) -> Self::Summary;
+
+    /// Returns the summary of two adjacent subtrees, `left` covering the smaller keys.
+    fn combine(left: &Self::Summary, right: &Self::Summary) -> Self::Summary;
+}
+
+/// Summarizes a subtree by its number of entries.
+pub struct Count;
+
+impl<K, V> Augment<K, V> for Count {
+    type Summary = usize;
+
+    fn empty() -> usize {
+        0
+    }
+
+    fn summarize(_: &K, _: &V) -> usize {
+        1
+    }
+
+    fn combine(left: &usize, right: &usize) -> usize {
+        left + right
+    }
+}
+
+/// Summarizes a subtree by the sum of its values, starting from `V::default()`.
+pub struct Sum;
+
+impl<K, V: Clone + Default + Add<Output = V>> Augment<K, V> for Sum {
+    type Summary = V;
+
+    fn empty() -> V {
+        V::default()
+    }
+
+    fn summarize(_: &K, value: &V) -> V {
+        value.clone()
+    }
+
+    fn combine(left: &V, right: &V) -> V {
+        left.clone() + right.clone()
+    }
+}
+
+/// Summarizes a subtree by the smallest of its values, `None` if it is empty.
+pub struct Min;
+
+impl<K, V: Clone + Ord> Augment<K, V> for Min {
+    type Summary = Option<V>;
+
+    fn empty() -> Option<V> {
+        None
+    }
+
+    fn summarize(_: &K, value: &V) -> Option<V> {
+        Some(value.clone())
+    }
+
+    fn combine(left: &Option<V>, right: &Option<V>) -> Option<V> {
+        match (left, right) {
+            (Some(l), Some(r)) => Some(l.min(r).clone()),
+            (l, r) => l.clone().or_else(|| r.clone()),
+        }
+    }
+}
+
+/// Summarizes a subtree by the largest of its values, `None` if it is empty.
+pub struct Max;
+
+impl<K, V: Clone + Ord> Augment<K, V> for Max {
+    type Summary = Option<V>;
+
+    fn empty() -> Option<V> {
+        None
+    }
+
+    fn summarize(_: &K, value: &V) -> Option<V> {
+        Some(value.clone())
+    }
+
+    fn combine(left: &Option<V>, right: &Option<V>) -> Option<V> {
+        match (left, right) {
+            (Some(l), Some(r)) => Some(l.max(r).clone()),
+            (l, r) => l.clone().or_else(|| r.clone()),
+        }
+    }
+}
+
+pub(crate) type Link<K, V, A> = Option<Box<Node<K, V, A>>>;
+
+pub(crate) struct Node<K, V, A: Augment<K, V>> {
+    pub(crate) key: K,
+    pub(crate) value: V,
+    height: usize,
+    size: usize,
+    pub(crate) summary: A::Summary,
+    pub(crate) left: Link<K, V, A>,
+    pub(crate) right: Link<K, V, A>,
+}
+
+impl<K, V, A: Augment<K, V>> Node<K, V, A> {
+
+    fn new(key: K, value: V) -> Self {
+        let summary = A::summarize(&key, &value);
+        Node { key, value, height: 1, size: 1, summary, left: None, right: None }
+    }
+
+    fn child(&self, side: Side) -> &Link<K, V, A> {
+        match side {
+            Side::Left => &self.left,
+            Side::Right => &self.right,
+        }
+    }
+
+    fn child_mut(&mut self, side: Side) -> &mut Link<K, V, A> {
+        match side {
+            Side::Left => &mut self.left,
+            Side::Right => &mut self.right,
+        }
+    }
+
+    fn height(&self, side: Side) -> usize {
+        self.child(side).as_ref().map_or(0, |n| n.height)
+    }
+
+    fn size(&self, side: Side) -> usize {
+        self.child(side).as_ref().map_or(0, |n| n.size)
+    }
+
+    /// Recomputes the `height`, `size` and `summary` fields from the ones of the children.
+    fn update(&mut self) {
+        self.height = 1 + max(self.height(Side::Left), self.height(Side::Right));
+        self.size = 1 + self.size(Side::Left) + self.size(Side::Right);
+        let own = A::summarize(&self.key, &self.value);
+        let left = summary(&self.left);
+        let right = summary(&self.right);
+        self.summary = A::combine(&A::combine(&left, &own), &right);
+    }
+
+    fn balance_factor(&self) -> isize {
+        self.height(Side::Right) as isize - self.height(Side::Left) as isize
+    }
+}
+
+fn summary<K, V, A: Augment<K, V>>(link: &Link<K, V, A>) -> A::Summary {
+    link.as_ref().map_or_else(A::empty, |n| n.summary.clone())
+}
+
+/// Rotates the subtree rooted at `node` towards `side`, returning the new root of the subtree.
+fn rotate<K, V, A: Augment<K, V>>(mut node: Box<Node<K, V, A>>, side: Side) -> Box<Node<K, V, A>> {
+    let mut z = node.child_mut(!side).take().unwrap();
+    *node.child_mut(!side) = z.child_mut(side).take();
+    node.update();
+    *z.child_mut(side) = Some(node);
+    z.update();
+    z
+}
+
+/// Refreshes the fields of `node` and rotates it if its balance factor has reached +2 or -2,
+/// returning the root of the subtree afterwards.
+fn balance<K, V, A: Augment<K, V>>(mut node: Box<Node<K, V, A>>) -> Box<Node<K, V, A>> {
+    node.update();
+    let b = node.balance_factor();
+    if b.abs() <= 1 {
+        return node;
+    }
+    let side = if b > 1 { Side::Left } else { Side::Right };
+    let zb = node.child(!side).as_ref().unwrap().balance_factor();
+    if (b > 1 && zb < 0) || (b < -1 && zb > 0) {
+        let z = node.child_mut(!side).take().unwrap();
+        *node.child_mut(!side) = Some(rotate(z, !side));
+    }
+    rotate(node, side)
+}
+
+/// Nodes taken out of the tree on the way down to a position, with the side taken at each, as
+/// in [`AVLTreeBoxed`](crate::AVLTreeBoxed).
+struct Path<K, V, A: Augment<K, V>> {
+    steps: Vec<Step<K, V, A>>,
+}
+
+type Step<K, V, A> = (Box<Node<K, V, A>>, Side);
+
+impl<K, V, A: Augment<K, V>> Path<K, V, A> {
+
+    fn new() -> Self {
+        Path { steps: Vec::new() }
+    }
+
+    /// Relinks the path on top of `subtree`, which replaces whatever the last step led to,
+    /// rebalancing and resummarizing every node, and returns the resulting tree.
+    fn retrace(mut self, mut subtree: Link<K, V, A>) -> Link<K, V, A> {
+        while let Some((mut node, side)) = self.steps.pop() {
+            *node.child_mut(side) = subtree;
+            subtree = Some(balance(node));
+        }
+        subtree
+    }
+}
+
+/// An ordered map based on an AVL tree which keeps the summary `A` of every subtree.
+///
+/// Apart from `summary`, it works like a map of uniquely owned nodes such as
+/// [`AVLTreeBoxed`](crate::AVLTreeBoxed). Values are only reachable mutably through
+/// [`update`](Self::update), which refreshes the summaries above the entry changed.
+pub struct AVLAugmentedMap<K, V, A: Augment<K, V>> {
+    root: Link<K, V, A>,
+    augment: PhantomData<A>,
+}
+
+impl<K: Ord, V, A: Augment<K, V>> AVLAugmentedMap<K, V, A> {
+
+    /// Creates an empty map.
+    pub fn new() -> Self {
+        AVLAugmentedMap { root: None, augment: PhantomData }
+    }
+
+    /// Returns the number of entries in the map.
+    pub fn len(&self) -> usize {
+        self.root.as_ref().map_or(0, |n| n.size)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+
+    /// Returns the summary of the whole map, in O(1).
+    pub fn summary(&self) -> A::Summary {
+        summary(&self.root)
+    }
+
+    pub(crate) fn root(&self) -> Option<&Node<K, V, A>> {
+        self.root.as_deref()
+    }
+
+    /// Returns the summary of the entries whose keys fall in `range`, in O(log n).
+    ///
+    /// The entries in range are covered by O(log n) whole subtrees, each summarized already,
+    /// and the nodes on the paths down to the two bounds.
+    pub fn aggregate_range<Q: ?Sized + Ord, R: RangeBounds<Q>>(&self, range: R) -> A::Summary where K: Borrow<Q> {
+        let (below, above) = out_of_bounds(range.start_bound(), range.end_bound());
+        let own = |n: &Node<K, V, A>| A::summarize(&n.key, &n.value);
+
+        // the topmost node in range, where the paths to both bounds part
+        let mut next = self.root.as_deref();
+        let split = loop {
+            let Some(n) = next else {
+                return A::empty();
+            };
+            next = if below(n.key.borrow()) {
+                n.right.as_deref()
+            }
+            else if above(n.key.borrow()) {
+                n.left.as_deref()
+            }
+            else {
+                break n;
+            };
+        };
+
+        // on its left, everything is below the upper bound: gather what is above the lower one
+        let mut left = A::empty();
+        let mut next = split.left.as_deref();
+        while let Some(n) = next {
+            if below(n.key.borrow()) {
+                next = n.right.as_deref();
+            }
+            else {
+                left = A::combine(&A::combine(&own(n), &summary(&n.right)), &left);
+                next = n.left.as_deref();
+            }
+        }
+        let mut right = A::empty();
+        let mut next = split.right.as_deref();
+        while let Some(n) = next {
+            if above(n.key.borrow()) {
+                next = n.left.as_deref();
+            }
+            else {
+                right = A::combine(&right, &A::combine(&summary(&n.left), &own(n)));
+                next = n.right.as_deref();
+            }
+        }
+        A::combine(&A::combine(&left, &own(split)), &right)
+    }
+
+    /// Returns `true` if the map has an entry for `key`.
+    pub fn contains_key<Q: ?Sized + Ord>(&self, key: &Q) -> bool where K: Borrow<Q> {
+        self.get(key).is_some()
+    }
+
+    /// Returns the value for `key`.
+    pub fn get<Q: ?Sized + Ord>(&self, key: &Q) -> Option<&V> where K: Borrow<Q> {
+        let mut next = self.root.as_deref();
+        while let Some(n) = next {
+            next = match key.cmp(n.key.borrow()) {
+                Ordering::Equal => return Some(&n.value),
+                Ordering::Less => n.left.as_deref(),
+                Ordering::Greater => n.right.as_deref(),
+            };
+        }
+        None
+    }
+
+    /// Takes the nodes on the way down to `key` out of the tree, returning them along with the
+    /// node for `key` if there is one. The tree is left empty until the path is retraced.
+    fn descend<Q: ?Sized + Ord>(&mut self, key: &Q) -> (Path<K, V, A>, Link<K, V, A>) where K: Borrow<Q> {
+        let mut path = Path::new();
+        let mut next = self.root.take();
+        while let Some(mut node) = next {
+            let side = match key.cmp(node.key.borrow()) {
+                Ordering::Equal => return (path, Some(node)),
+                Ordering::Less => Side::Left,
+                Ordering::Greater => Side::Right,
+            };
+            next = node.child_mut(side).take();
+            path.steps.push((node, side));
+        }
+        (path, None)
+    }
+
+    /// Inserts `value` for `key`, returning the value it replaces, if any.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        let (path, found) = self.descend(&key);
+        let (node, old) = match found {
+            Some(mut node) => {
+                let old = mem::replace(&mut node.value, value);
+                node.update();
+                (node, Some(old))
+            }
+            None => (Box::new(Node::new(key, value)), None),
+        };
+        self.root = path.retrace(Some(node));
+        old
+    }
+
+    /// Removes the entry for `key`, returning its value.
+    pub fn remove<Q: ?Sized + Ord>(&mut self, key: &Q) -> Option<V> where K: Borrow<Q> {
+        let (path, found) = self.descend(key);
+        let Some(node) = found else {
+            self.root = path.retrace(None);
+            return None;
+        };
+        let ((_, value), rest) = remove_root(node);
+        self.root = path.retrace(rest);
+        Some(value)
+    }
+
+    /// Calls `f` on the value for `key`, then refreshes the summaries of the subtrees holding
+    /// it. Returns `false` if there is no entry for `key`.
+    pub fn update<Q: ?Sized + Ord, F: FnOnce(&mut V)>(&mut self, key: &Q, f: F) -> bool where K: Borrow<Q> {
+        let (path, found) = self.descend(key);
+        let found = found.map(|mut node| {
+            f(&mut node.value);
+            node.update();
+            node
+        });
+        let updated = found.is_some();
+        self.root = path.retrace(found);
+        updated
+    }
+
+    /// Returns an iterator over the entries in sorted order.
+    pub fn iter(&self) -> Iter<'_, K, V, A> {
+        let mut iter = Iter { stack: Vec::new(), remaining: self.len() };
+        iter.descend(self.root.as_deref());
+        iter
+    }
+}
+
+/// Removes the root of a subtree, returning its entry and what is left of the subtree.
+fn remove_root<K, V, A: Augment<K, V>>(mut node: Box<Node<K, V, A>>) -> ((K, V), Link<K, V, A>) {
+    match (node.left.take(), node.right.take()) {
+        (None, child) | (child, None) => ((node.key, node.value), child),
+        (left, Some(right)) => {
+            // the successor takes the place of the removed entry
+            let ((key, value), right) = pop_first(right);
+            node.left = left;
+            node.right = right;
+            let entry = (mem::replace(&mut node.key, key), mem::replace(&mut node.value, value));
+            (entry, Some(balance(node)))
+        }
+    }
+}
+
+/// Removes the smallest entry of a subtree, returning it and what is left of the subtree.
+fn pop_first<K, V, A: Augment<K, V>>(root: Box<Node<K, V, A>>) -> ((K, V), Link<K, V, A>) {
+    let mut path = Path::new();
+    let mut node = root;
+    while let Some(left) = node.left.take() {
+        path.steps.push((node, Side::Left));
+        node = left;
+    }
+    let rest = node.right.take();
+    ((node.key, node.value), path.retrace(rest))
+}
+
+impl<K: Ord, V, A: Augment<K, V>> Default for AVLAugmentedMap<K, V, A> {
+    fn default() -> Self {
+        AVLAugmentedMap::new()
+    }
+}
+
+/// Drops the nodes one by one, as dropping the root `Box` would recurse down the tree.
+impl<K, V, A: Augment<K, V>> Drop for AVLAugmentedMap<K, V, A> {
+    fn drop(&mut self) {
+        let mut stack: Vec<Box<Node<K, V, A>>> = self.root.take().into_iter().collect();
+        while let Some(mut node) = stack.pop() {
+            stack.extend(node.left.take());
+            stack.extend(node.right.take());
+        }
+    }
+}
+
+impl<K: Ord, V, A: Augment<K, V>> FromIterator<(K, V)> for AVLAugmentedMap<K, V, A> {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut map = AVLAugmentedMap::new();
+        map.extend(iter);
+        map
+    }
+}
+
+impl<K: Ord, V, A: Augment<K, V>> Extend<(K, V)> for AVLAugmentedMap<K, V, A> {
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        for (key, value) in iter {
+            self.insert(key, value);
+        }
+    }
+}
+
+/// An iterator over the entries of an `AVLAugmentedMap`, in sorted order.
+pub struct Iter<'a, K, V, A: Augment<K, V>> {
+    stack: Vec<&'a Node<K, V, A>>,
+    remaining: usize,
+}
+
+impl<'a, K, V, A: Augment<K, V>> Iter<'a, K, V, A> {
+
+    /// Stacks `node` and its left children as far as they go.
+    fn descend(&mut self, mut next: Option<&'a Node<K, V, A>>) {
+        while let Some(n) = next {
+            self.stack.push(n);
+            next = n.left.as_deref();
+        }
+    }
+}
+
+impl<'a, K, V, A: Augment<K, V>> Iterator for Iter<'a, K, V, A> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let n = self.stack.pop()?;
+        self.remaining -= 1;
+        self.descend(n.right.as_deref());
+        Some((&n.key, &n.value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<K, V, A: Augment<K, V>> ExactSizeIterator for Iter<'_, K, V, A> {}
+
+impl<'a, K: Ord, V, A: Augment<K, V>> IntoIterator for &'a AVLAugmentedMap<K, V, A> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V, A>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ops::Bound;
+
+    /// Checks heights, sizes, balance and summaries of the whole tree, returning its height.
+    fn check<K: Ord, V, A: Augment<K, V>>(link: &Link<K, V, A>) -> usize where A::Summary: PartialEq + std::fmt::Debug {
+        let Some(n) = link else {
+            return 0;
+        };
+        let (hl, hr) = (check(&n.left), check(&n.right));
+        assert!(hl.abs_diff(hr) <= 1);
+        assert_eq!(n.height, 1 + max(hl, hr));
+        assert_eq!(n.size, 1 + n.size(Side::Left) + n.size(Side::Right));
+        let own = A::summarize(&n.key, &n.value);
+        assert_eq!(n.summary, A::combine(&A::combine(&summary(&n.left), &own), &summary(&n.right)));
+        n.height
+    }
+
+    /// Keeps the key of the largest value, the first one on ties.
+    struct ArgMax;
+
+    impl Augment<i32, i64> for ArgMax {
+        type Summary = Option<(i64, i32)>;
+
+        fn empty() -> Self::Summary {
+            None
+        }
+
+        fn summarize(key: &i32, value: &i64) -> Self::Summary {
+            Some((*value, *key))
+        }
+
+        fn combine(left: &Self::Summary, right: &Self::Summary) -> Self::Summary {
+            match (left, right) {
+                (Some(l), Some(r)) if r.0 > l.0 => Some(*r),
+                (l, r) => l.or(*r),
+            }
+        }
+    }
+
+    fn arg_max(keys: impl Iterator<Item = i32>) -> Option<(i64, i32)> {
+        keys.map(|k| ((k as i64 * 7) % 101, k)).max_by(|a, b| a.0.cmp(&b.0).then(b.1.cmp(&a.1)))
+    }
+
+    #[test]
+    fn test_augmented() {
+        let mut sums: AVLAugmentedMap<i32, i64, Sum> = AVLAugmentedMap::new();
+        let mut maxima: AVLAugmentedMap<i32, i64, ArgMax> = AVLAugmentedMap::new();
+        for i in 0..200 {
+            let key = (i * 37) % 200;
+            assert_eq!(sums.insert(key, key as i64), None);
+            maxima.insert(key, (key as i64 * 7) % 101);
+            check(&sums.root);
+            check(&maxima.root);
+        }
+        assert_eq!(sums.summary(), (0..200).sum::<i64>());
+        assert_eq!(maxima.summary(), arg_max(0..200));
+        assert_eq!(sums.insert(5, 1005), Some(5));
+        assert_eq!(sums.summary(), (0..200).sum::<i64>() + 1000);
+
+        for i in (0..200).filter(|i| i % 3 != 0) {
+            assert_eq!(sums.remove(&i), Some(if i == 5 { 1005 } else { i as i64 }));
+            maxima.remove(&i);
+            check(&sums.root);
+            check(&maxima.root);
+        }
+        assert_eq!(sums.remove(&1), None);
+        assert_eq!(sums.summary(), (0..200).step_by(3).sum::<i64>());
+        assert_eq!(maxima.summary(), arg_max((0..200).step_by(3)));
+
+        assert!(sums.update(&3, |v| *v += 10));
+        assert!(!sums.update(&4, |v| *v += 10));
+        check(&sums.root);
+        assert_eq!(sums.get(&3), Some(&13));
+        assert_eq!(sums.summary(), (0..200).step_by(3).sum::<i64>() + 10);
+        assert!(sums.iter().map(|(k, _)| *k).eq((0..200).step_by(3)));
+
+        for (lo, hi) in [(0, 200), (10, 11), (10, 10), (-5, 50), (31, 160), (150, 400), (300, 400)] {
+            let expected: i64 = sums.iter().filter(|(k, _)| (lo..hi).contains(*k)).map(|(_, v)| v).sum();
+            assert_eq!(sums.aggregate_range(lo..hi), expected);
+            let expected = arg_max((0..200).step_by(3).filter(|k| (lo..=hi).contains(k)));
+            assert_eq!(maxima.aggregate_range(lo..=hi), expected);
+        }
+        assert_eq!(sums.aggregate_range((Bound::Excluded(3), Bound::Excluded(9))), 6);
+        assert_eq!(sums.aggregate_range(..), sums.summary());
+
+        let counted: AVLAugmentedMap<_, _, Count> = (0..50).map(|i| (i, ())).collect();
+        assert_eq!(counted.summary(), 50);
+        let lowest: AVLAugmentedMap<_, _, Min> = (0..50).map(|i| (i, 100 - i)).collect();
+        assert_eq!(lowest.summary(), Some(51));
+        let highest: AVLAugmentedMap<_, _, Max> = (0..50).map(|i| (i, 100 - i)).collect();
+        assert_eq!(highest.summary(), Some(100));
+    }
+}
@@ -0,0 +1,182 @@
+//! An ordered set holding at most a given number of elements, such as the top k of a stream.
+
+use std::borrow::Borrow;
+use std::rc::Rc;
+
+use crate::{peek, AVLTree, Iter, Side};
+
+/// Which element a [`BoundedAVLSet`] evicts when an insertion takes it past its capacity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Evict {
+    /// Evict the smallest element, keeping the largest ones.
+    Smallest,
+    /// Evict the largest element, keeping the smallest ones.
+    Largest,
+}
+
+impl Evict {
+    fn side(self) -> Side {
+        match self {
+            Evict::Smallest => Side::Left,
+            Evict::Largest => Side::Right,
+        }
+    }
+}
+
+/// An ordered set based on an [`AVLTree`] which, once full, evicts an element at one end for
+/// each one inserted.
+///
+/// Keeping the k largest elements seen, as for a leaderboard, is a set of capacity k evicting
+/// the smallest.
+pub struct BoundedAVLSet<T: Ord> {
+    tree: AVLTree<T>,
+    capacity: usize,
+    evict: Evict,
+}
+
+impl<T: Ord> BoundedAVLSet<T> {
+
+    /// Creates an empty set holding up to `capacity` elements, evicting as told by `evict`.
+    pub fn new(capacity: usize, evict: Evict) -> Self {
+        BoundedAVLSet { tree: AVLTree::new(), capacity, evict }
+    }
+
+    /// Returns the maximum number of elements in the set.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Returns which element gets evicted.
+    pub fn evict(&self) -> Evict {
+        self.evict
+    }
+
+    /// Returns the number of elements in the set.
+    pub fn len(&self) -> usize {
+        self.tree.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    /// Returns `true` if the set holds as many elements as its capacity.
+    pub fn is_full(&self) -> bool {
+        self.tree.len() >= self.capacity
+    }
+
+    /// Returns `true` if the set contains `value`.
+    pub fn contains<Q: ?Sized + Ord>(&self, value: &Q) -> bool where T: Borrow<Q> {
+        self.tree.contains(value)
+    }
+
+    /// Returns the smallest element.
+    pub fn first(&self) -> Option<&T> {
+        self.tree.extreme(Side::Left).map(|node| &peek(node).value)
+    }
+
+    /// Returns the largest element.
+    pub fn last(&self) -> Option<&T> {
+        self.tree.extreme(Side::Right).map(|node| &peek(node).value)
+    }
+
+    /// Returns the element the next insertion would evict if the set is full: the smallest or
+    /// the largest one.
+    pub fn next_evicted(&self) -> Option<&T> {
+        self.tree.extreme(self.evict.side()).map(|node| &peek(node).value)
+    }
+
+    /// Adds `value` to the set, evicting an element if the set is full. Returns the element left
+    /// out of the set, if any: the evicted one, or `value` itself if an equal element is already
+    /// present, or if the set is full and `value` would be the one evicted.
+    pub fn insert(&mut self, value: T) -> Option<T> {
+        let side = self.evict.side();
+        if self.tree.contains(&value) {
+            return Some(value);
+        }
+        if !self.is_full() {
+            self.tree.insert(value);
+            return None;
+        }
+        let beaten = match self.tree.extreme(side) {
+            Some(node) => match self.evict {
+                Evict::Smallest => value < peek(node).value,
+                Evict::Largest => value > peek(node).value,
+            },
+            None => true, // no room at all
+        };
+        if beaten {
+            return Some(value);
+        }
+        let evicted = Rc::clone(self.tree.extreme(side).unwrap());
+        let evicted = self.tree.remove_node(evicted);
+        self.tree.insert(value);
+        Some(evicted)
+    }
+
+    /// Removes `value` from the set. Returns `false` if it wasn't present.
+    pub fn remove<Q: ?Sized + Ord>(&mut self, value: &Q) -> bool where T: Borrow<Q> {
+        self.tree.remove(value)
+    }
+
+    /// Returns an iterator over the elements in sorted order.
+    pub fn iter(&self) -> Iter<'_, T> {
+        self.tree.iter()
+    }
+
+    /// Returns the elements in sorted order.
+    pub fn into_sorted_vec(self) -> Vec<T> {
+        self.tree.into_sorted_vec()
+    }
+}
+
+impl<'a, T: Ord> IntoIterator for &'a BoundedAVLSet<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bounded() {
+        let mut top = BoundedAVLSet::new(10, Evict::Smallest);
+        let mut evicted = Vec::new();
+        for i in 0..100 {
+            let score = (i * 37) % 100;
+            if let Some(out) = top.insert(score) {
+                evicted.push(out);
+            }
+            assert!(top.len() <= 10);
+        }
+        assert!(top.is_full());
+        assert!(top.iter().copied().eq(90..100));
+        assert_eq!(evicted.len(), 90);
+        evicted.sort();
+        assert!(evicted.into_iter().eq(0..90));
+        assert_eq!(top.insert(95), Some(95));
+        assert_eq!(top.insert(5), Some(5));
+        assert_eq!(top.next_evicted(), Some(&90));
+        assert_eq!(top.insert(100), Some(90));
+        assert_eq!((top.first(), top.last()), (Some(&91), Some(&100)));
+        assert!(top.remove(&95) && !top.is_full());
+        assert_eq!(top.insert(5), None);
+
+        let mut bottom = BoundedAVLSet::new(3, Evict::Largest);
+        for v in [5, 1, 9, 3] {
+            bottom.insert(v);
+        }
+        assert_eq!(bottom.insert(2), Some(5));
+        assert_eq!(bottom.insert(7), Some(7));
+        assert_eq!(bottom.into_sorted_vec(), [1, 2, 3]);
+
+        let mut none = BoundedAVLSet::new(0, Evict::Smallest);
+        assert_eq!(none.insert(1), Some(1));
+        assert!(none.is_empty() && none.is_full());
+    }
+}
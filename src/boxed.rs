@@ -0,0 +1,437 @@
+//! An ordered set made of uniquely owned nodes, without parent links or interior mutability.
+
+use std::borrow::Borrow;
+use std::cmp::{max, Ordering};
+use std::mem;
+
+use crate::alloc::{Allocator, Box, Global};
+use crate::Side;
+
+/// A node, owned by its parent or the set.
+type Owned<T, A> = Box<Node<T, A>, A>;
+type Link<T, A> = Option<Owned<T, A>>;
+
+struct Node<T, A: Allocator> {
+    value: T,
+    height: usize,
+    size: usize,
+    left: Link<T, A>,
+    right: Link<T, A>,
+}
+
+impl<T, A: Allocator> Node<T, A> {
+
+    fn new(value: T) -> Self {
+        Node { value, height: 1, size: 1, left: None, right: None }
+    }
+
+    fn child(&self, side: Side) -> &Link<T, A> {
+        match side {
+            Side::Left => &self.left,
+            Side::Right => &self.right,
+        }
+    }
+
+    fn child_mut(&mut self, side: Side) -> &mut Link<T, A> {
+        match side {
+            Side::Left => &mut self.left,
+            Side::Right => &mut self.right,
+        }
+    }
+
+    fn height(&self, side: Side) -> usize {
+        self.child(side).as_ref().map_or(0, |n| n.height)
+    }
+
+    fn size(&self, side: Side) -> usize {
+        self.child(side).as_ref().map_or(0, |n| n.size)
+    }
+
+    /// Recomputes the `height` and `size` fields.
+    fn update(&mut self) {
+        self.height = 1 + max(self.height(Side::Left), self.height(Side::Right));
+        self.size = 1 + self.size(Side::Left) + self.size(Side::Right);
+    }
+
+    fn balance_factor(&self) -> isize {
+        self.height(Side::Right) as isize - self.height(Side::Left) as isize
+    }
+}
+
+/// Rotates the subtree rooted at `node` towards `side`, returning the new root of the subtree.
+fn rotate<T, A: Allocator>(mut node: Owned<T, A>, side: Side) -> Owned<T, A> {
+    let mut z = node.child_mut(!side).take().unwrap();
+    *node.child_mut(!side) = z.child_mut(side).take();
+    node.update();
+    *z.child_mut(side) = Some(node);
+    z.update();
+    z
+}
+
+/// Refreshes the height and size of `node` and rotates it if its balance factor has reached
+/// +2 or -2, returning the root of the subtree afterwards.
+fn balance<T, A: Allocator>(mut node: Owned<T, A>) -> Owned<T, A> {
+    node.update();
+    let b = node.balance_factor();
+    if b.abs() <= 1 {
+        return node;
+    }
+    let side = if b > 1 { Side::Left } else { Side::Right };
+    let zb = node.child(!side).as_ref().unwrap().balance_factor();
+    if (b > 1 && zb < 0) || (b < -1 && zb > 0) {
+        let z = node.child_mut(!side).take().unwrap();
+        *node.child_mut(!side) = Some(rotate(z, !side));
+    }
+    rotate(node, side)
+}
+
+/// Nodes taken out of the tree on the way down to a position, with the side taken at each.
+///
+/// Each node is detached from its parent while on the path, which is how the tree gets by
+/// without parent links: the path is linked back together, bottom-up, by `retrace`.
+struct Path<T, A: Allocator> {
+    steps: Vec<(Owned<T, A>, Side)>,
+}
+
+impl<T, A: Allocator> Path<T, A> {
+
+    fn new() -> Self {
+        Path { steps: Vec::new() }
+    }
+
+    /// Relinks the path on top of `subtree`, which replaces whatever the last step led to,
+    /// rebalancing every node, and returns the resulting tree.
+    fn retrace(mut self, mut subtree: Link<T, A>) -> Link<T, A> {
+        while let Some((mut node, side)) = self.steps.pop() {
+            *node.child_mut(side) = subtree;
+            subtree = Some(balance(node));
+        }
+        subtree
+    }
+}
+
+/// An ordered set based on an AVL tree whose nodes are owned through `Box`es, with no parent
+/// links.
+///
+/// Nodes are reached from the root only, so there is no reference counting and no `RefCell`
+/// borrow to check. Updates retrace through a stack of the nodes visited on the way down.
+///
+/// The nodes are allocated from `A`, which can be any `allocator_api2::alloc::Allocator`, such
+/// as a reference to a bump arena, with the `allocator_api2` feature, and is the global
+/// allocator otherwise.
+pub struct AVLTreeBoxed<T: Ord, A: Allocator = Global> {
+    root: Link<T, A>,
+    alloc: A,
+}
+
+impl<T: Ord> AVLTreeBoxed<T> {
+
+    /// Creates an empty set.
+    pub fn new() -> Self {
+        AVLTreeBoxed::new_in(Global)
+    }
+}
+
+impl<T: Ord, A: Allocator> AVLTreeBoxed<T, A> {
+
+    /// Creates an empty set allocating its nodes from `alloc`.
+    pub fn new_in(alloc: A) -> Self {
+        AVLTreeBoxed { root: None, alloc }
+    }
+
+    /// Returns the allocator the set allocates its nodes from.
+    pub fn allocator(&self) -> &A {
+        &self.alloc
+    }
+
+    /// Returns the number of elements in the set.
+    pub fn len(&self) -> usize {
+        self.root.as_ref().map_or(0, |n| n.size)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+
+    /// Returns `true` if the set contains `value`.
+    pub fn contains<Q: ?Sized + Ord>(&self, value: &Q) -> bool where T: Borrow<Q> {
+        self.get(value).is_some()
+    }
+
+    /// Returns the element equal to `value`.
+    pub fn get<Q: ?Sized + Ord>(&self, value: &Q) -> Option<&T> where T: Borrow<Q> {
+        let mut next = self.root.as_deref();
+        while let Some(n) = next {
+            next = match value.cmp(n.value.borrow()) {
+                Ordering::Equal => return Some(&n.value),
+                Ordering::Less => n.left.as_deref(),
+                Ordering::Greater => n.right.as_deref(),
+            };
+        }
+        None
+    }
+
+    /// Adds `value` to the set. Returns `false` if an equal element was already present, in
+    /// which case the set is left untouched.
+    pub fn insert(&mut self, value: T) -> bool where A: Clone {
+        let mut path = Path::new();
+        let mut next = self.root.take();
+        while let Some(mut node) = next {
+            let side = match value.cmp(&node.value) {
+                Ordering::Equal => {
+                    self.root = path.retrace(Some(node));
+                    return false;
+                }
+                Ordering::Less => Side::Left,
+                Ordering::Greater => Side::Right,
+            };
+            next = node.child_mut(side).take();
+            path.steps.push((node, side));
+        }
+        self.root = path.retrace(Some(Box::new_in(Node::new(value), self.alloc.clone())));
+        true
+    }
+
+    /// Removes `value` from the set. Returns `false` if it wasn't present.
+    pub fn remove<Q: ?Sized + Ord>(&mut self, value: &Q) -> bool where T: Borrow<Q> {
+        self.take(value).is_some()
+    }
+
+    /// Removes the element equal to `value` and returns it, or `None` if there was none.
+    pub fn take<Q: ?Sized + Ord>(&mut self, value: &Q) -> Option<T> where T: Borrow<Q> {
+        let mut path = Path::new();
+        let mut next = self.root.take();
+        while let Some(mut node) = next {
+            let side = match value.cmp(node.value.borrow()) {
+                Ordering::Equal => {
+                    let (value, rest) = remove_root(node);
+                    self.root = path.retrace(rest);
+                    return Some(value);
+                }
+                Ordering::Less => Side::Left,
+                Ordering::Greater => Side::Right,
+            };
+            next = node.child_mut(side).take();
+            path.steps.push((node, side));
+        }
+        self.root = path.retrace(None);
+        None
+    }
+
+    /// Returns the element at position `rank` in sorted order (starting from 0).
+    pub fn get_by_rank(&self, rank: usize) -> Option<&T> {
+        let mut next = self.root.as_deref();
+        let mut rank = rank;
+        while let Some(n) = next {
+            let left = n.size(Side::Left);
+            next = match rank.cmp(&left) {
+                Ordering::Less => n.left.as_deref(),
+                Ordering::Equal => return Some(&n.value),
+                Ordering::Greater => {
+                    rank -= left + 1;
+                    n.right.as_deref()
+                }
+            };
+        }
+        None
+    }
+
+    /// Returns an iterator over the elements in sorted order.
+    pub fn iter(&self) -> Iter<'_, T, A> {
+        let mut iter = Iter { front: Vec::new(), back: Vec::new(), remaining: self.len() };
+        iter.descend(self.root.as_deref(), Side::Left);
+        iter.descend(self.root.as_deref(), Side::Right);
+        iter
+    }
+}
+
+/// Removes the root of a subtree, returning its element and what is left of the subtree.
+fn remove_root<T, A: Allocator>(mut node: Owned<T, A>) -> (T, Link<T, A>) {
+    match (node.left.take(), node.right.take()) {
+        (None, child) | (child, None) => (Box::into_inner(node).value, child),
+        (left, Some(right)) => {
+            // the successor takes the place of the removed element
+            let (successor, right) = pop_first(right);
+            node.left = left;
+            node.right = right;
+            let value = mem::replace(&mut node.value, successor);
+            (value, Some(balance(node)))
+        }
+    }
+}
+
+/// Removes the smallest element of a subtree, returning it and what is left of the subtree.
+fn pop_first<T, A: Allocator>(root: Owned<T, A>) -> (T, Link<T, A>) {
+    let mut path = Path::new();
+    let mut node = root;
+    while let Some(left) = node.left.take() {
+        path.steps.push((node, Side::Left));
+        node = left;
+    }
+    let rest = node.right.take();
+    (Box::into_inner(node).value, path.retrace(rest))
+}
+
+impl<T: Ord> Default for AVLTreeBoxed<T> {
+    fn default() -> Self {
+        AVLTreeBoxed::new()
+    }
+}
+
+/// Drops the nodes one by one, as dropping the root `Box` would recurse down the tree.
+impl<T: Ord, A: Allocator> Drop for AVLTreeBoxed<T, A> {
+    fn drop(&mut self) {
+        let mut stack: Vec<Owned<T, A>> = self.root.take().into_iter().collect();
+        while let Some(mut node) = stack.pop() {
+            stack.extend(node.left.take());
+            stack.extend(node.right.take());
+        }
+    }
+}
+
+impl<T: Ord> FromIterator<T> for AVLTreeBoxed<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut tree = AVLTreeBoxed::new();
+        tree.extend(iter);
+        tree
+    }
+}
+
+impl<T: Ord, A: Allocator + Clone> Extend<T> for AVLTreeBoxed<T, A> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for value in iter {
+            self.insert(value);
+        }
+    }
+}
+
+/// An iterator over the elements of an `AVLTreeBoxed`, in sorted order.
+///
+/// Created by [`AVLTreeBoxed::iter`]. Having no parent links to follow, it keeps a stack of
+/// the nodes still to visit at each end.
+pub struct Iter<'a, T, A: Allocator = Global> {
+    front: Vec<&'a Node<T, A>>,
+    back: Vec<&'a Node<T, A>>,
+    remaining: usize,
+}
+
+impl<'a, T, A: Allocator> Iter<'a, T, A> {
+
+    /// Stacks `node` and its `side` children as far as they go.
+    fn descend(&mut self, mut next: Option<&'a Node<T, A>>, side: Side) {
+        let stack = match side {
+            Side::Left => &mut self.front,
+            Side::Right => &mut self.back,
+        };
+        while let Some(n) = next {
+            stack.push(n);
+            next = n.child(side).as_deref();
+        }
+    }
+
+    fn step(&mut self, side: Side) -> Option<&'a T> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        let stack = match side {
+            Side::Left => &mut self.front,
+            Side::Right => &mut self.back,
+        };
+        let n = stack.pop()?;
+        self.descend(n.child(!side).as_deref(), side);
+        Some(&n.value)
+    }
+}
+
+impl<'a, T, A: Allocator> Iterator for Iter<'a, T, A> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        self.step(Side::Left)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, T, A: Allocator> DoubleEndedIterator for Iter<'a, T, A> {
+    fn next_back(&mut self) -> Option<&'a T> {
+        self.step(Side::Right)
+    }
+}
+
+impl<T, A: Allocator> ExactSizeIterator for Iter<'_, T, A> {}
+
+impl<'a, T: Ord, A: Allocator> IntoIterator for &'a AVLTreeBoxed<T, A> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T, A>;
+
+    fn into_iter(self) -> Iter<'a, T, A> {
+        self.iter()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Checks heights, sizes and balance of the whole tree, returning its height.
+    fn check<T: Ord, A: Allocator>(node: &Link<T, A>) -> usize {
+        let Some(n) = node
+        else {
+            return 0;
+        };
+        let (hl, hr) = (check(&n.left), check(&n.right));
+        assert!(hl.abs_diff(hr) <= 1);
+        assert_eq!(n.height, 1 + max(hl, hr));
+        assert_eq!(n.size, 1 + n.size(Side::Left) + n.size(Side::Right));
+        n.height
+    }
+
+    #[test]
+    fn test_boxed() {
+        let mut tree = AVLTreeBoxed::new();
+        for i in 0..200 {
+            assert!(tree.insert((i * 71) % 200));
+        }
+        assert!(!tree.insert(3));
+        assert!(check(&tree.root) <= 10);
+        assert_eq!(tree.len(), 200);
+        assert!(tree.iter().copied().eq(0..200));
+        assert!(tree.iter().rev().copied().eq((0..200).rev()));
+        assert_eq!(tree.get_by_rank(150), Some(&150));
+
+        for i in (0..200).filter(|i| i % 4 != 1) {
+            assert_eq!(tree.take(&i), Some(i));
+            check(&tree.root);
+        }
+        assert!(!tree.remove(&0));
+        assert!(tree.iter().copied().eq((1..200).step_by(4)));
+        let mut iter = tree.iter();
+        assert_eq!((iter.next(), iter.next_back()), (Some(&1), Some(&197)));
+        assert_eq!(iter.len(), 48);
+        assert!(tree.contains(&101));
+    }
+
+    #[cfg(feature = "allocator_api2")]
+    #[test]
+    fn test_boxed_allocator() {
+        let counting = crate::tests::Counting::default();
+        let mut tree = AVLTreeBoxed::new_in(&counting);
+        for i in 0..100 {
+            tree.insert((i * 71) % 100);
+        }
+        check(&tree.root);
+        // one allocation per node, and none for the paths
+        assert_eq!(counting.allocations.get(), 100);
+        assert!(!tree.insert(7));
+        assert_eq!(tree.take(&7), Some(7));
+        assert_eq!((counting.allocations.get(), counting.live.get()), (100, 99));
+        assert!(tree.iter().copied().eq((0..100).filter(|&i| i != 7)));
+        drop(tree);
+        assert_eq!(counting.live.get(), 0);
+    }
+}
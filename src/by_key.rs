@@ -0,0 +1,193 @@
+//! An ordered set whose elements are ordered by a key extracted from them.
+
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+
+use crate::{peek, AVLTree, Iter as SetIter};
+
+/// An element of an `AVLTreeBy`. Its `Ord` impl is never used, the tree being searched with
+/// the key function only, except by the checks of `debug-validate` made by the tree itself.
+struct Unordered<T>(T);
+
+impl<T> PartialEq for Unordered<T> {
+    fn eq(&self, _: &Self) -> bool {
+        unreachable!("elements of an AVLTreeBy are compared through the key function")
+    }
+}
+
+impl<T> Eq for Unordered<T> {}
+
+impl<T> PartialOrd for Unordered<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T> Ord for Unordered<T> {
+    fn cmp(&self, _: &Self) -> Ordering {
+        // but for the checks of `debug-validate` made by the tree itself, which find all
+        // elements alike, the order being checked through the key function by `validate`
+        if cfg!(feature = "debug-validate") {
+            return Ordering::Equal;
+        }
+        unreachable!("elements of an AVLTreeBy are compared through the key function")
+    }
+}
+
+/// An ordered set based on an AVL tree, ordering its elements by the key `key` extracts from
+/// them rather than by an `Ord` impl on the elements themselves.
+///
+/// Two elements with equal keys can't be in the set together. The key is extracted anew for
+/// every comparison, so `key` should be cheap, typically reading or copying a field.
+pub struct AVLTreeBy<T, K: Ord, F: Fn(&T) -> K> {
+    tree: AVLTree<Unordered<T>>,
+    key: F,
+}
+
+impl<T, K: Ord, F: Fn(&T) -> K> AVLTreeBy<T, K, F> {
+
+    /// Creates an empty set ordering its elements by `key`.
+    pub fn new(key: F) -> Self {
+        AVLTreeBy { tree: AVLTree::new(), key }
+    }
+
+    /// Returns the number of elements in the set.
+    pub fn len(&self) -> usize {
+        self.tree.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    /// Adds `value` to the set. Returns `false` if an element with an equal key was already
+    /// present, in which case the set is left untouched.
+    pub fn insert(&mut self, value: T) -> bool {
+        let key = (self.key)(&value);
+        match self.tree.locate(|e| key.cmp(&(self.key)(&e.0))) {
+            Ok(_) => false,
+            Err((parent, side)) => {
+                self.tree.insert_at(parent, side, Unordered(value));
+                self.validate();
+                true
+            }
+        }
+    }
+
+    /// Returns the element whose key is equal to `key`.
+    pub fn get<Q: ?Sized + Ord>(&self, key: &Q) -> Option<&T> where K: Borrow<Q> {
+        self.tree.find_by(|e| key.cmp((self.key)(&e.0).borrow()))
+            .map(|node| &peek(node).value.0)
+    }
+
+    /// Returns `true` if the set holds an element whose key is equal to `key`.
+    pub fn contains_key<Q: ?Sized + Ord>(&self, key: &Q) -> bool where K: Borrow<Q> {
+        self.get(key).is_some()
+    }
+
+    /// Removes the element whose key is equal to `key` and returns it.
+    pub fn remove<Q: ?Sized + Ord>(&mut self, key: &Q) -> Option<T> where K: Borrow<Q> {
+        let node = self.tree.find_by(|e| key.cmp((self.key)(&e.0).borrow())).cloned()?;
+        let value = self.tree.remove_node(node).0;
+        self.validate();
+        Some(value)
+    }
+
+    /// Checks the invariants of the tree like `AVLTree::validate`, comparing the keys.
+    fn validate(&self) {
+        self.tree.validate_by(|a, b| (self.key)(&a.0).cmp(&(self.key)(&b.0)));
+    }
+
+    /// Returns an iterator over the elements of the set, sorted by key.
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter { inner: self.tree.iter() }
+    }
+}
+
+/// An iterator over the elements of an `AVLTreeBy`.
+///
+/// Created by [`AVLTreeBy::iter`].
+pub struct Iter<'a, T> {
+    inner: SetIter<'a, Unordered<T>>,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        self.inner.next().map(|e| &e.0)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a, T> DoubleEndedIterator for Iter<'a, T> {
+    fn next_back(&mut self) -> Option<&'a T> {
+        self.inner.next_back().map(|e| &e.0)
+    }
+}
+
+impl<T> ExactSizeIterator for Iter<'_, T> {}
+
+impl<'a, T, K: Ord, F: Fn(&T) -> K> IntoIterator for &'a AVLTreeBy<T, K, F> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Employee {
+        name: String,
+        age: u32,
+    }
+
+    fn employee(name: &str, age: u32) -> Employee {
+        Employee { name: name.to_string(), age }
+    }
+
+    #[test]
+    fn test_tree_by() {
+        let mut by_age = AVLTreeBy::new(|e: &Employee| e.age);
+        assert!(by_age.insert(employee("carol", 41)));
+        assert!(by_age.insert(employee("alice", 29)));
+        assert!(by_age.insert(employee("bob", 35)));
+        assert!(!by_age.insert(employee("dave", 35)));
+        assert_eq!(by_age.len(), 3);
+        assert_eq!(by_age.iter().map(|e| e.name.as_str()).collect::<Vec<_>>(), vec!["alice", "bob", "carol"]);
+        assert_eq!(by_age.get(&35).map(|e| e.name.as_str()), Some("bob"));
+        assert!(!by_age.contains_key(&30));
+        assert_eq!(by_age.remove(&29).map(|e| e.name), Some("alice".to_string()));
+        assert_eq!(by_age.remove(&29).map(|e| e.name), None);
+        assert_eq!(by_age.iter().rev().map(|e| e.age).collect::<Vec<_>>(), vec![41, 35]);
+
+        // an owned key can be looked up by its borrowed form
+        let mut by_name = AVLTreeBy::new(|e: &Employee| e.name.clone());
+        for i in 0..20 {
+            by_name.insert(employee(&format!("e{:02}", i), i));
+        }
+        assert_eq!(by_name.get("e07").map(|e| e.age), Some(7));
+        assert_eq!(by_name.iter().map(|e| e.age).collect::<Vec<_>>(), (0..20).collect::<Vec<_>>());
+    }
+
+    #[test]
+    #[cfg(all(feature = "debug-validate", debug_assertions))]
+    #[should_panic(expected = "AVLTree invariant broken: element 1 is out of order")]
+    fn test_validate_by_key() {
+        let mut tree = AVLTreeBy::new(|e: &std::cell::Cell<i32>| e.get());
+        for v in 0..3 {
+            tree.insert(std::cell::Cell::new(v));
+        }
+        // a key changing while in the tree is caught by the following change
+        tree.iter().next().unwrap().set(5);
+        tree.insert(std::cell::Cell::new(4));
+    }
+}
@@ -0,0 +1,410 @@
+//! An ordered set that several threads can read and modify at once, enabled by the
+//! `concurrent` feature.
+//!
+//! Each node sits behind its own read-write lock, and every operation walks down from the root
+//! with lock coupling: the lock of a child is taken before the one of its parent is released,
+//! so that an operation never shows up in a subtree before it is done with the links around
+//! it. All locks being taken top-down, parent before child, no deadlock can occur.
+//!
+//! Lookups hold at most two read locks at a time. Insertions and removals hold write locks on
+//! the part of the path that retracing may change: reaching a node whose height can't change
+//! whatever happens below (a leaning node when inserting, a balanced one when removing)
+//! releases all the locks above it. Rotations swap the contents of the nodes involved rather
+//! than relinking the subtree into its parent, as [`AVLTree`](crate::AVLTree) does, so the
+//! parent doesn't need to be locked.
+
+use std::borrow::Borrow;
+use std::cmp::{max, Ordering};
+use std::mem;
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+use std::sync::Arc;
+
+use parking_lot::{ArcRwLockReadGuard, ArcRwLockWriteGuard, RawRwLock, RwLock};
+
+use crate::{rotated_balances, Side};
+
+type NodeRef<T> = Arc<RwLock<Node<T>>>;
+
+type Link<T> = Option<NodeRef<T>>;
+
+type WriteGuard<T> = ArcRwLockWriteGuard<RawRwLock, Node<T>>;
+
+struct Node<T> {
+    value: T,
+    /// Height of the right subtree minus height of the left one.
+    balance: i8,
+    left: Link<T>,
+    right: Link<T>,
+}
+
+impl<T> Node<T> {
+
+    fn child(&self, side: Side) -> &Link<T> {
+        match side {
+            Side::Left => &self.left,
+            Side::Right => &self.right,
+        }
+    }
+
+    fn child_mut(&mut self, side: Side) -> &mut Link<T> {
+        match side {
+            Side::Left => &mut self.left,
+            Side::Right => &mut self.right,
+        }
+    }
+
+    /// Locks the child on `side`, if any.
+    fn lock_child(&self, side: Side) -> Option<WriteGuard<T>> {
+        self.child(side).as_ref().map(|c| c.write_arc())
+    }
+}
+
+/// A write lock held along the path of an insertion or removal: on the root link of the tree,
+/// or on a node.
+enum Held<T> {
+    Root(ArcRwLockWriteGuard<RawRwLock, Link<T>>),
+    Node(WriteGuard<T>),
+}
+
+impl<T> Held<T> {
+
+    /// The link leading down on `side`: the root link itself, or a child link of the node.
+    fn link_mut(&mut self, side: Side) -> &mut Link<T> {
+        match self {
+            Held::Root(root) => root,
+            Held::Node(node) => node.child_mut(side),
+        }
+    }
+
+    fn node_mut(&mut self) -> &mut Node<T> {
+        match self {
+            Held::Root(_) => unreachable!("the root link is not a node"),
+            Held::Node(node) => node,
+        }
+    }
+}
+
+/// The locks held by an insertion or removal, from the topmost node retracing may reach down
+/// to the current one, each along with the side the path takes from it. Each node held is the
+/// child of the one before.
+struct Path<T> {
+    held: Vec<(Held<T>, Side)>,
+}
+
+impl<T> Path<T> {
+
+    fn new(root: &Arc<RwLock<Link<T>>>) -> Self {
+        Path { held: vec![(Held::Root(root.write_arc()), Side::Left)] }
+    }
+
+    /// Locks the node below the last one held, on the side recorded for it.
+    fn lock_next(&self) -> Option<WriteGuard<T>> {
+        let (last, side) = self.held.last().unwrap();
+        match last {
+            Held::Root(root) => root.as_ref().map(|r| r.write_arc()),
+            Held::Node(node) => node.lock_child(*side),
+        }
+    }
+
+    /// Releases all the locks above the last one held, but the one at `keep` which is handed
+    /// back. Returns the number of locks taken out of the path.
+    fn release_above(&mut self, keep: Option<usize>) -> (usize, Option<Held<T>>) {
+        let last = self.held.len() - 1;
+        let kept = self.held.drain(..last).enumerate()
+            .find_map(|(i, (held, _))| (keep == Some(i)).then_some(held));
+        (last, kept)
+    }
+
+    /// Retraces from the `n`-th node held, whose subtree on the recorded side grew by one level
+    /// (`delta` = 1) or shrank by one (`delta` = -1), up to the topmost one, rotating the nodes
+    /// whose balance factor reaches +2 or -2.
+    fn retrace(&mut self, n: usize, mut delta: i8) {
+        for i in (0..=n).rev() {
+            if delta == 0 {
+                return;
+            }
+            let (above, below) = self.held.split_at_mut(i + 1);
+            let (x, side) = match above.last_mut().unwrap() {
+                (Held::Root(_), _) => return,
+                (Held::Node(node), side) => (node, *side),
+            };
+            let towards = |b: i8| if side == Side::Right { b } else { -b };
+            let old = x.balance;
+            let b = old + towards(delta);
+            delta += max(0, -towards(b)) - max(0, -towards(old));
+            x.balance = b;
+            if b.abs() < 2 {
+                continue;
+            }
+            let rot = if b > 1 { Side::Left } else { Side::Right };
+            let zb;
+            if !rot == side {
+                // the tall child is on the path, hence locked already
+                let (z, rest) = below.split_first_mut().unwrap();
+                let z = z.0.node_mut();
+                zb = z.balance;
+                if towards(zb) < 0 { // right-left or left-right case
+                    rotate(z, rest[0].0.node_mut(), !rot);
+                }
+                rotate(x, z, rot);
+            } else {
+                let mut z = x.lock_child(!rot).unwrap();
+                zb = z.balance;
+                if (b > 1 && zb < 0) || (b < -1 && zb > 0) {
+                    let mut y = z.lock_child(rot).unwrap();
+                    rotate(&mut z, &mut y, !rot);
+                }
+                rotate(x, &mut z, rot);
+            }
+            // only a single rotation over a balanced child keeps the height
+            if zb != 0 {
+                delta -= 1;
+            }
+        }
+    }
+}
+
+/// Rotates the subtree rooted at `x` towards `side`, `z` being its child on the other side.
+///
+/// The contents of the two nodes are swapped, so that `x` stays at the root of the subtree and
+/// the link to it doesn't change.
+fn rotate<T>(x: &mut Node<T>, z: &mut Node<T>, side: Side) {
+    let (bx, bz) = rotated_balances(x.balance, z.balance, side);
+    mem::swap(x, z);
+    // `x` now holds the element of `z` and its children, `z` the element of `x` and its
+    // children, among which `z` itself
+    let inner = x.child_mut(side).take();
+    let this = z.child_mut(!side).take();
+    *z.child_mut(!side) = inner;
+    *x.child_mut(side) = this;
+    x.balance = bz;
+    z.balance = bx;
+}
+
+/// An ordered set based on an AVL tree which can be shared between threads, each node having
+/// its own lock.
+///
+/// All methods take `&self`: lookups, insertions and removals run concurrently as long as they
+/// work in different parts of the tree. There is no iteration, as it would have to hold the
+/// whole tree still while running.
+pub struct AVLTreeConcurrent<T: Ord> {
+    root: Arc<RwLock<Link<T>>>,
+    len: AtomicUsize,
+}
+
+impl<T: Ord> AVLTreeConcurrent<T> {
+
+    /// Creates an empty set.
+    pub fn new() -> Self {
+        AVLTreeConcurrent { root: Arc::new(RwLock::new(None)), len: AtomicUsize::new(0) }
+    }
+
+    /// Returns the number of elements in the set, which may already be outdated when other
+    /// threads modify it.
+    pub fn len(&self) -> usize {
+        self.len.load(AtomicOrdering::Relaxed)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns `true` if the set contains `value`.
+    pub fn contains<Q: ?Sized + Ord>(&self, value: &Q) -> bool where T: Borrow<Q> {
+        self.find_map(value, |_| ()).is_some()
+    }
+
+    /// Returns a clone of the element equal to `value`.
+    pub fn get<Q: ?Sized + Ord>(&self, value: &Q) -> Option<T> where T: Borrow<Q> + Clone {
+        self.find_map(value, T::clone)
+    }
+
+    /// Searches for `value` with read locks, applying `f` to the matching element while its
+    /// node is locked.
+    fn find_map<Q: ?Sized + Ord, R, F: FnOnce(&T) -> R>(&self, value: &Q, f: F) -> Option<R> where T: Borrow<Q> {
+        let root = self.root.read_arc();
+        let mut guard: ArcRwLockReadGuard<RawRwLock, Node<T>> = root.as_ref()?.read_arc();
+        drop(root);
+        loop {
+            let side = match value.cmp(guard.value.borrow()) {
+                Ordering::Equal => return Some(f(&guard.value)),
+                Ordering::Less => Side::Left,
+                Ordering::Greater => Side::Right,
+            };
+            // lock the child before letting go of its parent
+            let child = guard.child(side).as_ref()?.read_arc();
+            guard = child;
+        }
+    }
+
+    /// Adds `value` to the set. Returns `false` if an equal element was already present, in
+    /// which case the set is left untouched.
+    pub fn insert(&self, value: T) -> bool {
+        let mut path = Path::new(&self.root);
+        while let Some(node) = path.lock_next() {
+            let side = match value.cmp(&node.value) {
+                Ordering::Equal => return false,
+                Ordering::Less => Side::Left,
+                Ordering::Greater => Side::Right,
+            };
+            // a leaning node absorbs the growth of a subtree, becoming balanced or rotating
+            // back to its height
+            let absorbs = node.balance != 0;
+            path.held.push((Held::Node(node), side));
+            if absorbs {
+                path.release_above(None);
+            }
+        }
+
+        // the new leaf is locked as well, as a double rotation may involve it
+        let leaf = Arc::new(RwLock::new(Node { value, balance: 0, left: None, right: None }));
+        let guard = leaf.write_arc();
+        let (last, side) = path.held.last_mut().unwrap();
+        *last.link_mut(*side) = Some(leaf);
+        path.held.push((Held::Node(guard), Side::Left));
+        self.len.fetch_add(1, AtomicOrdering::Relaxed);
+        path.retrace(path.held.len() - 2, 1);
+        true
+    }
+
+    /// Removes `value` from the set. Returns `false` if it wasn't present.
+    ///
+    /// The removed element is dropped once no other thread holds on to its node, which may be
+    /// a little later.
+    pub fn remove<Q: ?Sized + Ord>(&self, value: &Q) -> bool where T: Borrow<Q> {
+        let mut path = Path::new(&self.root);
+        // when the node holding `value` has two children, its element is swapped with the one
+        // of its successor, which is unlinked instead: the node stays locked meanwhile, in
+        // the path at `target` or out of it once released
+        let mut target: Option<usize> = None;
+        let mut pinned: Option<Held<T>> = None;
+        loop {
+            let Some(node) = path.lock_next() else {
+                return false;
+            };
+            let side = if target.is_some() || pinned.is_some() {
+                // looking for the successor
+                if node.left.is_none() {
+                    path.held.push((Held::Node(node), Side::Right));
+                    break;
+                }
+                Side::Left
+            } else {
+                match value.cmp(node.value.borrow()) {
+                    Ordering::Less => Side::Left,
+                    Ordering::Greater => Side::Right,
+                    Ordering::Equal if node.left.is_some() && node.right.is_some() => {
+                        target = Some(path.held.len());
+                        Side::Right
+                    }
+                    Ordering::Equal => {
+                        path.held.push((Held::Node(node), Side::Right));
+                        break;
+                    }
+                }
+            };
+            // a balanced node keeps its height when one of its subtrees shrinks
+            let absorbs = node.balance == 0;
+            path.held.push((Held::Node(node), side));
+            if absorbs {
+                let (released, kept) = path.release_above(target);
+                if kept.is_some() {
+                    pinned = kept;
+                }
+                target = target.and_then(|t| t.checked_sub(released));
+            }
+        }
+
+        // unlink the last node held, which has at most one child
+        let (mut node, _) = path.held.pop().unwrap();
+        let n = node.node_mut();
+        if let Some(held) = target.map(|t| &mut path.held[t].0).or(pinned.as_mut()) {
+            mem::swap(&mut held.node_mut().value, &mut n.value);
+        }
+        let child = n.left.take().or_else(|| n.right.take());
+        let (parent, side) = path.held.last_mut().unwrap();
+        *parent.link_mut(*side) = child;
+        drop(node);
+        drop(pinned);
+        self.len.fetch_sub(1, AtomicOrdering::Relaxed);
+        path.retrace(path.held.len() - 1, -1);
+        true
+    }
+}
+
+impl<T: Ord> Default for AVLTreeConcurrent<T> {
+    fn default() -> Self {
+        AVLTreeConcurrent::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Checks the balance factors and the ordering of the tree, returning its elements in
+    /// order.
+    fn check<T: Ord + Clone>(tree: &AVLTreeConcurrent<T>) -> Vec<T> {
+        fn walk<T: Clone>(link: &Link<T>, out: &mut Vec<T>) -> i32 {
+            let Some(node) = link else {
+                return 0;
+            };
+            let node = node.read();
+            let hl = walk(&node.left, out);
+            out.push(node.value.clone());
+            let hr = walk(&node.right, out);
+            assert_eq!(node.balance as i32, hr - hl);
+            assert!(node.balance.abs() <= 1);
+            1 + max(hl, hr)
+        }
+        let mut values = Vec::new();
+        walk(&tree.root.read(), &mut values);
+        assert!(values.windows(2).all(|w| w[0] < w[1]));
+        assert_eq!(values.len(), tree.len());
+        values
+    }
+
+    #[test]
+    fn test_concurrent() {
+        let tree = AVLTreeConcurrent::new();
+        for i in 0..200 {
+            assert!(tree.insert((i * 37) % 200));
+            check(&tree);
+        }
+        assert!(!tree.insert(5));
+        assert!(tree.contains(&199));
+        assert_eq!(tree.get(&42), Some(42));
+        assert_eq!(tree.get(&200), None);
+
+        for i in (0..200).filter(|i| i % 3 != 0) {
+            assert!(tree.remove(&i));
+            check(&tree);
+        }
+        assert!(!tree.remove(&1));
+        assert_eq!(check(&tree), (0..200).step_by(3).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_concurrent_threads() {
+        let tree = AVLTreeConcurrent::new();
+        std::thread::scope(|s| {
+            for t in 0..4 {
+                let tree = &tree;
+                s.spawn(move || {
+                    // each thread inserts its own residue class, then removes half of it,
+                    // while looking up elements of the others
+                    for i in (t..4000).step_by(4) {
+                        assert!(tree.insert(i));
+                        tree.contains(&(i ^ 1));
+                    }
+                    for i in (t..4000).step_by(8) {
+                        assert!(tree.remove(&i));
+                        assert!(tree.contains(&(i + 4)));
+                    }
+                });
+            }
+        });
+        let expected: Vec<_> = (0..4000).filter(|i| i % 8 >= 4).collect();
+        assert_eq!(check(&tree), expected);
+    }
+}
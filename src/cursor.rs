@@ -0,0 +1,196 @@
+use std::borrow::Borrow;
+use std::rc::Rc;
+
+use crate::{neighbour, peek, quiet, rebalance_following, AVLTree, Link, NodeRef, Side, Step, UnorderedError};
+
+impl<T: Ord> AVLTree<T> {
+
+    /// Returns a cursor pointing at the smallest element, or at the ghost position if the tree
+    /// is empty.
+    pub fn cursor_front_mut(&mut self) -> CursorMut<'_, T> {
+        let current = self.extreme(Side::Left).cloned();
+        CursorMut { tree: self, current }
+    }
+
+    /// Returns a cursor pointing at the largest element, or at the ghost position if the tree
+    /// is empty.
+    pub fn cursor_back_mut(&mut self) -> CursorMut<'_, T> {
+        let current = self.extreme(Side::Right).cloned();
+        CursorMut { tree: self, current }
+    }
+
+    /// Returns a cursor pointing at the element equal to `value`, or `None` if there is none.
+    pub fn cursor_mut_at<Q: ?Sized + Ord>(&mut self, value: &Q) -> Option<CursorMut<'_, T>> where T: Borrow<Q> {
+        let current = Some(Rc::clone(self.find(value)?));
+        Some(CursorMut { tree: self, current })
+    }
+}
+
+/// A cursor over an `AVLTree` that can insert elements next to the one it points at.
+///
+/// Besides the elements, the cursor can point at a "ghost" position lying past the largest
+/// element and before the smallest one, from which moving on wraps around.
+pub struct CursorMut<'a, T: Ord> {
+    tree: &'a mut AVLTree<T>,
+    current: Link<T>,
+}
+
+impl<T: Ord> CursorMut<'_, T> {
+
+    /// Returns the element the cursor points at, or `None` at the ghost position.
+    pub fn current(&self) -> Option<&T> {
+        self.current.as_ref().map(|node| &peek(node).value)
+    }
+
+    /// Returns the element following the cursor position.
+    pub fn peek_next(&self) -> Option<&T> {
+        self.neighbour(Side::Right).map(|node| &peek(node).value)
+    }
+
+    /// Returns the element preceding the cursor position.
+    pub fn peek_prev(&self) -> Option<&T> {
+        self.neighbour(Side::Left).map(|node| &peek(node).value)
+    }
+
+    /// Moves the cursor to the next element, or from the largest one to the ghost position.
+    pub fn move_next(&mut self) {
+        self.current = self.neighbour(Side::Right).cloned();
+    }
+
+    /// Moves the cursor to the previous element, or from the smallest one to the ghost
+    /// position.
+    pub fn move_prev(&mut self) {
+        self.current = self.neighbour(Side::Left).cloned();
+    }
+
+    /// Inserts `value` right after the cursor position, leaving the cursor where it is.
+    ///
+    /// The new leaf is attached without searching the tree, `value` being compared with the
+    /// current and the next element only. The cost is still O(log n): finding the next element,
+    /// and the walk up to the root refreshing subtree sizes after the rebalancing. Fails if
+    /// `value` doesn't lie strictly between the current and the next element.
+    pub fn insert_after(&mut self, value: T) -> Result<(), UnorderedError<T>> {
+        self.insert_beside(value, Side::Right)
+    }
+
+    /// Inserts `value` right before the cursor position, leaving the cursor where it is.
+    ///
+    /// Fails if `value` doesn't lie strictly between the previous and the current element.
+    pub fn insert_before(&mut self, value: T) -> Result<(), UnorderedError<T>> {
+        self.insert_beside(value, Side::Left)
+    }
+
+    /// Returns the node next to the cursor position on `side`. From the ghost position, that's
+    /// the extreme element on the other side.
+    fn neighbour(&self, side: Side) -> Option<&NodeRef<T>> {
+        match &self.current {
+            Some(node) => neighbour(&self.tree.root, node, side),
+            None => self.tree.extreme(!side),
+        }
+    }
+
+    fn insert_beside(&mut self, value: T, side: Side) -> Result<(), UnorderedError<T>> {
+        let next = self.neighbour(side).cloned();
+        let (low, high) = match side {
+            Side::Right => (&self.current, &next),
+            Side::Left => (&next, &self.current),
+        };
+        let in_order = low.as_ref().is_none_or(|n| peek(n).value < value)
+            && high.as_ref().is_none_or(|n| value < peek(n).value);
+        if !in_order {
+            return Err(UnorderedError { value });
+        }
+
+        // the free slot is either on `side` of the current node, or on the other side of its
+        // neighbour, which then is the outermost node of the current one's subtree on `side`
+        let parent = match &self.current {
+            Some(node) if peek(node).child(side).is_none() => Some((Rc::clone(node), side)),
+            _ => next.map(|node| (node, !side)),
+        };
+        let (parent, side) = match parent {
+            Some((node, side)) => (Some(node), side),
+            None => (None, Side::Left), // the tree is empty
+        };
+        let current = &mut self.current;
+        self.tree.narrated(&mut quiet, |tree, step| {
+            step(Step::Attached { value: &value, parent: parent.as_ref().map(|p| &peek(p).value), side: side.into() });
+            tree.attach(parent.clone(), side, value);
+            rebalance_following(parent, side, 1, 1, current, &tree.counters, step);
+        });
+        self.tree.validate();
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::checked_height;
+
+    fn tree_of(values: &[i32]) -> AVLTree<i32> {
+        let mut tree = AVLTree::new();
+        for &v in values {
+            tree.insert(v);
+        }
+        tree
+    }
+
+    fn assert_balanced(tree: &AVLTree<i32>) {
+        checked_height(&tree.root);
+    }
+
+    #[test]
+    fn test_cursor_moves() {
+        let mut tree = tree_of(&[10, 20, 30]);
+        let mut cursor = tree.cursor_front_mut();
+        assert_eq!(cursor.current(), Some(&10));
+        assert_eq!(cursor.peek_prev(), None);
+        cursor.move_next();
+        cursor.move_next();
+        assert_eq!(cursor.current(), Some(&30));
+        cursor.move_next();
+        assert_eq!(cursor.current(), None);
+        assert_eq!(cursor.peek_next(), Some(&10));
+        assert_eq!(cursor.peek_prev(), Some(&30));
+        cursor.move_prev();
+        assert_eq!(cursor.current(), Some(&30));
+
+        assert_eq!(tree.cursor_mut_at(&20).unwrap().peek_next(), Some(&30));
+        assert!(tree.cursor_mut_at(&25).is_none());
+        assert_eq!(AVLTree::<i32>::new().cursor_back_mut().current(), None);
+    }
+
+    #[test]
+    fn test_cursor_insert() {
+        // ascending insertions right after the cursor, then descending ones before it
+        let mut tree = tree_of(&[0]);
+        let mut cursor = tree.cursor_front_mut();
+        for v in 1..100 {
+            cursor.insert_after(v).unwrap();
+            cursor.move_next();
+        }
+        assert_eq!(cursor.current(), Some(&99));
+        for v in (-100..0).rev() {
+            let mut cursor = tree.cursor_front_mut();
+            cursor.insert_before(v).unwrap();
+            assert_eq!(cursor.current(), Some(&(v + 1)));
+        }
+        let mut cursor = tree.cursor_mut_at(&50).unwrap();
+        assert_eq!(cursor.insert_after(50), Err(UnorderedError { value: 50 }));
+        assert_eq!(cursor.insert_after(52), Err(UnorderedError { value: 52 }));
+        assert_eq!(cursor.insert_before(48), Err(UnorderedError { value: 48 }));
+        assert_eq!(tree.len(), 200);
+        assert!(tree.iter().copied().eq(-100..100));
+        assert_balanced(&tree);
+
+        // from the ghost position, after means before the smallest element
+        let mut tree = tree_of(&[]);
+        let mut cursor = tree.cursor_back_mut();
+        cursor.insert_after(5).unwrap();
+        cursor.insert_after(3).unwrap();
+        cursor.insert_before(7).unwrap();
+        assert_eq!(cursor.insert_before(6), Err(UnorderedError { value: 6 }));
+        assert!(tree.iter().copied().eq([3, 5, 7]));
+    }
+}
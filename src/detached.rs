@@ -0,0 +1,191 @@
+//! An iterator position kept apart from the tree, so that the tree can be changed between
+//! steps.
+
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::rc::{Rc, Weak};
+
+use crate::{child_link, neighbour, parent_link, peek, AVLNode, AVLTree, NodeRef, Side, StaleError};
+
+/// A position in the sorted order of an [`AVLTree`], remembered as the last element visited
+/// rather than as a borrow of the tree, which can be mutated between calls to
+/// [`next`](Self::next).
+///
+/// A step seeks past the last element visited, in O(log n), unless the tree is still at the
+/// [generation](AVLTree::generation) of the previous step, in which case it moves on from the
+/// node visited then, as `iter` would. Elements inserted after the position are visited when
+/// reached, removed ones are not, and no element is visited twice however the tree is
+/// rebalanced meanwhile. For a tree that doesn't change while it is iterated over, use
+/// [`AVLTree::snapshot`] instead.
+pub struct DetachedIter<T: Ord> {
+    last: Option<T>,
+    /// The number of elements equal to `last` visited so far, which trees accepting duplicates
+    /// can hold several of.
+    equal: usize,
+    /// The node holding `last` and the generation of the tree it was visited at.
+    at: Option<(u64, Weak<RefCell<AVLNode<T>>>)>,
+}
+
+impl<T: Ord + Clone> DetachedIter<T> {
+
+    /// Creates a position before the first element.
+    pub fn new() -> Self {
+        DetachedIter { last: None, equal: 0, at: None }
+    }
+
+    /// Creates a position right after `value`, whether it is in the tree or not.
+    pub fn after(value: T) -> Self {
+        DetachedIter { last: Some(value), equal: 1, at: None }
+    }
+
+    /// Returns the element of `tree` following the last one visited, and moves past it.
+    pub fn next<'a>(&mut self, tree: &'a AVLTree<T>) -> Option<&'a T> {
+        let next = match (self.unchanged(tree), &self.last) {
+            (Some(node), _) => neighbour(&tree.root, node, Side::Right)?,
+            (None, None) => tree.extreme(Side::Left)?,
+            (None, Some(last)) => {
+                let mut skipped = 0;
+                tree.range_nodes_by(|v| v < last, |_| false).find(|&node| {
+                    let v = &peek(node).value;
+                    if v == last && skipped < self.equal {
+                        skipped += 1;
+                        return false;
+                    }
+                    true
+                })?
+            }
+        };
+        self.at = Some((tree.generation(), Rc::downgrade(next)));
+        let next = &peek(next).value;
+        match self.last.as_ref().map(|last| next.cmp(last)) {
+            Some(Ordering::Equal) => self.equal += 1,
+            _ => {
+                self.last = Some(next.clone());
+                self.equal = 1;
+            }
+        }
+        Some(next)
+    }
+
+    /// Steps like [`next`](Self::next), but fails instead if `tree` changed since the previous
+    /// step, or isn't the tree it was taken on. The position is left as it was, for `next` to
+    /// resume from if need be.
+    pub fn try_next<'a>(&mut self, tree: &'a AVLTree<T>) -> Result<Option<&'a T>, StaleError> {
+        if self.last.is_some() && self.unchanged(tree).is_none() {
+            return Err(StaleError);
+        }
+        Ok(self.next(tree))
+    }
+
+    /// Finds the node visited last in `tree`, provided the tree didn't change since.
+    ///
+    /// No two trees sharing a generation, an equal one means the node is still in `tree`, at
+    /// the same place.
+    fn unchanged<'a>(&self, tree: &'a AVLTree<T>) -> Option<&'a NodeRef<T>> {
+        let (generation, node) = self.at.as_ref()?;
+        if *generation != tree.generation() {
+            return None;
+        }
+        let node = node.upgrade()?;
+        // reach the node through the tree, for the borrow to be the tree's
+        match parent_link(&tree.root, &node) {
+            None => tree.root.as_ref(),
+            Some(parent) => [child_link(parent, Side::Left), child_link(parent, Side::Right)]
+                .into_iter()
+                .flatten()
+                .find(|child| Rc::ptr_eq(child, &node)),
+        }
+    }
+
+    /// Returns the last element visited.
+    pub fn last(&self) -> Option<&T> {
+        self.last.as_ref()
+    }
+}
+
+impl<T: Ord + Clone> Default for DetachedIter<T> {
+    fn default() -> Self {
+        DetachedIter::new()
+    }
+}
+
+impl<T: Ord + Clone> Clone for DetachedIter<T> {
+    fn clone(&self) -> Self {
+        DetachedIter { last: self.last.clone(), equal: self.equal, at: self.at.clone() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DuplicatePolicy;
+    use std::collections::BTreeSet;
+    use std::ops::Bound;
+
+    #[test]
+    fn test_detached_iter() {
+        let mut tree: AVLTree<i32> = (0..100).map(|v| v * 2).collect();
+        let mut model: BTreeSet<i32> = tree.iter().copied().collect();
+        let mut iter = DetachedIter::new();
+        let mut last = None;
+        while let Some(&v) = iter.next(&tree) {
+            // the element following the last one visited, in the tree as it is now
+            let expected = model.range((last.map_or(Bound::Unbounded, Bound::Excluded), Bound::Unbounded)).next();
+            assert_eq!(Some(&v), expected);
+            last = Some(v);
+            // mutate on both sides of the position while iterating
+            if v % 10 == 0 {
+                for (remove, insert) in [(v + 2, v + 3), (v - 4, v - 5)] {
+                    tree.remove(&remove);
+                    model.remove(&remove);
+                    tree.insert(insert);
+                    model.insert(insert);
+                }
+            }
+        }
+        assert_eq!(tree.len(), model.len());
+        assert_eq!(iter.last(), Some(&198));
+
+        // later insertions past the end are picked up
+        assert_eq!(iter.next(&tree), None);
+        tree.insert(500);
+        assert_eq!(iter.next(&tree), Some(&500));
+
+        let mut dups = AVLTree::new();
+        for v in [1, 2, 2, 2, 3] {
+            dups.insert_with_policy(v, DuplicatePolicy::KeepBoth);
+        }
+        let mut iter = DetachedIter::after(1);
+        assert_eq!((iter.next(&dups), iter.next(&dups)), (Some(&2), Some(&2)));
+        dups.insert_with_policy(2, DuplicatePolicy::KeepBoth);
+        assert_eq!((iter.next(&dups), iter.next(&dups)), (Some(&2), Some(&2)));
+        assert_eq!((iter.next(&dups), iter.next(&dups)), (Some(&3), None));
+    }
+
+    #[test]
+    fn test_detached_staleness() {
+        let mut tree: AVLTree<i32> = (0..10).collect();
+        let mut iter = DetachedIter::new();
+        assert_eq!(iter.try_next(&tree), Ok(Some(&0)));
+        assert_eq!(iter.try_next(&tree), Ok(Some(&1)));
+        let generation = tree.generation();
+        tree.insert(20);
+        assert_ne!(tree.generation(), generation);
+        assert_eq!(iter.try_next(&tree), Err(StaleError));
+        assert_eq!(iter.next(&tree), Some(&2));
+
+        // a tree with the same elements is another tree
+        let other = tree.clone();
+        assert_ne!(other.generation(), tree.generation());
+        assert_eq!(iter.try_next(&other), Err(StaleError));
+        assert_eq!(iter.try_next(&tree), Ok(Some(&3)));
+        assert!(!tree.insert(5));
+        assert_eq!(iter.try_next(&tree), Ok(Some(&4)));
+        tree.remove(&4);
+        assert_eq!(iter.try_next(&tree), Err(StaleError));
+
+        let mut resumed = iter.clone();
+        assert!(std::iter::from_fn(|| resumed.next(&tree)).copied().eq([5, 6, 7, 8, 9, 20]));
+        assert_eq!(AVLTree::<i32>::new().generation(), 0);
+    }
+}
@@ -0,0 +1,450 @@
+//! Persistence of a tree to a directory through a write-ahead log and snapshots, for an ordered
+//! store that survives crashes.
+//!
+//! The directory holds two files. `snapshot` is the tree as of the last checkpoint: the number
+//! of the checkpoint as a little-endian `u64`, then the tree in the
+//! [snapshot format](crate::snapshot). `wal` logs the changes made since: the magic bytes
+//! `AVLW`, a format version byte and the number of the checkpoint it follows, then one record
+//! per change, its length and its CRC-32 as little-endian `u32`s followed by the change, in the
+//! encoding of an [`OperationLog`](crate::OperationLog).
+//!
+//! A checkpoint writes a new snapshot, then starts a new log, each in a temporary file renamed
+//! over the old one. A crash in between leaves a log older than the snapshot, which recovery
+//! ignores. A crash while appending to the log leaves a torn record at its end, which recovery
+//! drops. A record failing its checksum anywhere else is corruption, which recovery reports
+//! rather than dropping the changes logged after it.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use crate::snapshot::invalid;
+use crate::{AVLTree, DuplicatePolicy, Operation, SnapshotElement};
+
+const MAGIC: &[u8; 4] = b"AVLW";
+const VERSION: u8 = 1;
+/// The length of the header of the log, up to the first record.
+const HEADER_LEN: usize = 13;
+const SNAPSHOT: &str = "snapshot";
+const WAL: &str = "wal";
+
+/// When a [`DurableAVLTree`] has the OS write its log through to the disk, with `fsync`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncPolicy {
+    /// After every change, which survives a crash of the machine once the call returns.
+    Always,
+    /// After every so many changes, the ones since the last time being lost to a crash of the
+    /// machine.
+    Every(usize),
+    /// Only at checkpoints and on [`sync`](DurableAVLTree::sync). Changes still survive a crash
+    /// of the process.
+    Never,
+}
+
+/// An [`AVLTree`] persisted to a directory, every change being appended to a write-ahead log
+/// before it is made, and the whole tree written to a snapshot at checkpoints, which start the
+/// log anew.
+///
+/// Changes that leave the tree as it was, such as inserting an element already present, are
+/// not logged. The tree is read through [`tree`](Self::tree).
+pub struct DurableAVLTree<T: Ord> {
+    tree: AVLTree<T>,
+    dir: PathBuf,
+    wal: File,
+    /// The length of the log up to the last complete record.
+    wal_len: u64,
+    checkpoint: u64,
+    sync: SyncPolicy,
+    /// The number of changes logged since the last checkpoint.
+    logged: usize,
+    /// The number of changes logged since the last `fsync`.
+    unsynced: usize,
+    snapshot_every: Option<usize>,
+}
+
+impl<T: Ord + SnapshotElement> DurableAVLTree<T> {
+
+    /// Opens the tree persisted in `dir`, recovered as by [`AVLTree::recover`], or an empty one
+    /// if there is none yet, creating `dir` if need be. A torn record at the end of the log is
+    /// cut off.
+    pub fn open(dir: impl AsRef<Path>, sync: SyncPolicy) -> io::Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+        let recovered = recover(&dir)?;
+        let (wal, wal_len) = match recovered.wal_len {
+            Some(len) => {
+                let wal = OpenOptions::new().append(true).open(dir.join(WAL))?;
+                wal.set_len(len)?;
+                (wal, len)
+            }
+            None => start_log(&dir, recovered.checkpoint)?,
+        };
+        Ok(DurableAVLTree {
+            tree: recovered.tree,
+            dir,
+            wal,
+            wal_len,
+            checkpoint: recovered.checkpoint,
+            sync,
+            logged: recovered.records,
+            unsynced: 0,
+            snapshot_every: None,
+        })
+    }
+
+    /// Returns the tree, for reading.
+    pub fn tree(&self) -> &AVLTree<T> {
+        &self.tree
+    }
+
+    /// Closes the log and returns the tree.
+    pub fn into_tree(self) -> AVLTree<T> {
+        self.tree
+    }
+
+    /// Makes a checkpoint after every `changes` changes logged, or only on calls to
+    /// [`checkpoint`](Self::checkpoint) if `None`, which is the default.
+    pub fn set_snapshot_every(&mut self, changes: Option<usize>) {
+        self.snapshot_every = changes;
+    }
+
+    /// Adds `value` to the tree. Returns `false` if an equal element was already present.
+    pub fn insert(&mut self, value: T) -> io::Result<bool> {
+        if self.tree.contains(&value) {
+            return Ok(false);
+        }
+        self.apply(Operation::Insert(value))?;
+        Ok(true)
+    }
+
+    /// Adds `value` to the tree as [`AVLTree::insert_with_policy`] does, returning the element
+    /// left out of the tree, if any.
+    pub fn insert_with_policy(&mut self, value: T, policy: DuplicatePolicy) -> io::Result<Option<T>> {
+        if policy == DuplicatePolicy::Reject && self.tree.contains(&value) {
+            return Ok(Some(value));
+        }
+        self.apply(Operation::InsertWithPolicy(value, policy))
+    }
+
+    /// Removes `value` from the tree. Returns `false` if it wasn't present.
+    pub fn remove(&mut self, value: &T) -> io::Result<bool> where T: Clone {
+        if !self.tree.contains(value) {
+            return Ok(false);
+        }
+        self.apply(Operation::Remove(value.clone()))?;
+        Ok(true)
+    }
+
+    /// Rebuilds the tree into a perfectly balanced one.
+    pub fn rebuild(&mut self) -> io::Result<()> {
+        self.apply(Operation::Rebuild)?;
+        Ok(())
+    }
+
+    /// Logs `operation`, then runs it, then makes a checkpoint if one is due.
+    fn apply(&mut self, operation: Operation<T>) -> io::Result<Option<T>> {
+        let mut payload = Vec::new();
+        operation.write_to(&mut payload)?;
+        let mut record = Vec::with_capacity(8 + payload.len());
+        record.extend((payload.len() as u32).to_le_bytes());
+        record.extend(crc32(&payload).to_le_bytes());
+        record.extend(payload);
+        if let Err(e) = self.wal.write_all(&record) {
+            // a record written in part would hide the ones logged after it from recovery
+            let _ = self.wal.set_len(self.wal_len);
+            return Err(e);
+        }
+        self.wal_len += record.len() as u64;
+        self.logged += 1;
+        self.unsynced += 1;
+        let due = match self.sync {
+            SyncPolicy::Always => true,
+            SyncPolicy::Every(changes) => self.unsynced >= changes,
+            SyncPolicy::Never => false,
+        };
+        if due {
+            self.sync()?;
+        }
+
+        let left_out = operation.apply(&mut self.tree);
+        if self.snapshot_every.is_some_and(|changes| self.logged >= changes) {
+            self.checkpoint()?;
+        }
+        Ok(left_out)
+    }
+
+    /// Has the OS write the log through to the disk.
+    pub fn sync(&mut self) -> io::Result<()> {
+        self.wal.sync_data()?;
+        self.unsynced = 0;
+        Ok(())
+    }
+
+    /// Writes the tree to a new snapshot and starts a new, empty log, which makes recovery
+    /// faster and the log shorter.
+    pub fn checkpoint(&mut self) -> io::Result<()> {
+        let checkpoint = self.checkpoint + 1;
+        let temporary = self.dir.join("snapshot.tmp");
+        let mut file = BufWriter::new(File::create(&temporary)?);
+        checkpoint.write_to(&mut file)?;
+        self.tree.write_snapshot(&mut file)?;
+        file.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        fs::rename(&temporary, self.dir.join(SNAPSHOT))?;
+        sync_dir(&self.dir)?;
+
+        (self.wal, self.wal_len) = start_log(&self.dir, checkpoint)?;
+        self.checkpoint = checkpoint;
+        self.logged = 0;
+        self.unsynced = 0;
+        Ok(())
+    }
+}
+
+impl<T: Ord + SnapshotElement> AVLTree<T> {
+
+    /// Restores the tree persisted in `dir` by a [`DurableAVLTree`]: reads the snapshot, then
+    /// replays the changes logged since, up to a torn record left by a crash if there is one.
+    ///
+    /// Fails with `InvalidData` if the files are corrupted other than by a torn record.
+    pub fn recover(dir: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(recover(dir.as_ref())?.tree)
+    }
+}
+
+/// What recovery finds in a directory.
+struct Recovered<T: Ord> {
+    tree: AVLTree<T>,
+    checkpoint: u64,
+    /// The number of changes replayed from the log.
+    records: usize,
+    /// The length of the log up to the last complete record, or `None` if there is no log to
+    /// go on with.
+    wal_len: Option<u64>,
+}
+
+fn recover<T: Ord + SnapshotElement>(dir: &Path) -> io::Result<Recovered<T>> {
+    let (mut tree, checkpoint) = match File::open(dir.join(SNAPSHOT)) {
+        Ok(file) => {
+            let mut r = BufReader::new(file);
+            let checkpoint = u64::read_from(&mut r)?;
+            (AVLTree::read_snapshot(r)?, checkpoint)
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => (AVLTree::new(), 0),
+        Err(e) => return Err(e),
+    };
+    let mut recovered = Recovered { tree: AVLTree::new(), checkpoint, records: 0, wal_len: None };
+    let log = match fs::read(dir.join(WAL)) {
+        Ok(log) => log,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            recovered.tree = tree;
+            return Ok(recovered);
+        }
+        Err(e) => return Err(e),
+    };
+
+    if log.len() < HEADER_LEN || &log[..4] != MAGIC {
+        return Err(invalid("not an AVLTree write-ahead log"));
+    }
+    if log[4] != VERSION {
+        return Err(invalid("unsupported write-ahead log version"));
+    }
+    let follows = u64::from_le_bytes(log[5..HEADER_LEN].try_into().unwrap());
+    if follows > checkpoint {
+        return Err(invalid("write-ahead log newer than the snapshot"));
+    }
+    // otherwise older, from a crash in the middle of a checkpoint: all in the snapshot already
+    if follows == checkpoint {
+        let mut at = HEADER_LEN;
+        while let Some(payload) = record_at(&log, at)? {
+            let mut r = payload;
+            Operation::read_from(&mut r)?.apply(&mut tree);
+            at += 8 + payload.len();
+            recovered.records += 1;
+        }
+        recovered.wal_len = Some(at as u64);
+    }
+    recovered.tree = tree;
+    Ok(recovered)
+}
+
+/// Returns the change recorded at offset `at` of the log, unless the log ends there or with a
+/// torn record. A record whose checksum doesn't match is torn only if it is the last one, and
+/// the log corrupted otherwise.
+fn record_at(log: &[u8], at: usize) -> io::Result<Option<&[u8]>> {
+    let Some(header) = log.get(at..at + 8) else { return Ok(None) };
+    let len = u32::from_le_bytes(header[..4].try_into().unwrap()) as usize;
+    let crc = u32::from_le_bytes(header[4..].try_into().unwrap());
+    let Some(payload) = log.get(at + 8..at + 8 + len) else { return Ok(None) };
+    if crc32(payload) == crc {
+        Ok(Some(payload))
+    }
+    else if at + 8 + len == log.len() {
+        Ok(None)
+    }
+    else {
+        Err(invalid("corrupted record in the write-ahead log"))
+    }
+}
+
+/// Writes an empty log following checkpoint `checkpoint` in place of the current one, and
+/// opens it for appending.
+fn start_log(dir: &Path, checkpoint: u64) -> io::Result<(File, u64)> {
+    let temporary = dir.join("wal.tmp");
+    let mut file = File::create(&temporary)?;
+    let mut header = Vec::with_capacity(HEADER_LEN);
+    header.extend(MAGIC);
+    header.push(VERSION);
+    header.extend(checkpoint.to_le_bytes());
+    file.write_all(&header)?;
+    file.sync_all()?;
+    fs::rename(&temporary, dir.join(WAL))?;
+    sync_dir(dir)?;
+    Ok((OpenOptions::new().append(true).open(dir.join(WAL))?, HEADER_LEN as u64))
+}
+
+/// Makes the renames in `dir` durable, on systems where directories can be synced.
+fn sync_dir(dir: &Path) -> io::Result<()> {
+    if cfg!(unix) {
+        File::open(dir)?.sync_all()?;
+    }
+    Ok(())
+}
+
+/// The CRC-32 of `bytes`, as used by zlib and PNG.
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xedb8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// A fresh directory for a test, removed when dropped.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new() -> Self {
+            static COUNT: AtomicUsize = AtomicUsize::new(0);
+            let name = format!("avl_tree-durable-{}-{}", std::process::id(), COUNT.fetch_add(1, Ordering::Relaxed));
+            let dir = std::env::temp_dir().join(name);
+            let _ = fs::remove_dir_all(&dir);
+            TempDir(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+        assert_eq!(crc32(b""), 0);
+    }
+
+    #[test]
+    fn test_durable_recovery() {
+        let dir = TempDir::new();
+        let mut model = BTreeSet::new();
+        let mut tree = DurableAVLTree::open(&dir.0, SyncPolicy::Every(10)).unwrap();
+        tree.set_snapshot_every(Some(64));
+        for v in 0..300u32 {
+            let v = v * 37 % 211;
+            if v % 3 == 0 {
+                assert_eq!(tree.remove(&v).unwrap(), model.remove(&v));
+            }
+            else {
+                assert_eq!(tree.insert(v).unwrap(), model.insert(v));
+            }
+        }
+        drop(tree);
+        let recovered = AVLTree::<u32>::recover(&dir.0).unwrap();
+        assert!(recovered.iter().eq(&model));
+        assert_eq!(recovered.check_invariants(), Ok(()));
+
+        // going on from where it was left
+        let mut tree = DurableAVLTree::open(&dir.0, SyncPolicy::Always).unwrap();
+        assert!(tree.tree().iter().eq(&model));
+        assert_eq!(tree.insert_with_policy(1000, DuplicatePolicy::KeepBoth).unwrap(), None);
+        assert_eq!(tree.insert_with_policy(1000, DuplicatePolicy::Reject).unwrap(), Some(1000));
+        tree.rebuild().unwrap();
+        tree.checkpoint().unwrap();
+        assert!(tree.insert(2000).unwrap());
+        drop(tree);
+        let recovered = AVLTree::<u32>::recover(&dir.0).unwrap();
+        assert_eq!(recovered.len(), model.len() + 2);
+        assert_eq!(recovered.iter().filter(|&&v| v >= 1000).count(), 2);
+
+        // nothing to recover from a directory never written to
+        let empty = TempDir::new();
+        assert!(AVLTree::<u32>::recover(&empty.0).unwrap().is_empty());
+        assert!(DurableAVLTree::<u32>::open(&empty.0, SyncPolicy::Never).unwrap().tree().is_empty());
+        assert_eq!(fs::read(empty.0.join(WAL)).unwrap(), b"AVLW\x01\0\0\0\0\0\0\0\0");
+    }
+
+    #[test]
+    fn test_durable_crashes() {
+        let dir = TempDir::new();
+        let mut tree = DurableAVLTree::open(&dir.0, SyncPolicy::Never).unwrap();
+        for v in 0..10u64 {
+            tree.insert(v).unwrap();
+        }
+        drop(tree);
+
+        // a record torn by a crash while appending is dropped, and cut off on opening
+        let wal = dir.0.join(WAL);
+        let log = fs::read(&wal).unwrap();
+        fs::write(&wal, &log[..log.len() - 3]).unwrap();
+        assert!(AVLTree::<u64>::recover(&dir.0).unwrap().iter().eq(&(0..9).collect::<Vec<_>>()));
+        let mut tree = DurableAVLTree::<u64>::open(&dir.0, SyncPolicy::Never).unwrap();
+        tree.insert(20).unwrap();
+        drop(tree);
+        assert!(AVLTree::<u64>::recover(&dir.0).unwrap().iter().eq(&[0, 1, 2, 3, 4, 5, 6, 7, 8, 20]));
+
+        // as is a record whose checksum doesn't match
+        let mut log = fs::read(&wal).unwrap();
+        let last = log.len() - 1;
+        log[last] ^= 1;
+        fs::write(&wal, &log).unwrap();
+        assert_eq!(AVLTree::<u64>::recover(&dir.0).unwrap().len(), 9);
+
+        // but not one followed by others, whose changes would be lost along with it
+        let first = HEADER_LEN + 8;
+        log[first] ^= 1;
+        fs::write(&wal, &log).unwrap();
+        let error = AVLTree::<u64>::recover(&dir.0).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(DurableAVLTree::<u64>::open(&dir.0, SyncPolicy::Never).is_err());
+        assert_eq!(fs::read(&wal).unwrap(), log);
+        log[first] ^= 1;
+        fs::write(&wal, &log).unwrap();
+
+        // a crash between writing the snapshot and starting the new log leaves the old log
+        let mut tree = DurableAVLTree::<u64>::open(&dir.0, SyncPolicy::Never).unwrap();
+        tree.remove(&0).unwrap();
+        let old_log = fs::read(&wal).unwrap();
+        tree.checkpoint().unwrap();
+        drop(tree);
+        fs::write(&wal, &old_log).unwrap();
+        let recovered = AVLTree::<u64>::recover(&dir.0).unwrap();
+        assert!(recovered.iter().eq(&[1, 2, 3, 4, 5, 6, 7, 8]));
+        let mut tree = DurableAVLTree::<u64>::open(&dir.0, SyncPolicy::Never).unwrap();
+        tree.insert(0).unwrap();
+        drop(tree);
+        assert_eq!(AVLTree::<u64>::recover(&dir.0).unwrap().len(), 9);
+
+        fs::write(&wal, b"AVLX").unwrap();
+        assert_eq!(AVLTree::<u64>::recover(&dir.0).unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,245 @@
+//! An ordered set for read-mostly workloads whose readers never wait for writers, enabled by
+//! the `epoch` feature.
+//!
+//! The tree is persistent, as in [`persistent`](crate::persistent): nodes are never modified
+//! once published, and a write copies the path from the root down to the place it changes,
+//! sharing all the other subtrees with the previous version, before swapping the new root in
+//! with a single atomic store. Readers load
+//! whichever root is current and walk it without taking any lock or touching any reference
+//! count. Versions replaced by writers are reclaimed with epoch-based reclamation
+//! (`crossbeam-epoch`), once no reader that could have loaded them is still pinned; the nodes
+//! they don't share with the versions after them go along.
+//!
+//! Writers are serialized by a mutex. Every write allocates O(log n) nodes and clones as many
+//! elements, which makes `Arc<T>` a good choice of element when cloning `T` is costly.
+
+use std::borrow::Borrow;
+use std::marker::PhantomData;
+use std::ops::RangeBounds;
+use std::sync::atomic::Ordering as AtomicOrdering;
+use std::sync::Mutex;
+
+use crossbeam_epoch::{self as epoch, Atomic, Guard, Owned};
+
+use crate::persistent::{self, insert, remove, Link, Range};
+
+/// A version of the tree, as published by a writer.
+struct Version<T> {
+    root: Link<T>,
+    len: usize,
+}
+
+/// An ordered set based on a persistent AVL tree, which threads can read without ever
+/// blocking while another thread writes to it.
+///
+/// Reads go through a [`Pinned`] view of the current version, which later writes leave
+/// untouched; [`contains`](Self::contains) pins one for a single lookup.
+pub struct AVLTreeEpoch<T> {
+    current: Atomic<Version<T>>,
+    writer: Mutex<()>,
+}
+
+impl<T: Ord> AVLTreeEpoch<T> {
+
+    /// Creates an empty set.
+    pub fn new() -> Self {
+        AVLTreeEpoch {
+            current: Atomic::new(Version { root: None, len: 0 }),
+            writer: Mutex::new(()),
+        }
+    }
+
+    /// Pins the current version of the set for reading. It stays readable, and its memory
+    /// allocated, until the view is dropped, so views should be short-lived.
+    pub fn pin(&self) -> Pinned<'_, T> {
+        let guard = epoch::pin();
+        let version = self.current.load(AtomicOrdering::Acquire, &guard).as_raw();
+        Pinned { _guard: guard, version, tree: PhantomData }
+    }
+
+    /// Returns the number of elements in the current version of the set.
+    pub fn len(&self) -> usize {
+        self.pin().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns `true` if the current version of the set contains `value`.
+    pub fn contains<Q: ?Sized + Ord>(&self, value: &Q) -> bool where T: Borrow<Q> {
+        self.pin().contains(value)
+    }
+
+    /// Adds `value` to the set. Returns `false` if an equal element was already present, in
+    /// which case the set is left untouched.
+    ///
+    /// The version replaced may be dropped by whichever thread reclaims it, at any later time,
+    /// hence the bounds of the writing methods.
+    pub fn insert(&self, value: T) -> bool where T: Clone + Send + Sync + 'static {
+        self.write(|version| {
+            let root = insert(&version.root, value).ok()?;
+            Some(Version { root: Some(root), len: version.len + 1 })
+        })
+    }
+
+    /// Removes `value` from the set. Returns `false` if it wasn't present.
+    pub fn remove<Q: ?Sized + Ord>(&self, value: &Q) -> bool where T: Borrow<Q> + Clone + Send + Sync + 'static {
+        self.write(|version| {
+            let root = remove(&version.root, value)?;
+            Some(Version { root, len: version.len - 1 })
+        })
+    }
+
+    /// Publishes the version `f` derives from the current one, if any, handing the current
+    /// one over for reclamation. Returns whether a version was published.
+    fn write<F: FnOnce(&Version<T>) -> Option<Version<T>>>(&self, f: F) -> bool where T: Send + Sync + 'static {
+        let _writing = self.writer.lock().unwrap_or_else(|e| e.into_inner());
+        let guard = epoch::pin();
+        let current = self.current.load(AtomicOrdering::Acquire, &guard);
+        // SAFETY: the current version is never null, and it can't be reclaimed while pinned
+        let Some(next) = f(unsafe { current.deref() }) else {
+            return false;
+        };
+        self.current.store(Owned::new(next), AtomicOrdering::Release);
+        // SAFETY: the version is no longer reachable from the tree, and readers which loaded
+        // it before the store are pinned in an epoch that must end before it is destroyed. The
+        // global collector may destroy it on another thread, after the tree is gone: `T` being
+        // `'static` borrows nothing that could have ended by then, and `Send + Sync` makes the
+        // nodes, shared through `Arc`s, fine to drop from any thread
+        unsafe { guard.defer_destroy(current) };
+        true
+    }
+}
+
+impl<T: Ord> Default for AVLTreeEpoch<T> {
+    fn default() -> Self {
+        AVLTreeEpoch::new()
+    }
+}
+
+impl<T> Drop for AVLTreeEpoch<T> {
+    fn drop(&mut self) {
+        // SAFETY: no view can outlive the tree, so no one else reads the current version
+        unsafe {
+            let current = self.current.load(AtomicOrdering::Relaxed, epoch::unprotected());
+            drop(current.into_owned());
+        }
+    }
+}
+
+/// A version of an [`AVLTreeEpoch`], pinned for reading.
+///
+/// Holding one delays the reclamation of all the versions replaced meanwhile, not only of this
+/// one.
+pub struct Pinned<'a, T> {
+    _guard: Guard,
+    version: *const Version<T>,
+    tree: PhantomData<&'a AVLTreeEpoch<T>>,
+}
+
+impl<'a, T: Ord> Pinned<'a, T> {
+
+    fn version(&self) -> &Version<T> {
+        // SAFETY: the version was current when `guard` got pinned, so it won't be destroyed
+        // before `guard` is dropped
+        unsafe { &*self.version }
+    }
+
+    /// Returns the number of elements in this version of the set.
+    pub fn len(&self) -> usize {
+        self.version().len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns `true` if this version of the set contains `value`.
+    pub fn contains<Q: ?Sized + Ord>(&self, value: &Q) -> bool where T: Borrow<Q> {
+        self.get(value).is_some()
+    }
+
+    /// Returns a reference to the element equal to `value`, if any.
+    pub fn get<Q: ?Sized + Ord>(&self, value: &Q) -> Option<&T> where T: Borrow<Q> {
+        persistent::get(&self.version().root, value)
+    }
+
+    /// Returns an iterator over the elements of this version of the set, in sorted order.
+    pub fn iter(&self) -> Range<'_, T> {
+        Range::new(&self.version().root, ..)
+    }
+
+    /// Returns an iterator over the elements falling in `range`, in sorted order.
+    pub fn range<Q: ?Sized + Ord, R: RangeBounds<Q>>(&self, range: R) -> Range<'_, T> where T: Borrow<Q> {
+        Range::new(&self.version().root, range)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::persistent::tests::check;
+    use std::ops::Bound;
+
+    #[test]
+    fn test_epoch() {
+        let tree = AVLTreeEpoch::new();
+        for i in 0..200 {
+            assert!(tree.insert((i * 37) % 200));
+        }
+        assert!(!tree.insert(5));
+        let before = tree.pin();
+        check(&before.version().root);
+        assert!(before.iter().copied().eq(0..200));
+
+        for i in (0..200).filter(|i| i % 3 != 0) {
+            assert!(tree.remove(&i));
+        }
+        assert!(!tree.remove(&1));
+        let after = tree.pin();
+        check(&after.version().root);
+        assert_eq!(after.len(), 67);
+        assert!(after.iter().copied().eq((0..200).step_by(3)));
+        assert!(after.range(10..=30).copied().eq([12, 15, 18, 21, 24, 27, 30]));
+        assert!(after.range((Bound::Excluded(12), Bound::Excluded(15))).next().is_none());
+        assert!(after.range(300..).next().is_none());
+        assert_eq!(after.get(&99), Some(&99));
+
+        // the earlier version is still there, whole
+        assert_eq!(before.len(), 200);
+        assert!(before.iter().copied().eq(0..200));
+        assert!(before.contains(&1) && !tree.contains(&1));
+    }
+
+    #[test]
+    fn test_epoch_threads() {
+        let tree = AVLTreeEpoch::new();
+        for i in (0..1000).step_by(2) {
+            tree.insert(i);
+        }
+        std::thread::scope(|s| {
+            let tree = &tree;
+            s.spawn(move || {
+                for i in (1..1000).step_by(2) {
+                    assert!(tree.insert(i));
+                }
+                for i in (0..1000).step_by(4) {
+                    assert!(tree.remove(&i));
+                }
+            });
+            for _ in 0..3 {
+                s.spawn(move || {
+                    for _ in 0..200 {
+                        // whatever the version, the elements untouched by the writer are there
+                        let view = tree.pin();
+                        assert!(view.iter().zip(view.iter().skip(1)).all(|(a, b)| a < b));
+                        assert_eq!(view.iter().count(), view.len());
+                        assert!((2..1000).step_by(4).all(|i| view.contains(&i)));
+                    }
+                });
+            }
+        });
+        assert!(tree.pin().iter().copied().eq((0..1000).filter(|i| i % 4 != 0)));
+    }
+}
@@ -0,0 +1,98 @@
+use std::error::Error;
+use std::fmt;
+
+/// The error returned by [`AVLTree::try_insert`](crate::AVLTree::try_insert) when an equal
+/// element is already stored. It hands the rejected value back.
+#[derive(Debug, PartialEq, Eq)]
+pub struct OccupiedError<T> {
+    /// The value that was not inserted.
+    pub value: T,
+}
+
+impl<T> fmt::Display for OccupiedError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "an equal element is already in the tree")
+    }
+}
+
+impl<T: fmt::Debug> Error for OccupiedError<T> {}
+
+/// The error returned by the insertion methods of [`CursorMut`](crate::CursorMut) when the
+/// value doesn't fit strictly between the elements around the insertion point. It hands the
+/// rejected value back.
+#[derive(Debug, PartialEq, Eq)]
+pub struct UnorderedError<T> {
+    /// The value that was not inserted.
+    pub value: T,
+}
+
+impl<T> fmt::Display for UnorderedError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "the element is out of order at the cursor position")
+    }
+}
+
+impl<T: fmt::Debug> Error for UnorderedError<T> {}
+
+/// The error returned by [`AVLTreeFixed::insert`](crate::AVLTreeFixed::insert) when the tree
+/// is already full. It hands the rejected value back.
+#[derive(Debug, PartialEq, Eq)]
+pub struct CapacityError<T> {
+    /// The value that was not inserted.
+    pub value: T,
+}
+
+impl<T> fmt::Display for CapacityError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "the tree is full")
+    }
+}
+
+impl<T: fmt::Debug> Error for CapacityError<T> {}
+
+/// The error returned by [`DetachedIter::try_next`](crate::DetachedIter::try_next) when the
+/// tree changed since the previous step.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StaleError;
+
+impl fmt::Display for StaleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "the tree changed since the previous step")
+    }
+}
+
+impl Error for StaleError {}
+
+/// A breach of the structure of an [`AVLTree`](crate::AVLTree), as found by
+/// [`check_invariants`](crate::AVLTree::check_invariants). Nodes are designated by the rank of
+/// their element in sorted order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InvariantError {
+    /// The element is smaller than the one before it.
+    Unordered { rank: usize },
+    /// The heights of the subtrees of the node differ by more than one.
+    Unbalanced { rank: usize, balance: isize },
+    /// The balance factor stored in the node doesn't match the heights of its subtrees.
+    Balance { rank: usize, stored: i8, actual: isize },
+    /// The size stored in the node doesn't match the number of nodes in its subtree.
+    Size { rank: usize, stored: usize, actual: usize },
+    /// The parent link of the node doesn't point at its parent.
+    Parent { rank: usize },
+    /// A handle to the element doesn't point at its node.
+    Handle { rank: usize },
+}
+
+impl fmt::Display for InvariantError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            InvariantError::Unordered { rank } => write!(f, "element {rank} is out of order"),
+            InvariantError::Unbalanced { rank, balance } => write!(f, "node {rank} is unbalanced, with a balance factor of {balance}"),
+            InvariantError::Balance { rank, stored, actual } => write!(f, "node {rank} has a balance factor of {actual}, but stores {stored}"),
+            InvariantError::Size { rank, stored, actual } => write!(f, "node {rank} has a subtree of size {actual}, but stores {stored}"),
+            InvariantError::Parent { rank } => write!(f, "node {rank} has a wrong parent link"),
+            InvariantError::Handle { rank } => write!(f, "node {rank} has a handle pointing elsewhere"),
+        }
+    }
+}
+
+impl Error for InvariantError {}
@@ -0,0 +1,202 @@
+//! A stream of structured events telling how insertions and removals change a tree, for tools
+//! animating them frame by frame.
+
+use std::fmt::{self, Write};
+
+use crate::render::json_escape;
+use crate::{AVLTree, Direction, Step};
+
+/// A change made to a tree, as received by an [`EventSink`]. Nodes are named after the
+/// elements they hold at the time of the event.
+///
+/// An insertion creates a node, then updates balance factors and heights on the way up,
+/// rotating where need be. A removal first swaps the element with its in-order predecessor
+/// when it has two children, then removes its node and goes up alike.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Event<'a, T> {
+    /// A leaf holding `value` was created as the child of `parent` on `side`, or as the root of
+    /// an empty tree.
+    NodeCreated { value: &'a T, parent: Option<&'a T>, side: Direction },
+    /// `value` and `with` swapped nodes, for `value` to be removed from a node with one child
+    /// at most.
+    ValuesSwapped { value: &'a T, with: &'a T },
+    /// The node holding `value` was removed, `replacement`, its only child if it had one,
+    /// taking its place.
+    NodeRemoved { value: &'a T, replacement: Option<&'a T> },
+    /// The balance factor of `node` became `balance`.
+    BalanceUpdated { node: &'a T, balance: i8 },
+    /// The subtree of `pivot` was rotated towards `dir`.
+    Rotated { pivot: &'a T, dir: Direction },
+    /// The subtree at the place of `node`, rotated or not, became `height` tall.
+    HeightUpdated { node: &'a T, height: usize },
+}
+
+impl<T> Clone for Event<'_, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Event<'_, T> {}
+
+impl<'a, T> Event<'a, T> {
+
+    /// Returns the event a step of an operation makes, if any.
+    fn from_step(step: Step<'a, T>) -> Option<Self> {
+        Some(match step {
+            Step::Attached { value, parent, side } => Event::NodeCreated { value, parent, side },
+            Step::Swapped { value, with } => Event::ValuesSwapped { value, with },
+            Step::Removed { value, child } => Event::NodeRemoved { value, replacement: child },
+            Step::Balance { at, balance } => Event::BalanceUpdated { node: at, balance },
+            Step::Rotation { at, direction } => Event::Rotated { pivot: at, dir: direction },
+            Step::Height { at, height } => Event::HeightUpdated { node: at, height },
+            Step::Settled { .. } => return None,
+        })
+    }
+
+    /// Returns the event as a JSON object, its name under `"event"` and its fields under their
+    /// names, elements being their `Display` output as JSON strings:
+    /// `{"event":"Rotated","pivot":"37","dir":"left"}`.
+    pub fn to_json(&self) -> String where T: fmt::Display {
+        fn element<T: fmt::Display>(value: Option<&T>) -> String {
+            value.map_or_else(|| "null".to_string(), |v| format!("\"{}\"", json_escape(&v.to_string())))
+        }
+        let mut out = String::new();
+        match *self {
+            Event::NodeCreated { value, parent, side } => write!(
+                out,
+                r#"{{"event":"NodeCreated","value":{},"parent":{},"side":"{side}"}}"#,
+                element(Some(value)),
+                element(parent),
+            ),
+            Event::ValuesSwapped { value, with } => write!(
+                out,
+                r#"{{"event":"ValuesSwapped","value":{},"with":{}}}"#,
+                element(Some(value)),
+                element(Some(with)),
+            ),
+            Event::NodeRemoved { value, replacement } => write!(
+                out,
+                r#"{{"event":"NodeRemoved","value":{},"replacement":{}}}"#,
+                element(Some(value)),
+                element(replacement),
+            ),
+            Event::BalanceUpdated { node, balance } => {
+                write!(out, r#"{{"event":"BalanceUpdated","node":{},"balance":{balance}}}"#, element(Some(node)))
+            }
+            Event::Rotated { pivot, dir } => {
+                write!(out, r#"{{"event":"Rotated","pivot":{},"dir":"{dir}"}}"#, element(Some(pivot)))
+            }
+            Event::HeightUpdated { node, height } => {
+                write!(out, r#"{{"event":"HeightUpdated","node":{},"height":{height}}}"#, element(Some(node)))
+            }
+        }
+        .unwrap();
+        out
+    }
+}
+
+/// Receives the events of a tree it was set on with [`AVLTree::set_event_sink`].
+///
+/// Implemented by closures, which can forward the events elsewhere, typically as
+/// [JSON](Event::to_json).
+pub trait EventSink<T> {
+    /// Receives the next event.
+    fn event(&mut self, event: Event<'_, T>);
+}
+
+impl<T, F: FnMut(Event<'_, T>)> EventSink<T> for F {
+    fn event(&mut self, event: Event<'_, T>) {
+        self(event)
+    }
+}
+
+/// The sink of a tree, if one was set.
+pub(crate) struct Hook<T>(Option<Box<dyn EventSink<T>>>);
+
+impl<T> Default for Hook<T> {
+    fn default() -> Self {
+        Hook(None)
+    }
+}
+
+impl<T> Hook<T> {
+
+    /// Turns a step of an operation to an event for the sink.
+    pub(crate) fn step(&mut self, step: Step<'_, T>) {
+        if let Some(sink) = &mut self.0 {
+            if let Some(event) = Event::from_step(step) {
+                sink.event(event);
+            }
+        }
+    }
+}
+
+impl<T: Ord> AVLTree<T> {
+
+    /// Sets the sink receiving the events of all the insertions and removals of single
+    /// elements from now on, cursors included, in place of the previous one.
+    ///
+    /// Operations joining whole subtrees together, such as `split_off`, `append`,
+    /// `insert_batch`, `retain` and the set operations, send no events, nor do the clones of
+    /// the tree.
+    pub fn set_event_sink(&mut self, sink: impl EventSink<T> + 'static) {
+        self.hook = Hook(Some(Box::new(sink)));
+    }
+
+    /// Removes the sink of the tree and returns it.
+    pub fn take_event_sink(&mut self) -> Option<Box<dyn EventSink<T>>> {
+        self.hook.0.take()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[test]
+    fn test_event_sink() {
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let mut tree = AVLTree::new();
+        let sink = Rc::clone(&log);
+        tree.set_event_sink(move |event: Event<'_, i32>| sink.borrow_mut().push(event.to_json()));
+        tree.insert(10);
+        tree.insert(20);
+        tree.cursor_back_mut().insert_before(15).unwrap();
+        assert_eq!(*log.borrow(), [
+            r#"{"event":"NodeCreated","value":"10","parent":null,"side":"left"}"#,
+            r#"{"event":"NodeCreated","value":"20","parent":"10","side":"right"}"#,
+            r#"{"event":"BalanceUpdated","node":"10","balance":1}"#,
+            r#"{"event":"HeightUpdated","node":"10","height":2}"#,
+            r#"{"event":"NodeCreated","value":"15","parent":"20","side":"left"}"#,
+            r#"{"event":"BalanceUpdated","node":"20","balance":-1}"#,
+            r#"{"event":"HeightUpdated","node":"20","height":2}"#,
+            r#"{"event":"BalanceUpdated","node":"10","balance":2}"#,
+            r#"{"event":"Rotated","pivot":"20","dir":"right"}"#,
+            r#"{"event":"Rotated","pivot":"10","dir":"left"}"#,
+        ]);
+        for line in log.borrow().iter() {
+            let _: serde_json::Value = serde_json::from_str(line).unwrap();
+        }
+
+        log.borrow_mut().clear();
+        assert!(tree.remove(&15));
+        assert_eq!(*log.borrow(), [
+            r#"{"event":"ValuesSwapped","value":"15","with":"10"}"#,
+            r#"{"event":"NodeRemoved","value":"15","replacement":null}"#,
+            r#"{"event":"BalanceUpdated","node":"10","balance":1}"#,
+        ]);
+
+        // no events without a sink, nor from joins
+        assert!(tree.take_event_sink().is_some());
+        tree.insert(30);
+        tree.set_event_sink(|_: Event<'_, i32>| panic!("no event expected"));
+        tree.split_off(&20);
+        assert_eq!(log.borrow().len(), 3);
+
+        let quoted = Event::Rotated { pivot: &"a\"b", dir: Direction::Left }.to_json();
+        assert_eq!(quoted, r#"{"event":"Rotated","pivot":"a\"b","dir":"left"}"#);
+    }
+}
@@ -0,0 +1,196 @@
+//! Insertions and removals narrated step by step, for watching the tree rebalance itself.
+
+use std::borrow::Borrow;
+use std::fmt;
+use std::rc::Rc;
+
+use crate::{AVLTree, Side};
+
+/// A direction in the tree, as reported by a [`Step`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// Towards the smaller elements.
+    Left,
+    /// Towards the larger elements.
+    Right,
+}
+
+impl From<Side> for Direction {
+    fn from(side: Side) -> Self {
+        match side {
+            Side::Left => Direction::Left,
+            Side::Right => Direction::Right,
+        }
+    }
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Direction::Left => "left",
+            Direction::Right => "right",
+        })
+    }
+}
+
+/// A step of an insertion or a removal, as narrated by [`AVLTree::insert_explained`] and
+/// [`AVLTree::remove_explained`]. Nodes are named after the elements they hold at the time of
+/// the step.
+///
+/// Its `Display` impl tells the step in a sentence, such as "balance factor of 37 became +2".
+#[derive(Debug, PartialEq, Eq)]
+pub enum Step<'a, T> {
+    /// `value` was put in a new leaf, as the child of `parent` on `side`, or as the root of an
+    /// empty tree.
+    Attached { value: &'a T, parent: Option<&'a T>, side: Direction },
+    /// `value`, to be removed but having two children, swapped places with `with`, the largest
+    /// element of its left subtree, which has one child at most.
+    Swapped { value: &'a T, with: &'a T },
+    /// `value` was taken out of its node, `child`, its only child if it had one, taking its
+    /// place.
+    Removed { value: &'a T, child: Option<&'a T> },
+    /// The balance factor of `at`, the height of its right subtree minus the one of its left
+    /// subtree, became `balance`.
+    Balance { at: &'a T, balance: i8 },
+    /// The subtree of `at` was rotated towards `direction`, the child on the other side moving
+    /// up in its place.
+    Rotation { at: &'a T, direction: Direction },
+    /// The subtree of `at`, rotated or not, is now `height` tall, which changes the balance
+    /// factor of its parent.
+    Height { at: &'a T, height: usize },
+    /// The subtree of `at` is as tall as before, so the balance factors above it stay as they
+    /// were and retracing stops.
+    Settled { at: &'a T },
+}
+
+// not derived, which would require `T: Clone`
+impl<T> Clone for Step<'_, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Step<'_, T> {}
+
+impl<T: fmt::Display> fmt::Display for Step<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Step::Attached { value, parent: None, .. } => write!(f, "inserted {value} as the root"),
+            Step::Attached { value, parent: Some(parent), side } => {
+                write!(f, "inserted {value} as {side} child of {parent}")
+            }
+            Step::Swapped { value, with } => write!(f, "swapped {value} with its in-order predecessor {with}"),
+            Step::Removed { value, child: None } => write!(f, "removed leaf {value}"),
+            Step::Removed { value, child: Some(child) } => {
+                write!(f, "removed {value}, its child {child} taking its place")
+            }
+            Step::Balance { at, balance: 0 } => write!(f, "balance factor of {at} became 0"),
+            Step::Balance { at, balance } => write!(f, "balance factor of {at} became {balance:+}"),
+            Step::Rotation { at, direction } => write!(f, "performing {direction} rotation at {at}"),
+            Step::Height { at, height } => write!(f, "subtree of {at} is now {height} tall"),
+            Step::Settled { at } => write!(f, "subtree of {at} kept its height, retracing stops"),
+        }
+    }
+}
+
+/// Narrates nothing, for the operations that aren't explained.
+pub(crate) fn quiet<T>(_: Step<'_, T>) {}
+
+impl<T: Ord> AVLTree<T> {
+
+    /// Inserts `value` like `insert`, calling `step` with each step taken: where the new leaf
+    /// goes, then the balance factors refreshed and the rotations made on the way back up.
+    ///
+    /// Collecting the steps as text makes a log of the insertion:
+    /// `tree.insert_explained(42, |step| log.push(step.to_string()))`.
+    pub fn insert_explained(&mut self, value: T, mut step: impl FnMut(Step<'_, T>)) -> bool {
+        match self.locate(|v| value.cmp(v)) {
+            Ok(_) => false,
+            Err((parent, side)) => {
+                self.narrated(&mut step, |tree, step| tree.insert_at_narrated(parent, side, value, step));
+                true
+            }
+        }
+    }
+
+    /// Removes `value` like `remove`, calling `step` with each step taken: the swap with the
+    /// predecessor if need be, the removal of the node, then the balance factors refreshed and
+    /// the rotations made on the way back up.
+    pub fn remove_explained<Q: ?Sized + Ord>(&mut self, value: &Q, mut step: impl FnMut(Step<'_, T>)) -> bool
+    where T: Borrow<Q> {
+        match self.find(value) {
+            Some(node) => {
+                let node = Rc::clone(node);
+                self.narrated(&mut step, |tree, step| tree.remove_node_narrated(node, step));
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn explain(tree: &mut AVLTree<i32>, insert: Option<i32>, remove: Option<i32>) -> Vec<String> {
+        let mut log = Vec::new();
+        if let Some(v) = insert {
+            assert!(tree.insert_explained(v, |step| log.push(step.to_string())));
+        }
+        if let Some(v) = remove {
+            assert!(tree.remove_explained(&v, |step| log.push(step.to_string())));
+        }
+        log
+    }
+
+    #[test]
+    fn test_explained() {
+        let mut tree: AVLTree<i32> = [40, 37, 45, 30, 38, 42, 50].into_iter().collect();
+        assert_eq!(explain(&mut tree, Some(39), None), [
+            "inserted 39 as right child of 38",
+            "balance factor of 38 became +1",
+            "subtree of 38 is now 2 tall",
+            "balance factor of 37 became +1",
+            "subtree of 37 is now 3 tall",
+            "balance factor of 40 became -1",
+            "subtree of 40 is now 4 tall",
+        ]);
+        // the right child of 37 is right-heavy: a single rotation
+        assert_eq!(explain(&mut tree, None, Some(30)), [
+            "removed leaf 30",
+            "balance factor of 37 became +2",
+            "performing left rotation at 37",
+            "subtree of 38 is now 2 tall",
+            "balance factor of 40 became 0",
+            "subtree of 40 is now 3 tall",
+        ]);
+        assert_eq!(tree.check_invariants(), Ok(()));
+        assert!(tree.iter().eq(&[37, 38, 39, 40, 42, 45, 50]));
+
+        // 15 goes between 10 and 20: a double rotation
+        let mut tree = AVLTree::new();
+        tree.insert(10);
+        tree.insert(20);
+        assert_eq!(explain(&mut tree, Some(15), None), [
+            "inserted 15 as left child of 20",
+            "balance factor of 20 became -1",
+            "subtree of 20 is now 2 tall",
+            "balance factor of 10 became +2",
+            "performing right rotation at 20",
+            "performing left rotation at 10",
+            "subtree of 15 kept its height, retracing stops",
+        ]);
+        assert_eq!(explain(&mut tree, None, Some(15)), [
+            "swapped 15 with its in-order predecessor 10",
+            "removed leaf 15",
+            "balance factor of 10 became +1",
+            "subtree of 10 kept its height, retracing stops",
+        ]);
+        assert_eq!(explain(&mut tree, None, Some(10)), ["removed 10, its child 20 taking its place"]);
+        assert_eq!(explain(&mut tree, None, Some(20)), ["removed leaf 20"]);
+        assert_eq!(explain(&mut tree, Some(1), None), ["inserted 1 as the root"]);
+        assert!(!tree.insert_explained(1, |_| panic!("nothing to explain")));
+        assert!(!tree.remove_explained(&2, |_| panic!("nothing to explain")));
+    }
+}
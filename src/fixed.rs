@@ -0,0 +1,498 @@
+//! An ordered set of bounded size keeping all of its nodes inline, without allocating.
+//!
+//! The set itself only uses `core`, but the crate still needs `std`, having no `no_std` mode:
+//! it suits code that must not allocate once running, not targets without the standard library.
+
+use core::borrow::Borrow;
+use core::cmp::{max, Ordering};
+use core::mem::{self, MaybeUninit};
+
+use crate::{CapacityError, Side};
+
+/// Stands for a missing node in the links.
+const NIL: u32 = u32::MAX;
+
+struct Node<T> {
+    value: T,
+    height: u8,
+    size: u32,
+    parent: u32,
+    left: u32,
+    right: u32,
+}
+
+impl<T> Node<T> {
+
+    fn child(&self, side: Side) -> u32 {
+        match side {
+            Side::Left => self.left,
+            Side::Right => self.right,
+        }
+    }
+
+    fn child_mut(&mut self, side: Side) -> &mut u32 {
+        match side {
+            Side::Left => &mut self.left,
+            Side::Right => &mut self.right,
+        }
+    }
+}
+
+/// An ordered set based on an AVL tree holding at most `N` elements, whose nodes are stored
+/// inline in an array and linked by `u32` indices.
+///
+/// The set never allocates: it takes the same room whatever its length, and can live on the
+/// stack or, being built by a `const fn`, in a `static`. Inserting into a full set fails with
+/// a [`CapacityError`]. As in [`AVLTreeArena`](crate::AVLTreeArena), removing moves the last
+/// node into the freed slot, so the nodes in use are always the first `len` ones.
+pub struct AVLTreeFixed<T: Ord, const N: usize> {
+    /// Only the first `len` slots are initialized.
+    nodes: [MaybeUninit<Node<T>>; N],
+    len: u32,
+    root: u32,
+}
+
+impl<T: Ord, const N: usize> AVLTreeFixed<T, N> {
+
+    /// Fails the build when `N` doesn't leave `NIL` out of the valid indices.
+    const FITS: () = assert!(N < NIL as usize, "capacity too large for u32 links");
+
+    /// Creates an empty set.
+    pub const fn new() -> Self {
+        let () = Self::FITS;
+        AVLTreeFixed { nodes: [const { MaybeUninit::uninit() }; N], len: 0, root: NIL }
+    }
+
+    /// Returns the number of elements in the set.
+    pub fn len(&self) -> usize {
+        self.len as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the number of elements the set can hold, `N`.
+    pub fn capacity(&self) -> usize {
+        N
+    }
+
+    /// Returns `true` if the set holds `N` elements.
+    pub fn is_full(&self) -> bool {
+        self.len() == N
+    }
+
+    /// Removes all elements.
+    pub fn clear(&mut self) {
+        // forget the elements first, so that a panicking `drop` can't lead to a double one
+        let len = mem::replace(&mut self.len, 0) as usize;
+        self.root = NIL;
+        for slot in &mut self.nodes[..len] {
+            // SAFETY: the first `len` slots were initialized and are no longer reachable
+            unsafe { slot.assume_init_drop() };
+        }
+    }
+
+    /// Returns `true` if the set contains `value`.
+    pub fn contains<Q: ?Sized + Ord>(&self, value: &Q) -> bool where T: Borrow<Q> {
+        self.find(value) != NIL
+    }
+
+    /// Returns the element equal to `value`.
+    pub fn get<Q: ?Sized + Ord>(&self, value: &Q) -> Option<&T> where T: Borrow<Q> {
+        match self.find(value) {
+            NIL => None,
+            i => Some(&self.node(i).value),
+        }
+    }
+
+    fn find<Q: ?Sized + Ord>(&self, value: &Q) -> u32 where T: Borrow<Q> {
+        let mut i = self.root;
+        while i != NIL {
+            let n = self.node(i);
+            i = match value.cmp(n.value.borrow()) {
+                Ordering::Equal => return i,
+                Ordering::Less => n.left,
+                Ordering::Greater => n.right,
+            };
+        }
+        NIL
+    }
+
+    /// Adds `value` to the set. Returns `Ok(false)` if an equal element was already present,
+    /// in which case the set is left untouched, even when full.
+    ///
+    /// # Errors
+    ///
+    /// Fails with a [`CapacityError`] handing `value` back if the set already holds `N`
+    /// elements and none of them is equal to `value`.
+    pub fn insert(&mut self, value: T) -> Result<bool, CapacityError<T>> {
+        let mut parent = NIL;
+        let mut side = Side::Left;
+        let mut i = self.root;
+        while i != NIL {
+            let n = self.node(i);
+            side = match value.cmp(&n.value) {
+                Ordering::Equal => return Ok(false),
+                Ordering::Less => Side::Left,
+                Ordering::Greater => Side::Right,
+            };
+            parent = i;
+            i = n.child(side);
+        }
+        if self.is_full() {
+            return Err(CapacityError { value });
+        }
+
+        let new = self.len;
+        self.nodes[new as usize].write(Node { value, height: 1, size: 1, parent, left: NIL, right: NIL });
+        self.len += 1;
+        if parent == NIL {
+            self.root = new;
+        }
+        else {
+            *self.node_mut(parent).child_mut(side) = new;
+        }
+        self.rebalance(parent);
+        Ok(true)
+    }
+
+    /// Removes `value` from the set. Returns `false` if it wasn't present.
+    pub fn remove<Q: ?Sized + Ord>(&mut self, value: &Q) -> bool where T: Borrow<Q> {
+        self.take(value).is_some()
+    }
+
+    /// Removes the element equal to `value` and returns it, or `None` if there was none.
+    pub fn take<Q: ?Sized + Ord>(&mut self, value: &Q) -> Option<T> where T: Borrow<Q> {
+        let mut i = self.find(value);
+        if i == NIL {
+            return None;
+        }
+        let n = self.node(i);
+        if n.left != NIL && n.right != NIL {
+            // trade elements with the successor, which has no left child
+            let mut s = n.right;
+            while self.node(s).left != NIL {
+                s = self.node(s).left;
+            }
+            let (a, b) = self.pair_mut(i, s);
+            mem::swap(&mut a.value, &mut b.value);
+            i = s;
+        }
+
+        let n = self.node(i);
+        let (parent, child) = (n.parent, if n.left != NIL { n.left } else { n.right });
+        if child != NIL {
+            self.node_mut(child).parent = parent;
+        }
+        self.replace_child(parent, i, child);
+        self.rebalance(parent);
+        Some(self.free(i))
+    }
+
+    /// Returns the element at position `rank` in sorted order (starting from 0).
+    pub fn get_by_rank(&self, rank: usize) -> Option<&T> {
+        let mut i = self.root;
+        let mut rank = rank;
+        while i != NIL {
+            let n = self.node(i);
+            let left = self.size(n.left);
+            i = match rank.cmp(&left) {
+                Ordering::Less => n.left,
+                Ordering::Equal => return Some(&n.value),
+                Ordering::Greater => {
+                    rank -= left + 1;
+                    n.right
+                }
+            };
+        }
+        None
+    }
+
+    /// Returns an iterator over the elements in sorted order.
+    pub fn iter(&self) -> Iter<'_, T, N> {
+        Iter {
+            tree: self,
+            front: self.outermost(self.root, Side::Left),
+            back: self.outermost(self.root, Side::Right),
+            remaining: self.len(),
+        }
+    }
+
+    fn node(&self, i: u32) -> &Node<T> {
+        let slot = &self.nodes[..self.len as usize][i as usize];
+        // SAFETY: the slots below `len` are initialized
+        unsafe { slot.assume_init_ref() }
+    }
+
+    fn node_mut(&mut self, i: u32) -> &mut Node<T> {
+        let slot = &mut self.nodes[..self.len as usize][i as usize];
+        // SAFETY: the slots below `len` are initialized
+        unsafe { slot.assume_init_mut() }
+    }
+
+    /// Borrows two distinct nodes at once.
+    fn pair_mut(&mut self, a: u32, b: u32) -> (&mut Node<T>, &mut Node<T>) {
+        let (a, b) = (a as usize, b as usize);
+        let nodes = &mut self.nodes[..self.len as usize];
+        let (a, b) = if a < b {
+            let (low, high) = nodes.split_at_mut(b);
+            (&mut low[a], &mut high[0])
+        }
+        else {
+            let (low, high) = nodes.split_at_mut(a);
+            (&mut high[0], &mut low[b])
+        };
+        // SAFETY: the slots below `len` are initialized
+        unsafe { (a.assume_init_mut(), b.assume_init_mut()) }
+    }
+
+    fn height(&self, i: u32) -> u8 {
+        if i == NIL { 0 } else { self.node(i).height }
+    }
+
+    fn size(&self, i: u32) -> usize {
+        if i == NIL { 0 } else { self.node(i).size as usize }
+    }
+
+    /// Recomputes the height and size of node `i`.
+    fn update(&mut self, i: u32) {
+        let n = self.node(i);
+        let height = 1 + max(self.height(n.left), self.height(n.right));
+        let size = 1 + self.size(n.left) + self.size(n.right);
+        let n = self.node_mut(i);
+        n.height = height;
+        n.size = size as u32;
+    }
+
+    fn balance_factor(&self, i: u32) -> i8 {
+        let n = self.node(i);
+        self.height(n.right) as i8 - self.height(n.left) as i8
+    }
+
+    /// Makes `parent` (the root if `NIL`) point at `new` where it pointed at `old`.
+    fn replace_child(&mut self, parent: u32, old: u32, new: u32) {
+        if parent == NIL {
+            self.root = new;
+        }
+        else if self.node(parent).left == old {
+            self.node_mut(parent).left = new;
+        }
+        else {
+            self.node_mut(parent).right = new;
+        }
+    }
+
+    /// Rotates the subtree rooted at `x` towards `side`, returning the new root of the subtree.
+    fn rotate(&mut self, x: u32, side: Side) -> u32 {
+        let z = self.node(x).child(!side);
+        let inner = self.node(z).child(side);
+        *self.node_mut(x).child_mut(!side) = inner;
+        if inner != NIL {
+            self.node_mut(inner).parent = x;
+        }
+        let parent = self.node(x).parent;
+        self.node_mut(z).parent = parent;
+        self.replace_child(parent, x, z);
+        *self.node_mut(z).child_mut(side) = x;
+        self.node_mut(x).parent = z;
+        self.update(x);
+        self.update(z);
+        z
+    }
+
+    /// Retraces from node `i` up to the root, refreshing heights and sizes and rotating
+    /// wherever a balance factor has reached +2 or -2.
+    fn rebalance(&mut self, mut i: u32) {
+        while i != NIL {
+            self.update(i);
+            let b = self.balance_factor(i);
+            if b.abs() > 1 {
+                let side = if b > 1 { Side::Left } else { Side::Right };
+                let z = self.node(i).child(!side);
+                let zb = self.balance_factor(z);
+                if (b > 1 && zb < 0) || (b < -1 && zb > 0) {
+                    self.rotate(z, !side);
+                }
+                i = self.rotate(i, side);
+            }
+            i = self.node(i).parent;
+        }
+    }
+
+    /// Removes the unlinked node `i` from the array, moving the last node into its slot.
+    fn free(&mut self, i: u32) -> T {
+        let last = self.len - 1;
+        if i != last {
+            let n = self.node(last);
+            let (parent, left, right) = (n.parent, n.left, n.right);
+            self.replace_child(parent, last, i);
+            for child in [left, right] {
+                if child != NIL {
+                    self.node_mut(child).parent = i;
+                }
+            }
+            self.nodes.swap(i as usize, last as usize);
+        }
+        self.len = last;
+        // SAFETY: the slot was initialized, and is past `len` now so it won't be read again
+        unsafe { self.nodes[last as usize].assume_init_read() }.value
+    }
+
+    /// Follows `side` children from `i` as far as they go.
+    fn outermost(&self, mut i: u32, side: Side) -> u32 {
+        if i == NIL {
+            return NIL;
+        }
+        while self.node(i).child(side) != NIL {
+            i = self.node(i).child(side);
+        }
+        i
+    }
+
+    /// Returns the in-order neighbour of node `i` on `side`.
+    fn neighbour(&self, i: u32, side: Side) -> u32 {
+        let child = self.node(i).child(side);
+        if child != NIL {
+            return self.outermost(child, !side);
+        }
+        let mut i = i;
+        let mut p = self.node(i).parent;
+        while p != NIL && self.node(p).child(side) == i {
+            i = p;
+            p = self.node(p).parent;
+        }
+        p
+    }
+}
+
+impl<T: Ord, const N: usize> Drop for AVLTreeFixed<T, N> {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+impl<T: Ord, const N: usize> Default for AVLTreeFixed<T, N> {
+    fn default() -> Self {
+        AVLTreeFixed::new()
+    }
+}
+
+/// An iterator over the elements of an `AVLTreeFixed`, in sorted order.
+///
+/// Created by [`AVLTreeFixed::iter`].
+pub struct Iter<'a, T: Ord, const N: usize> {
+    tree: &'a AVLTreeFixed<T, N>,
+    front: u32,
+    back: u32,
+    remaining: usize,
+}
+
+impl<'a, T: Ord, const N: usize> Iterator for Iter<'a, T, N> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        let i = self.front;
+        self.front = self.tree.neighbour(i, Side::Right);
+        Some(&self.tree.node(i).value)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, T: Ord, const N: usize> DoubleEndedIterator for Iter<'a, T, N> {
+    fn next_back(&mut self) -> Option<&'a T> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        let i = self.back;
+        self.back = self.tree.neighbour(i, Side::Left);
+        Some(&self.tree.node(i).value)
+    }
+}
+
+impl<T: Ord, const N: usize> ExactSizeIterator for Iter<'_, T, N> {}
+
+impl<'a, T: Ord, const N: usize> IntoIterator for &'a AVLTreeFixed<T, N> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T, N>;
+
+    fn into_iter(self) -> Iter<'a, T, N> {
+        self.iter()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::rc::Rc;
+
+    /// Checks links, heights, sizes and balance of the whole tree.
+    fn check<T: Ord, const N: usize>(tree: &AVLTreeFixed<T, N>) {
+        fn walk<T: Ord, const N: usize>(tree: &AVLTreeFixed<T, N>, i: u32, parent: u32) -> (u8, usize) {
+            if i == NIL {
+                return (0, 0);
+            }
+            let n = tree.node(i);
+            assert_eq!(n.parent, parent);
+            let (hl, sl) = walk(tree, n.left, i);
+            let (hr, sr) = walk(tree, n.right, i);
+            assert!(hl.abs_diff(hr) <= 1);
+            assert_eq!(n.height, 1 + max(hl, hr));
+            assert_eq!(n.size as usize, 1 + sl + sr);
+            (n.height, n.size as usize)
+        }
+        assert_eq!(walk(tree, tree.root, NIL).1, tree.len());
+        assert!(tree.iter().zip(tree.iter().skip(1)).all(|(a, b)| a < b));
+    }
+
+    // run it under Miri too (`cargo +nightly miri test fixed::`), as it reads uninitialized
+    // slots unless the `len` bookkeeping is right
+    #[test]
+    fn test_fixed() {
+        let mut tree: AVLTreeFixed<i32, 100> = AVLTreeFixed::new();
+        for i in 0..100 {
+            assert_eq!(tree.insert((i * 37) % 100), Ok(true));
+        }
+        assert!(tree.is_full());
+        assert_eq!(tree.insert(5), Ok(false));
+        assert_eq!(tree.insert(100), Err(CapacityError { value: 100 }));
+        check(&tree);
+        assert!(tree.iter().copied().eq(0..100));
+        assert!(tree.iter().rev().copied().eq((0..100).rev()));
+        assert_eq!(tree.get_by_rank(42), Some(&42));
+
+        for i in (0..100).filter(|i| i % 3 != 0) {
+            assert_eq!(tree.take(&i), Some(i));
+            check(&tree);
+        }
+        assert!(!tree.remove(&1));
+        assert!(tree.contains(&99));
+        assert!(tree.iter().copied().eq((0..100).step_by(3)));
+        assert_eq!(tree.len(), 34);
+        assert_eq!(tree.insert(100), Ok(true));
+
+        tree.clear();
+        assert!(tree.is_empty());
+        assert_eq!(tree.iter().next(), None);
+        assert_eq!(tree.capacity(), 100);
+
+        let counted: Vec<Rc<i32>> = (0..8).map(Rc::new).collect();
+        let mut tree: AVLTreeFixed<Rc<i32>, 8> = AVLTreeFixed::default();
+        for v in &counted {
+            tree.insert(Rc::clone(v)).unwrap();
+        }
+        let taken = tree.take(&3).unwrap();
+        assert!(Rc::ptr_eq(&taken, &counted[3]));
+        drop(tree);
+        assert!(counted.iter().all(|v| Rc::strong_count(v) == 1 + Rc::ptr_eq(v, &taken) as usize));
+    }
+}
@@ -0,0 +1,392 @@
+//! An ordered set whose nodes are checked by a branded token rather than by `RefCell`s,
+//! enabled by the `ghost` feature.
+
+// `Borrow` is not imported by name, its `borrow` method would shadow `GhostCell::borrow`
+use std::borrow;
+use std::cmp::{max, Ordering};
+use std::mem;
+use std::rc::{Rc, Weak};
+
+use ghost_cell::GhostCell;
+pub use ghost_cell::GhostToken;
+
+use crate::Side;
+
+type NodeRef<'id, T> = Rc<GhostCell<'id, Node<'id, T>>>;
+type Link<'id, T> = Option<NodeRef<'id, T>>;
+
+struct Node<'id, T> {
+    value: T,
+    height: usize,
+    size: usize,
+    parent: Weak<GhostCell<'id, Node<'id, T>>>,
+    left: Link<'id, T>,
+    right: Link<'id, T>,
+}
+
+impl<'id, T> Node<'id, T> {
+
+    fn child(&self, side: Side) -> &Link<'id, T> {
+        match side {
+            Side::Left => &self.left,
+            Side::Right => &self.right,
+        }
+    }
+
+    fn child_mut(&mut self, side: Side) -> &mut Link<'id, T> {
+        match side {
+            Side::Left => &mut self.left,
+            Side::Right => &mut self.right,
+        }
+    }
+}
+
+/// An ordered set based on an AVL tree whose nodes live in `GhostCell`s, all branded with the
+/// lifetime `'id` of the `GhostToken` the tree owns.
+///
+/// Reaching a node takes a borrow of the token, shared for reading, mutable for writing, so
+/// the borrow checker rules out conflicting accesses at compile time and nodes carry no
+/// borrow flag to update. Parents are linked with `Weak` pointers.
+///
+/// A token, and so a tree, only exists inside the closure passed to `GhostToken::new`:
+/// `GhostToken::new(|token| { let mut tree = AVLTreeGhost::new(token); ... })`.
+pub struct AVLTreeGhost<'id, T: Ord> {
+    root: Link<'id, T>,
+    token: GhostToken<'id>,
+}
+
+impl<'id, T: Ord> AVLTreeGhost<'id, T> {
+
+    /// Creates an empty set, whose nodes are accessed with `token`.
+    pub fn new(token: GhostToken<'id>) -> Self {
+        AVLTreeGhost { root: None, token }
+    }
+
+    /// Returns the number of elements in the set.
+    pub fn len(&self) -> usize {
+        self.size(&self.root)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+
+    /// Gives the token back, dropping the set.
+    pub fn into_token(self) -> GhostToken<'id> {
+        self.token
+    }
+
+    /// Returns `true` if the set contains `value`.
+    pub fn contains<Q: ?Sized + Ord>(&self, value: &Q) -> bool where T: borrow::Borrow<Q> {
+        self.get(value).is_some()
+    }
+
+    /// Returns the element equal to `value`.
+    pub fn get<Q: ?Sized + Ord>(&self, value: &Q) -> Option<&T> where T: borrow::Borrow<Q> {
+        let mut next = self.root.as_ref();
+        while let Some(node) = next {
+            let n = node.borrow(&self.token);
+            next = match value.cmp(borrow::Borrow::borrow(&n.value)) {
+                Ordering::Equal => return Some(&n.value),
+                Ordering::Less => n.left.as_ref(),
+                Ordering::Greater => n.right.as_ref(),
+            };
+        }
+        None
+    }
+
+    /// Adds `value` to the set. Returns `false` if an equal element was already present, in
+    /// which case the set is left untouched.
+    pub fn insert(&mut self, value: T) -> bool {
+        let mut parent: Link<'id, T> = None;
+        let mut side = Side::Left;
+        let mut next = self.root.clone();
+        while let Some(node) = next {
+            let n = node.borrow(&self.token);
+            side = match value.cmp(&n.value) {
+                Ordering::Equal => return false,
+                Ordering::Less => Side::Left,
+                Ordering::Greater => Side::Right,
+            };
+            next = n.child(side).clone();
+            parent = Some(node);
+        }
+
+        let weak_parent = parent.as_ref().map_or_else(Weak::new, Rc::downgrade);
+        let new = Rc::new(GhostCell::new(Node { value, height: 1, size: 1, parent: weak_parent, left: None, right: None }));
+        match parent {
+            None => self.root = Some(new),
+            Some(ref p) => *p.borrow_mut(&mut self.token).child_mut(side) = Some(new),
+        }
+        self.rebalance(parent);
+        true
+    }
+
+    /// Removes `value` from the set. Returns `false` if it wasn't present.
+    pub fn remove<Q: ?Sized + Ord>(&mut self, value: &Q) -> bool where T: borrow::Borrow<Q> {
+        self.take(value).is_some()
+    }
+
+    /// Removes the element equal to `value` and returns it, or `None` if there was none.
+    pub fn take<Q: ?Sized + Ord>(&mut self, value: &Q) -> Option<T> where T: borrow::Borrow<Q> {
+        let mut node = self.root.clone()?;
+        loop {
+            let n = node.borrow(&self.token);
+            let next = match value.cmp(borrow::Borrow::borrow(&n.value)) {
+                Ordering::Equal => break,
+                Ordering::Less => n.left.clone(),
+                Ordering::Greater => n.right.clone(),
+            };
+            node = next?;
+        }
+
+        let n = node.borrow(&self.token);
+        if let (Some(_), Some(right)) = (&n.left, &n.right) {
+            // the successor, which has no left child, is unlinked and takes the place of the
+            // removed element; rotations relink nodes without moving elements, so `node`
+            // still holds it afterwards
+            let mut s = Rc::clone(right);
+            while let Some(left) = s.borrow(&self.token).left.clone() {
+                s = left;
+            }
+            let successor = self.unlink(s);
+            Some(mem::replace(&mut node.borrow_mut(&mut self.token).value, successor))
+        }
+        else {
+            Some(self.unlink(node))
+        }
+    }
+
+    /// Returns the element at position `rank` in sorted order (starting from 0).
+    pub fn get_by_rank(&self, rank: usize) -> Option<&T> {
+        let mut next = self.root.as_ref();
+        let mut rank = rank;
+        while let Some(node) = next {
+            let n = node.borrow(&self.token);
+            let left = self.size(&n.left);
+            next = match rank.cmp(&left) {
+                Ordering::Less => n.left.as_ref(),
+                Ordering::Equal => return Some(&n.value),
+                Ordering::Greater => {
+                    rank -= left + 1;
+                    n.right.as_ref()
+                }
+            };
+        }
+        None
+    }
+
+    /// Returns an iterator over the elements in sorted order.
+    pub fn iter(&self) -> Iter<'_, 'id, T> {
+        let mut iter = Iter { token: &self.token, front: Vec::new(), back: Vec::new(), remaining: self.len() };
+        iter.descend(self.root.as_ref(), Side::Left);
+        iter.descend(self.root.as_ref(), Side::Right);
+        iter
+    }
+
+    fn height(&self, link: &Link<'id, T>) -> usize {
+        link.as_ref().map_or(0, |n| n.borrow(&self.token).height)
+    }
+
+    fn size(&self, link: &Link<'id, T>) -> usize {
+        link.as_ref().map_or(0, |n| n.borrow(&self.token).size)
+    }
+
+    /// Recomputes the height and size of `node`.
+    fn update(&mut self, node: &NodeRef<'id, T>) {
+        let n = node.borrow(&self.token);
+        let height = 1 + max(self.height(&n.left), self.height(&n.right));
+        let size = 1 + self.size(&n.left) + self.size(&n.right);
+        let n = node.borrow_mut(&mut self.token);
+        n.height = height;
+        n.size = size;
+    }
+
+    fn balance_factor(&self, node: &NodeRef<'id, T>) -> isize {
+        let n = node.borrow(&self.token);
+        self.height(&n.right) as isize - self.height(&n.left) as isize
+    }
+
+    /// Makes `parent` (the root if `None`) point at `new` where it pointed at `old`.
+    fn replace_child(&mut self, parent: &Link<'id, T>, old: &NodeRef<'id, T>, new: Link<'id, T>) {
+        match parent {
+            None => self.root = new,
+            Some(p) => {
+                let p = p.borrow_mut(&mut self.token);
+                let side = if p.left.as_ref().is_some_and(|l| Rc::ptr_eq(l, old)) { Side::Left } else { Side::Right };
+                *p.child_mut(side) = new;
+            }
+        }
+    }
+
+    /// Detaches a node having at most one child, putting the child in its place, and returns
+    /// its element.
+    fn unlink(&mut self, node: NodeRef<'id, T>) -> T {
+        let n = node.borrow_mut(&mut self.token);
+        let child = n.left.take().or_else(|| n.right.take());
+        let weak_parent = mem::take(&mut n.parent);
+        if let Some(ref c) = child {
+            c.borrow_mut(&mut self.token).parent = weak_parent.clone();
+        }
+        let parent = weak_parent.upgrade();
+        self.replace_child(&parent, &node, child);
+        self.rebalance(parent);
+        match Rc::try_unwrap(node) {
+            Ok(cell) => cell.into_inner().value,
+            Err(_) => panic!("unlinked node is still referenced"),
+        }
+    }
+
+    /// Rotates the subtree rooted at `x` towards `side`, returning the new root of the subtree.
+    fn rotate(&mut self, x: &NodeRef<'id, T>, side: Side) -> NodeRef<'id, T> {
+        let z = x.borrow_mut(&mut self.token).child_mut(!side).take().unwrap();
+        let inner = z.borrow_mut(&mut self.token).child_mut(side).take();
+        if let Some(ref i) = inner {
+            i.borrow_mut(&mut self.token).parent = Rc::downgrade(x);
+        }
+        *x.borrow_mut(&mut self.token).child_mut(!side) = inner;
+
+        let weak_parent = mem::replace(&mut x.borrow_mut(&mut self.token).parent, Rc::downgrade(&z));
+        let parent = weak_parent.upgrade();
+        z.borrow_mut(&mut self.token).parent = weak_parent;
+        self.replace_child(&parent, x, Some(Rc::clone(&z)));
+        *z.borrow_mut(&mut self.token).child_mut(side) = Some(Rc::clone(x));
+        self.update(x);
+        self.update(&z);
+        z
+    }
+
+    /// Retraces from `next` up to the root, refreshing heights and sizes and rotating
+    /// wherever a balance factor has reached +2 or -2.
+    fn rebalance(&mut self, mut next: Link<'id, T>) {
+        while let Some(mut node) = next {
+            self.update(&node);
+            let b = self.balance_factor(&node);
+            if b.abs() > 1 {
+                let side = if b > 1 { Side::Left } else { Side::Right };
+                let z = node.borrow(&self.token).child(!side).clone().unwrap();
+                let zb = self.balance_factor(&z);
+                if (b > 1 && zb < 0) || (b < -1 && zb > 0) {
+                    self.rotate(&z, !side);
+                }
+                node = self.rotate(&node, side);
+            }
+            next = node.borrow(&self.token).parent.upgrade();
+        }
+    }
+}
+
+impl<'id, T: Ord> Extend<T> for AVLTreeGhost<'id, T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for value in iter {
+            self.insert(value);
+        }
+    }
+}
+
+/// An iterator over the elements of an `AVLTreeGhost`, in sorted order.
+///
+/// Created by [`AVLTreeGhost::iter`]. Parents being only reachable through `Weak` pointers that
+/// would have to be upgraded, it keeps a stack of the nodes still to visit at each end.
+pub struct Iter<'a, 'id, T> {
+    token: &'a GhostToken<'id>,
+    front: Vec<&'a Node<'id, T>>,
+    back: Vec<&'a Node<'id, T>>,
+    remaining: usize,
+}
+
+impl<'a, 'id, T> Iter<'a, 'id, T> {
+
+    /// Stacks `node` and its `side` children as far as they go.
+    fn descend(&mut self, mut next: Option<&'a NodeRef<'id, T>>, side: Side) {
+        let stack = match side {
+            Side::Left => &mut self.front,
+            Side::Right => &mut self.back,
+        };
+        while let Some(node) = next {
+            let n = node.borrow(self.token);
+            stack.push(n);
+            next = n.child(side).as_ref();
+        }
+    }
+
+    fn step(&mut self, side: Side) -> Option<&'a T> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        let stack = match side {
+            Side::Left => &mut self.front,
+            Side::Right => &mut self.back,
+        };
+        let n = stack.pop()?;
+        self.descend(n.child(!side).as_ref(), side);
+        Some(&n.value)
+    }
+}
+
+impl<'a, T> Iterator for Iter<'a, '_, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        self.step(Side::Left)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, T> DoubleEndedIterator for Iter<'a, '_, T> {
+    fn next_back(&mut self) -> Option<&'a T> {
+        self.step(Side::Right)
+    }
+}
+
+impl<T> ExactSizeIterator for Iter<'_, '_, T> {}
+
+impl<'a, 'id, T: Ord> IntoIterator for &'a AVLTreeGhost<'id, T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, 'id, T>;
+
+    fn into_iter(self) -> Iter<'a, 'id, T> {
+        self.iter()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ghost() {
+        GhostToken::new(|token| {
+            let mut tree = AVLTreeGhost::new(token);
+            for i in 0..100 {
+                assert!(tree.insert((i * 13) % 100));
+            }
+            assert!(!tree.insert(13));
+            assert_eq!(tree.len(), 100);
+            assert!(tree.height(&tree.root) <= 8);
+            assert!(tree.iter().copied().eq(0..100));
+            assert!(tree.iter().rev().copied().eq((0..100).rev()));
+            assert_eq!(tree.get_by_rank(64), Some(&64));
+
+            for i in (0..100).filter(|i| i % 5 != 0) {
+                assert_eq!(tree.take(&i), Some(i));
+                let root = tree.root.as_ref().unwrap();
+                assert!(tree.balance_factor(root).abs() <= 1);
+            }
+            assert!(!tree.remove(&1));
+            assert!(tree.iter().copied().eq((0..100).step_by(5)));
+            assert!(tree.contains(&95));
+            assert_eq!(tree.len(), 20);
+
+            // the nodes are freed along with the tree, `Weak` parents making no cycle
+            let root = Rc::downgrade(tree.root.as_ref().unwrap());
+            tree.into_token();
+            assert!(root.upgrade().is_none());
+        });
+    }
+}
@@ -0,0 +1,100 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::{peek, Anchor, AVLTree, NodeRef, OccupiedError};
+
+/// Designates an element of an `AVLTree`, so that it can be reached again without searching.
+///
+/// Created by [`AVLTree::insert_with_handle`]. The handle keeps designating the element while
+/// other elements come and go, but stops resolving once the element leaves the tree, or when
+/// the whole tree is built anew from its elements, as done by `retain`, or goes through a
+/// parallel set operation such as `par_union`. `rebuild` keeps the nodes, and so the handles.
+pub struct NodeHandle<T: Ord> {
+    anchor: Anchor<T>,
+}
+
+impl<T: Ord> Clone for NodeHandle<T> {
+    fn clone(&self) -> Self {
+        NodeHandle { anchor: Rc::clone(&self.anchor) }
+    }
+}
+
+impl<T: Ord> AVLTree<T> {
+
+    /// Adds `value` to the tree like `try_insert`, returning a handle to it.
+    pub fn insert_with_handle(&mut self, value: T) -> Result<NodeHandle<T>, OccupiedError<T>> {
+        let (parent, side) = match self.locate(|v| value.cmp(v)) {
+            Ok(_) => return Err(OccupiedError { value }),
+            Err(slot) => slot,
+        };
+        let node = self.insert_at(parent, side, value);
+        let anchor = Rc::new(RefCell::new(Rc::downgrade(&node)));
+        node.borrow_mut().anchor = Some(Rc::clone(&anchor));
+        Ok(NodeHandle { anchor })
+    }
+
+    /// Returns the element designated by `handle`, or `None` if it is no longer in this tree.
+    pub fn get_by_handle(&self, handle: &NodeHandle<T>) -> Option<&T> {
+        let node = self.resolve(handle)?;
+        let value: *const T = &peek(&node).value;
+        // SAFETY: the node is part of this tree, which keeps it alive and unchanged for as long
+        // as it is borrowed
+        Some(unsafe { &*value })
+    }
+
+    /// Removes the element designated by `handle` and returns it, or `None` if it is no longer
+    /// in this tree.
+    ///
+    /// No search by value is involved: the node is found through the handle, and checking that
+    /// it belongs to this tree only takes a walk up to the root.
+    pub fn remove_handle(&mut self, handle: NodeHandle<T>) -> Option<T> {
+        let node = self.resolve(&handle)?;
+        Some(self.remove_node(node))
+    }
+
+    /// Finds the node designated by `handle`, making sure it is part of this tree.
+    fn resolve(&self, handle: &NodeHandle<T>) -> Option<NodeRef<T>> {
+        let node = handle.anchor.borrow().upgrade()?;
+        let mut top = Rc::clone(&node);
+        while let Some(parent) = peek(&top).parent.upgrade() {
+            top = parent;
+        }
+        let root = self.root.as_ref()?;
+        Rc::ptr_eq(&top, root).then_some(node)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_handles() {
+        let mut tree = AVLTree::new();
+        let handles: Vec<NodeHandle<i32>> = (0..64).map(|v| tree.insert_with_handle(v * 2).unwrap()).collect();
+        assert_eq!(tree.insert_with_handle(10).err(), Some(OccupiedError { value: 10 }));
+
+        // rotations and removals of other elements move values between nodes
+        for v in (1..64).step_by(2) {
+            tree.insert(v * 2 + 1);
+        }
+        for v in (0..64).step_by(3) {
+            assert_eq!(tree.remove_handle(handles[v].clone()), Some(v as i32 * 2));
+        }
+        for (v, handle) in handles.iter().enumerate() {
+            let expected = (v % 3 != 0).then_some(v as i32 * 2);
+            assert_eq!(tree.get_by_handle(handle), expected.as_ref());
+        }
+        assert_eq!(tree.remove_handle(handles[0].clone()), None);
+
+        // a handle doesn't resolve in another tree
+        let mut other = tree.split_off(&64);
+        assert_eq!(tree.get_by_handle(&handles[40]), None);
+        assert_eq!(other.get_by_handle(&handles[40]), Some(&80));
+        tree.append(&mut other);
+        assert_eq!(tree.remove_handle(handles[40].clone()), Some(80));
+        assert!(!tree.contains(&80));
+        assert_eq!(tree.len(), 64 + 32 - 22 - 1);
+    }
+}
@@ -0,0 +1,197 @@
+//! An ordered set recording its past states, so that changes can be undone and redone.
+//!
+//! States are versions of a [`persistent::AVLTree`], which share all the nodes a change doesn't
+//! touch: each step of history costs O(log n) memory, and undoing or redoing one, or reading
+//! any past version, O(1) time.
+
+use std::borrow::Borrow;
+
+use crate::persistent;
+
+/// Identifies a state of an [`AVLTreeHistory`], as returned by its
+/// [`version`](AVLTreeHistory::version) method.
+///
+/// Versions are numbered in the order the states were reached, starting at 0 for the empty
+/// set the history was created with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct VersionId(usize);
+
+/// An ordered set keeping the states it went through, for [`undo`](Self::undo) and
+/// [`redo`](Self::redo), and for reading past versions with [`as_of`](Self::as_of).
+///
+/// Every call that changes the set records a step and a new version; calls that leave it as it
+/// was, such as inserting an element already present, record nothing. Undoing and redoing move
+/// back and forth between recorded versions. Making a change after undoing discards the steps
+/// that could have been redone, but not their versions: all versions stay readable for as long
+/// as the history lives.
+pub struct AVLTreeHistory<T> {
+    /// Every state reached, by version.
+    versions: Vec<persistent::AVLTree<T>>,
+    current: usize,
+    undo: Vec<usize>,
+    redo: Vec<usize>,
+}
+
+impl<T: Ord + Clone> AVLTreeHistory<T> {
+
+    /// Creates an empty set with no history.
+    pub fn new() -> Self {
+        AVLTreeHistory {
+            versions: vec![persistent::AVLTree::new()],
+            current: 0,
+            undo: Vec::new(),
+            redo: Vec::new(),
+        }
+    }
+
+    /// Returns the current state of the set, for reading.
+    pub fn current(&self) -> &persistent::AVLTree<T> {
+        &self.versions[self.current]
+    }
+
+    /// Returns the version of the current state.
+    pub fn version(&self) -> VersionId {
+        VersionId(self.current)
+    }
+
+    /// Returns the state of the set at `version`, or `None` if it comes from another history
+    /// and is newer than any version of this one.
+    pub fn as_of(&self, version: VersionId) -> Option<&persistent::AVLTree<T>> {
+        self.versions.get(version.0)
+    }
+
+    pub fn len(&self) -> usize {
+        self.current().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.current().is_empty()
+    }
+
+    /// Returns `true` if the set contains `value`.
+    pub fn contains<Q: ?Sized + Ord>(&self, value: &Q) -> bool where T: Borrow<Q> {
+        self.current().contains(value)
+    }
+
+    /// Makes `next` the current state, recording a step and a version if it differs from the
+    /// current one. Returns whether it does.
+    fn record(&mut self, next: persistent::AVLTree<T>) -> bool {
+        if next.ptr_eq(self.current()) {
+            return false;
+        }
+        self.undo.push(self.current);
+        self.redo.clear();
+        self.current = self.versions.len();
+        self.versions.push(next);
+        true
+    }
+
+    /// Adds `value` to the set. Returns `false` if an equal element was already present, in
+    /// which case nothing is recorded.
+    pub fn insert(&mut self, value: T) -> bool {
+        let next = self.current().insert(value);
+        self.record(next)
+    }
+
+    /// Removes `value` from the set. Returns `false` if it wasn't present, in which case
+    /// nothing is recorded.
+    pub fn remove<Q: ?Sized + Ord>(&mut self, value: &Q) -> bool where T: Borrow<Q> {
+        let next = self.current().remove(value);
+        self.record(next)
+    }
+
+    /// Removes all elements from the set, as a single step.
+    pub fn clear(&mut self) {
+        self.record(persistent::AVLTree::new());
+    }
+
+    /// Reverts the last step not undone yet, going back to the version before it. Returns
+    /// `false` if there is none.
+    pub fn undo(&mut self) -> bool {
+        let Some(previous) = self.undo.pop() else {
+            return false;
+        };
+        self.redo.push(std::mem::replace(&mut self.current, previous));
+        true
+    }
+
+    /// Makes the last step undone again, going back to the version after it. Returns `false`
+    /// if there is none, nothing having been undone since the last change.
+    pub fn redo(&mut self) -> bool {
+        let Some(next) = self.redo.pop() else {
+            return false;
+        };
+        self.undo.push(std::mem::replace(&mut self.current, next));
+        true
+    }
+
+    /// Returns the number of steps that can be undone.
+    pub fn history_len(&self) -> usize {
+        self.undo.len()
+    }
+}
+
+impl<T: Ord + Clone> Default for AVLTreeHistory<T> {
+    fn default() -> Self {
+        AVLTreeHistory::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_undo_redo() {
+        let mut set = AVLTreeHistory::new();
+        for i in [5, 3, 8, 1] {
+            assert!(set.insert(i));
+        }
+        assert!(!set.insert(3));
+        assert!(!set.remove(&4));
+        assert!(set.remove(&5));
+        assert_eq!(set.history_len(), 5);
+        assert!(set.current().iter().copied().eq([1, 3, 8]));
+
+        assert!(set.undo() && set.undo());
+        assert!(set.current().iter().copied().eq([3, 5, 8]));
+        assert!(set.redo());
+        assert!(set.current().iter().copied().eq([1, 3, 5, 8]));
+        assert_eq!(set.history_len(), 4);
+
+        // a change drops what was left to redo
+        set.clear();
+        assert!(set.is_empty());
+        assert!(!set.redo());
+        assert!(set.undo());
+        assert_eq!(set.len(), 4);
+        while set.undo() {}
+        assert!(set.is_empty());
+        assert_eq!(set.history_len(), 0);
+        assert!(set.redo() && set.contains(&5));
+    }
+
+    #[test]
+    fn test_as_of() {
+        let mut set = AVLTreeHistory::new();
+        let empty = set.version();
+        set.insert(1);
+        set.insert(2);
+        let two = set.version();
+        set.remove(&1);
+        assert!(set.undo() && set.undo());
+        assert_eq!(set.version(), VersionId(1));
+        // a change after undoing branches off, and the discarded versions remain
+        set.insert(3);
+        let branched = set.version();
+        assert!(branched > two);
+
+        assert!(set.as_of(empty).unwrap().is_empty());
+        assert!(set.as_of(two).unwrap().iter().copied().eq([1, 2]));
+        assert!(set.as_of(VersionId(3)).unwrap().iter().copied().eq([2]));
+        assert!(set.as_of(branched).unwrap().iter().copied().eq([1, 3]));
+        assert!(set.as_of(VersionId(5)).is_none());
+        assert!(set.undo());
+        assert_eq!(set.version(), VersionId(1));
+    }
+}
@@ -0,0 +1,457 @@
+//! A map from intervals to values, finding all the intervals which contain a point or overlap
+//! a range, and a map from points to values, stored as ranges of points.
+//!
+//! Intervals are kept in an [`AVLAugmentedMap`] ordered by start, each subtree summarized by the
+//! largest end in it: a search skips the subtrees ending before the query, and stops at the first
+//! interval starting after it, so that it takes O(log n) per interval found.
+
+use std::cmp::{max, min};
+use std::ops::{Bound, Range};
+
+use crate::augmented::{self, AVLAugmentedMap, Augment};
+
+/// Summarizes a subtree of intervals by the largest of their ends.
+pub(crate) struct MaxEnd;
+
+impl<T: Ord + Clone, V> Augment<(T, T), V> for MaxEnd {
+    type Summary = Option<T>;
+
+    fn empty() -> Option<T> {
+        None
+    }
+
+    fn summarize(interval: &(T, T), _: &V) -> Option<T> {
+        Some(interval.1.clone())
+    }
+
+    fn combine(left: &Option<T>, right: &Option<T>) -> Option<T> {
+        match (left, right) {
+            (Some(l), Some(r)) => Some(l.max(r).clone()),
+            (l, r) => l.clone().or_else(|| r.clone()),
+        }
+    }
+}
+
+type Node<T, V> = augmented::Node<(T, T), V, MaxEnd>;
+
+/// A map from half-open intervals `start..end` to values, based on an AVL tree.
+///
+/// Intervals are ordered by start, then by end. Each interval has at most one value, as in a map,
+/// but intervals may overlap freely. Empty intervals can be stored, but overlap nothing.
+pub struct IntervalTree<T: Ord + Clone, V> {
+    map: AVLAugmentedMap<(T, T), V, MaxEnd>,
+}
+
+impl<T: Ord + Clone, V> IntervalTree<T, V> {
+
+    /// Creates an empty tree.
+    pub fn new() -> Self {
+        IntervalTree { map: AVLAugmentedMap::new() }
+    }
+
+    /// Returns the number of intervals in the tree.
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Returns the value for `interval`.
+    pub fn get(&self, interval: &Range<T>) -> Option<&V> {
+        self.map.get(&(interval.start.clone(), interval.end.clone()))
+    }
+
+    /// Inserts `value` for `interval`, returning the value it replaces if the tree has the same
+    /// interval already.
+    pub fn insert(&mut self, interval: Range<T>, value: V) -> Option<V> {
+        self.map.insert((interval.start, interval.end), value)
+    }
+
+    /// Removes `interval` from the tree, returning its value.
+    pub fn remove(&mut self, interval: &Range<T>) -> Option<V> {
+        self.map.remove(&(interval.start.clone(), interval.end.clone()))
+    }
+
+    /// Returns an iterator over the intervals containing `point`, in order.
+    pub fn query_point(&self, point: &T) -> Overlapping<'_, T, V> {
+        Overlapping::new(self.map.root(), Bound::Excluded(point.clone()), Bound::Included(point.clone()))
+    }
+
+    /// Returns an iterator over the intervals sharing at least one point with `range`, in order.
+    pub fn query_overlapping(&self, range: Range<T>) -> Overlapping<'_, T, V> {
+        Overlapping::new(self.map.root(), Bound::Excluded(range.start), Bound::Excluded(range.end))
+    }
+
+    /// Returns an iterator over the intervals overlapping `range` or adjacent to it, in order.
+    fn touching(&self, range: Range<T>) -> Overlapping<'_, T, V> {
+        Overlapping::new(self.map.root(), Bound::Included(range.start), Bound::Included(range.end))
+    }
+
+    /// Returns an iterator over all intervals, in order.
+    pub fn iter(&self) -> Iter<'_, T, V> {
+        Iter { iter: self.map.iter() }
+    }
+}
+
+impl<T: Ord + Clone, V> Default for IntervalTree<T, V> {
+    fn default() -> Self {
+        IntervalTree::new()
+    }
+}
+
+impl<T: Ord + Clone, V> FromIterator<(Range<T>, V)> for IntervalTree<T, V> {
+    fn from_iter<I: IntoIterator<Item = (Range<T>, V)>>(iter: I) -> Self {
+        let mut tree = IntervalTree::new();
+        tree.extend(iter);
+        tree
+    }
+}
+
+impl<T: Ord + Clone, V> Extend<(Range<T>, V)> for IntervalTree<T, V> {
+    fn extend<I: IntoIterator<Item = (Range<T>, V)>>(&mut self, iter: I) {
+        for (interval, value) in iter {
+            self.insert(interval, value);
+        }
+    }
+}
+
+/// An iterator over the intervals of an `IntervalTree` overlapping a query, in order.
+pub struct Overlapping<'a, T: Ord + Clone, V> {
+    /// The nodes left to visit, whose left subtrees were visited or skipped.
+    stack: Vec<&'a Node<T, V>>,
+    /// Intervals have to end after `start`, or at it if included.
+    start: Bound<T>,
+    /// And to start before, or at, `end`.
+    end: Bound<T>,
+}
+
+impl<'a, T: Ord + Clone, V> Overlapping<'a, T, V> {
+
+    fn new(root: Option<&'a Node<T, V>>, start: Bound<T>, end: Bound<T>) -> Self {
+        let mut iter = Overlapping { stack: Vec::new(), start, end };
+        iter.descend(root);
+        iter
+    }
+
+    fn after_start(&self, end: &T) -> bool {
+        match &self.start {
+            Bound::Included(s) => end >= s,
+            Bound::Excluded(s) => end > s,
+            Bound::Unbounded => true,
+        }
+    }
+
+    /// Stacks `node` and its left children as far as their subtrees end after the query starts.
+    fn descend(&mut self, mut next: Option<&'a Node<T, V>>) {
+        while let Some(n) = next {
+            if n.summary.as_ref().is_none_or(|end| !self.after_start(end)) {
+                break;
+            }
+            self.stack.push(n);
+            next = n.left.as_deref();
+        }
+    }
+}
+
+impl<'a, T: Ord + Clone, V> Iterator for Overlapping<'a, T, V> {
+    type Item = (Range<&'a T>, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(n) = self.stack.pop() {
+            let (start, end) = &n.key;
+            let before_end = match &self.end {
+                Bound::Included(e) => start <= e,
+                Bound::Excluded(e) => start < e,
+                Bound::Unbounded => true,
+            };
+            if !before_end {
+                // all the intervals left start later
+                self.stack.clear();
+                return None;
+            }
+            self.descend(n.right.as_deref());
+            if self.after_start(end) && start < end {
+                return Some((start..end, &n.value));
+            }
+        }
+        None
+    }
+}
+
+/// An iterator over the intervals of an `IntervalTree`, in order.
+pub struct Iter<'a, T: Ord + Clone, V> {
+    iter: augmented::Iter<'a, (T, T), V, MaxEnd>,
+}
+
+impl<'a, T: Ord + Clone, V> Iterator for Iter<'a, T, V> {
+    type Item = (Range<&'a T>, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next().map(|((start, end), value)| (start..end, value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<T: Ord + Clone, V> ExactSizeIterator for Iter<'_, T, V> {}
+
+impl<'a, T: Ord + Clone, V> IntoIterator for &'a IntervalTree<T, V> {
+    type Item = (Range<&'a T>, &'a V);
+    type IntoIter = Iter<'a, T, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// A map from points to values, which stores runs of points with the same value as single
+/// intervals, based on an [`IntervalTree`].
+///
+/// Intervals never overlap: inserting one overwrites whatever the map had over it, and merges it
+/// with the intervals it overlaps or touches which have the same value. The map thus always holds
+/// the fewest intervals possible. The ranges no interval covers are found with
+/// [`gaps`](Self::gaps).
+pub struct IntervalMap<T: Ord + Clone, V> {
+    tree: IntervalTree<T, V>,
+}
+
+impl<T: Ord + Clone, V: Clone + PartialEq> IntervalMap<T, V> {
+
+    /// Creates an empty map.
+    pub fn new() -> Self {
+        IntervalMap { tree: IntervalTree::new() }
+    }
+
+    /// Returns the number of intervals in the map, once merged.
+    pub fn len(&self) -> usize {
+        self.tree.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    /// Returns the value at `point`.
+    pub fn get(&self, point: &T) -> Option<&V> {
+        self.tree.query_point(point).next().map(|(_, value)| value)
+    }
+
+    /// Returns the interval covering `point`, with its value.
+    pub fn get_interval(&self, point: &T) -> Option<(Range<&T>, &V)> {
+        self.tree.query_point(point).next()
+    }
+
+    /// Sets `value` over `range`, merging it with the intervals of equal value it overlaps or
+    /// touches. Does nothing if `range` is empty.
+    pub fn insert(&mut self, range: Range<T>, value: V) {
+        if range.is_empty() {
+            return;
+        }
+        let Range { mut start, mut end } = range;
+        let touching: Vec<_> = self.tree.touching(start.clone()..end.clone())
+            .map(|(r, _)| r.start.clone()..r.end.clone())
+            .collect();
+        for r in touching {
+            let old = self.tree.remove(&r).unwrap();
+            if old == value {
+                start = min(start, r.start);
+                end = max(end, r.end);
+            }
+            else {
+                self.keep_outside(r, old, &start, &end);
+            }
+        }
+        self.tree.insert(start..end, value);
+    }
+
+    /// Removes all values over `range`, cutting the intervals it overlaps.
+    pub fn remove(&mut self, range: Range<T>) {
+        let overlapping: Vec<_> = self.tree.query_overlapping(range.clone())
+            .map(|(r, _)| r.start.clone()..r.end.clone())
+            .collect();
+        for r in overlapping {
+            let old = self.tree.remove(&r).unwrap();
+            self.keep_outside(r, old, &range.start, &range.end);
+        }
+    }
+
+    /// Puts back the parts of `interval` out of `start..end`, with `value`.
+    fn keep_outside(&mut self, interval: Range<T>, value: V, start: &T, end: &T) {
+        if interval.start < *start {
+            let before = interval.start.clone()..min(interval.end.clone(), start.clone());
+            self.tree.insert(before, value.clone());
+        }
+        if interval.end > *end {
+            self.tree.insert(max(interval.start, end.clone())..interval.end, value);
+        }
+    }
+
+    /// Returns an iterator over the intervals overlapping `range`, in order.
+    pub fn overlapping(&self, range: Range<T>) -> Overlapping<'_, T, V> {
+        self.tree.query_overlapping(range)
+    }
+
+    /// Returns an iterator over the maximal ranges within `span` which no interval covers, in
+    /// order.
+    pub fn gaps(&self, span: Range<T>) -> Gaps<'_, T, V> {
+        Gaps { intervals: self.tree.query_overlapping(span.clone()), next: Some(span.start), end: span.end }
+    }
+
+    /// Returns an iterator over all intervals, in order.
+    pub fn iter(&self) -> Iter<'_, T, V> {
+        self.tree.iter()
+    }
+}
+
+impl<T: Ord + Clone, V: Clone + PartialEq> Default for IntervalMap<T, V> {
+    fn default() -> Self {
+        IntervalMap::new()
+    }
+}
+
+impl<T: Ord + Clone, V: Clone + PartialEq> FromIterator<(Range<T>, V)> for IntervalMap<T, V> {
+    fn from_iter<I: IntoIterator<Item = (Range<T>, V)>>(iter: I) -> Self {
+        let mut map = IntervalMap::new();
+        map.extend(iter);
+        map
+    }
+}
+
+impl<T: Ord + Clone, V: Clone + PartialEq> Extend<(Range<T>, V)> for IntervalMap<T, V> {
+    fn extend<I: IntoIterator<Item = (Range<T>, V)>>(&mut self, iter: I) {
+        for (range, value) in iter {
+            self.insert(range, value);
+        }
+    }
+}
+
+impl<'a, T: Ord + Clone, V: Clone + PartialEq> IntoIterator for &'a IntervalMap<T, V> {
+    type Item = (Range<&'a T>, &'a V);
+    type IntoIter = Iter<'a, T, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// An iterator over the uncovered ranges of an `IntervalMap` within a span, in order.
+pub struct Gaps<'a, T: Ord + Clone, V> {
+    intervals: Overlapping<'a, T, V>,
+    /// Where the next gap may start, `None` once the span is done.
+    next: Option<T>,
+    end: T,
+}
+
+impl<T: Ord + Clone, V> Iterator for Gaps<'_, T, V> {
+    type Item = Range<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut start = self.next.take()?;
+        // the intervals come sorted and disjoint
+        for (r, _) in self.intervals.by_ref() {
+            if *r.start > start {
+                self.next = Some(r.end.clone());
+                return Some(start..r.start.clone());
+            }
+            start = r.end.clone();
+        }
+        (start < self.end).then(|| start..self.end.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_interval_tree() {
+        let mut tree = IntervalTree::new();
+        let intervals: Vec<_> = (0..200).map(|i| {
+            let start = (i * 37) % 200;
+            start..start + (i * 13) % 20
+        }).collect();
+        for (i, interval) in intervals.iter().enumerate() {
+            assert_eq!(tree.insert(interval.clone(), i), None);
+        }
+        assert_eq!(tree.insert(intervals[7].clone(), 1000), Some(7));
+        assert_eq!(tree.get(&intervals[7]), Some(&1000));
+        assert_eq!(tree.len(), 200);
+
+        let naive = |tree: &IntervalTree<i32, usize>, f: &dyn Fn(&Range<i32>) -> bool| {
+            tree.iter().filter(|(r, _)| f(&(*r.start..*r.end))).map(|(r, v)| (*r.start..*r.end, *v)).collect::<Vec<_>>()
+        };
+        for p in [-1, 0, 5, 99, 150, 199, 210, 250] {
+            let found: Vec<_> = tree.query_point(&p).map(|(r, v)| (*r.start..*r.end, *v)).collect();
+            assert_eq!(found, naive(&tree, &|r| r.contains(&p)));
+        }
+        for q in [0..10, 40..41, 50..50, 100..180, -20..0, 190..300] {
+            let found: Vec<_> = tree.query_overlapping(q.clone()).map(|(r, v)| (*r.start..*r.end, *v)).collect();
+            assert_eq!(found, naive(&tree, &|r| !r.is_empty() && r.start < q.end && q.start < r.end));
+        }
+
+        for interval in intervals.iter().step_by(2) {
+            assert!(tree.remove(interval).is_some());
+        }
+        assert_eq!(tree.remove(&intervals[0]), None);
+        assert_eq!(tree.len(), 100);
+        let found: Vec<_> = tree.query_overlapping(60..70).map(|(r, v)| (*r.start..*r.end, *v)).collect();
+        assert_eq!(found, naive(&tree, &|r| !r.is_empty() && r.start < 70 && 60 < r.end));
+        assert!(tree.iter().zip(tree.iter().skip(1)).all(|(a, b)| (a.0.start, a.0.end) < (b.0.start, b.0.end)));
+    }
+
+    #[test]
+    fn test_interval_map() {
+        // the value at each point from 0 to 100
+        let mut cells = [None; 100];
+        let mut map = IntervalMap::new();
+        for i in 0..60usize {
+            let start = (i * 37) % 100;
+            let range = start..(start + (i * 11) % 25).min(100);
+            if i % 4 == 3 {
+                map.remove(range.clone());
+                cells[range].fill(None);
+            }
+            else {
+                map.insert(range.clone(), i % 3);
+                cells[range].fill(Some(i % 3));
+            }
+
+            // intervals are disjoint, merged, and cover what they should
+            let intervals: Vec<_> = map.iter().map(|(r, v)| (*r.start..*r.end, *v)).collect();
+            assert!(intervals.windows(2).all(|w| w[0].0.end < w[1].0.start || (w[0].0.end == w[1].0.start && w[0].1 != w[1].1)));
+            let mut covered = [None; 100];
+            for (r, v) in intervals {
+                covered[r].fill(Some(v));
+            }
+            assert_eq!(covered, cells);
+        }
+        assert_eq!(map.get(&37), cells[37].as_ref());
+        let covered = (0..100).rev().find(|&p| cells[p].is_some()).unwrap();
+        let (range, value) = map.get_interval(&covered).unwrap();
+        assert!(cells[*range.start..*range.end].iter().all(|c| c.as_ref() == Some(value)));
+
+        for span in [0..100, 10..30, 55..56, 70..70, 90..120] {
+            let mut expected: Vec<Range<usize>> = Vec::new();
+            for p in span.clone().filter(|&p| p >= 100 || cells[p].is_none()) {
+                match expected.last_mut() {
+                    Some(r) if r.end == p => *r = r.start..p + 1,
+                    _ => expected.push(p..p + 1),
+                }
+            }
+            assert_eq!(map.gaps(span).collect::<Vec<_>>(), expected);
+        }
+        assert!(map.overlapping(20..40).all(|(r, _)| *r.start < 40 && *r.end > 20));
+
+        let mut map: IntervalMap<_, _> = [(0..10, 'a'), (10..20, 'a'), (30..40, 'b'), (25..35, 'b')].into_iter().collect();
+        assert_eq!(map.len(), 2);
+        map.insert(5..28, 'c');
+        map.remove(12..14);
+        let intervals: Vec<_> = map.iter().map(|(r, v)| (*r.start..*r.end, *v)).collect();
+        assert_eq!(intervals, [(0..5, 'a'), (5..12, 'c'), (14..28, 'c'), (28..40, 'b')]);
+        assert!(map.gaps(0..50).eq([12..14, 40..50]));
+    }
+}
@@ -0,0 +1,158 @@
+//! A check of the structure of a tree, for tests of code using it as much as of the tree.
+
+use std::cmp::{max, Ordering};
+use std::ptr;
+use std::rc::Rc;
+
+use crate::{peek, AVLTree, InvariantError, Link, NodeRef};
+
+impl<T: Ord> AVLTree<T> {
+
+    /// Checks that the tree is sound, in O(n): elements in order of the links, balance factors
+    /// matching the heights of the subtrees and within -1..=1, subtree sizes matching the
+    /// number of nodes, parent links pointing back at the parents, and handles at their
+    /// elements. Returns the first breach found, the in-order rank of a node telling where.
+    ///
+    /// The tree takes care of all of these itself; a breach is a bug, or a sign of elements
+    /// whose order changed while they were in the tree.
+    pub fn check_invariants(&self) -> Result<(), InvariantError> {
+        self.check_invariants_by(T::cmp)
+    }
+
+    /// Same as `check_invariants`, checking the order of the elements with `cmp` rather than
+    /// their `Ord` impl, for trees searched by other means.
+    pub(crate) fn check_invariants_by(&self, cmp: impl Fn(&T, &T) -> Ordering) -> Result<(), InvariantError> {
+        let mut walk = Walk { last: None, rank: 0, cmp: &cmp };
+        walk.check(&self.root, None)?;
+        Ok(())
+    }
+
+    /// Checks the invariants after a change to the structure of the tree, when built with the
+    /// `debug-validate` feature and debug assertions, and panics on a breach. Rotations are
+    /// covered by checking once the operation they are part of is over, balance factors further
+    /// up being unsettled until then.
+    #[inline]
+    pub(crate) fn validate(&self) {
+        self.validate_by(T::cmp);
+    }
+
+    /// Same as `validate`, checking the order of the elements with `cmp`.
+    #[inline]
+    pub(crate) fn validate_by(&self, _cmp: impl Fn(&T, &T) -> Ordering) {
+        #[cfg(all(feature = "debug-validate", debug_assertions))]
+        if let Err(e) = self.check_invariants_by(_cmp) {
+            panic!("AVLTree invariant broken: {e}");
+        }
+    }
+}
+
+/// The progress of a check through the nodes, in sorted order.
+struct Walk<'a, 'c, T: Ord> {
+    last: Option<&'a T>,
+    rank: usize,
+    cmp: &'c dyn Fn(&T, &T) -> Ordering,
+}
+
+impl<'a, T: Ord> Walk<'a, '_, T> {
+
+    /// Checks the subtree at `link`, whose parent is `parent`, and returns its height and size.
+    fn check(&mut self, link: &'a Link<T>, parent: Option<&NodeRef<T>>) -> Result<(usize, usize), InvariantError> {
+        let Some(node) = link else { return Ok((0, 0)) };
+        let n = peek(node);
+        let (left_height, left_size) = self.check(&n.left, Some(node))?;
+
+        let rank = self.rank;
+        let parent_ok = match parent {
+            None => n.parent.upgrade().is_none(),
+            Some(p) => ptr::eq(n.parent.as_ptr(), Rc::as_ptr(p)),
+        };
+        if !parent_ok {
+            return Err(InvariantError::Parent { rank });
+        }
+        if n.anchor.as_ref().is_some_and(|a| !ptr::eq(a.borrow().as_ptr(), Rc::as_ptr(node))) {
+            return Err(InvariantError::Handle { rank });
+        }
+        if self.last.is_some_and(|last| (self.cmp)(last, &n.value) == Ordering::Greater) {
+            return Err(InvariantError::Unordered { rank });
+        }
+        self.last = Some(&n.value);
+        self.rank += 1;
+
+        let (right_height, right_size) = self.check(&n.right, Some(node))?;
+        let balance = right_height as isize - left_height as isize;
+        if balance.abs() > 1 {
+            return Err(InvariantError::Unbalanced { rank, balance });
+        }
+        if balance != n.balance as isize {
+            return Err(InvariantError::Balance { rank, stored: n.balance, actual: balance });
+        }
+        let size = 1 + left_size + right_size;
+        if n.size != size {
+            return Err(InvariantError::Size { rank, stored: n.size, actual: size });
+        }
+        Ok((1 + max(left_height, right_height), size))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DuplicatePolicy;
+
+    #[test]
+    fn test_check_invariants() {
+        let mut tree: AVLTree<i32> = (0..200).map(|v| (v * 37) % 200).collect();
+        assert_eq!(tree.check_invariants(), Ok(()));
+        for v in (0..200).step_by(3) {
+            tree.remove(&v);
+            assert_eq!(tree.check_invariants(), Ok(()));
+        }
+        let handle = tree.insert_with_handle(1000).unwrap();
+        tree.insert_with_policy(1, DuplicatePolicy::KeepBoth);
+        assert_eq!(tree.check_invariants(), Ok(()));
+        assert_eq!(AVLTree::<i32>::new().check_invariants(), Ok(()));
+
+        let root = Rc::clone(tree.root.as_ref().unwrap());
+        let root_rank = root.borrow().size(crate::Side::Left);
+        let left = Rc::clone(root.borrow().left.as_ref().unwrap());
+
+        let value = root.borrow().value;
+        root.borrow_mut().value = -1;
+        assert_eq!(tree.check_invariants(), Err(InvariantError::Unordered { rank: root_rank }));
+        root.borrow_mut().value = value;
+
+        root.borrow_mut().size += 1;
+        assert!(matches!(tree.check_invariants(), Err(InvariantError::Size { rank, .. }) if rank == root_rank));
+        root.borrow_mut().size -= 1;
+
+        let balance = root.borrow().balance;
+        root.borrow_mut().balance = if balance == 0 { 1 } else { 0 };
+        assert!(matches!(tree.check_invariants(), Err(InvariantError::Balance { stored, .. }) if stored != balance));
+        root.borrow_mut().balance = balance;
+
+        let parent = std::mem::take(&mut left.borrow_mut().parent);
+        assert!(matches!(tree.check_invariants(), Err(InvariantError::Parent { .. })));
+        left.borrow_mut().parent = parent;
+
+        let anchor = root.borrow_mut().anchor.replace(Rc::new(Default::default()));
+        assert_eq!(tree.check_invariants(), Err(InvariantError::Handle { rank: root_rank }));
+        root.borrow_mut().anchor = anchor;
+
+        // cutting off the right subtree of the root
+        let right = root.borrow_mut().right.take();
+        assert!(matches!(tree.check_invariants(), Err(InvariantError::Unbalanced { .. })));
+        root.borrow_mut().right = right;
+        assert_eq!(tree.check_invariants(), Ok(()));
+        assert_eq!(tree.get_by_handle(&handle), Some(&1000));
+    }
+
+    #[test]
+    #[cfg(all(feature = "debug-validate", debug_assertions))]
+    #[should_panic(expected = "AVLTree invariant broken: element 3 is out of order")]
+    fn test_validate() {
+        let mut tree: AVLTree<i32> = (0..3).collect();
+        // caught by the following change to the structure
+        tree.root.as_ref().unwrap().borrow_mut().value = 5;
+        tree.insert(4);
+    }
+}
@@ -0,0 +1,711 @@
+//! An augmented ordered map which applies updates to whole ranges of entries in O(log n), such as
+//! adding to all the values with keys in a range, or marking them all as deleted.
+//!
+//! A range update only reaches the O(log n) subtrees covering the range, and leaves a pending tag
+//! at their roots. Tags are pushed down one level whenever a later operation goes through the
+//! node, so that an entry gets its value updated only once something looks at it.
+
+use std::borrow::Borrow;
+use std::cmp::{max, Ordering};
+use std::marker::PhantomData;
+use std::mem;
+use std::ops::{Add, RangeBounds};
+
+use crate::augmented::{Augment, Max, Min};
+use crate::{out_of_bounds, Side};
+
+/// Defines the updates an [`AVLLazyMap`] applies to ranges of entries, on top of the summaries
+/// of an [`Augment`].
+///
+/// Applying an update to every entry of a subtree must be the same as applying it to the summary
+/// of the subtree directly: `apply_summary(update, summary, size)` has to be the summary of the
+/// `size` entries once they are all updated. `compose` has to be associative, so that updates
+/// queued on a subtree can be merged into one.
+pub trait LazyUpdate<K, V>: Augment<K, V> {
+    type Update: Clone;
+
+    /// Applies `update` to the value of a single entry.
+    fn apply(update: &Self::Update, key: &K, value: &mut V);
+
+    /// Returns the summary of a subtree of `size` entries, of summary `summary`, once `update` is
+    /// applied to all of them.
+    fn apply_summary(update: &Self::Update, summary: &Self::Summary, size: usize) -> Self::Summary;
+
+    /// Returns the update doing what `first`, then `then` do.
+    fn compose(first: &Self::Update, then: &Self::Update) -> Self::Update;
+}
+
+/// Adds the update to every value, which shifts the minimum by as much.
+impl<K, V: Clone + Ord + Add<Output = V>> LazyUpdate<K, V> for Min {
+    type Update = V;
+
+    fn apply(update: &V, _: &K, value: &mut V) {
+        *value = value.clone() + update.clone();
+    }
+
+    fn apply_summary(update: &V, summary: &Option<V>, _: usize) -> Option<V> {
+        summary.clone().map(|m| m + update.clone())
+    }
+
+    fn compose(first: &V, then: &V) -> V {
+        first.clone() + then.clone()
+    }
+}
+
+/// Adds the update to every value, which shifts the maximum by as much.
+impl<K, V: Clone + Ord + Add<Output = V>> LazyUpdate<K, V> for Max {
+    type Update = V;
+
+    fn apply(update: &V, _: &K, value: &mut V) {
+        *value = value.clone() + update.clone();
+    }
+
+    fn apply_summary(update: &V, summary: &Option<V>, _: usize) -> Option<V> {
+        summary.clone().map(|m| m + update.clone())
+    }
+
+    fn compose(first: &V, then: &V) -> V {
+        first.clone() + then.clone()
+    }
+}
+
+type Link<K, V, L> = Option<Box<Node<K, V, L>>>;
+
+struct Node<K, V, L: LazyUpdate<K, V>> {
+    key: K,
+    value: V,
+    height: usize,
+    size: usize,
+    /// The summary of the subtree, `pending` included.
+    summary: L::Summary,
+    /// The update applied to this node but not to its children yet.
+    pending: Option<L::Update>,
+    left: Link<K, V, L>,
+    right: Link<K, V, L>,
+}
+
+impl<K, V, L: LazyUpdate<K, V>> Node<K, V, L> {
+
+    fn new(key: K, value: V) -> Self {
+        let summary = L::summarize(&key, &value);
+        Node { key, value, height: 1, size: 1, summary, pending: None, left: None, right: None }
+    }
+
+    fn child(&self, side: Side) -> &Link<K, V, L> {
+        match side {
+            Side::Left => &self.left,
+            Side::Right => &self.right,
+        }
+    }
+
+    fn child_mut(&mut self, side: Side) -> &mut Link<K, V, L> {
+        match side {
+            Side::Left => &mut self.left,
+            Side::Right => &mut self.right,
+        }
+    }
+
+    fn height(&self, side: Side) -> usize {
+        self.child(side).as_ref().map_or(0, |n| n.height)
+    }
+
+    fn size(&self, side: Side) -> usize {
+        self.child(side).as_ref().map_or(0, |n| n.size)
+    }
+
+    /// Applies `update` to the whole subtree: right away to the entry and the summary, and
+    /// lazily to the children.
+    fn apply(&mut self, update: &L::Update) {
+        L::apply(update, &self.key, &mut self.value);
+        self.summary = L::apply_summary(update, &self.summary, self.size);
+        self.pending = Some(match self.pending.take() {
+            Some(first) => L::compose(&first, update),
+            None => update.clone(),
+        });
+    }
+
+    /// Hands the pending update down to the children. It must be done before taking any of them
+    /// out of the node.
+    fn push(&mut self) {
+        if let Some(update) = self.pending.take() {
+            for child in [&mut self.left, &mut self.right].into_iter().flatten() {
+                child.apply(&update);
+            }
+        }
+    }
+
+    /// Recomputes the `height`, `size` and `summary` fields from the ones of the children,
+    /// pushing the pending update down first.
+    fn update(&mut self) {
+        self.push();
+        self.height = 1 + max(self.height(Side::Left), self.height(Side::Right));
+        self.size = 1 + self.size(Side::Left) + self.size(Side::Right);
+        let own = L::summarize(&self.key, &self.value);
+        let left = summary(&self.left);
+        let right = summary(&self.right);
+        self.summary = L::combine(&L::combine(&left, &own), &right);
+    }
+
+    fn balance_factor(&self) -> isize {
+        self.height(Side::Right) as isize - self.height(Side::Left) as isize
+    }
+}
+
+fn summary<K, V, L: LazyUpdate<K, V>>(link: &Link<K, V, L>) -> L::Summary {
+    link.as_ref().map_or_else(L::empty, |n| n.summary.clone())
+}
+
+/// Returns what `first`, then `then` do, either being possibly nothing.
+fn compose<K, V, L: LazyUpdate<K, V>>(first: &Option<L::Update>, then: &Option<L::Update>) -> Option<L::Update> {
+    match (first, then) {
+        (Some(first), Some(then)) => Some(L::compose(first, then)),
+        (first, then) => first.as_ref().or(then.as_ref()).cloned(),
+    }
+}
+
+/// Rotates the subtree rooted at `node` towards `side`, returning the new root of the subtree.
+fn rotate<K, V, L: LazyUpdate<K, V>>(mut node: Box<Node<K, V, L>>, side: Side) -> Box<Node<K, V, L>> {
+    node.push();
+    let mut z = node.child_mut(!side).take().unwrap();
+    z.push();
+    *node.child_mut(!side) = z.child_mut(side).take();
+    node.update();
+    *z.child_mut(side) = Some(node);
+    z.update();
+    z
+}
+
+/// Refreshes the fields of `node` and rotates it if its balance factor has reached +2 or -2,
+/// returning the root of the subtree afterwards.
+fn balance<K, V, L: LazyUpdate<K, V>>(mut node: Box<Node<K, V, L>>) -> Box<Node<K, V, L>> {
+    node.update();
+    let b = node.balance_factor();
+    if b.abs() <= 1 {
+        return node;
+    }
+    let side = if b > 1 { Side::Left } else { Side::Right };
+    let zb = node.child(!side).as_ref().unwrap().balance_factor();
+    if (b > 1 && zb < 0) || (b < -1 && zb > 0) {
+        let z = node.child_mut(!side).take().unwrap();
+        *node.child_mut(!side) = Some(rotate(z, !side));
+    }
+    rotate(node, side)
+}
+
+/// Applies `update` to the entries of the subtree under `node` which fall in the range whose
+/// bounds `below` and `above` test, knowing whether the whole subtree is past the start of the
+/// range and before its end.
+fn apply_range<K, V, L, Q, B, A>(node: &mut Node<K, V, L>, update: &L::Update, below: &B, above: &A, past_start: bool, before_end: bool)
+where
+    K: Borrow<Q>,
+    L: LazyUpdate<K, V>,
+    Q: ?Sized + Ord,
+    B: Fn(&Q) -> bool,
+    A: Fn(&Q) -> bool,
+{
+    if past_start && before_end {
+        node.apply(update);
+        return;
+    }
+    node.push();
+    let (from_start, to_end) = (!below(node.key.borrow()), !above(node.key.borrow()));
+    if from_start && to_end {
+        L::apply(update, &node.key, &mut node.value);
+    }
+    // the keys on the left are all before the end if this one is, and similarly on the right
+    if let (true, Some(left)) = (from_start, &mut node.left) {
+        apply_range(left, update, below, above, past_start, before_end || to_end);
+    }
+    if let (true, Some(right)) = (to_end, &mut node.right) {
+        apply_range(right, update, below, above, past_start || from_start, before_end);
+    }
+    node.update();
+}
+
+/// Nodes taken out of the tree on the way down to a position, with the side taken at each, as
+/// in [`AVLTreeBoxed`](crate::AVLTreeBoxed).
+struct Path<K, V, L: LazyUpdate<K, V>> {
+    steps: Vec<Step<K, V, L>>,
+}
+
+type Step<K, V, L> = (Box<Node<K, V, L>>, Side);
+
+impl<K, V, L: LazyUpdate<K, V>> Path<K, V, L> {
+
+    fn new() -> Self {
+        Path { steps: Vec::new() }
+    }
+
+    /// Relinks the path on top of `subtree`, which replaces whatever the last step led to,
+    /// rebalancing and resummarizing every node, and returns the resulting tree.
+    fn retrace(mut self, mut subtree: Link<K, V, L>) -> Link<K, V, L> {
+        while let Some((mut node, side)) = self.steps.pop() {
+            *node.child_mut(side) = subtree;
+            subtree = Some(balance(node));
+        }
+        subtree
+    }
+}
+
+/// An ordered map based on an AVL tree which keeps the summary `L` of every subtree, and applies
+/// the updates of `L` to ranges of entries in O(log n).
+///
+/// It works like an [`AVLAugmentedMap`](crate::AVLAugmentedMap), except that reading values
+/// takes a mutable reference: it has the updates pending on the way to them applied.
+pub struct AVLLazyMap<K, V, L: LazyUpdate<K, V>> {
+    root: Link<K, V, L>,
+    augment: PhantomData<L>,
+}
+
+impl<K: Ord, V, L: LazyUpdate<K, V>> AVLLazyMap<K, V, L> {
+
+    /// Creates an empty map.
+    pub fn new() -> Self {
+        AVLLazyMap { root: None, augment: PhantomData }
+    }
+
+    /// Returns the number of entries in the map.
+    pub fn len(&self) -> usize {
+        self.root.as_ref().map_or(0, |n| n.size)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+
+    /// Returns the summary of the whole map, in O(1).
+    pub fn summary(&self) -> L::Summary {
+        summary(&self.root)
+    }
+
+    /// Returns the summary of the entries whose keys fall in `range`, in O(log n).
+    ///
+    /// As in [`AVLAugmentedMap::aggregate_range`](crate::AVLAugmentedMap::aggregate_range), the
+    /// summaries of the subtrees covering the range get combined, with the updates still pending
+    /// above each applied on the way.
+    pub fn aggregate_range<Q: ?Sized + Ord, R: RangeBounds<Q>>(&self, range: R) -> L::Summary where K: Borrow<Q> {
+        // the subtree under `link`, or the entry of `n`, with the updates `pending` above applied
+        let subtree = |link: &Link<K, V, L>, pending: &Option<L::Update>| match (link, pending) {
+            (Some(n), Some(u)) => L::apply_summary(u, &n.summary, n.size),
+            (link, _) => summary(link),
+        };
+        let own = |n: &Node<K, V, L>, pending: &Option<L::Update>| {
+            let own = L::summarize(&n.key, &n.value);
+            match pending {
+                Some(u) => L::apply_summary(u, &own, 1),
+                None => own,
+            }
+        };
+
+        let (below, above) = out_of_bounds(range.start_bound(), range.end_bound());
+
+        // the topmost node in range, where the paths to both bounds part
+        let mut pending = None;
+        let mut next = self.root.as_deref();
+        let split = loop {
+            let Some(n) = next else {
+                return L::empty();
+            };
+            let side = if below(n.key.borrow()) {
+                Side::Right
+            }
+            else if above(n.key.borrow()) {
+                Side::Left
+            }
+            else {
+                break n;
+            };
+            pending = compose::<K, V, L>(&n.pending, &pending);
+            next = n.child(side).as_deref();
+        };
+        let middle = own(split, &pending);
+        let pending = compose::<K, V, L>(&split.pending, &pending);
+
+        // on its left, everything is before the end of the range: gather what is past the start
+        let mut left = L::empty();
+        let mut above_n = pending.clone();
+        let mut next = split.left.as_deref();
+        while let Some(n) = next {
+            let below_n = compose::<K, V, L>(&n.pending, &above_n);
+            if below(n.key.borrow()) {
+                next = n.right.as_deref();
+            }
+            else {
+                left = L::combine(&L::combine(&own(n, &above_n), &subtree(&n.right, &below_n)), &left);
+                next = n.left.as_deref();
+            }
+            above_n = below_n;
+        }
+        let mut right = L::empty();
+        let mut above_n = pending;
+        let mut next = split.right.as_deref();
+        while let Some(n) = next {
+            let below_n = compose::<K, V, L>(&n.pending, &above_n);
+            if above(n.key.borrow()) {
+                next = n.left.as_deref();
+            }
+            else {
+                right = L::combine(&right, &L::combine(&subtree(&n.left, &below_n), &own(n, &above_n)));
+                next = n.right.as_deref();
+            }
+            above_n = below_n;
+        }
+        L::combine(&L::combine(&left, &middle), &right)
+    }
+
+    /// Applies `update` to the values of all the entries whose keys fall in `range`, in
+    /// O(log n).
+    pub fn update_range<Q: ?Sized + Ord, R: RangeBounds<Q>>(&mut self, range: R, update: &L::Update) where K: Borrow<Q> {
+        let (below, above) = out_of_bounds(range.start_bound(), range.end_bound());
+        if let Some(root) = &mut self.root {
+            apply_range(root, update, &below, &above, false, false);
+        }
+    }
+
+    /// Returns `true` if the map has an entry for `key`.
+    pub fn contains_key<Q: ?Sized + Ord>(&self, key: &Q) -> bool where K: Borrow<Q> {
+        let mut next = self.root.as_deref();
+        while let Some(n) = next {
+            next = match key.cmp(n.key.borrow()) {
+                Ordering::Equal => return true,
+                Ordering::Less => n.left.as_deref(),
+                Ordering::Greater => n.right.as_deref(),
+            };
+        }
+        false
+    }
+
+    /// Returns the value for `key`, once the updates pending on the way to it are applied.
+    pub fn get<Q: ?Sized + Ord>(&mut self, key: &Q) -> Option<&V> where K: Borrow<Q> {
+        let mut next = self.root.as_deref_mut();
+        while let Some(n) = next {
+            let side = match key.cmp(n.key.borrow()) {
+                Ordering::Equal => return Some(&n.value),
+                Ordering::Less => Side::Left,
+                Ordering::Greater => Side::Right,
+            };
+            n.push();
+            next = n.child_mut(side).as_deref_mut();
+        }
+        None
+    }
+
+    /// Takes the nodes on the way down to `key` out of the tree, returning them along with the
+    /// node for `key` if there is one. The tree is left empty until the path is retraced.
+    fn descend<Q: ?Sized + Ord>(&mut self, key: &Q) -> (Path<K, V, L>, Link<K, V, L>) where K: Borrow<Q> {
+        let mut path = Path::new();
+        let mut next = self.root.take();
+        while let Some(mut node) = next {
+            let side = match key.cmp(node.key.borrow()) {
+                Ordering::Equal => return (path, Some(node)),
+                Ordering::Less => Side::Left,
+                Ordering::Greater => Side::Right,
+            };
+            node.push();
+            next = node.child_mut(side).take();
+            path.steps.push((node, side));
+        }
+        (path, None)
+    }
+
+    /// Inserts `value` for `key`, returning the value it replaces, if any.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        let (path, found) = self.descend(&key);
+        let (node, old) = match found {
+            Some(mut node) => {
+                let old = mem::replace(&mut node.value, value);
+                node.update();
+                (node, Some(old))
+            }
+            None => (Box::new(Node::new(key, value)), None),
+        };
+        self.root = path.retrace(Some(node));
+        old
+    }
+
+    /// Removes the entry for `key`, returning its value.
+    pub fn remove<Q: ?Sized + Ord>(&mut self, key: &Q) -> Option<V> where K: Borrow<Q> {
+        let (path, found) = self.descend(key);
+        let Some(node) = found else {
+            self.root = path.retrace(None);
+            return None;
+        };
+        let ((_, value), rest) = remove_root(node);
+        self.root = path.retrace(rest);
+        Some(value)
+    }
+
+    /// Calls `f` on the value for `key`, then refreshes the summaries of the subtrees holding
+    /// it. Returns `false` if there is no entry for `key`.
+    pub fn update<Q: ?Sized + Ord, F: FnOnce(&mut V)>(&mut self, key: &Q, f: F) -> bool where K: Borrow<Q> {
+        let (path, found) = self.descend(key);
+        let found = found.map(|mut node| {
+            f(&mut node.value);
+            node.update();
+            node
+        });
+        let updated = found.is_some();
+        self.root = path.retrace(found);
+        updated
+    }
+
+    /// Returns an iterator over the entries in sorted order.
+    ///
+    /// All pending updates get applied first, which takes O(n).
+    pub fn iter(&mut self) -> Iter<'_, K, V, L> {
+        let mut stack: Vec<&mut Node<K, V, L>> = self.root.as_deref_mut().into_iter().collect();
+        while let Some(n) = stack.pop() {
+            n.push();
+            stack.extend(n.left.as_deref_mut());
+            stack.extend(n.right.as_deref_mut());
+        }
+        let mut iter = Iter { stack: Vec::new(), remaining: self.len() };
+        iter.descend(self.root.as_deref());
+        iter
+    }
+}
+
+/// Removes the root of a subtree, returning its entry and what is left of the subtree.
+fn remove_root<K, V, L: LazyUpdate<K, V>>(mut node: Box<Node<K, V, L>>) -> ((K, V), Link<K, V, L>) {
+    node.push();
+    match (node.left.take(), node.right.take()) {
+        (None, child) | (child, None) => ((node.key, node.value), child),
+        (left, Some(right)) => {
+            // the successor takes the place of the removed entry
+            let ((key, value), right) = pop_first(right);
+            node.left = left;
+            node.right = right;
+            let entry = (mem::replace(&mut node.key, key), mem::replace(&mut node.value, value));
+            (entry, Some(balance(node)))
+        }
+    }
+}
+
+/// Removes the smallest entry of a subtree, returning it and what is left of the subtree.
+fn pop_first<K, V, L: LazyUpdate<K, V>>(root: Box<Node<K, V, L>>) -> ((K, V), Link<K, V, L>) {
+    let mut path = Path::new();
+    let mut node = root;
+    node.push();
+    while let Some(mut left) = node.left.take() {
+        left.push();
+        path.steps.push((node, Side::Left));
+        node = left;
+    }
+    let rest = node.right.take();
+    ((node.key, node.value), path.retrace(rest))
+}
+
+impl<K: Ord, V, L: LazyUpdate<K, V>> Default for AVLLazyMap<K, V, L> {
+    fn default() -> Self {
+        AVLLazyMap::new()
+    }
+}
+
+/// Drops the nodes one by one, as dropping the root `Box` would recurse down the tree.
+impl<K, V, L: LazyUpdate<K, V>> Drop for AVLLazyMap<K, V, L> {
+    fn drop(&mut self) {
+        let mut stack: Vec<Box<Node<K, V, L>>> = self.root.take().into_iter().collect();
+        while let Some(mut node) = stack.pop() {
+            stack.extend(node.left.take());
+            stack.extend(node.right.take());
+        }
+    }
+}
+
+impl<K: Ord, V, L: LazyUpdate<K, V>> FromIterator<(K, V)> for AVLLazyMap<K, V, L> {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut map = AVLLazyMap::new();
+        map.extend(iter);
+        map
+    }
+}
+
+impl<K: Ord, V, L: LazyUpdate<K, V>> Extend<(K, V)> for AVLLazyMap<K, V, L> {
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        for (key, value) in iter {
+            self.insert(key, value);
+        }
+    }
+}
+
+/// An iterator over the entries of an `AVLLazyMap`, in sorted order.
+pub struct Iter<'a, K, V, L: LazyUpdate<K, V>> {
+    stack: Vec<&'a Node<K, V, L>>,
+    remaining: usize,
+}
+
+impl<'a, K, V, L: LazyUpdate<K, V>> Iter<'a, K, V, L> {
+
+    /// Stacks `node` and its left children as far as they go.
+    fn descend(&mut self, mut next: Option<&'a Node<K, V, L>>) {
+        while let Some(n) = next {
+            self.stack.push(n);
+            next = n.left.as_deref();
+        }
+    }
+}
+
+impl<'a, K, V, L: LazyUpdate<K, V>> Iterator for Iter<'a, K, V, L> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let n = self.stack.pop()?;
+        self.remaining -= 1;
+        self.descend(n.right.as_deref());
+        Some((&n.key, &n.value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<K, V, L: LazyUpdate<K, V>> ExactSizeIterator for Iter<'_, K, V, L> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    /// Checks heights, sizes, balance and summaries of the whole tree, once all updates are
+    /// pushed down.
+    fn check<K: Ord, V, L: LazyUpdate<K, V>>(map: &mut AVLLazyMap<K, V, L>) where L::Summary: PartialEq + std::fmt::Debug {
+        fn check<K, V, L: LazyUpdate<K, V>>(link: &Link<K, V, L>) -> usize where L::Summary: PartialEq + std::fmt::Debug {
+            let Some(n) = link else {
+                return 0;
+            };
+            let (hl, hr) = (check(&n.left), check(&n.right));
+            assert!(hl.abs_diff(hr) <= 1);
+            assert_eq!(n.height, 1 + max(hl, hr));
+            assert_eq!(n.size, 1 + n.size(Side::Left) + n.size(Side::Right));
+            assert!(n.pending.is_none());
+            let own = L::summarize(&n.key, &n.value);
+            assert_eq!(n.summary, L::combine(&L::combine(&summary(&n.left), &own), &summary(&n.right)));
+            n.height
+        }
+        map.iter();
+        check(&map.root);
+    }
+
+    /// Sums the values, adding to them on update.
+    struct AddSum;
+
+    impl Augment<i32, i64> for AddSum {
+        type Summary = i64;
+
+        fn empty() -> i64 {
+            0
+        }
+
+        fn summarize(_: &i32, value: &i64) -> i64 {
+            *value
+        }
+
+        fn combine(left: &i64, right: &i64) -> i64 {
+            left + right
+        }
+    }
+
+    impl LazyUpdate<i32, i64> for AddSum {
+        type Update = i64;
+
+        fn apply(update: &i64, _: &i32, value: &mut i64) {
+            *value += update;
+        }
+
+        fn apply_summary(update: &i64, summary: &i64, size: usize) -> i64 {
+            summary + update * size as i64
+        }
+
+        fn compose(first: &i64, then: &i64) -> i64 {
+            first + then
+        }
+    }
+
+    /// Counts the entries not deleted, deleting them on update.
+    struct Tombstone;
+
+    impl Augment<i32, bool> for Tombstone {
+        type Summary = usize;
+
+        fn empty() -> usize {
+            0
+        }
+
+        fn summarize(_: &i32, live: &bool) -> usize {
+            *live as usize
+        }
+
+        fn combine(left: &usize, right: &usize) -> usize {
+            left + right
+        }
+    }
+
+    impl LazyUpdate<i32, bool> for Tombstone {
+        type Update = ();
+
+        fn apply(_: &(), _: &i32, live: &mut bool) {
+            *live = false;
+        }
+
+        fn apply_summary(_: &(), _: &usize, _: usize) -> usize {
+            0
+        }
+
+        fn compose(_: &(), _: &()) {}
+    }
+
+    #[test]
+    fn test_lazy() {
+        let mut map: AVLLazyMap<i32, i64, AddSum> = AVLLazyMap::new();
+        let mut expected = BTreeMap::new();
+        for i in 0..300 {
+            let key = (i * 37) % 300;
+            map.insert(key, key as i64);
+            expected.insert(key, key as i64);
+        }
+        for (i, (lo, hi)) in [(10, 50), (0, 300), (45, 46), (200, 400), (-10, 5), (30, 30), (20, 280)].into_iter().enumerate() {
+            map.update_range(lo..hi, &(i as i64 + 1));
+            expected.range(lo..hi).map(|(k, _)| *k).collect::<Vec<_>>().into_iter()
+                .for_each(|k| *expected.get_mut(&k).unwrap() += i as i64 + 1);
+            assert_eq!(map.summary(), expected.values().sum::<i64>());
+            for (lo, hi) in [(0, 300), (15, 47), (44, 46), (-3, 12), (150, 500), (100, 100)] {
+                assert_eq!(map.aggregate_range(lo..=hi), expected.range(lo..=hi).map(|(_, v)| v).sum::<i64>());
+            }
+            // changes go through the pending updates
+            let key = (i as i32 * 41) % 300;
+            assert_eq!(map.get(&key), expected.get(&key));
+            assert_eq!(map.remove(&(key + 1)), expected.remove(&(key + 1)));
+            map.insert(key + 1000, 0);
+            expected.insert(key + 1000, 0);
+            assert!(map.update(&key, |v| *v *= 2));
+            *expected.get_mut(&key).unwrap() *= 2;
+        }
+        assert!(map.contains_key(&1000) && !map.contains_key(&1));
+        assert!(map.iter().map(|(k, v)| (*k, *v)).eq(expected.clone()));
+        check(&mut map);
+
+        let mut highest: AVLLazyMap<_, _, Max> = (0..100).map(|i| (i, 100 - i)).collect();
+        highest.update_range(50.., &100);
+        assert_eq!(highest.summary(), Some(150));
+        assert_eq!(highest.aggregate_range(..50), Some(100));
+        check(&mut highest);
+    }
+
+    #[test]
+    fn test_tombstones() {
+        let mut map: AVLLazyMap<_, _, Tombstone> = (0..1000).map(|i| (i, true)).collect();
+        map.update_range(100..200, &());
+        map.update_range(150..=400, &());
+        assert_eq!(map.summary(), 699);
+        assert_eq!(map.aggregate_range(0..300), 100);
+        assert_eq!(map.get(&99), Some(&true));
+        assert_eq!(map.get(&400), Some(&false));
+        for i in (0..1000).step_by(7) {
+            map.insert(i, true);
+        }
+        assert_eq!(map.aggregate_range(100..=400), (100..=400).filter(|k| k % 7 == 0).count());
+        check(&mut map);
+        assert!(map.iter().all(|(k, live)| *live == (!(100..=400).contains(k) || k % 7 == 0)));
+    }
+}
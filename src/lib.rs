@@ -0,0 +1,2875 @@
+// `Borrow` is not imported by name, its `borrow` method would shadow `RefCell::borrow`
+use std::borrow;
+use std::rc::{Rc, Weak};
+use std::cell::RefCell;
+use std::collections::{BTreeSet, HashSet};
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::mem;
+use std::cmp::{max, min, Ordering};
+use std::ops::{Bound, Index, Not, RangeBounds};
+use std::sync::atomic::{self, AtomicU64};
+
+use events::Hook;
+use explain::quiet;
+use persistent::Shadow;
+use stats::Counters;
+
+mod alloc;
+pub mod arena;
+pub mod augmented;
+pub mod boxed;
+mod bounded;
+pub mod by_key;
+#[cfg(feature = "concurrent")]
+pub mod concurrent;
+mod cursor;
+mod detached;
+mod durable;
+#[cfg(feature = "epoch")]
+pub mod epoch;
+mod error;
+mod events;
+mod explain;
+pub mod fixed;
+#[cfg(feature = "ghost")]
+pub mod ghost;
+mod handle;
+pub mod history;
+pub mod interval;
+mod invariants;
+pub mod lazy;
+pub mod map;
+#[cfg(all(feature = "mmap", unix))]
+pub mod mapped;
+mod merge;
+#[cfg(feature = "merkle")]
+pub mod merkle;
+pub mod multimap;
+pub mod multiset;
+mod nearest;
+mod operation_log;
+#[cfg(test)]
+mod oracle;
+pub mod persistent;
+mod priority_queue;
+mod quantile;
+#[cfg(feature = "rand")]
+mod rand_impl;
+#[cfg(feature = "raw")]
+pub mod raw;
+#[cfg(feature = "rayon")]
+mod rayon_impl;
+mod render;
+#[cfg(feature = "rkyv")]
+mod rkyv_impl;
+#[cfg(feature = "serde")]
+mod serde_impl;
+pub mod sequence;
+mod set_ops;
+pub mod sharded;
+pub mod snapshot;
+mod stats;
+#[cfg(feature = "proptest")]
+pub mod strategy;
+mod total_ord;
+mod window;
+
+pub use arena::AVLTreeArena;
+pub use augmented::{AVLAugmentedMap, Augment};
+pub use boxed::AVLTreeBoxed;
+pub use bounded::{BoundedAVLSet, Evict};
+pub use by_key::AVLTreeBy;
+#[cfg(feature = "concurrent")]
+pub use concurrent::AVLTreeConcurrent;
+pub use cursor::CursorMut;
+pub use detached::DetachedIter;
+pub use durable::{DurableAVLTree, SyncPolicy};
+#[cfg(feature = "epoch")]
+pub use epoch::AVLTreeEpoch;
+pub use error::{CapacityError, InvariantError, OccupiedError, StaleError, UnorderedError};
+pub use events::{Event, EventSink};
+pub use explain::{Direction, Step};
+pub use fixed::AVLTreeFixed;
+#[cfg(feature = "ghost")]
+pub use ghost::AVLTreeGhost;
+pub use handle::NodeHandle;
+pub use history::{AVLTreeHistory, VersionId};
+pub use interval::{IntervalMap, IntervalTree};
+pub use lazy::{AVLLazyMap, LazyUpdate};
+pub use map::AVLMap;
+#[cfg(all(feature = "mmap", unix))]
+pub use mapped::AVLTreeMapped;
+pub use merge::{Merge, MergeAll};
+#[cfg(feature = "merkle")]
+pub use merkle::AVLTreeMerkle;
+pub use multimap::AVLMultiMap;
+pub use multiset::AVLMultiSet;
+pub use nearest::{Distance, Tie};
+pub use operation_log::{Operation, OperationLog};
+pub use persistent::Snapshot;
+pub use priority_queue::AVLPriorityQueue;
+pub use quantile::Interpolation;
+#[cfg(feature = "raw")]
+pub use raw::AVLTreeRaw;
+#[cfg(feature = "rayon")]
+pub use rayon_impl::ParIter;
+#[cfg(feature = "rkyv")]
+pub use rkyv_impl::ArchivedAVLTree;
+pub use sequence::Sequence;
+pub use set_ops::{Diff, DiffItem, Difference, Intersection, SymmetricDifference, Union};
+pub use sharded::ShardedAVLSet;
+pub use snapshot::SnapshotElement;
+#[cfg(feature = "stats")]
+pub use stats::Stats;
+pub use total_ord::{TotalOrd, TotalOrder};
+pub use window::SlidingWindow;
+
+struct AVLNode<T: Ord> {
+    value: T,
+    /// Height of the right subtree minus height of the left one, kept within -1..=1 between
+    /// operations.
+    balance: i8,
+    size: usize,
+    /// Weak, so that a node and its children don't keep each other alive. Dangling at the root.
+    parent: Weak<RefCell<AVLNode<T>>>,
+    left: Option<Rc<RefCell<AVLNode<T>>>>,
+    right: Option<Rc<RefCell<AVLNode<T>>>>,
+    /// Set when a `NodeHandle` to the element was handed out, moves along with the element.
+    anchor: Option<Anchor<T>>,
+}
+
+/// An ordered set based on an AVL tree.
+///
+/// Each element is held in its own `Rc<RefCell<_>>` node, allocated from the global allocator.
+/// [`AVLTreeArena`] and [`AVLTreeBoxed`] allocate their nodes from an allocator of choice with
+/// the `allocator_api2` feature.
+pub struct AVLTree<T: Ord> {
+    root: Option<Rc<RefCell<AVLNode<T>>>>,
+    /// See `generation`.
+    generation: u64,
+    counters: Counters,
+    hook: Hook<T>,
+    /// The copy `snapshot` hands out clones of, kept up to date by changes of one element.
+    shadow: RefCell<Option<Shadow<T>>>,
+}
+
+/// Hands out the generations of all trees, so that no two trees ever share one but empty trees
+/// fresh from `new`, which have 0.
+static GENERATIONS: AtomicU64 = AtomicU64::new(1);
+
+/// What [`AVLTree::insert_with_policy`] does when an element equal to the inserted one is
+/// already in the tree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DuplicatePolicy {
+    /// Leave the tree untouched and give the new element back, as `insert` does.
+    Reject,
+    /// Put the new element in place of the old one and give the old one back, as `replace`
+    /// does.
+    Replace,
+    /// Keep both, the new element going after the equal ones already in the tree.
+    ///
+    /// Lookups and removals then act on any one of the equal elements, and the set operations
+    /// treat them as distinct.
+    KeepBoth,
+}
+
+/// A shared handle to a node.
+type NodeRef<T> = Rc<RefCell<AVLNode<T>>>;
+
+/// A possibly empty subtree.
+type Link<T> = Option<NodeRef<T>>;
+
+/// Where a `NodeHandle` finds the node currently holding its element.
+type Anchor<T> = Rc<RefCell<Weak<RefCell<AVLNode<T>>>>>;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Side {
+    Left,
+    Right,
+}
+
+impl<T: Ord> AVLNode<T> {
+
+    fn new(value: T, parent: &Link<T>) -> Self {
+        AVLNode {
+            value,
+            balance: 0,
+            size: 1,
+            parent: parent.as_ref().map_or_else(Weak::new, Rc::downgrade),
+            left: None,
+            right: None,
+            anchor: None,
+        }
+    }
+
+    /// Returns a reference to the left or right child.
+    fn child(&self, side: Side) -> &Option<Rc<RefCell<AVLNode<T>>>> {
+        match side {
+            Side::Left => &self.left,
+            Side::Right => &self.right
+        }
+    }
+
+    /// Returns a mutable reference to the left or right child.
+    fn child_mut(&mut self, side: Side) -> &mut Option<Rc<RefCell<AVLNode<T>>>> {
+        match side {
+            Side::Left => &mut self.left,
+            Side::Right => &mut self.right,
+        }
+    }
+
+    /// Returns a mutable reference to the parent.
+    fn parent_mut(&mut self) -> &mut Weak<RefCell<AVLNode<T>>> {
+        &mut self.parent
+    }
+
+    fn is_left_child(&self) -> bool {
+        match self.parent.upgrade() {
+            None => false,
+            Some(p) => {
+                p.borrow().child(Side::Left).as_ref().is_some_and(|l| std::ptr::eq(l.as_ptr(), self))
+            }
+        }
+    }
+
+    /// Number of elements in the left or right subtree.
+    fn size(&self, side: Side) -> usize {
+        self.child(side).as_ref().map_or(0, |n| n.borrow().size)
+    }
+
+    /// Recomputes the `size` field.
+    fn update_size(&mut self) {
+        self.size = 1 + self.size(Side::Left) + self.size(Side::Right);
+    }
+
+    fn balance_factor(&self) -> i8 {
+        self.balance
+    }
+
+    /// Height of the subtree on `side`, given that this node's subtree is `height` tall.
+    fn child_height(&self, side: Side, height: usize) -> usize {
+        let leaning = if side == Side::Right { self.balance } else { -self.balance };
+        if leaning < 0 { height - 2 } else { height - 1 }
+    }
+
+    /*
+    fn find_replacement_node(&self) -> &AVLNode<T> {
+        match self.child(Side::Left) {
+            None => { // get replacement from right subtree
+                let mut n = self.child(Side::Right).as_ref().unwrap();
+                loop {
+                    match n.left {
+                        None => break n,
+                        Some(ref left_node) => n = left_node,
+                    }
+                }
+            },
+            Some(ref child) => { // get replacement from left subtree
+                let mut n = self.child(Side::Left).as_ref().unwrap();
+                loop {
+                    match n.right {
+                        None => break n,
+                        Some(ref right_node) => n = right_node,
+                    }
+                }
+            }
+        }
+    }
+    */
+
+    fn replacement(&mut self) -> Option<Rc<RefCell<AVLNode<T>>>> {
+        match self.child(Side::Left) {
+            None => { // search for replacement in the right subtree
+                let mut next = self.child_mut(Side::Right).clone();
+                let mut curr = None;
+                while let Some(node) = next {
+                    curr = Some(Rc::clone(&node));
+                    next = node.borrow_mut().child_mut(Side::Left).clone();
+                }
+                curr
+            },
+            Some(_) => {
+                let mut next = self.child_mut(Side::Left).clone();
+                let mut curr = None;
+                while let Some(node) = next {
+                    curr = Some(Rc::clone(&node));
+                    next = node.borrow_mut().child_mut(Side::Right).clone();
+                }
+                curr
+            }
+        }
+    }
+
+    /// Rotates the subtree rooted at this node towards `side`.
+    ///
+    /// The contents of this node and of its `!side` child are swapped rather than relinked, so
+    /// whoever points at this node (the parent or the tree root) keeps pointing at the root of
+    /// the rotated subtree.
+    fn rotate(&mut self, side: Side) {
+        let subtree = self.child_mut(!side).take().unwrap();
+        *self.child_mut(!side) = subtree.borrow_mut().child_mut(side).take();
+        self.update_size();
+        let (x, z) = rotated_balances(self.balance, subtree.borrow().balance, side);
+        mem::swap(self, &mut subtree.borrow_mut());
+        mem::swap(self.parent_mut(), subtree.borrow_mut().parent_mut());
+        self.balance = z;
+        subtree.borrow_mut().balance = x;
+
+        // the outer grandchildren moved along with the swapped contents, fix their parent links
+        if let Some(ref outer) = *subtree.borrow().child(side) {
+            outer.borrow_mut().parent = Rc::downgrade(&subtree);
+        }
+        if let Some(ref outer) = *self.child(!side) {
+            outer.borrow_mut().parent = subtree.borrow().parent.clone();
+        }
+        // the parent of `subtree` is now the node holding `self`
+        if let Some(ref anchor) = self.anchor {
+            *anchor.borrow_mut() = subtree.borrow().parent.clone();
+        }
+        if let Some(ref anchor) = subtree.borrow().anchor {
+            *anchor.borrow_mut() = Rc::downgrade(&subtree);
+        }
+
+        *self.child_mut(side) = Some(subtree);
+        self.update_size();
+    }
+
+}
+
+/// Returns the balance factors of a node `x` and of its child `z` after rotating `x` towards
+/// `side`, which brings `z` up with `x` as child, given their balance factors before.
+fn rotated_balances(x: i8, z: i8, side: Side) -> (i8, i8) {
+    match side {
+        Side::Left => {
+            let x = x - 1 - max(z, 0);
+            (x, z - 1 + min(x, 0))
+        }
+        Side::Right => {
+            let x = x + 1 - min(z, 0);
+            (x, z + 1 + max(x, 0))
+        }
+    }
+}
+
+/// Retraces from `r_node`, whose subtree on `side` just grew (`delta` = 1) or shrank
+/// (`delta` = -1) by one level, up to the root: refreshes balance factors as long as heights
+/// keep changing and sizes all the way, rotating wherever a balance factor has reached +2 or
+/// -2. Returns by how much the height of the whole tree changed.
+///
+/// `height` is the height of the subtree on `side` after the change, which tells the heights
+/// of the subtrees further up as they are retraced.
+fn rebalance<T: Ord>(r_node: Option<Rc<RefCell<AVLNode<T>>>>, side: Side, delta: i8, height: usize) -> i8 {
+    rebalance_following(r_node, side, delta, height, &mut None, &Counters::default(), &mut quiet)
+}
+
+/// Same as `rebalance`, additionally keeping `tracked` on the node holding the element it held
+/// before, as rotations move elements between nodes, reporting the work to `counters` and
+/// narrating it to `step`.
+fn rebalance_following<T: Ord>(r_node: Link<T>, side: Side, delta: i8, height: usize, tracked: &mut Link<T>, counters: &Counters, step: &mut dyn FnMut(Step<'_, T>)) -> i8 {
+
+    let mut next = r_node;
+    let (mut side, mut delta, mut height) = (side, delta, height);
+
+    while let Some(node_ref) = next {
+        let mut n = node_ref.borrow_mut();
+        n.update_size();
+        if delta != 0 {
+            counters.retrace_step();
+            // the height of a node is the one of its `side` subtree, plus one, plus whatever
+            // the other subtree is taller by
+            let towards = |b: i8| if side == Side::Right { b } else { -b };
+            let old = n.balance;
+            let b = old + towards(delta);
+            delta += max(0, -towards(b)) - max(0, -towards(old));
+            n.balance = b;
+            step(Step::Balance { at: &n.value, balance: b });
+            // the other subtree is as tall as the `side` one, less how far the node leans to it
+            height = 1 + max(height, (height as isize - towards(b) as isize) as usize);
+            if b.abs() > 1 {
+                // rotate away from the tall side
+                let side = if b > 1 { Side::Left } else { Side::Right };
+                let z = Rc::clone(n.child(!side).as_ref().unwrap());
+                let zb = z.borrow().balance_factor();
+                let double = (b > 1 && zb < 0) || (b < -1 && zb > 0);
+                counters.rotation(double);
+                if double { // right-left or left-right case
+                    let y = Rc::clone(z.borrow().child(side).as_ref().unwrap());
+                    step(Step::Rotation { at: &z.borrow().value, direction: (!side).into() });
+                    z.borrow_mut().rotate(!side);
+                    follow_swap(tracked, &z, &y);
+                }
+                step(Step::Rotation { at: &n.value, direction: side.into() });
+                n.rotate(side);
+                follow_swap(tracked, &node_ref, &z);
+                // only a single rotation over a balanced child keeps the height
+                if double || zb != 0 {
+                    delta -= 1;
+                    height -= 1;
+                }
+            }
+            if delta == 0 {
+                step(Step::Settled { at: &n.value });
+            }
+            else {
+                step(Step::Height { at: &n.value, height });
+            }
+        }
+        side = if n.is_left_child() { Side::Left } else { Side::Right };
+        next = n.parent.upgrade();
+    }
+    delta
+}
+
+/// Moves `tracked` over to the other node if it points at either of two nodes whose contents
+/// were just swapped by a rotation.
+fn follow_swap<T: Ord>(tracked: &mut Link<T>, a: &NodeRef<T>, b: &NodeRef<T>) {
+    if let Some(t) = tracked.as_ref() {
+        if Rc::ptr_eq(t, a) {
+            *tracked = Some(Rc::clone(b));
+        }
+        else if Rc::ptr_eq(t, b) {
+            *tracked = Some(Rc::clone(a));
+        }
+    }
+}
+
+/// Computes the height of a subtree in O(log n), by walking down along its taller children.
+fn height_of<T: Ord>(node: &Link<T>) -> usize {
+    let mut next = node.clone();
+    let mut height = 0;
+    while let Some(n) = next {
+        height += 1;
+        let n = n.borrow();
+        next = n.child(if n.balance < 0 { Side::Left } else { Side::Right }).clone();
+    }
+    height
+}
+
+/// Height of a subtree of `len` elements as built by `build_balanced`.
+fn balanced_height(len: usize) -> usize {
+    (usize::BITS - len.leading_zeros()) as usize
+}
+
+/// Cuts `node` loose from its parent and children, returning the children as standalone subtrees.
+fn detach<T: Ord>(node: &Rc<RefCell<AVLNode<T>>>) -> (Link<T>, Link<T>) {
+    let mut n = node.borrow_mut();
+    n.parent = Weak::new();
+    let (left, right) = (n.left.take(), n.right.take());
+    for child in [&left, &right].into_iter().flatten() {
+        child.borrow_mut().parent = Weak::new();
+    }
+    n.balance = 0;
+    n.update_size();
+    (left, right)
+}
+
+/// Joins two standalone subtrees and a detached node `mid` whose value sits between the two,
+/// returning the root of the resulting subtree.
+///
+/// Costs O(log n), to find out the heights of the subtrees.
+fn join<T: Ord>(left: Link<T>, mid: Rc<RefCell<AVLNode<T>>>, right: Link<T>) -> Rc<RefCell<AVLNode<T>>> {
+    let (hl, hr) = (height_of(&left), height_of(&right));
+    join_with_heights((left, hl), mid, (right, hr)).0
+}
+
+/// Same as `join`, for subtrees of known heights. Returns the height of the result along with
+/// its root.
+///
+/// Costs O(|height(left) - height(right)|).
+fn join_with_heights<T: Ord>(left: (Link<T>, usize), mid: NodeRef<T>, right: (Link<T>, usize)) -> (NodeRef<T>, usize) {
+    let ((left, hl), (right, hr)) = (left, right);
+    if hl > hr + 1 {
+        join_along_spine((left.unwrap(), hl), mid, (right, hr), Side::Right)
+    }
+    else if hr > hl + 1 {
+        join_along_spine((right.unwrap(), hr), mid, (left, hl), Side::Left)
+    }
+    else {
+        for child in [&left, &right].into_iter().flatten() {
+            child.borrow_mut().parent = Rc::downgrade(&mid);
+        }
+        let mut m = mid.borrow_mut();
+        m.left = left;
+        m.right = right;
+        m.balance = hr as i8 - hl as i8;
+        m.update_size();
+        drop(m);
+        (mid, 1 + max(hl, hr))
+    }
+}
+
+/// Walks down the `side` spine of the taller subtree until reaching a node about as tall as
+/// `short`, and puts `mid` in its place with that node and `short` as children.
+fn join_along_spine<T: Ord>(tall: (NodeRef<T>, usize), mid: NodeRef<T>, short: (Link<T>, usize), side: Side) -> (NodeRef<T>, usize) {
+    let ((tall, tall_height), (short, h)) = (tall, short);
+    let mut parent = Rc::clone(&tall);
+    let mut c = parent.borrow().child(side).clone();
+    let mut hc = parent.borrow().child_height(side, tall_height);
+    while hc > h + 1 {
+        let next = c.unwrap();
+        c = next.borrow().child(side).clone();
+        hc = next.borrow().child_height(side, hc);
+        parent = next;
+    }
+
+    for child in [&c, &short].into_iter().flatten() {
+        child.borrow_mut().parent = Rc::downgrade(&mid);
+    }
+    let mut m = mid.borrow_mut();
+    *m.child_mut(!side) = c;
+    *m.child_mut(side) = short;
+    m.parent = Rc::downgrade(&parent);
+    m.balance = if side == Side::Right { h as i8 - hc as i8 } else { hc as i8 - h as i8 };
+    m.update_size();
+    drop(m);
+    // `mid` is one level taller than the subtree it replaces
+    *parent.borrow_mut().child_mut(side) = Some(mid);
+    let grown = rebalance(Some(parent), side, 1, 1 + max(h, hc));
+    (tall, (tall_height as isize + grown as isize) as usize)
+}
+
+/// Splits a standalone subtree into the elements for which `goes_right` returns `false` and the
+/// ones for which it returns `true`. `goes_right` has to be monotone over the sorted elements.
+///
+/// Costs O(log n), the joins on the way back up telescope.
+fn split<T: Ord, F: Fn(&T) -> bool>(root: Link<T>, goes_right: &F) -> (Link<T>, Link<T>) {
+    let height = height_of(&root);
+    let ((left, _), (right, _)) = split_with_height((root, height), goes_right);
+    (left, right)
+}
+
+/// Same as `split`, for a subtree of known height. Returns the heights of both parts along
+/// with them.
+fn split_with_height<T: Ord, F: Fn(&T) -> bool>(root: (Link<T>, usize), goes_right: &F) -> ((Link<T>, usize), (Link<T>, usize)) {
+    let (node, height) = match root {
+        (None, _) => return ((None, 0), (None, 0)),
+        (Some(node), height) => (node, height),
+    };
+    let heights = {
+        let n = node.borrow();
+        (n.child_height(Side::Left, height), n.child_height(Side::Right, height))
+    };
+    let (left, right) = detach(&node);
+    let (left, right) = ((left, heights.0), (right, heights.1));
+    if goes_right(&node.borrow().value) {
+        let (ll, lr) = split_with_height(left, goes_right);
+        let (joined, h) = join_with_heights(lr, node, right);
+        (ll, (Some(joined), h))
+    }
+    else {
+        let (rl, rr) = split_with_height(right, goes_right);
+        let (joined, h) = join_with_heights(left, node, rl);
+        ((Some(joined), h), rr)
+    }
+}
+
+/// Merges two standalone subtrees into one. Elements of `b` equal to an element of `a` are
+/// dropped.
+///
+/// The root of `a` splits `b` in two and both halves are merged recursively, which costs
+/// O(m log(n/m + 1)) for subtrees of sizes m <= n.
+fn union<T: Ord>(a: Link<T>, b: Link<T>) -> Link<T> {
+    let pivot = match a {
+        None => return b,
+        Some(pivot) => pivot,
+    };
+    if b.is_none() {
+        return Some(pivot);
+    }
+    let (la, ra) = detach(&pivot);
+    let (lb, rb) = {
+        let p = pivot.borrow();
+        let (lb, rest) = split(b, &|v: &T| *v >= p.value);
+        let (_duplicate, rb) = split(rest, &|v: &T| *v > p.value);
+        (lb, rb)
+    };
+    Some(join(union(la, lb), pivot, union(ra, rb)))
+}
+
+/// Removes from a standalone subtree the elements equal to one of `keys`, which must be sorted.
+/// Returns what is left of the subtree and how many elements were removed.
+///
+/// The middle key splits the subtree in two, the keys on each side are removed from the
+/// matching half recursively and the halves are joined back, which costs O(k log(n/k + 1))
+/// for k keys.
+fn subtract<T: Ord + borrow::Borrow<Q>, Q: Ord>(root: Link<T>, keys: &[&Q]) -> (Link<T>, usize) {
+    if root.is_none() || keys.is_empty() {
+        return (root, 0);
+    }
+    let mid = keys.len() / 2;
+    let key = keys[mid];
+    let (low, rest) = split(root, &|v: &T| v.borrow() >= key);
+    let (found, high) = split(rest, &|v: &T| v.borrow() > key);
+    let (low, removed_low) = subtract(low, &keys[..mid]);
+    let (high, removed_high) = subtract(high, &keys[mid + 1..]);
+    (concat(low, high), removed_low + found.is_some() as usize + removed_high)
+}
+
+/// Joins two standalone subtrees, all elements of `low` being smaller than those of `high`.
+fn concat<T: Ord>(low: Link<T>, high: Link<T>) -> Link<T> {
+    if low.is_none() {
+        return high;
+    }
+    let mut high = AVLTree::from_root(high);
+    if high.is_empty() {
+        return low;
+    }
+    let mid = high.pop_node(Side::Left);
+    Some(join(low, mid, high.root.take()))
+}
+
+/// Builds a perfectly balanced subtree out of the next `len` elements of `values`, which must
+/// come in increasing order.
+fn build_balanced<T: Ord, I: Iterator<Item = T>>(values: &mut I, len: usize) -> Option<Rc<RefCell<AVLNode<T>>>> {
+    if len == 0 {
+        return None;
+    }
+    let left = build_balanced(values, len / 2);
+    let value = values.next().expect("iterator shorter than announced");
+    let right = build_balanced(values, len - len / 2 - 1);
+
+    let node = Rc::new(RefCell::new(AVLNode::new(value, &None)));
+    let mut n = node.borrow_mut();
+    for child in [&left, &right].into_iter().flatten() {
+        child.borrow_mut().parent = Rc::downgrade(&node);
+    }
+    n.left = left;
+    n.right = right;
+    n.balance = balanced_height(len - len / 2 - 1) as i8 - balanced_height(len / 2) as i8;
+    n.update_size();
+    drop(n);
+    Some(node)
+}
+
+/// Links `nodes`, detached and in increasing order, into a perfectly balanced subtree of the
+/// same shape as `build_balanced` gives.
+fn link_balanced<T: Ord>(nodes: &[NodeRef<T>]) -> Link<T> {
+    if nodes.is_empty() {
+        return None;
+    }
+    let mid = nodes.len() / 2;
+    let left = link_balanced(&nodes[..mid]);
+    let right = link_balanced(&nodes[mid + 1..]);
+
+    let node = &nodes[mid];
+    let mut n = node.borrow_mut();
+    for child in [&left, &right].into_iter().flatten() {
+        child.borrow_mut().parent = Rc::downgrade(node);
+    }
+    n.left = left;
+    n.right = right;
+    n.balance = balanced_height(nodes.len() - mid - 1) as i8 - balanced_height(mid) as i8;
+    n.update_size();
+    drop(n);
+    Some(Rc::clone(node))
+}
+
+/// Borrows a node for as long as the tree owning it is borrowed.
+///
+/// Nodes are only ever mutably borrowed by methods taking `&mut AVLTree`, so `node` has to be
+/// reached from a shared borrow of its tree for the returned reference to be sound.
+fn peek<T: Ord>(node: &Rc<RefCell<AVLNode<T>>>) -> &AVLNode<T> {
+    // SAFETY: see above, no `RefMut` to the node can be created while the tree is shared.
+    unsafe { node.try_borrow_unguarded() }.expect("node is mutably borrowed")
+}
+
+/// Reads a child link of `node` without borrowing the element, so that it can be used while
+/// references to elements are handed out, mutable ones included.
+fn child_link<T: Ord>(node: &NodeRef<T>, side: Side) -> &Link<T> {
+    // SAFETY: links are only written through `&mut AVLTree`, and no reference to this field
+    // outlives such a write, as for `peek`.
+    unsafe {
+        match side {
+            Side::Left => &(*node.as_ptr()).left,
+            Side::Right => &(*node.as_ptr()).right,
+        }
+    }
+}
+
+/// Returns the parent of `node`, as held by its own parent or by `root`, which must be the
+/// root of the tree owning `node`.
+///
+/// Parent links being weak, the parent is found without touching reference counts by reading
+/// links as `child_link` does.
+fn parent_link<'a, T: Ord>(root: &'a Link<T>, node: &NodeRef<T>) -> Option<&'a NodeRef<T>> {
+    // SAFETY: see `child_link`. A parent link that can't be upgraded is the dangling one of a
+    // root, any other points at a node of the same tree, which outlives `'a`.
+    unsafe {
+        let parent = &(*node.as_ptr()).parent;
+        if parent.strong_count() == 0 {
+            return None;
+        }
+        let grandparent = &(*(*parent.as_ptr()).as_ptr()).parent;
+        if grandparent.strong_count() == 0 {
+            return root.as_ref();
+        }
+        let g = &*(*grandparent.as_ptr()).as_ptr();
+        [&g.left, &g.right].into_iter().flatten().find(|c| Rc::as_ptr(c) == parent.as_ptr())
+    }
+}
+
+/// Follows `side` children from `node` as far as they go.
+fn outermost<T: Ord>(node: &Rc<RefCell<AVLNode<T>>>, side: Side) -> &Rc<RefCell<AVLNode<T>>> {
+    let mut node = node;
+    while let Some(next) = child_link(node, side) {
+        node = next;
+    }
+    node
+}
+
+/// Returns the in-order neighbour of `node` on `side`, i.e. its successor for `Side::Right`
+/// and its predecessor for `Side::Left`. `root` is the root of the tree owning `node`.
+fn neighbour<'a, T: Ord>(root: &'a Link<T>, node: &'a NodeRef<T>, side: Side) -> Option<&'a NodeRef<T>> {
+    if let Some(child) = child_link(node, side) {
+        return Some(outermost(child, !side));
+    }
+    // climb until coming up from the other side
+    let mut node = node;
+    while let Some(p) = parent_link(root, node) {
+        if child_link(p, !side).as_ref().is_some_and(|c| Rc::ptr_eq(c, node)) {
+            return Some(p);
+        }
+        node = p;
+    }
+    None
+}
+
+/// Mutably borrows the element held by a node, for an arbitrary lifetime.
+///
+/// Only the element is borrowed, links to other nodes can still be read with `child_link` and
+/// `parent_link` meanwhile.
+///
+/// # Safety
+///
+/// The caller must hold a mutable borrow of the tree owning `node` for all of `'a`, and must
+/// not create any other reference to the element during that time.
+unsafe fn value_mut<'a, T: Ord>(node: &NodeRef<T>) -> &'a mut T {
+    &mut (*node.as_ptr()).value
+}
+
+/// Turns a pair of bounds into tests telling whether a value lies before the start bound and
+/// after the end bound, respectively.
+fn out_of_bounds<'a, Q: ?Sized + Ord>(start: Bound<&'a Q>, end: Bound<&'a Q>) -> (impl Fn(&Q) -> bool + 'a, impl Fn(&Q) -> bool + 'a) {
+    let below = move |v: &Q| match start {
+        Bound::Included(s) => v < s,
+        Bound::Excluded(s) => v <= s,
+        Bound::Unbounded => false,
+    };
+    let above = move |v: &Q| match end {
+        Bound::Included(e) => v > e,
+        Bound::Excluded(e) => v >= e,
+        Bound::Unbounded => false,
+    };
+    (below, above)
+}
+
+impl Not for Side {
+    type Output = Side;
+
+    fn not(self) -> Self::Output {
+        match self {
+            Side::Left => Side::Right,
+            Side::Right => Side::Left,
+        }
+    }
+}
+
+
+impl<T: Ord> AVLTree<T> {
+
+    /// Creates an empty tree.
+    pub fn new() -> Self {
+        AVLTree { root: None, generation: 0, counters: Counters::default(), hook: Hook::default(), shadow: RefCell::new(None) }
+    }
+
+    fn from_root(root: Link<T>) -> Self {
+        let mut tree = AVLTree { root, generation: 0, counters: Counters::default(), hook: Hook::default(), shadow: RefCell::new(None) };
+        tree.touch();
+        tree
+    }
+
+    /// Returns the generation of the tree, a number which changes every time the tree is
+    /// modified, and that no other tree ever has, except empty ones.
+    ///
+    /// Borrowed iterators and cursors can't see the tree change under them, the borrow checker
+    /// sees to it. Positions kept apart from the tree, such as ranks, can be checked against
+    /// the generation they were taken at to tell whether they still hold.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Moves the tree to a new generation, as every change to its structure has to.
+    fn touch(&mut self) {
+        self.generation = GENERATIONS.fetch_add(1, atomic::Ordering::Relaxed);
+    }
+
+    /// Moves the tree to a new generation like `touch`, for a change of one element that `edit`
+    /// makes to the copy kept for snapshots too. The copy is dropped if it was behind, if the
+    /// last snapshot taken of it is gone, or if `edit` fails.
+    fn touch_shadowed(&mut self, edit: impl FnOnce(&mut Shadow<T>) -> bool) {
+        let before = self.generation;
+        self.touch();
+        let shadow = self.shadow.get_mut();
+        let kept = shadow.as_mut().is_some_and(|s| s.generation == before && s.is_shared() && edit(s));
+        match shadow {
+            Some(s) if kept => s.generation = self.generation,
+            _ => *shadow = None,
+        }
+    }
+
+    /// Builds a tree out of elements coming in sorted order. Of several equal elements in a
+    /// row, the first one is kept, as `insert` would.
+    ///
+    /// The tree is built perfectly balanced in O(n), comparing each element to the previous
+    /// one only, with no search and no rotation.
+    ///
+    /// # Panics
+    ///
+    /// Panics if an element is smaller than the one before it.
+    pub fn from_sorted_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let iter = iter.into_iter();
+        let mut values: Vec<T> = Vec::with_capacity(iter.size_hint().0);
+        for value in iter {
+            match values.last().map(|last| value.cmp(last)) {
+                Some(Ordering::Less) => panic!("from_sorted_iter: elements out of order"),
+                Some(Ordering::Equal) => {}
+                _ => values.push(value),
+            }
+        }
+        AVLTree::from_sorted_vec(values)
+    }
+
+    /// Builds a tree out of elements in any order, settling clashes between equal elements
+    /// according to `policy` as if they were inserted one by one with `insert_with_policy`:
+    /// `Reject` keeps the first of the equal elements, `Replace` the last, and `KeepBoth` all
+    /// of them in their original order.
+    ///
+    /// The elements are sorted, then built into a balanced tree in one go, which takes
+    /// O(n log n) with no rotation.
+    pub fn from_vec_with_policy(mut values: Vec<T>, policy: DuplicatePolicy) -> Self {
+        // the sort is stable, so equal elements stay in their original order
+        values.sort();
+        match policy {
+            DuplicatePolicy::Reject => values.dedup(),
+            DuplicatePolicy::Replace => values.dedup_by(|next, kept| {
+                let equal = next == kept;
+                if equal {
+                    mem::swap(next, kept);
+                }
+                equal
+            }),
+            DuplicatePolicy::KeepBoth => {}
+        }
+        AVLTree::from_sorted_vec(values)
+    }
+
+    /// Builds a balanced tree out of elements in increasing order, equal ones being allowed
+    /// only for `DuplicatePolicy::KeepBoth`.
+    fn from_sorted_vec(values: Vec<T>) -> Self {
+        let len = values.len();
+        AVLTree::from_root(build_balanced(&mut values.into_iter(), len))
+    }
+
+    /// Returns the number of elements in the tree.
+    pub fn len(&self) -> usize {
+        self.root.as_ref().map_or(0, |n| n.borrow().size)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+
+    /// Prints the shape of the tree to the standard output, as drawn by the `Display` impl.
+    pub fn print_tree(&self) where T: fmt::Display {
+        print!("{}", self);
+    }
+
+    /// Returns an iterator over the elements in sorted order.
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter { nodes: self.nodes() }
+    }
+
+    /// Returns an iterator over the elements in sorted order which keeps a stack of the nodes
+    /// still to visit instead of following parent links.
+    ///
+    /// It allocates up to the height of the tree at each end, but only ever reads child links,
+    /// as an iterator over a tree without parent links would.
+    pub fn stack_iter(&self) -> StackIter<'_, T> {
+        let mut iter = StackIter { front: Vec::new(), back: Vec::new(), remaining: self.len() };
+        iter.descend(self.root.as_ref(), Side::Left);
+        iter.descend(self.root.as_ref(), Side::Right);
+        iter
+    }
+
+    /// Returns an iterator over the elements not smaller than `start`, in sorted order.
+    ///
+    /// Seeking to the first of them takes O(log n), so reading a page of k elements following
+    /// the last one of the previous page takes O(log n + k), rather than the length of the pages
+    /// before.
+    pub fn iter_from<Q: ?Sized + Ord>(&self, start: &Q) -> Iter<'_, T> where T: borrow::Borrow<Q> {
+        Iter { nodes: self.range_nodes_by(|v| v.borrow() < start, |_| false) }
+    }
+
+    /// Returns `true` if the tree contains `value`.
+    ///
+    /// `value` may be any borrowed form of the element type, as long as the ordering of the
+    /// borrowed form matches the one of the element type.
+    pub fn contains<Q: ?Sized + Ord>(&self, value: &Q) -> bool where T: borrow::Borrow<Q> {
+        self.find(value).is_some()
+    }
+
+    fn find<Q: ?Sized + Ord>(&self, value: &Q) -> Option<&Rc<RefCell<AVLNode<T>>>> where T: borrow::Borrow<Q> {
+        self.find_by(|v| value.cmp(borrow::Borrow::borrow(v)))
+    }
+
+    /// Looks up a node using `f`, which tells how the element searched for compares to
+    /// the element passed in.
+    fn find_by<F: FnMut(&T) -> Ordering>(&self, mut f: F) -> Option<&Rc<RefCell<AVLNode<T>>>> {
+        let mut next = self.root.as_ref();
+        while let Some(node) = next {
+            let n = peek(node);
+            self.counters.comparison();
+            match f(&n.value) {
+                Ordering::Equal => return Some(node),
+                Ordering::Less => next = n.left.as_ref(),
+                Ordering::Greater => next = n.right.as_ref(),
+            }
+        }
+        None
+    }
+
+    /// Adds `value` to the tree. Returns `false` if an equal element was already present, in
+    /// which case the tree is left untouched.
+    pub fn insert(&mut self, value: T) -> bool {
+        self.try_insert(value).is_ok()
+    }
+
+    /// Adds `value` to the tree, or gives it back inside the error if an equal element is
+    /// already present.
+    pub fn try_insert(&mut self, value: T) -> Result<(), OccupiedError<T>> {
+        match self.locate(|v| value.cmp(v)) {
+            Ok(_) => Err(OccupiedError { value }),
+            Err((parent, side)) => {
+                self.insert_at(parent, side, value);
+                Ok(())
+            }
+        }
+    }
+
+    /// Searches like `find_by`. When nothing matches, returns where a matching element would
+    /// have to be attached instead: a parent node (`None` for an empty tree) and a side.
+    fn locate<F: FnMut(&T) -> Ordering>(&self, mut f: F) -> Result<NodeRef<T>, (Link<T>, Side)> {
+        let mut next = self.root.clone();
+        let mut parent = None;
+        let mut side = Side::Left;
+        while let Some(node) = next {
+            let n = node.borrow();
+            self.counters.comparison();
+            side = match f(&n.value) {
+                Ordering::Equal => {
+                    drop(n);
+                    return Ok(node);
+                }
+                Ordering::Less => Side::Left,
+                Ordering::Greater => Side::Right,
+            };
+            next = n.child(side).clone();
+            drop(n);
+            parent = Some(node);
+        }
+        Err((parent, side))
+    }
+
+    /// Attaches `value` in a new leaf on the `side` of `parent` (which must be free), as found
+    /// by `locate`, and rebalances. Returns the node holding `value` afterwards.
+    fn insert_at(&mut self, parent: Link<T>, side: Side, value: T) -> NodeRef<T> {
+        self.narrated(&mut quiet, |tree, step| tree.insert_at_narrated(parent, side, value, step))
+    }
+
+    /// Runs `f` with a narration going to `step` and to the event sink of the tree.
+    fn narrated<R>(&mut self, step: &mut dyn FnMut(Step<'_, T>), f: impl FnOnce(&mut Self, &mut dyn FnMut(Step<'_, T>)) -> R) -> R {
+        let mut hook = mem::take(&mut self.hook);
+        let result = f(self, &mut |s| {
+            hook.step(s);
+            step(s);
+        });
+        self.hook = hook;
+        result
+    }
+
+    /// Same as `insert_at`, narrating each step to `step`.
+    fn insert_at_narrated(&mut self, parent: Link<T>, side: Side, value: T, step: &mut dyn FnMut(Step<'_, T>)) -> NodeRef<T> {
+        step(Step::Attached { value: &value, parent: parent.as_ref().map(|p| &peek(p).value), side: side.into() });
+        let mut tracked = Some(self.attach(parent.clone(), side, value));
+        rebalance_following(parent, side, 1, 1, &mut tracked, &self.counters, step);
+        self.validate();
+        tracked.unwrap()
+    }
+
+    /// Attaches `value` in a new leaf like `insert_at`, leaving the rebalancing to the caller.
+    fn attach(&mut self, parent: Link<T>, side: Side, value: T) -> NodeRef<T> {
+        self.touch_shadowed(|shadow| shadow.insert(&value));
+        let new_node = Rc::new(RefCell::new(AVLNode::new(value, &parent)));
+        match parent {
+            None => self.root = Some(Rc::clone(&new_node)),
+            Some(ref p) => *p.borrow_mut().child_mut(side) = Some(Rc::clone(&new_node)),
+        }
+        new_node
+    }
+
+    /// Removes `value` from the tree. Returns `false` if it wasn't present.
+    pub fn remove<Q: ?Sized + Ord>(&mut self, value: &Q) -> bool where T: borrow::Borrow<Q> {
+        self.take(value).is_some()
+    }
+
+    /// Removes the element equal to `value` and returns it, or `None` if there was none.
+    pub fn take<Q: ?Sized + Ord>(&mut self, value: &Q) -> Option<T> where T: borrow::Borrow<Q> {
+        let node = Rc::clone(self.find(value)?);
+        Some(self.remove_node(node))
+    }
+
+    /// Adds `value` to the tree, settling a clash with an equal element according to `policy`.
+    /// Returns the element left out of the tree, if any: the rejected `value` or the replaced
+    /// element.
+    pub fn insert_with_policy(&mut self, value: T, policy: DuplicatePolicy) -> Option<T> {
+        match policy {
+            DuplicatePolicy::Reject => self.try_insert(value).err().map(|e| e.value),
+            DuplicatePolicy::Replace => self.replace(value),
+            DuplicatePolicy::KeepBoth => {
+                // never reporting a match lands after the equal elements
+                let Err((parent, side)) = self.locate(|v| match value.cmp(v) {
+                    Ordering::Less => Ordering::Less,
+                    _ => Ordering::Greater,
+                })
+                else {
+                    unreachable!()
+                };
+                self.insert_at(parent, side, value);
+                None
+            }
+        }
+    }
+
+    /// Adds `value` to the tree, replacing the equal element if there is one. Returns the
+    /// element that got replaced.
+    pub fn replace(&mut self, value: T) -> Option<T> {
+        match self.find(&value) {
+            Some(node) => {
+                let node = Rc::clone(node);
+                self.touch_shadowed(|shadow| shadow.remove(&node.borrow().value) && shadow.insert(&value));
+                let old = mem::replace(&mut node.borrow_mut().value, value);
+                Some(old)
+            }
+            None => {
+                self.insert(value);
+                None
+            }
+        }
+    }
+
+    /// Removes the element held by `node` from the tree and returns it.
+    fn remove_node(&mut self, node: Rc<RefCell<AVLNode<T>>>) -> T {
+        self.narrated(&mut quiet, |tree, step| tree.remove_node_narrated(node, step))
+    }
+
+    /// Same as `remove_node`, narrating each step to `step`.
+    fn remove_node_narrated(&mut self, node: NodeRef<T>, step: &mut dyn FnMut(Step<'_, T>)) -> T {
+        let mut n = node.borrow_mut();
+        if n.left.is_some() && n.right.is_some() { // n has two children. we need replacement.
+            let r = n.replacement().unwrap();
+            let mut rn = r.borrow_mut();
+            step(Step::Swapped { value: &n.value, with: &rn.value });
+            mem::swap(&mut n.value, &mut rn.value);
+            mem::swap(&mut n.anchor, &mut rn.anchor);
+            if let Some(ref anchor) = n.anchor {
+                *anchor.borrow_mut() = Rc::downgrade(&node);
+            }
+            if let Some(ref anchor) = rn.anchor {
+                *anchor.borrow_mut() = Rc::downgrade(&r);
+            }
+            drop(rn);
+            drop(n);
+            self.unlink(r, step)
+        }
+        else {
+            drop(n);
+            self.unlink(node, step)
+        }
+    }
+
+    /// Detaches a node having at most one child, putting the child in its place, and narrates
+    /// each step to `step`.
+    fn unlink(&mut self, node: Rc<RefCell<AVLNode<T>>>, step: &mut dyn FnMut(Step<'_, T>)) -> T {
+        let node = self.cut_narrated(node, step);
+        match Rc::try_unwrap(node) {
+            Ok(cell) => cell.into_inner().value,
+            Err(_) => panic!("unlinked node is still referenced"),
+        }
+    }
+
+    /// Same as `unlink`, but gives the node back instead of its element.
+    fn cut(&mut self, node: NodeRef<T>) -> NodeRef<T> {
+        self.narrated(&mut quiet, |tree, step| tree.cut_narrated(node, step))
+    }
+
+    /// Same as `cut`, narrating each step to `step`.
+    fn cut_narrated(&mut self, node: NodeRef<T>, step: &mut dyn FnMut(Step<'_, T>)) -> NodeRef<T> {
+        self.touch_shadowed(|shadow| shadow.remove(&node.borrow().value));
+        let was_left_child = node.borrow().is_left_child();
+        let (parent, child) = {
+            let mut n = node.borrow_mut();
+            let child = n.left.take().or_else(|| n.right.take());
+            step(Step::Removed { value: &n.value, child: child.as_ref().map(|c| &peek(c).value) });
+            (mem::take(&mut n.parent), child)
+        };
+        // the child, if any, can only be a leaf
+        let height = child.is_some() as usize;
+        if let Some(ref c) = child {
+            c.borrow_mut().parent = parent.clone();
+        }
+        let parent = parent.upgrade();
+        let side = if was_left_child { Side::Left } else { Side::Right };
+        match parent {
+            None => self.root = child,
+            Some(ref p) => *p.borrow_mut().child_mut(side) = child,
+        }
+        rebalance_following(parent, side, -1, height, &mut None, &self.counters, step);
+        self.validate();
+        node
+    }
+
+    /// Returns the element at position `rank` in sorted order (starting from 0), or `None` if
+    /// `rank` is out of bounds.
+    pub fn get_by_rank(&self, rank: usize) -> Option<&T> {
+        self.node_by_rank(rank).map(|node| &peek(node).value)
+    }
+
+    fn node_by_rank(&self, rank: usize) -> Option<&Rc<RefCell<AVLNode<T>>>> {
+        let mut next = self.root.as_ref();
+        let mut rank = rank;
+        while let Some(node) = next {
+            let n = peek(node);
+            let left = n.size(Side::Left);
+            match rank.cmp(&left) {
+                Ordering::Less => next = n.left.as_ref(),
+                Ordering::Equal => return Some(node),
+                Ordering::Greater => {
+                    rank -= left + 1;
+                    next = n.right.as_ref();
+                }
+            }
+        }
+        None
+    }
+
+    /// Counts the elements smaller than `value`, or not greater than it if `inclusive` is set.
+    fn count_below<Q: ?Sized + Ord>(&self, value: &Q, inclusive: bool) -> usize where T: borrow::Borrow<Q> {
+        let mut next = self.root.as_ref();
+        let mut count = 0;
+        while let Some(node) = next {
+            let n = peek(node);
+            self.counters.comparison();
+            let go_right = match value.cmp(borrow::Borrow::borrow(&n.value)) {
+                Ordering::Less => false,
+                Ordering::Equal => inclusive,
+                Ordering::Greater => true,
+            };
+            if go_right {
+                count += n.size(Side::Left) + 1;
+                next = n.right.as_ref();
+            }
+            else {
+                next = n.left.as_ref();
+            }
+        }
+        count
+    }
+
+    /// Returns the number of elements falling in `range`, without visiting them.
+    pub fn count_range<Q: ?Sized + Ord, R: RangeBounds<Q>>(&self, range: R) -> usize where T: borrow::Borrow<Q> {
+        let lower = match range.start_bound() {
+            Bound::Included(v) => self.count_below(v, false),
+            Bound::Excluded(v) => self.count_below(v, true),
+            Bound::Unbounded => 0,
+        };
+        let upper = match range.end_bound() {
+            Bound::Included(v) => self.count_below(v, true),
+            Bound::Excluded(v) => self.count_below(v, false),
+            Bound::Unbounded => self.len(),
+        };
+        upper.saturating_sub(lower)
+    }
+
+    /// Returns the rank of the first element for which `pred` returns `false`, `pred` having to
+    /// return `true` for a prefix of the sorted elements and `false` for the rest, as for
+    /// `slice::partition_point`. The elements of the prefix are counted without visiting them,
+    /// in O(log n).
+    pub fn partition_point<F: FnMut(&T) -> bool>(&self, pred: F) -> usize {
+        self.count_where(pred)
+    }
+
+    /// Searches the tree with a comparator, as `slice::binary_search_by` does: `f` tells how
+    /// each element compares to the one searched for, and has to be consistent with the order
+    /// of the tree.
+    ///
+    /// Returns `Ok` with the rank of an element for which `f` returns `Equal`, or `Err` with the
+    /// rank at which such an element would be inserted.
+    pub fn binary_search_by<F: FnMut(&T) -> Ordering>(&self, mut f: F) -> Result<usize, usize> {
+        let mut next = self.root.as_ref();
+        let mut rank = 0;
+        while let Some(node) = next {
+            let n = peek(node);
+            match f(&n.value) {
+                Ordering::Less => {
+                    rank += n.size(Side::Left) + 1;
+                    next = n.right.as_ref();
+                }
+                Ordering::Equal => return Ok(rank + n.size(Side::Left)),
+                Ordering::Greater => next = n.left.as_ref(),
+            }
+        }
+        Err(rank)
+    }
+
+    /// Returns the number of elements for which `f` returns `true`, where `f` has to return
+    /// `true` for a prefix of the sorted elements and `false` for the rest.
+    fn count_where<F: FnMut(&T) -> bool>(&self, mut f: F) -> usize {
+        let mut next = self.root.as_ref();
+        let mut count = 0;
+        while let Some(node) = next {
+            let n = peek(node);
+            self.counters.comparison();
+            if f(&n.value) {
+                count += n.size(Side::Left) + 1;
+                next = n.right.as_ref();
+            }
+            else {
+                next = n.left.as_ref();
+            }
+        }
+        count
+    }
+
+    /// Returns the node holding the largest element for which `f` returns `true`, with `f`
+    /// partitioning the elements as for `count_where`.
+    fn last_where<F: FnMut(&T) -> bool>(&self, mut f: F) -> Option<&NodeRef<T>> {
+        let mut next = self.root.as_ref();
+        let mut found = None;
+        while let Some(node) = next {
+            let n = peek(node);
+            self.counters.comparison();
+            if f(&n.value) {
+                found = Some(node);
+                next = n.right.as_ref();
+            }
+            else {
+                next = n.left.as_ref();
+            }
+        }
+        found
+    }
+
+    /// Returns the node holding the smallest element for which `f` returns `false`, with `f`
+    /// partitioning the elements as for `count_where`.
+    fn first_where_not<F: FnMut(&T) -> bool>(&self, mut f: F) -> Option<&NodeRef<T>> {
+        let mut next = self.root.as_ref();
+        let mut found = None;
+        while let Some(node) = next {
+            let n = peek(node);
+            self.counters.comparison();
+            if f(&n.value) {
+                next = n.right.as_ref();
+            }
+            else {
+                found = Some(node);
+                next = n.left.as_ref();
+            }
+        }
+        found
+    }
+
+    /// Walks the nodes lying between the prefix for which `below` returns `true` and the
+    /// suffix for which `above` returns `true`.
+    fn range_nodes_by<F, G>(&self, mut below: F, mut above: G) -> Nodes<'_, T>
+    where F: FnMut(&T) -> bool, G: FnMut(&T) -> bool {
+        let skipped = self.count_where(&mut below);
+        let kept = self.count_where(|v| !above(v));
+        Nodes {
+            root: &self.root,
+            front: self.first_where_not(below),
+            back: self.last_where(|v| !above(v)),
+            remaining: kept.saturating_sub(skipped),
+        }
+    }
+
+    /// Keeps only the elements for which `f` returns `true`.
+    ///
+    /// The tree is torn down and rebuilt balanced from the surviving elements, which costs O(n)
+    /// regardless of how many elements are removed.
+    pub fn retain<F: FnMut(&T) -> bool>(&mut self, mut f: F) {
+        let kept: Vec<T> = self.drain().filter(|v| f(v)).collect();
+        let len = kept.len();
+        self.touch();
+        self.root = build_balanced(&mut kept.into_iter(), len);
+        self.validate();
+    }
+
+    /// Relinks the nodes into a perfectly balanced tree, of minimum height.
+    ///
+    /// An AVL tree can be up to about 1.44 times taller than that, and its average depth drifts
+    /// up after skewed removals. Rebuilding takes O(n) and keeps the nodes, and so the handles
+    /// to them, without moving any element.
+    pub fn rebuild(&mut self) {
+        let nodes: Vec<NodeRef<T>> = self.nodes().cloned().collect();
+        self.touch();
+        self.root = None;
+        for node in &nodes {
+            detach(node);
+        }
+        self.root = link_balanced(&nodes);
+        self.validate();
+    }
+
+    /// Removes all elements from the tree, yielding them in sorted order.
+    ///
+    /// The tree is empty as soon as `drain` returns. Nodes are torn down one by one as the
+    /// iterator advances; elements not consumed are dropped together with the iterator.
+    pub fn drain(&mut self) -> Drain<T> {
+        self.touch();
+        Drain::new(self.root.take())
+    }
+
+    /// Consumes the tree, returning its elements in sorted order. Nothing is cloned or compared.
+    pub fn into_sorted_vec(mut self) -> Vec<T> {
+        let mut values = Vec::with_capacity(self.len());
+        values.extend(self.drain());
+        values
+    }
+
+    /// Returns clones of the elements, in sorted order.
+    pub fn to_vec(&self) -> Vec<T> where T: Clone {
+        self.iter().cloned().collect()
+    }
+
+    /// Returns a read-only copy of the set as it is now, which the tree can go on changing
+    /// under, for iterating over a consistent state meanwhile.
+    ///
+    /// The first snapshot clones the elements into a balanced persistent tree, in O(n), which
+    /// the tree keeps. As long as the last snapshot taken is alive, each insertion or removal
+    /// of a single element is then applied to that copy too, cloning the O(log n) nodes on its
+    /// path and sharing the rest with the snapshots already taken, so that the next snapshot
+    /// takes O(1). Once the last snapshot is dropped, the next change drops the copy along.
+    /// Changes of many elements at once, such as `retain` or `append`, leave the copy behind,
+    /// and the next snapshot is built anew. A tree holding equal elements, through
+    /// `DuplicatePolicy::KeepBoth`, builds every snapshot anew.
+    ///
+    /// To iterate over the tree itself while changing it, see [`DetachedIter`].
+    pub fn snapshot(&self) -> Snapshot<T> where T: Clone {
+        let mut shadow = self.shadow.borrow_mut();
+        if let Some(s) = shadow.as_mut().filter(|s| s.generation == self.generation) {
+            return s.snapshot();
+        }
+        let copy = Snapshot::from_sorted(self.iter().cloned(), self.len());
+        if self.iter().zip(self.iter().skip(1)).any(|(a, b)| a == b) {
+            *shadow = None;
+            return copy;
+        }
+        shadow.insert(Shadow::new(copy, self.generation)).snapshot()
+    }
+
+    /// Splits the tree in two at `key`: elements smaller than `key` stay in `self`, the rest is
+    /// returned as a new tree.
+    ///
+    /// Runs in O(log n) by cutting the search path and joining the pieces back together.
+    pub fn split_off<Q: ?Sized + Ord>(&mut self, key: &Q) -> AVLTree<T> where T: borrow::Borrow<Q> {
+        let (left, right) = split(self.root.take(), &|v: &T| borrow::Borrow::borrow(v) >= key);
+        self.touch();
+        self.root = left;
+        self.validate();
+        let high = AVLTree::from_root(right);
+        high.validate();
+        high
+    }
+
+    /// Moves all elements of `other` into `self`, leaving `other` empty. Where both trees hold
+    /// equal elements, the one from `self` is kept.
+    ///
+    /// If all elements of one tree are smaller than those of the other, the trees are joined
+    /// along a spine in O(log n). Otherwise they are merged with repeated splits and joins in
+    /// O(m log(n/m + 1)), m being the size of the smaller tree.
+    pub fn append(&mut self, other: &mut AVLTree<T>) {
+        if other.is_empty() {
+            return;
+        }
+        if self.is_empty() {
+            mem::swap(&mut self.root, &mut other.root);
+            self.touch();
+            other.touch();
+            return;
+        }
+        self.touch();
+        other.touch();
+
+        let below = |low: &AVLTree<T>, high: &AVLTree<T>| {
+            low.extreme(Side::Right).unwrap().borrow().value < high.extreme(Side::Left).unwrap().borrow().value
+        };
+        if below(self, other) {
+            let mid = other.pop_node(Side::Left);
+            self.root = Some(join(self.root.take(), mid, other.root.take()));
+        }
+        else if below(other, self) {
+            let mid = other.pop_node(Side::Right);
+            self.root = Some(join(other.root.take(), mid, self.root.take()));
+        }
+        else {
+            self.root = union(self.root.take(), other.root.take());
+        }
+        self.validate();
+    }
+
+    /// Removes the elements equal to one of `keys`. Returns how many elements were removed.
+    ///
+    /// The tree is cut at each key and the remaining pieces are joined back, in
+    /// O(k log(n/k + 1)) for k keys instead of k separate removals each retracing up to the
+    /// root. `keys` are sorted first unless they already are.
+    pub fn remove_batch<Q: Ord>(&mut self, keys: &[Q]) -> usize where T: borrow::Borrow<Q> {
+        let mut keys: Vec<&Q> = keys.iter().collect();
+        if !keys.is_sorted() {
+            keys.sort();
+        }
+        let (root, removed) = subtract(self.root.take(), &keys);
+        self.touch();
+        self.root = root;
+        self.validate();
+        removed
+    }
+
+    /// Adds all elements of `items`. As with repeated `insert`s, elements equal to one already
+    /// present, or to an earlier one of `items`, are dropped.
+    ///
+    /// The batch is sorted, deduplicated and built into a balanced tree, which is then merged
+    /// in like `append` does. For k new elements, this costs O(k log k + k log(n/k + 1))
+    /// instead of k separate descents and retracings.
+    pub fn insert_batch<I: IntoIterator<Item = T>>(&mut self, items: I) {
+        let mut batch: AVLTree<T> = items.into_iter().collect();
+        self.append(&mut batch);
+    }
+
+    /// Returns the node holding the smallest or largest element.
+    fn extreme(&self, side: Side) -> Option<&Rc<RefCell<AVLNode<T>>>> {
+        self.root.as_ref().map(|root| outermost(root, side))
+    }
+
+    /// Removes the node holding the smallest or largest element and returns it, detached.
+    fn pop_node(&mut self, side: Side) -> Rc<RefCell<AVLNode<T>>> {
+        let node = Rc::clone(self.extreme(side).unwrap());
+        let node = self.cut(node);
+        let mut n = node.borrow_mut();
+        n.balance = 0;
+        n.update_size();
+        drop(n);
+        node
+    }
+
+    /// Returns an iterator removing and yielding, in sorted order, the elements for which `pred`
+    /// returns `true`.
+    ///
+    /// Each removal rebalances the tree on the spot. Elements the iterator doesn't reach because
+    /// it was dropped or not exhausted stay in the tree.
+    pub fn extract_if<F: FnMut(&T) -> bool>(&mut self, pred: F) -> ExtractIf<'_, T, F> {
+        ExtractIf { tree: self, rank: 0, pred }
+    }
+}
+
+/// An iterator removing the elements of an `AVLTree` that match a predicate.
+///
+/// Created by [`AVLTree::extract_if`].
+pub struct ExtractIf<'a, T: Ord, F: FnMut(&T) -> bool> {
+    tree: &'a mut AVLTree<T>,
+    // rank of the next element to test. node contents move around during rotations, ranks don't.
+    rank: usize,
+    pred: F,
+}
+
+impl<T: Ord, F: FnMut(&T) -> bool> Iterator for ExtractIf<'_, T, F> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        while let Some(node) = self.tree.node_by_rank(self.rank) {
+            if (self.pred)(&node.borrow().value) {
+                let node = Rc::clone(node);
+                return Some(self.tree.remove_node(node));
+            }
+            self.rank += 1;
+        }
+        None
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.tree.len() - self.rank))
+    }
+}
+
+/// A draining iterator over the elements of an `AVLTree`, in sorted order.
+///
+/// Created by [`AVLTree::drain`]. Nodes are rotated out of the remaining subtree one by one,
+/// so no stack is allocated.
+pub struct Drain<T: Ord> {
+    next: Option<Rc<RefCell<AVLNode<T>>>>,
+    remaining: usize,
+}
+
+impl<T: Ord> Drain<T> {
+    fn new(root: Option<Rc<RefCell<AVLNode<T>>>>) -> Self {
+        let remaining = root.as_ref().map_or(0, |n| n.borrow().size);
+        Drain { next: root, remaining }
+    }
+}
+
+impl<T: Ord> Iterator for Drain<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        let node = unwind_smallest(self.next.take()?);
+        self.next = node.borrow_mut().right.take();
+        self.remaining -= 1;
+        match Rc::try_unwrap(node) {
+            Ok(cell) => Some(cell.into_inner().value),
+            Err(_) => panic!("detached node is still referenced"),
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<T: Ord> ExactSizeIterator for Drain<T> {}
+
+impl<T: Ord> Drop for Drain<T> {
+    fn drop(&mut self) {
+        // dropping the remaining subtrees as they are would recurse down to their leaves
+        self.for_each(drop);
+    }
+}
+
+impl<T: Ord> Index<usize> for AVLTree<T> {
+    type Output = T;
+
+    /// Returns the element at position `rank` in sorted order.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `rank` is out of bounds.
+    fn index(&self, rank: usize) -> &T {
+        match self.get_by_rank(rank) {
+            Some(value) => value,
+            None => panic!("rank {} out of bounds for tree of length {}", rank, self.len()),
+        }
+    }
+}
+
+/// Copies the tree node by node, keeping its shape. The nodes are visited with an explicit
+/// stack rather than recursively. Handles to elements of the original don't resolve in the
+/// copy.
+impl<T: Ord + Clone> Clone for AVLTree<T> {
+    fn clone(&self) -> Self {
+        let mut copy = AVLTree::new();
+        let mut stack: Vec<(&NodeRef<T>, Link<T>, Side)> = Vec::new();
+        if let Some(root) = &self.root {
+            stack.push((root, None, Side::Left));
+        }
+        while let Some((node, parent, side)) = stack.pop() {
+            let n = peek(node);
+            let mut new = AVLNode::new(n.value.clone(), &parent);
+            new.balance = n.balance;
+            new.size = n.size;
+            let new = Rc::new(RefCell::new(new));
+            match parent {
+                None => copy = AVLTree::from_root(Some(Rc::clone(&new))),
+                Some(p) => *p.borrow_mut().child_mut(side) = Some(Rc::clone(&new)),
+            }
+            for side in [Side::Left, Side::Right] {
+                if let Some(child) = n.child(side) {
+                    stack.push((child, Some(Rc::clone(&new)), side));
+                }
+            }
+        }
+        copy
+    }
+}
+
+/// Trees are equal when they hold equal elements, whatever their shapes.
+impl<T: Ord> PartialEq for AVLTree<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().eq(other.iter())
+    }
+}
+
+impl<T: Ord> Eq for AVLTree<T> {}
+
+/// Trees compare lexicographically by their sorted elements, as `BTreeSet`s do.
+impl<T: Ord> PartialOrd for AVLTree<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T: Ord> Ord for AVLTree<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.iter().cmp(other.iter())
+    }
+}
+
+/// Hashes the number of elements, then the elements in sorted order, so that trees equal by
+/// `PartialEq` hash alike.
+impl<T: Ord + Hash> Hash for AVLTree<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_usize(self.len());
+        for value in self.iter() {
+            value.hash(state);
+        }
+    }
+}
+
+/// Frees the nodes one by one, smallest first, as `Drain` does. Dropping the root as is would
+/// recurse through the nested `Rc`s, one call per level.
+impl<T: Ord> Drop for AVLTree<T> {
+    fn drop(&mut self) {
+        let mut next = self.root.take();
+        while let Some(node) = next {
+            next = unwind_smallest(node).borrow_mut().right.take();
+        }
+    }
+}
+
+/// Rotates left children of `node` up until the top of the subtree has none, and returns that
+/// top, the smallest element with the rest of the subtree as its right child.
+///
+/// Each rotation lengthens the right spine of what is left, so tearing a whole tree down from
+/// its smallest element this way takes O(n) overall, in constant space. Heights, sizes and
+/// parent links are left stale.
+fn unwind_smallest<T: Ord>(mut node: NodeRef<T>) -> NodeRef<T> {
+    loop {
+        let left = node.borrow_mut().left.take();
+        let Some(left) = left else { return node };
+        node.borrow_mut().left = left.borrow_mut().right.take();
+        left.borrow_mut().right = Some(node);
+        node = left;
+    }
+}
+
+impl<T: Ord> Default for AVLTree<T> {
+    fn default() -> Self {
+        AVLTree::new()
+    }
+}
+
+/// Collects the elements, sorts them and builds a balanced tree in one go, which beats
+/// inserting them one by one. Of several equal elements, the first one is kept, as `insert`
+/// would.
+impl<T: Ord> FromIterator<T> for AVLTree<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        AVLTree::from_vec_with_policy(iter.into_iter().collect(), DuplicatePolicy::Reject)
+    }
+}
+
+/// Sorts the elements and builds a balanced tree in one go. Of several equal elements, the
+/// first one is kept, as `insert` would; see [`AVLTree::from_vec_with_policy`] for the other
+/// options.
+impl<T: Ord> From<Vec<T>> for AVLTree<T> {
+    fn from(values: Vec<T>) -> Self {
+        AVLTree::from_vec_with_policy(values, DuplicatePolicy::Reject)
+    }
+}
+
+/// Sorts the elements and builds a balanced tree in one go.
+impl<T: Ord, S> From<HashSet<T, S>> for AVLTree<T> {
+    fn from(set: HashSet<T, S>) -> Self {
+        let mut values: Vec<T> = set.into_iter().collect();
+        // no duplicates to keep in order
+        values.sort_unstable();
+        AVLTree::from_sorted_vec(values)
+    }
+}
+
+/// Builds the tree balanced in O(n), the elements of a `BTreeSet` being sorted already.
+impl<T: Ord> From<BTreeSet<T>> for AVLTree<T> {
+    fn from(set: BTreeSet<T>) -> Self {
+        let len = set.len();
+        AVLTree::from_root(build_balanced(&mut set.into_iter(), len))
+    }
+}
+
+impl<T: Ord> From<AVLTree<T>> for BTreeSet<T> {
+    fn from(tree: AVLTree<T>) -> Self {
+        tree.into_sorted_vec().into_iter().collect()
+    }
+}
+
+impl<T: Ord, const N: usize> From<[T; N]> for AVLTree<T> {
+    fn from(values: [T; N]) -> Self {
+        values.into_iter().collect()
+    }
+}
+
+/// Inserts the elements one by one, skipping those equal to an element already present.
+impl<T: Ord> Extend<T> for AVLTree<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for value in iter {
+            self.insert(value);
+        }
+    }
+}
+
+/// Lists the elements in sorted order, like a set. The alternate flag (`{:#?}`) shows the
+/// nodes instead, nested as in the tree and along with their balance factors and subtree sizes.
+impl<T: Ord + fmt::Debug> fmt::Debug for AVLTree<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if f.alternate() {
+            f.debug_struct("AVLTree").field("root", &self.root.as_ref().map(peek)).finish()
+        }
+        else {
+            f.debug_set().entries(self.iter()).finish()
+        }
+    }
+}
+
+/// Shows a node and, recursively, its subtree. The parent link is left out.
+impl<T: Ord + fmt::Debug> fmt::Debug for AVLNode<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // only reached through `AVLTree`'s impl, so the nodes can be peeked at
+        f.debug_struct("AVLNode")
+            .field("value", &self.value)
+            .field("balance", &self.balance)
+            .field("size", &self.size)
+            .field("left", &self.left.as_ref().map(peek))
+            .field("right", &self.right.as_ref().map(peek))
+            .finish()
+    }
+}
+
+/// Draws the tree sideways, one node per line: the root comes first in its column, the right
+/// subtree above it and the left one below. Each node shows its element, its height and its
+/// balance factor (right height minus left height).
+///
+/// ```text
+/// /-- 3 (h=1, bf=0)
+/// 2 (h=2, bf=0)
+/// \-- 1 (h=1, bf=0)
+/// ```
+impl<T: Ord + fmt::Display> fmt::Display for AVLTree<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.root {
+            None => Ok(()),
+            Some(root) => write_sideways(f, root, height_of(&self.root), "", None),
+        }
+    }
+}
+
+/// Draws the subtree of `node`, of height `height`, for `Display`, each line starting with
+/// `prefix`. `side` tells which child of its parent the node is, `None` for the root.
+fn write_sideways<T: Ord + fmt::Display>(f: &mut fmt::Formatter<'_>, node: &NodeRef<T>, height: usize, prefix: &str, side: Option<Side>) -> fmt::Result {
+    let n = peek(node);
+    // the vertical bar joins a node to its parent from the branch on the far side
+    let child_prefix = |child: Side| match side {
+        Some(s) if s != child => format!("{}|   ", prefix),
+        _ => format!("{}    ", prefix),
+    };
+    if let Some(right) = &n.right {
+        write_sideways(f, right, n.child_height(Side::Right, height), &child_prefix(Side::Right), Some(Side::Right))?;
+    }
+    let branch = match side {
+        None => "",
+        Some(Side::Right) => "/-- ",
+        Some(Side::Left) => "\\-- ",
+    };
+    writeln!(f, "{}{}{} (h={}, bf={})", prefix, branch, n.value, height, n.balance_factor())?;
+    if let Some(left) = &n.left {
+        write_sideways(f, left, n.child_height(Side::Left, height), &child_prefix(Side::Left), Some(Side::Left))?;
+    }
+    Ok(())
+}
+
+/// Walks the nodes of a tree in sorted order, from both ends. Stepping follows parent links,
+/// so no stack is allocated.
+struct Nodes<'a, T: Ord> {
+    root: &'a Link<T>,
+    front: Option<&'a NodeRef<T>>,
+    back: Option<&'a NodeRef<T>>,
+    remaining: usize,
+}
+
+impl<T: Ord> AVLTree<T> {
+    fn nodes(&self) -> Nodes<'_, T> {
+        Nodes {
+            root: &self.root,
+            front: self.extreme(Side::Left),
+            back: self.extreme(Side::Right),
+            remaining: self.len(),
+        }
+    }
+}
+
+impl<'a, T: Ord> Iterator for Nodes<'a, T> {
+    type Item = &'a NodeRef<T>;
+
+    fn next(&mut self) -> Option<&'a NodeRef<T>> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let node = self.front?;
+        self.remaining -= 1;
+        self.front = neighbour(self.root, node, Side::Right);
+        Some(node)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, T: Ord> DoubleEndedIterator for Nodes<'a, T> {
+    fn next_back(&mut self) -> Option<&'a NodeRef<T>> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let node = self.back?;
+        self.remaining -= 1;
+        self.back = neighbour(self.root, node, Side::Left);
+        Some(node)
+    }
+}
+
+impl<T: Ord> Clone for Nodes<'_, T> {
+    fn clone(&self) -> Self {
+        Nodes { root: self.root, front: self.front, back: self.back, remaining: self.remaining }
+    }
+}
+
+/// An iterator over the elements of an `AVLTree`, in sorted order.
+///
+/// Created by [`AVLTree::iter`]. Stepping follows parent links, so no stack is allocated.
+pub struct Iter<'a, T: Ord> {
+    nodes: Nodes<'a, T>,
+}
+
+impl<'a, T: Ord> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        self.nodes.next().map(|node| &peek(node).value)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.nodes.size_hint()
+    }
+}
+
+impl<'a, T: Ord> DoubleEndedIterator for Iter<'a, T> {
+    fn next_back(&mut self) -> Option<&'a T> {
+        self.nodes.next_back().map(|node| &peek(node).value)
+    }
+}
+
+impl<T: Ord> ExactSizeIterator for Iter<'_, T> {}
+
+impl<T: Ord> Clone for Iter<'_, T> {
+    fn clone(&self) -> Self {
+        Iter { nodes: self.nodes.clone() }
+    }
+}
+
+/// An iterator over the elements of an `AVLTree`, in sorted order, which ignores parent links.
+///
+/// Created by [`AVLTree::stack_iter`]. It keeps a stack of the nodes still to visit at each end.
+pub struct StackIter<'a, T: Ord> {
+    front: Vec<&'a NodeRef<T>>,
+    back: Vec<&'a NodeRef<T>>,
+    remaining: usize,
+}
+
+impl<'a, T: Ord> StackIter<'a, T> {
+
+    /// Stacks `node` and its `side` children as far as they go.
+    fn descend(&mut self, mut next: Option<&'a NodeRef<T>>, side: Side) {
+        let stack = match side {
+            Side::Left => &mut self.front,
+            Side::Right => &mut self.back,
+        };
+        while let Some(node) = next {
+            stack.push(node);
+            next = child_link(node, side).as_ref();
+        }
+    }
+
+    fn step(&mut self, side: Side) -> Option<&'a T> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        let stack = match side {
+            Side::Left => &mut self.front,
+            Side::Right => &mut self.back,
+        };
+        let node = stack.pop()?;
+        self.descend(child_link(node, !side).as_ref(), side);
+        Some(&peek(node).value)
+    }
+}
+
+impl<'a, T: Ord> Iterator for StackIter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        self.step(Side::Left)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, T: Ord> DoubleEndedIterator for StackIter<'a, T> {
+    fn next_back(&mut self) -> Option<&'a T> {
+        self.step(Side::Right)
+    }
+}
+
+impl<T: Ord> ExactSizeIterator for StackIter<'_, T> {}
+
+impl<'a, T: Ord> IntoIterator for &'a AVLTree<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::cell::Cell;
+
+    fn tree_of(values: &[i32]) -> AVLTree<i32> {
+        let mut tree = AVLTree::new();
+        for &v in values {
+            tree.insert(v);
+        }
+        tree
+    }
+
+    /// Counts the allocations made by each thread, so that tests running side by side don't
+    /// see each other's.
+    struct CountingAllocator;
+
+    thread_local! {
+        static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    unsafe impl GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+            System.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            System.dealloc(ptr, layout)
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+    fn allocations() -> usize {
+        ALLOCATIONS.with(Cell::get)
+    }
+
+    /// Counts the allocations made through it, and the ones not freed yet, passing them on to
+    /// the global allocator.
+    #[cfg(feature = "allocator_api2")]
+    #[derive(Default)]
+    pub(crate) struct Counting {
+        pub(crate) allocations: Cell<usize>,
+        pub(crate) live: Cell<usize>,
+    }
+
+    #[cfg(feature = "allocator_api2")]
+    unsafe impl allocator_api2::alloc::Allocator for Counting {
+        fn allocate(&self, layout: Layout) -> Result<std::ptr::NonNull<[u8]>, allocator_api2::alloc::AllocError> {
+            self.allocations.set(self.allocations.get() + 1);
+            self.live.set(self.live.get() + 1);
+            allocator_api2::alloc::Global.allocate(layout)
+        }
+
+        unsafe fn deallocate(&self, ptr: std::ptr::NonNull<u8>, layout: Layout) {
+            self.live.set(self.live.get() - 1);
+            allocator_api2::alloc::Global.deallocate(ptr, layout)
+        }
+    }
+
+    #[test]
+    fn test_index_by_rank() {
+        let mut tree = tree_of(&[50, 20, 80, 10, 30, 70, 90, 60, 40, 0]);
+        assert_eq!(tree.len(), 10);
+        for i in 0..10 {
+            assert_eq!(tree[i], i as i32 * 10);
+        }
+        assert_eq!(tree.get_by_rank(10), None);
+
+        assert!(tree.remove(&20));
+        assert!(tree.remove(&50));
+        assert!(!tree.remove(&50));
+        assert_eq!(tree.len(), 8);
+        let ranked: Vec<i32> = (0..tree.len()).map(|i| tree[i]).collect();
+        assert_eq!(ranked, vec![0, 10, 30, 40, 60, 70, 80, 90]);
+    }
+
+    #[test]
+    fn test_insert_keeps_balance() {
+        let tree = tree_of(&(0..1000).collect::<Vec<_>>());
+        assert_eq!(tree.len(), 1000);
+        assert!(height_of(&tree.root) <= 11);
+        assert_eq!(tree[999], 999);
+        assert!(tree.contains(&500));
+        assert!(!tree.contains(&1000));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_index_out_of_bounds() {
+        let tree = tree_of(&[1, 2, 3]);
+        let _ = tree[3];
+    }
+
+    #[test]
+    fn test_count_range() {
+        let tree = tree_of(&(0..100).map(|v| v * 2).collect::<Vec<_>>());
+        assert_eq!(tree.count_range(..), 100);
+        assert_eq!(tree.count_range(10..20), 5);
+        assert_eq!(tree.count_range(10..=20), 6);
+        assert_eq!(tree.count_range(11..=19), 4);
+        assert_eq!(tree.count_range((Bound::Excluded(10), Bound::Included(20))), 5);
+        assert_eq!(tree.count_range(..0), 0);
+        assert_eq!(tree.count_range(150..), 25);
+        assert_eq!(tree.count_range(300..), 0);
+        assert_eq!(tree.count_range((Bound::Included(20), Bound::Excluded(10))), 0);
+    }
+
+    #[test]
+    fn test_partition_point() {
+        let tree = tree_of(&(0..100).map(|v| v * 2).collect::<Vec<_>>());
+        assert_eq!(tree.partition_point(|&v| v < 31), 16);
+        assert_eq!(tree.partition_point(|&v| v < 0), 0);
+        assert_eq!(tree.partition_point(|_| true), 100);
+        assert_eq!(AVLTree::<i32>::new().partition_point(|_| true), 0);
+
+        // searching by a criterion other than the element itself
+        assert_eq!(tree.binary_search_by(|v| (v / 10).cmp(&7)).map(|r| tree[r] / 10), Ok(7));
+        assert_eq!(tree.binary_search_by(|v| v.cmp(&64)), Ok(32));
+        assert_eq!(tree.binary_search_by(|v| v.cmp(&65)), Err(33));
+        assert_eq!(tree.binary_search_by(|v| v.cmp(&-1)), Err(0));
+        assert_eq!(tree.binary_search_by(|v| v.cmp(&500)), Err(100));
+    }
+
+    #[test]
+    fn test_iter_from() {
+        let tree = tree_of(&(0..100).map(|v| v * 2).collect::<Vec<_>>());
+        assert!(tree.iter_from(&31).copied().eq((16..100).map(|v| v * 2)));
+        assert!(tree.iter_from(&32).copied().eq((16..100).map(|v| v * 2)));
+        assert!(tree.iter_from(&-5).eq(tree.iter()));
+        assert_eq!(tree.iter_from(&198).len(), 1);
+        assert_eq!(tree.iter_from(&199).next(), None);
+        assert_eq!(tree.iter_from(&50).next_back(), Some(&198));
+
+        // paging through, each page starting after the last element of the previous one
+        let mut pages = Vec::new();
+        let mut last = None;
+        loop {
+            let page: Vec<i32> = match last {
+                None => tree.iter().take(30).copied().collect(),
+                Some(last) => tree.iter_from(&last).skip_while(|&&v| v == last).take(30).copied().collect(),
+            };
+            let Some(&end) = page.last() else { break };
+            last = Some(end);
+            pages.push(page);
+        }
+        assert_eq!(pages.len(), 4);
+        assert!(pages.concat().iter().eq(tree.iter()));
+    }
+
+    #[test]
+    fn test_retain() {
+        let mut tree = tree_of(&(0..100).collect::<Vec<_>>());
+        tree.retain(|v| v % 3 == 0);
+        assert_eq!(tree.len(), 34);
+        assert_eq!(tree[0], 0);
+        assert_eq!(tree[33], 99);
+        assert!(!tree.contains(&50));
+        assert!(height_of(&tree.root) <= 6);
+
+        // the rebuilt tree keeps working as a regular tree
+        assert!(tree.insert(50));
+        assert!(tree.remove(&51));
+        assert_eq!(tree.count_range(45..=54), 4);
+
+        tree.retain(|_| false);
+        assert!(tree.is_empty());
+    }
+
+    #[test]
+    fn test_drain() {
+        let mut tree = tree_of(&[5, 3, 8, 1, 4, 7, 9, 2, 6, 0]);
+        let mut drain = tree.drain();
+        assert_eq!(drain.len(), 10);
+        assert_eq!(drain.next(), Some(0));
+        assert_eq!(drain.len(), 9);
+        assert_eq!(drain.collect::<Vec<_>>(), vec![1, 2, 3, 4, 5, 6, 7, 8, 9]);
+        assert!(tree.is_empty());
+
+        assert!(tree.insert(0));
+        assert_eq!(tree.drain().collect::<Vec<_>>(), vec![0]);
+
+        // dropping a partially consumed drain frees the remaining elements
+        let values = Rc::new(());
+        let mut shared = AVLTree::new();
+        for i in 0..10 {
+            shared.insert((i, Rc::clone(&values)));
+        }
+        drop(shared.drain().take(3));
+        assert!(shared.is_empty());
+        assert_eq!(Rc::strong_count(&values), 1);
+    }
+
+    #[test]
+    fn test_stack_iter() {
+        let tree = tree_of(&(0..200).map(|v| (v * 37) % 200).collect::<Vec<_>>());
+        assert!(tree.stack_iter().eq(tree.iter()));
+        assert!(tree.stack_iter().rev().eq(tree.iter().rev()));
+        let mut iter = tree.stack_iter();
+        assert_eq!((iter.next(), iter.next_back()), (Some(&0), Some(&199)));
+        assert_eq!(iter.len(), 198);
+        assert!(iter.by_ref().take(99).copied().eq(1..100));
+        assert!(iter.rev().copied().eq((100..199).rev()));
+        assert_eq!(tree_of(&[]).stack_iter().next(), None);
+    }
+
+    #[test]
+    fn test_traversals_do_not_allocate() {
+        let tree = tree_of(&(0..1000).map(|v| (v * 37) % 1000).collect::<Vec<_>>());
+        let mut drained = tree.clone();
+        let dropped = tree.clone();
+        let before = allocations();
+        assert!(tree.iter().copied().eq(0..1000));
+        assert!(tree.iter().rev().copied().eq((0..1000).rev()));
+        assert!(tree.iter_from(&500).copied().eq(500..1000));
+        assert!(drained.drain().take(600).eq(0..600));
+        drop(dropped);
+        assert_eq!(allocations(), before);
+    }
+
+    #[test]
+    fn test_extract_if() {
+        let mut tree = tree_of(&(0..50).collect::<Vec<_>>());
+        let evens: Vec<i32> = tree.extract_if(|v| v % 2 == 0).collect();
+        assert_eq!(evens, (0..50).step_by(2).collect::<Vec<_>>());
+        assert_eq!(tree.len(), 25);
+        assert_eq!((0..25).map(|i| tree[i]).collect::<Vec<_>>(), (1..50).step_by(2).collect::<Vec<_>>());
+        assert!(height_of(&tree.root) <= 6);
+
+        // stopping early keeps whatever wasn't visited
+        let first: Vec<i32> = tree.extract_if(|v| v % 5 == 0).take(2).collect();
+        assert_eq!(first, vec![5, 15]);
+        assert_eq!(tree.len(), 23);
+        assert!(tree.contains(&25));
+    }
+
+    #[test]
+    fn test_split_off() {
+        for key in [-1, 0, 1, 17, 63, 64, 99, 100, 150] {
+            let mut tree = tree_of(&(0..100).collect::<Vec<_>>());
+            let upper = tree.split_off(&key);
+            let split = key.clamp(0, 100) as usize;
+            assert_eq!(tree.len(), split);
+            assert_eq!(upper.len(), 100 - split);
+            assert_eq!((0..tree.len()).map(|i| tree[i]).collect::<Vec<_>>(), (0..split as i32).collect::<Vec<_>>());
+            assert_eq!((0..upper.len()).map(|i| upper[i]).collect::<Vec<_>>(), (split as i32..100).collect::<Vec<_>>());
+            for half in [&tree, &upper] {
+                assert!(height_of(&half.root) <= 10);
+                assert!(half.root.as_ref().is_none_or(|n| n.borrow().parent.upgrade().is_none()));
+            }
+        }
+
+        let mut tree = tree_of(&[10, 20, 30]);
+        let upper = tree.split_off(&20);
+        assert!(tree.insert(25));
+        assert!(!tree.contains(&20));
+        assert!(upper.contains(&20));
+        assert_eq!(tree.len(), 2);
+    }
+
+    #[test]
+    fn test_append() {
+        let contents = |tree: &AVLTree<i32>| (0..tree.len()).map(|i| tree[i]).collect::<Vec<_>>();
+
+        // disjoint ranges, in both directions and with very different sizes
+        let mut low = tree_of(&(0..200).collect::<Vec<_>>());
+        let mut high = tree_of(&[500, 300, 400]);
+        low.append(&mut high);
+        assert!(high.is_empty());
+        assert_eq!(low.len(), 203);
+        assert_eq!(low[199], 199);
+        assert_eq!(low[200], 300);
+        let mut high = tree_of(&(1000..1010).collect::<Vec<_>>());
+        high.append(&mut low);
+        assert_eq!(high.len(), 213);
+        assert_eq!(high[0], 0);
+        assert_eq!(high[212], 1009);
+        assert!(height_of(&high.root) <= 10);
+
+        // interleaved elements with duplicates
+        let mut evens = tree_of(&(0..100).step_by(2).collect::<Vec<_>>());
+        let mut threes = tree_of(&(0..100).step_by(3).collect::<Vec<_>>());
+        evens.append(&mut threes);
+        let expected: Vec<i32> = (0..100).filter(|v| v % 2 == 0 || v % 3 == 0).collect();
+        assert_eq!(contents(&evens), expected);
+        assert!(threes.is_empty());
+
+        let mut empty = AVLTree::new();
+        empty.append(&mut evens);
+        assert_eq!(contents(&empty), expected);
+        assert!(evens.is_empty());
+    }
+
+    #[test]
+    fn test_append_to_empty_keeps_sink_and_counters() {
+        let events = Rc::new(Cell::new(0));
+        let mut empty = AVLTree::new();
+        let counted = Rc::clone(&events);
+        empty.set_event_sink(move |_: Event<'_, i32>| counted.set(counted.get() + 1));
+        empty.contains(&0);
+        let mut other = tree_of(&[1, 2, 3]);
+        other.set_event_sink(|_: Event<'_, i32>| panic!("event sent to the other tree"));
+        #[cfg(feature = "stats")]
+        let comparisons = (empty.stats().comparisons, other.stats().comparisons);
+
+        empty.append(&mut other);
+        #[cfg(feature = "stats")]
+        assert_eq!((empty.stats().comparisons, other.stats().comparisons), comparisons);
+        empty.insert(4);
+        assert!(events.get() > 0);
+        assert!(other.take_event_sink().is_some());
+    }
+
+    #[test]
+    fn test_iter() {
+        let tree = tree_of(&[5, 3, 8, 1, 4, 7, 9, 2, 6, 0]);
+        assert_eq!(tree.iter().copied().collect::<Vec<_>>(), (0..10).collect::<Vec<_>>());
+        assert_eq!(tree.iter().rev().copied().collect::<Vec<_>>(), (0..10).rev().collect::<Vec<_>>());
+
+        let mut iter = tree.iter();
+        assert_eq!(iter.len(), 10);
+        assert_eq!(iter.next(), Some(&0));
+        assert_eq!(iter.next_back(), Some(&9));
+        assert_eq!(iter.len(), 8);
+        assert_eq!(iter.collect::<Vec<_>>(), vec![&1, &2, &3, &4, &5, &6, &7, &8]);
+
+        let mut sum = 0;
+        for v in &tree {
+            sum += v;
+        }
+        assert_eq!(sum, 45);
+        assert_eq!(tree_of(&[]).iter().next(), None);
+    }
+
+    #[test]
+    fn test_replace_and_take() {
+        // ordered by the first field only, the second one tells equal instances apart
+        #[derive(Debug)]
+        struct Keyed(i32, &'static str);
+        impl PartialEq for Keyed {
+            fn eq(&self, other: &Self) -> bool {
+                self.0 == other.0
+            }
+        }
+        impl Eq for Keyed {}
+        impl PartialOrd for Keyed {
+            fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+                Some(self.cmp(other))
+            }
+        }
+        impl Ord for Keyed {
+            fn cmp(&self, other: &Self) -> Ordering {
+                self.0.cmp(&other.0)
+            }
+        }
+        let mut tree = AVLTree::new();
+        tree.insert(Keyed(1, "a"));
+        tree.insert(Keyed(2, "b"));
+
+        assert_eq!(tree.replace(Keyed(3, "c")), None);
+        assert_eq!(tree.len(), 3);
+        let generation = tree.generation();
+        let old = tree.replace(Keyed(3, "d")).unwrap();
+        assert_ne!(tree.generation(), generation);
+        assert_eq!(old.1, "c");
+        assert_eq!(tree[2].1, "d");
+        assert_eq!(tree.len(), 3);
+
+        assert_eq!(tree.take(&Keyed(1, "")).map(|k| k.1), Some("a"));
+        assert_eq!(tree.take(&Keyed(1, "")), None);
+        assert_eq!(tree.len(), 2);
+    }
+
+    #[test]
+    fn test_try_insert() {
+        let mut tree = AVLTree::new();
+        assert_eq!(tree.try_insert(String::from("a")), Ok(()));
+        let err = tree.try_insert(String::from("a")).unwrap_err();
+        assert_eq!(err.value, "a");
+        assert_eq!(err.to_string(), "an equal element is already in the tree");
+        assert_eq!(tree.len(), 1);
+    }
+
+    #[test]
+    fn test_borrowed_lookups() {
+        let mut tree = AVLTree::new();
+        for word in ["pear", "apple", "fig", "kiwi", "plum"] {
+            tree.insert(word.to_string());
+        }
+        assert!(tree.contains("fig"));
+        assert!(!tree.contains("grape"));
+        assert_eq!(tree.count_range::<str, _>((Bound::Included("b"), Bound::Excluded("p"))), 2);
+        assert_eq!(tree.take("kiwi"), Some("kiwi".to_string()));
+        assert!(tree.remove("pear"));
+        assert!(!tree.remove("pear"));
+        let upper = tree.split_off("g");
+        assert_eq!(tree.iter().collect::<Vec<_>>(), vec!["apple", "fig"]);
+        assert_eq!(upper.iter().collect::<Vec<_>>(), vec!["plum"]);
+    }
+
+    #[test]
+    fn test_insert_with_policy() {
+        // equal by `key` only, `tag` tells the elements apart
+        #[derive(Debug)]
+        struct Tagged { key: i32, tag: char }
+        impl PartialEq for Tagged {
+            fn eq(&self, other: &Self) -> bool { self.key == other.key }
+        }
+        impl Eq for Tagged {}
+        impl PartialOrd for Tagged {
+            fn partial_cmp(&self, other: &Self) -> Option<Ordering> { Some(self.cmp(other)) }
+        }
+        impl Ord for Tagged {
+            fn cmp(&self, other: &Self) -> Ordering { self.key.cmp(&other.key) }
+        }
+        let tagged = |key, tag| Tagged { key, tag };
+        let tags = |tree: &AVLTree<Tagged>| tree.iter().map(|t| t.tag).collect::<String>();
+
+        let mut tree = AVLTree::new();
+        for (key, tag) in [(2, 'a'), (1, 'b'), (3, 'c')] {
+            assert_eq!(tree.insert_with_policy(tagged(key, tag), DuplicatePolicy::Reject), None);
+        }
+        let rejected = tree.insert_with_policy(tagged(2, 'd'), DuplicatePolicy::Reject);
+        assert_eq!(rejected.map(|t| t.tag), Some('d'));
+        assert_eq!(tags(&tree), "bac");
+
+        let replaced = tree.insert_with_policy(tagged(2, 'e'), DuplicatePolicy::Replace);
+        assert_eq!(replaced.map(|t| t.tag), Some('a'));
+        assert_eq!(tags(&tree), "bec");
+
+        for (key, tag) in [(2, 'f'), (1, 'g'), (2, 'h'), (3, 'i'), (2, 'j')] {
+            assert_eq!(tree.insert_with_policy(tagged(key, tag), DuplicatePolicy::KeepBoth), None);
+        }
+        assert_eq!(tags(&tree), "bgefhjci");
+        assert_eq!(tree.len(), 8);
+        assert_eq!(tree.count_range(&tagged(2, ' ')..=&tagged(2, ' ')), 4);
+        assert!(tree.remove(&tagged(2, ' ')));
+        assert_eq!(tree.len(), 7);
+    }
+
+    #[test]
+    fn test_debug() {
+        let tree = tree_of(&[2, 1, 3]);
+        assert_eq!(format!("{:?}", tree), "{1, 2, 3}");
+        assert_eq!(format!("{:?}", tree_of(&[])), "{}");
+        assert_eq!(
+            format!("{:#?}", tree_of(&[1])),
+            "AVLTree {\n    root: Some(\n        AVLNode {\n            value: 1,\n            balance: 0,\n            size: 1,\n            left: None,\n            right: None,\n        },\n    ),\n}"
+        );
+        let nested = format!("{:#?}", tree);
+        assert!(nested.contains("value: 2,\n            balance: 0,"));
+        assert_eq!(nested.matches("AVLNode").count(), 3);
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(tree_of(&[]).to_string(), "");
+        let drawing = tree_of(&[4, 2, 6, 1, 3, 5]).to_string();
+        let expected = [
+            "    /-- 6 (h=2, bf=-1)",
+            "    |   \\-- 5 (h=1, bf=0)",
+            "4 (h=3, bf=0)",
+            "    |   /-- 3 (h=1, bf=0)",
+            "    \\-- 2 (h=2, bf=0)",
+            "        \\-- 1 (h=1, bf=0)",
+        ];
+        assert_eq!(drawing.lines().collect::<Vec<_>>(), expected);
+    }
+
+    #[test]
+    fn test_clone() {
+        let mut tree = tree_of(&(0..100).collect::<Vec<_>>());
+        let copy = tree.clone();
+        tree.remove(&50);
+        tree.insert(200);
+        assert!(copy.iter().copied().eq(0..100));
+        assert_eq!(copy.len(), 100);
+        assert_eq!(copy[50], 50);
+        assert_eq!(copy.count_range(10..20), 10);
+
+        // the parent links of the copy are usable
+        let mut copy = copy;
+        for v in 0..100 {
+            assert!(copy.remove(&v));
+        }
+        assert!(copy.is_empty());
+        assert!(tree_of(&[]).clone().is_empty());
+    }
+
+    #[test]
+    fn test_eq() {
+        // same elements, built in different orders
+        let ascending = tree_of(&(0..20).collect::<Vec<_>>());
+        let descending = tree_of(&(0..20).rev().collect::<Vec<_>>());
+        assert!(ascending == descending);
+        assert!(ascending != tree_of(&(0..19).collect::<Vec<_>>()));
+        assert!(ascending != tree_of(&(1..21).collect::<Vec<_>>()));
+        assert!(tree_of(&[]) == tree_of(&[]));
+    }
+
+    #[test]
+    fn test_ord() {
+        assert!(tree_of(&[1, 2, 3]) < tree_of(&[1, 2, 4]));
+        assert!(tree_of(&[1, 2]) < tree_of(&[1, 2, 3]));
+        assert!(tree_of(&[]) < tree_of(&[0]));
+        assert!(tree_of(&[5]) > tree_of(&[1, 2, 3]));
+        assert_eq!(tree_of(&[3, 1, 2]).cmp(&tree_of(&[1, 2, 3])), Ordering::Equal);
+
+        // trees of trees
+        let mut forest = AVLTree::new();
+        for values in [&[2][..], &[1, 3], &[1], &[1, 3]] {
+            forest.insert(tree_of(values));
+        }
+        assert_eq!(forest.len(), 3);
+        assert!(forest.iter().map(|t| t.iter().copied().collect::<Vec<_>>()).eq([vec![1], vec![1, 3], vec![2]]));
+    }
+
+    #[test]
+    #[allow(clippy::mutable_key_type)] // the cells are never written through a shared tree
+    fn test_hash() {
+        use std::collections::hash_map::DefaultHasher;
+        use std::collections::HashMap;
+
+        let hash = |tree: &AVLTree<i32>| {
+            let mut hasher = DefaultHasher::new();
+            tree.hash(&mut hasher);
+            hasher.finish()
+        };
+        let ascending = tree_of(&(0..20).collect::<Vec<_>>());
+        let descending = tree_of(&(0..20).rev().collect::<Vec<_>>());
+        assert_eq!(hash(&ascending), hash(&descending));
+        assert_ne!(hash(&ascending), hash(&tree_of(&(0..19).collect::<Vec<_>>())));
+
+        let mut cache = HashMap::new();
+        cache.insert(ascending, "squares");
+        assert_eq!(cache.get(&descending), Some(&"squares"));
+    }
+
+    #[test]
+    fn test_constructors() {
+        let empty: AVLTree<i32> = AVLTree::new();
+        assert!(empty.is_empty());
+        assert!(AVLTree::<i32>::default().is_empty());
+
+        let tree: AVLTree<i32> = [5, 3, 9, 3, 1, 5].into_iter().collect();
+        assert!(tree.iter().copied().eq([1, 3, 5, 9]));
+        assert_eq!(tree.len(), 4);
+        assert_eq!(tree.count_range(2..6), 2);
+        assert!(AVLTree::from([2, 1]) == tree_of(&[1, 2]));
+
+        let mut tree: AVLTree<i32> = (0..1000).rev().collect();
+        assert!(height_of(&tree.root) <= 10);
+        tree.extend([999, 1000, -1]);
+        assert_eq!(tree.len(), 1002);
+        assert_eq!(tree[0], -1);
+    }
+
+    #[test]
+    fn test_drop_frees_nodes() {
+        let counted: Vec<Rc<i32>> = (0..100).map(Rc::new).collect();
+        let mut tree: AVLTree<Rc<i32>> = AVLTree::new();
+        tree.extend(counted.iter().cloned());
+        let root = Rc::downgrade(tree.root.as_ref().unwrap());
+        for v in &counted[..50] {
+            tree.remove(v);
+        }
+        let mut other = tree.split_off(&Rc::new(75));
+        tree.append(&mut other);
+        assert!(tree.iter().map(|v| **v).eq(50..100));
+        drop(tree);
+        assert!(root.upgrade().is_none());
+        assert!(counted.iter().all(|v| Rc::strong_count(v) == 1));
+    }
+
+    /// Returns the height of a subtree, checking the balance factor stored in every node.
+    pub(crate) fn checked_height<T: Ord>(node: &Link<T>) -> isize {
+        node.as_ref().map_or(0, |node| {
+            let n = node.borrow();
+            let (hl, hr) = (checked_height(&n.left), checked_height(&n.right));
+            assert_eq!(n.balance as isize, hr - hl);
+            assert!(n.balance.abs() <= 1);
+            1 + max(hl, hr)
+        })
+    }
+
+    #[test]
+    fn test_balance_factors() {
+        let mut tree: AVLTree<i32> = (0..500).map(|i| (i * 7919) % 1000).collect();
+        checked_height(&tree.root);
+        for i in (0..1000).step_by(3) {
+            tree.remove(&i);
+            checked_height(&tree.root);
+        }
+        let mut high = tree.split_off(&600);
+        assert_eq!(checked_height(&high.root) as usize, height_of(&high.root));
+        assert_eq!(checked_height(&tree.root) as usize, height_of(&tree.root));
+        high.extend(1000..1100);
+        tree.append(&mut high);
+        assert_eq!(checked_height(&tree.root) as usize, height_of(&tree.root));
+        let built: AVLTree<i32> = (0..37).collect::<Vec<_>>().into_iter().collect();
+        checked_height(&built.root);
+    }
+
+    #[test]
+    fn test_from_sorted_iter() {
+        let tree = AVLTree::from_sorted_iter(0..1000);
+        assert!(tree.iter().copied().eq(0..1000));
+        assert_eq!(checked_height(&tree.root), 10);
+        let tree = AVLTree::from_sorted_iter([1, 1, 2, 3, 3, 3]);
+        assert!(tree.iter().copied().eq([1, 2, 3]));
+        assert!(AVLTree::<i32>::from_sorted_iter([]).is_empty());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_from_unsorted_iter() {
+        AVLTree::from_sorted_iter([1, 3, 2]);
+    }
+
+    #[test]
+    fn test_insert_batch() {
+        let kept: Vec<Rc<i32>> = (0..100).step_by(2).map(Rc::new).collect();
+        let mut tree = AVLTree::from_sorted_iter(kept.iter().cloned());
+        tree.insert_batch((0..100).rev().map(Rc::new).chain([Rc::new(7), Rc::new(150)]));
+        assert!(tree.iter().map(|v| **v).eq((0..100).chain([150])));
+        assert!(tree.iter().filter(|v| ***v < 100 && ***v % 2 == 0).zip(&kept).all(|(v, k)| Rc::ptr_eq(v, k)));
+        checked_height(&tree.root);
+
+        let mut tree = AVLTree::new();
+        tree.insert_batch([3, 1, 2, 1]);
+        tree.insert_batch([]);
+        assert!(tree.iter().copied().eq([1, 2, 3]));
+    }
+
+    #[test]
+    fn test_remove_batch() {
+        let mut tree = AVLTree::from_sorted_iter(0..1000);
+        let keys: Vec<i32> = (0..1100).rev().filter(|k| k % 3 == 0).collect();
+        assert_eq!(tree.remove_batch(&keys), 334);
+        assert!(tree.iter().copied().eq((0..1000).filter(|k| k % 3 != 0)));
+        checked_height(&tree.root);
+        assert_eq!(tree.remove_batch(&[1, 1, 2, 5000]), 2);
+        assert_eq!(tree.remove_batch::<i32>(&[]), 0);
+        assert_eq!(tree.len(), 664);
+        let all: Vec<i32> = tree.iter().copied().collect();
+        assert_eq!(tree.remove_batch(&all), 664);
+        assert!(tree.is_empty());
+    }
+
+    #[test]
+    fn test_rebuild() {
+        let mut tree = AVLTree::from_sorted_iter(0..1000);
+        let handle = tree.insert_with_handle(1000).unwrap();
+        for v in (0..1000).filter(|v| v % 10 != 0 || *v < 500) {
+            tree.remove(&v);
+        }
+        assert!(checked_height(&tree.root) > 6);
+        tree.rebuild();
+        assert_eq!(checked_height(&tree.root), 6);
+        assert!(tree.iter().copied().eq((500..=1000).step_by(10)));
+        assert_eq!(tree.get_by_handle(&handle), Some(&1000));
+
+        let mut empty = AVLTree::<i32>::new();
+        empty.rebuild();
+        assert!(empty.is_empty());
+    }
+
+    #[test]
+    fn test_into_sorted_vec() {
+        let tree = tree_of(&[5, 3, 8, 1, 4]);
+        assert_eq!(tree.to_vec(), vec![1, 3, 4, 5, 8]);
+        assert_eq!(tree.into_sorted_vec(), vec![1, 3, 4, 5, 8]);
+        assert_eq!(AVLTree::<i32>::new().into_sorted_vec(), Vec::<i32>::new());
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let mut tree = tree_of(&[5, 3, 8, 1, 4]);
+        let snapshot = tree.snapshot();
+        tree.insert(2);
+        tree.remove(&5);
+        assert!(snapshot.iter().copied().eq([1, 3, 4, 5, 8]));
+        assert!(tree.iter().copied().eq([1, 2, 3, 4, 8]));
+        assert_eq!(snapshot.clone(), snapshot);
+        assert!(AVLTree::<i32>::new().snapshot().is_empty());
+
+        // snapshots taken between changes of one element, of many, and with equal elements
+        let mut tree: AVLTree<i32> = (0..50).collect();
+        let mut snapshots = vec![(tree.snapshot(), tree.to_vec())];
+        for i in 0..200 {
+            match i % 5 {
+                0 => { tree.remove(&(i / 4)); }
+                1 => { tree.replace(i / 3); }
+                2 => { tree.take(&(i / 2)); }
+                3 if i % 40 == 3 => tree.retain(|v| v % 7 != 0),
+                _ => { tree.insert(i); }
+            }
+            snapshots.push((tree.snapshot(), tree.to_vec()));
+        }
+        tree.insert_with_policy(60, DuplicatePolicy::KeepBoth);
+        snapshots.push((tree.snapshot(), tree.to_vec()));
+        tree.remove(&60);
+        snapshots.push((tree.snapshot(), tree.to_vec()));
+        for (snapshot, values) in &snapshots {
+            assert!(snapshot.iter().eq(values));
+        }
+        assert!(tree.snapshot().ptr_eq(&tree.snapshot()));
+
+        // the copy is dropped at the first change after the last snapshot is
+        drop(snapshots);
+        let kept = tree.snapshot();
+        tree.insert(1000);
+        assert!(tree.shadow.borrow().is_some());
+        tree.insert(1001);
+        assert!(tree.shadow.borrow().is_some());
+        drop(kept);
+        tree.insert(1002);
+        assert!(tree.shadow.borrow().is_none());
+        assert!(tree.snapshot().iter().eq(tree.iter()));
+    }
+
+    #[test]
+    fn test_btree_set_conversions() {
+        let set: BTreeSet<i32> = (0..100).rev().collect();
+        let tree = AVLTree::from(set.clone());
+        assert!(tree.iter().eq(set.iter()));
+        assert_eq!(checked_height(&tree.root), 7);
+        assert_eq!(BTreeSet::from(tree), set);
+    }
+
+    #[test]
+    fn test_from_vec() {
+        let tree = AVLTree::from(vec![5, 1, 4, 1, 3, 5]);
+        assert!(tree.iter().copied().eq([1, 3, 4, 5]));
+        checked_height(&tree.root);
+
+        let tree = AVLTree::from((0..100).collect::<HashSet<i32>>());
+        assert!(tree.iter().copied().eq(0..100));
+        checked_height(&tree.root);
+
+        // equal when compared, told apart by their addresses
+        let values: Vec<Rc<i32>> = [2, 1, 2, 3, 2].into_iter().map(Rc::new).collect();
+        let twos = |tree: &AVLTree<Rc<i32>>| tree.iter().filter(|v| ***v == 2).map(Rc::as_ptr).collect::<Vec<_>>();
+        let first = AVLTree::from_vec_with_policy(values.clone(), DuplicatePolicy::Reject);
+        assert_eq!(twos(&first), [Rc::as_ptr(&values[0])]);
+        let last = AVLTree::from_vec_with_policy(values.clone(), DuplicatePolicy::Replace);
+        assert_eq!(twos(&last), [Rc::as_ptr(&values[4])]);
+        let all = AVLTree::from_vec_with_policy(values.clone(), DuplicatePolicy::KeepBoth);
+        assert_eq!(twos(&all), [0, 2, 4].map(|i| Rc::as_ptr(&values[i])));
+        assert_eq!(all.len(), 5);
+        checked_height(&all.root);
+    }
+
+    #[test]
+    fn test_drop_deep_tree() {
+        // a chain far deeper than any AVL tree, which recursive dropping would overflow on
+        let root = Rc::new(RefCell::new(AVLNode::new(0, &None)));
+        let mut last = Rc::clone(&root);
+        for v in 1..200_000 {
+            let node = Rc::new(RefCell::new(AVLNode::new(v, &Some(Rc::clone(&last)))));
+            last.borrow_mut().right = Some(Rc::clone(&node));
+            last = node;
+        }
+        drop(last);
+        drop(AVLTree::from_root(Some(root)));
+    }
+
+    #[test]
+    fn test_replacement_node() {
+        /*
+        let mut root = AVLNode {
+            value: 1,
+            height: 2,
+            parent: None,
+            right: None,
+            left: None
+        };
+        let mut level1_node1 = AVLNode {
+            value: 2,
+            height: 1,
+            parent: None,
+            right: None,
+            left: None
+        };
+        let mut level1_node2 = AVLNode {
+            value: 3,
+            height: 1,
+            parent: None,
+            right: None,
+            left: None
+        };
+        let mut level2_node1 = AVLNode {
+            value: 4,
+            height: 0,
+            parent: None,
+            right: None,
+            left: None
+        };
+        let mut level2_node2 = AVLNode {
+            value: 5,
+            height: 0,
+            parent: None,
+            right: None,
+            left: None
+        };
+        let mut level2_node3 = AVLNode {
+            value: 6,
+            height: 0,
+            parent: None,
+            right: None,
+            left: None
+        };
+        let mut level2_node4 = AVLNode {
+            value: 7,
+            height: 0,
+            parent: None,
+            right: None,
+            left: None
+        };
+
+        level1_node1.left = Some(Box::new(level2_node1));
+        level1_node1.right = Some(Box::new(level2_node2));
+        level1_node2.left = Some(Box::new(level2_node3));
+        level1_node2.right = Some(Box::new(level2_node4));
+        root.left = Some(Box::new(level1_node1));
+        root.right = Some(Box::new(level1_node2));
+
+        //let node = root.left.as_ref().unwrap();
+        let replacement_node = root.find_replacement_node();
+        assert_eq!(replacement_node.value, 5);
+        */
+
+        let level3_node1 = Rc::new(RefCell::new(AVLNode {
+            value: 4,
+            balance: 0,
+            size: 1,
+            parent: Weak::new(),
+            right: None,
+            left: None,
+            anchor: None
+        }));
+
+        let level3_node2 = Rc::new(RefCell::new(AVLNode {
+            value: 5,
+            balance: 0,
+            size: 1,
+            parent: Weak::new(),
+            right: None,
+            left: None,
+            anchor: None
+        }));
+        let level3_node3 = Rc::new(RefCell::new(AVLNode {
+            value: 6,
+            balance: 0,
+            size: 1,
+            parent: Weak::new(),
+            right: None,
+            left: None,
+            anchor: None
+        }));
+        let level3_node4 = Rc::new(RefCell::new(AVLNode {
+            value: 7,
+            balance: 0,
+            size: 1,
+            parent: Weak::new(),
+            right: None,
+            left: None,
+            anchor: None
+        }));
+        let level2_node1 = Rc::new(RefCell::new(AVLNode {
+            value: 2,
+            balance: 0,
+            size: 3,
+            parent: Weak::new(),
+            right: Some(Rc::clone(&level3_node2)),
+            left: Some(Rc::clone(&level3_node1)),
+            anchor: None
+        }));
+
+        let level2_node2 = Rc::new(RefCell::new(AVLNode {
+            value: 3,
+            balance: 0,
+            size: 3,
+            parent: Weak::new(),
+            right: Some(Rc::clone(&level3_node4)),
+            left: Some(Rc::clone(&level3_node3)),
+            anchor: None
+        }));
+        level3_node1.borrow_mut().parent = Rc::downgrade(&level2_node1);
+        level3_node2.borrow_mut().parent = Rc::downgrade(&level2_node1);
+        level3_node3.borrow_mut().parent = Rc::downgrade(&level2_node2);
+        level3_node4.borrow_mut().parent = Rc::downgrade(&level2_node2);
+        let root = Rc::new(RefCell::new(AVLNode {
+            value: 1,
+            balance: 0,
+            size: 7,
+            parent: Weak::new(),
+            right: Some(Rc::clone(&level2_node2)),
+            left: Some(Rc::clone(&level2_node1)),
+            anchor: None
+        }));
+        level2_node1.borrow_mut().parent = Rc::downgrade(&root);
+        level2_node2.borrow_mut().parent = Rc::downgrade(&root);
+
+        let mut replacement = root.borrow_mut().replacement();
+        assert!(replacement.is_some());
+        assert_eq!(replacement.unwrap().borrow().value, 5);
+
+        level2_node1.borrow_mut().left = None;
+        level2_node1.borrow_mut().right = None;
+        replacement = root.borrow_mut().replacement();
+        assert!(replacement.is_some());
+        assert_eq!(replacement.unwrap().borrow().value, 2);
+
+        level2_node1.borrow_mut().left = Some(Rc::clone(&level3_node1));
+        level2_node1.borrow_mut().right = Some(Rc::clone(&level3_node2));
+
+        root.borrow_mut().rotate(Side::Right);
+        assert_eq!(2, root.borrow().value);
+        //println!("root value: {}", root.borrow().value);
+    }
+}
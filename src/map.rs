@@ -0,0 +1,641 @@
+//! An ordered map storing a value alongside each key of the tree.
+
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::marker::PhantomData;
+use std::ops::RangeBounds;
+use std::mem;
+use std::rc::Rc;
+
+use crate::{out_of_bounds, peek, value_mut, AVLTree, Iter as SetIter, Link, NodeRef, Nodes, Side};
+
+/// A key and its value, ordered by the key alone.
+struct KeyValue<K, V> {
+    key: K,
+    value: V,
+}
+
+impl<K: Ord, V> PartialEq for KeyValue<K, V> {
+    fn eq(&self, other: &Self) -> bool {
+        self.key == other.key
+    }
+}
+
+impl<K: Ord, V> Eq for KeyValue<K, V> {}
+
+impl<K: Ord, V> PartialOrd for KeyValue<K, V> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<K: Ord, V> Ord for KeyValue<K, V> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key.cmp(&other.key)
+    }
+}
+
+/// An ordered map based on an AVL tree.
+///
+/// Keys are kept in sorted order, lookups, insertions and removals take O(log n).
+pub struct AVLMap<K: Ord, V> {
+    tree: AVLTree<KeyValue<K, V>>,
+}
+
+impl<K: Ord, V> AVLMap<K, V> {
+
+    /// Creates an empty map.
+    pub fn new() -> Self {
+        AVLMap { tree: AVLTree::new() }
+    }
+
+    /// Builds a balanced map out of entries whose keys are strictly increasing.
+    pub(crate) fn from_sorted_entries<I: ExactSizeIterator<Item = (K, V)>>(entries: I) -> Self {
+        let len = entries.len();
+        let mut entries = entries.map(|(key, value)| KeyValue { key, value });
+        AVLMap { tree: AVLTree::from_root(crate::build_balanced(&mut entries, len)) }
+    }
+
+    /// Returns the number of entries in the map.
+    pub fn len(&self) -> usize {
+        self.tree.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    /// Looks up the node holding `key`.
+    fn find<Q: ?Sized + Ord>(&self, key: &Q) -> Option<&NodeRef<KeyValue<K, V>>> where K: Borrow<Q> {
+        self.tree.find_by(|e| key.cmp(e.key.borrow()))
+    }
+
+    /// Returns a reference to the value stored for `key`.
+    pub fn get<Q: ?Sized + Ord>(&self, key: &Q) -> Option<&V> where K: Borrow<Q> {
+        self.find(key).map(|node| &peek(node).value.value)
+    }
+
+    /// Returns a mutable reference to the value stored for `key`.
+    ///
+    /// No guard is involved: borrowing the map mutably already rules out any other access to
+    /// its nodes while the reference lives.
+    pub fn get_mut<Q: ?Sized + Ord>(&mut self, key: &Q) -> Option<&mut V> where K: Borrow<Q> {
+        let node = self.find(key)?;
+        // SAFETY: the map is mutably borrowed for as long as the returned reference
+        Some(unsafe { &mut value_mut(node).value })
+    }
+
+    /// Returns the stored key and the value for `key`.
+    pub fn get_key_value<Q: ?Sized + Ord>(&self, key: &Q) -> Option<(&K, &V)> where K: Borrow<Q> {
+        self.find(key).map(|node| {
+            let e = &peek(node).value;
+            (&e.key, &e.value)
+        })
+    }
+
+    /// Returns the entry with the smallest key.
+    pub fn first_key_value(&self) -> Option<(&K, &V)> {
+        self.iter().next()
+    }
+
+    /// Returns the entry with the largest key.
+    pub fn last_key_value(&self) -> Option<(&K, &V)> {
+        self.iter().next_back()
+    }
+
+    /// Returns `true` if the map holds a value for `key`.
+    pub fn contains_key<Q: ?Sized + Ord>(&self, key: &Q) -> bool where K: Borrow<Q> {
+        self.find(key).is_some()
+    }
+
+    /// Stores `value` for `key`, returning the value previously stored for it.
+    ///
+    /// When the key is already present, the key in the map is kept and only the value changes.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        match self.entry(key) {
+            Entry::Occupied(mut entry) => Some(entry.insert(value)),
+            Entry::Vacant(entry) => {
+                entry.insert(value);
+                None
+            }
+        }
+    }
+
+    /// Gets the entry for `key`, to inspect or update it in place.
+    ///
+    /// The tree is descended once; a vacant entry remembers where its key belongs, so
+    /// inserting through it doesn't search again.
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V> {
+        match self.tree.locate(|e| key.cmp(&e.key)) {
+            Ok(node) => Entry::Occupied(OccupiedEntry { node, map: self }),
+            Err((parent, side)) => Entry::Vacant(VacantEntry { key, parent, side, map: self }),
+        }
+    }
+
+    /// Removes `key` from the map, returning its value.
+    pub fn remove<Q: ?Sized + Ord>(&mut self, key: &Q) -> Option<V> where K: Borrow<Q> {
+        let node = Rc::clone(self.find(key)?);
+        Some(self.tree.remove_node(node).value)
+    }
+
+    /// Returns an iterator over the entries of the map, sorted by key.
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter { inner: self.tree.iter() }
+    }
+
+    /// Returns an iterator over the entries with keys within `range`, sorted by key, giving
+    /// mutable access to the values.
+    pub fn range_mut<Q: ?Sized + Ord, R: RangeBounds<Q>>(&mut self, range: R) -> RangeMut<'_, K, V> where K: Borrow<Q> {
+        let (below, above) = out_of_bounds(range.start_bound(), range.end_bound());
+        let nodes = self.tree.range_nodes_by(|e| below(e.key.borrow()), |e| above(e.key.borrow()));
+        RangeMut { nodes, marker: PhantomData }
+    }
+
+    /// Returns an iterator over the keys of the map, in sorted order.
+    pub fn keys(&self) -> Keys<'_, K, V> {
+        Keys { inner: self.tree.iter() }
+    }
+
+    /// Returns an iterator over the values of the map, sorted by key.
+    pub fn values(&self) -> Values<'_, K, V> {
+        Values { inner: self.tree.iter() }
+    }
+
+    /// Returns an iterator over mutable references to the values of the map, sorted by key.
+    pub fn values_mut(&mut self) -> ValuesMut<'_, K, V> {
+        ValuesMut { nodes: self.tree.nodes(), marker: PhantomData }
+    }
+}
+
+impl<K: Ord, V> Default for AVLMap<K, V> {
+    fn default() -> Self {
+        AVLMap::new()
+    }
+}
+
+/// Builds the map balanced in O(n), the entries of a `BTreeMap` being sorted already.
+impl<K: Ord, V> From<BTreeMap<K, V>> for AVLMap<K, V> {
+    fn from(map: BTreeMap<K, V>) -> Self {
+        AVLMap::from_sorted_entries(map.into_iter())
+    }
+}
+
+impl<K: Ord, V> From<AVLMap<K, V>> for BTreeMap<K, V> {
+    fn from(map: AVLMap<K, V>) -> Self {
+        map.tree.into_sorted_vec().into_iter().map(|kv| (kv.key, kv.value)).collect()
+    }
+}
+
+/// A view into a single entry of an `AVLMap`, which is either vacant or occupied.
+///
+/// Created by [`AVLMap::entry`].
+pub enum Entry<'a, K: Ord, V> {
+    Vacant(VacantEntry<'a, K, V>),
+    Occupied(OccupiedEntry<'a, K, V>),
+}
+
+/// A view into a vacant entry of an `AVLMap`.
+pub struct VacantEntry<'a, K: Ord, V> {
+    key: K,
+    // where the new node is to be attached
+    parent: Link<KeyValue<K, V>>,
+    side: Side,
+    map: &'a mut AVLMap<K, V>,
+}
+
+/// A view into an occupied entry of an `AVLMap`.
+pub struct OccupiedEntry<'a, K: Ord, V> {
+    node: NodeRef<KeyValue<K, V>>,
+    map: &'a mut AVLMap<K, V>,
+}
+
+impl<'a, K: Ord, V> Entry<'a, K, V> {
+
+    /// Returns the key of the entry.
+    pub fn key(&self) -> &K {
+        match self {
+            Entry::Vacant(entry) => entry.key(),
+            Entry::Occupied(entry) => entry.key(),
+        }
+    }
+
+    /// Inserts `default` if the entry is vacant, and returns the value of the entry.
+    pub fn or_insert(self, default: V) -> &'a mut V {
+        self.or_insert_with(|| default)
+    }
+
+    /// Inserts the result of `default` if the entry is vacant, and returns the value of the
+    /// entry.
+    pub fn or_insert_with<F: FnOnce() -> V>(self, default: F) -> &'a mut V {
+        match self {
+            Entry::Vacant(entry) => entry.insert(default()),
+            Entry::Occupied(entry) => entry.into_mut(),
+        }
+    }
+
+    /// Runs `f` on the value if the entry is occupied.
+    pub fn and_modify<F: FnOnce(&mut V)>(self, f: F) -> Self {
+        match self {
+            Entry::Vacant(entry) => Entry::Vacant(entry),
+            Entry::Occupied(mut entry) => {
+                f(entry.get_mut());
+                Entry::Occupied(entry)
+            }
+        }
+    }
+}
+
+impl<'a, K: Ord, V: Default> Entry<'a, K, V> {
+
+    /// Inserts `V::default()` if the entry is vacant, and returns the value of the entry.
+    pub fn or_default(self) -> &'a mut V {
+        self.or_insert_with(V::default)
+    }
+}
+
+impl<'a, K: Ord, V> VacantEntry<'a, K, V> {
+
+    /// Returns the key that would be inserted.
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    /// Takes back the key.
+    pub fn into_key(self) -> K {
+        self.key
+    }
+
+    /// Inserts `value` under the entry's key and returns a reference to it.
+    pub fn insert(self, value: V) -> &'a mut V {
+        let entry = KeyValue { key: self.key, value };
+        let node = self.map.tree.insert_at(self.parent, self.side, entry);
+        // SAFETY: the map stays mutably borrowed for 'a
+        unsafe { &mut value_mut(&node).value }
+    }
+}
+
+impl<'a, K: Ord, V> OccupiedEntry<'a, K, V> {
+
+    /// Returns the key stored in the map.
+    pub fn key(&self) -> &K {
+        &peek(&self.node).value.key
+    }
+
+    /// Returns a reference to the value.
+    pub fn get(&self) -> &V {
+        &peek(&self.node).value.value
+    }
+
+    /// Returns a mutable reference to the value.
+    pub fn get_mut(&mut self) -> &mut V {
+        // SAFETY: the map is mutably borrowed by the entry, which is itself borrowed mutably
+        unsafe { &mut value_mut(&self.node).value }
+    }
+
+    /// Turns the entry into a mutable reference to the value, living as long as the borrow of
+    /// the map.
+    pub fn into_mut(self) -> &'a mut V {
+        // SAFETY: the map stays mutably borrowed for 'a
+        unsafe { &mut value_mut(&self.node).value }
+    }
+
+    /// Replaces the value, returning the old one.
+    pub fn insert(&mut self, value: V) -> V {
+        mem::replace(self.get_mut(), value)
+    }
+
+    /// Removes the entry from the map, returning its value.
+    pub fn remove(self) -> V {
+        self.remove_entry().1
+    }
+
+    /// Removes the entry from the map, returning the stored key and value.
+    pub fn remove_entry(self) -> (K, V) {
+        let entry = self.map.tree.remove_node(self.node);
+        (entry.key, entry.value)
+    }
+}
+
+/// An iterator over the entries of an `AVLMap`, sorted by key.
+///
+/// Created by [`AVLMap::iter`].
+pub struct Iter<'a, K: Ord, V> {
+    inner: SetIter<'a, KeyValue<K, V>>,
+}
+
+impl<'a, K: Ord, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<(&'a K, &'a V)> {
+        self.inner.next().map(|e| (&e.key, &e.value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a, K: Ord, V> DoubleEndedIterator for Iter<'a, K, V> {
+    fn next_back(&mut self) -> Option<(&'a K, &'a V)> {
+        self.inner.next_back().map(|e| (&e.key, &e.value))
+    }
+}
+
+impl<K: Ord, V> ExactSizeIterator for Iter<'_, K, V> {}
+
+impl<'a, K: Ord, V> IntoIterator for &'a AVLMap<K, V> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Iter<'a, K, V> {
+        self.iter()
+    }
+}
+
+/// An iterator over the keys of an `AVLMap`, in sorted order.
+///
+/// Created by [`AVLMap::keys`].
+pub struct Keys<'a, K: Ord, V> {
+    inner: SetIter<'a, KeyValue<K, V>>,
+}
+
+impl<'a, K: Ord, V> Iterator for Keys<'a, K, V> {
+    type Item = &'a K;
+
+    fn next(&mut self) -> Option<&'a K> {
+        self.inner.next().map(|e| &e.key)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a, K: Ord, V> DoubleEndedIterator for Keys<'a, K, V> {
+    fn next_back(&mut self) -> Option<&'a K> {
+        self.inner.next_back().map(|e| &e.key)
+    }
+}
+
+impl<K: Ord, V> ExactSizeIterator for Keys<'_, K, V> {}
+
+/// An iterator over the values of an `AVLMap`, sorted by key.
+///
+/// Created by [`AVLMap::values`].
+pub struct Values<'a, K: Ord, V> {
+    inner: SetIter<'a, KeyValue<K, V>>,
+}
+
+impl<'a, K: Ord, V> Iterator for Values<'a, K, V> {
+    type Item = &'a V;
+
+    fn next(&mut self) -> Option<&'a V> {
+        self.inner.next().map(|e| &e.value)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a, K: Ord, V> DoubleEndedIterator for Values<'a, K, V> {
+    fn next_back(&mut self) -> Option<&'a V> {
+        self.inner.next_back().map(|e| &e.value)
+    }
+}
+
+impl<K: Ord, V> ExactSizeIterator for Values<'_, K, V> {}
+
+/// An iterator over mutable references to the values of an `AVLMap`, sorted by key.
+///
+/// Created by [`AVLMap::values_mut`].
+pub struct ValuesMut<'a, K: Ord, V> {
+    nodes: Nodes<'a, KeyValue<K, V>>,
+    marker: PhantomData<&'a mut V>,
+}
+
+impl<'a, K: Ord, V> Iterator for ValuesMut<'a, K, V> {
+    type Item = &'a mut V;
+
+    fn next(&mut self) -> Option<&'a mut V> {
+        // SAFETY: the map is mutably borrowed for 'a and every node is yielded at most once
+        self.nodes.next().map(|node| unsafe { &mut value_mut(node).value })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.nodes.size_hint()
+    }
+}
+
+impl<'a, K: Ord, V> DoubleEndedIterator for ValuesMut<'a, K, V> {
+    fn next_back(&mut self) -> Option<&'a mut V> {
+        // SAFETY: as for `next`
+        self.nodes.next_back().map(|node| unsafe { &mut value_mut(node).value })
+    }
+}
+
+impl<K: Ord, V> ExactSizeIterator for ValuesMut<'_, K, V> {}
+
+/// An iterator over a range of entries of an `AVLMap`, with mutable access to the values.
+///
+/// Created by [`AVLMap::range_mut`].
+pub struct RangeMut<'a, K: Ord, V> {
+    nodes: Nodes<'a, KeyValue<K, V>>,
+    marker: PhantomData<&'a mut V>,
+}
+
+impl<'a, K: Ord, V> Iterator for RangeMut<'a, K, V> {
+    type Item = (&'a K, &'a mut V);
+
+    fn next(&mut self) -> Option<(&'a K, &'a mut V)> {
+        // SAFETY: the map is mutably borrowed for 'a and every node is yielded at most once
+        self.nodes.next().map(|node| {
+            let e = unsafe { value_mut(node) };
+            (&e.key, &mut e.value)
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.nodes.size_hint()
+    }
+}
+
+impl<'a, K: Ord, V> DoubleEndedIterator for RangeMut<'a, K, V> {
+    fn next_back(&mut self) -> Option<(&'a K, &'a mut V)> {
+        // SAFETY: as for `next`
+        self.nodes.next_back().map(|node| {
+            let e = unsafe { value_mut(node) };
+            (&e.key, &mut e.value)
+        })
+    }
+}
+
+impl<K: Ord, V> ExactSizeIterator for RangeMut<'_, K, V> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_map() {
+        let mut map = AVLMap::new();
+        assert!(map.is_empty());
+        assert_eq!(map.insert("b".to_string(), 2), None);
+        assert_eq!(map.insert("a".to_string(), 1), None);
+        assert_eq!(map.insert("c".to_string(), 3), None);
+        assert_eq!(map.insert("b".to_string(), 20), Some(2));
+        assert_eq!(map.len(), 3);
+
+        assert_eq!(map.get("b"), Some(&20));
+        assert_eq!(map.get("d"), None);
+        assert!(map.contains_key("a"));
+
+        let entries: Vec<(&String, &i32)> = map.iter().collect();
+        assert_eq!(entries, vec![(&"a".to_string(), &1), (&"b".to_string(), &20), (&"c".to_string(), &3)]);
+        assert_eq!(map.iter().rev().map(|(_, v)| *v).collect::<Vec<_>>(), vec![3, 20, 1]);
+
+        assert_eq!(map.remove("a"), Some(1));
+        assert_eq!(map.remove("a"), None);
+        assert_eq!((&map).into_iter().count(), 2);
+    }
+
+    #[test]
+    fn test_entry() {
+        let mut counts: AVLMap<char, usize> = AVLMap::new();
+        for c in "abracadabra".chars() {
+            *counts.entry(c).or_insert(0) += 1;
+        }
+        let counted: Vec<(char, usize)> = counts.iter().map(|(k, v)| (*k, *v)).collect();
+        assert_eq!(counted, vec![('a', 5), ('b', 2), ('c', 1), ('d', 1), ('r', 2)]);
+
+        counts.entry('a').and_modify(|v| *v *= 10).or_insert(0);
+        counts.entry('z').and_modify(|v| *v *= 10).or_insert(7);
+        assert_eq!(counts.get(&'a'), Some(&50));
+        assert_eq!(counts.get(&'z'), Some(&7));
+        assert_eq!(*counts.entry('y').or_default(), 0);
+        assert_eq!(*counts.entry('x').or_insert_with(|| 3), 3);
+        assert_eq!(counts.entry('q').key(), &'q');
+
+        match counts.entry('b') {
+            Entry::Occupied(entry) => assert_eq!(entry.remove_entry(), ('b', 2)),
+            Entry::Vacant(_) => unreachable!(),
+        }
+        match counts.entry('b') {
+            Entry::Vacant(entry) => assert_eq!(entry.into_key(), 'b'),
+            Entry::Occupied(_) => unreachable!(),
+        }
+        assert_eq!(counts.len(), 7);
+
+        // values inserted through vacant entries stay reachable while rotations shuffle nodes
+        let mut squares = AVLMap::new();
+        for i in 0..200 {
+            let v = squares.entry(i).or_insert(0);
+            *v = i * i;
+        }
+        assert!(squares.iter().all(|(k, v)| *v == k * k));
+    }
+
+    #[test]
+    fn test_key_value_lookups() {
+        // keys compare by their first field only
+        #[derive(Debug)]
+        struct Tagged(u32, &'static str);
+        impl PartialEq for Tagged {
+            fn eq(&self, other: &Self) -> bool {
+                self.0 == other.0
+            }
+        }
+        impl Eq for Tagged {}
+        impl PartialOrd for Tagged {
+            fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+                Some(self.cmp(other))
+            }
+        }
+        impl Ord for Tagged {
+            fn cmp(&self, other: &Self) -> Ordering {
+                self.0.cmp(&other.0)
+            }
+        }
+
+        let mut map = AVLMap::new();
+        assert_eq!(map.first_key_value(), None);
+        map.insert(Tagged(2, "two"), 'b');
+        map.insert(Tagged(1, "one"), 'a');
+        map.insert(Tagged(3, "three"), 'c');
+        map.insert(Tagged(2, "deux"), 'B');
+
+        let (k, v) = map.get_key_value(&Tagged(2, "")).unwrap();
+        assert_eq!((k.1, *v), ("two", 'B'));
+        assert_eq!(map.first_key_value().map(|(k, v)| (k.1, *v)), Some(("one", 'a')));
+        assert_eq!(map.last_key_value().map(|(k, v)| (k.1, *v)), Some(("three", 'c')));
+        assert!(map.get_key_value(&Tagged(4, "")).is_none());
+    }
+
+    #[test]
+    fn test_get_mut() {
+        let mut totals = AVLMap::new();
+        for (account, amount) in [("x", 5), ("y", 1), ("x", 7), ("z", 2), ("y", 3)] {
+            match totals.get_mut(account) {
+                Some(total) => *total += amount,
+                None => {
+                    totals.insert(account, amount);
+                }
+            }
+        }
+        assert_eq!(totals.values().copied().collect::<Vec<_>>(), vec![12, 4, 2]);
+        assert!(totals.get_mut("w").is_none());
+    }
+
+    #[test]
+    fn test_range_mut() {
+        let mut map = AVLMap::new();
+        for k in 0..20 {
+            map.insert(k, 0);
+        }
+        for (k, v) in map.range_mut(5..10) {
+            *v = *k * 100;
+        }
+        let mut tail = map.range_mut(17..);
+        assert_eq!(tail.len(), 3);
+        *tail.next_back().unwrap().1 = -1;
+        assert_eq!(map.range_mut(..=1).map(|(k, _)| *k).collect::<Vec<_>>(), vec![0, 1]);
+        assert_eq!(map.range_mut(30..).next(), None);
+        assert_eq!(map.range_mut(12..12).count(), 0);
+
+        let values: Vec<i32> = map.values().copied().collect();
+        let mut expected: Vec<i32> = (0..20).map(|k| if (5..10).contains(&k) { k * 100 } else { 0 }).collect();
+        expected[19] = -1;
+        assert_eq!(values, expected);
+    }
+
+    #[test]
+    fn test_projections() {
+        let mut map = AVLMap::new();
+        for (i, word) in ["delta", "alpha", "charlie", "bravo"].into_iter().enumerate() {
+            map.insert(word, i);
+        }
+        assert_eq!(map.keys().copied().collect::<Vec<_>>(), vec!["alpha", "bravo", "charlie", "delta"]);
+        assert_eq!(map.values().copied().collect::<Vec<_>>(), vec![1, 3, 2, 0]);
+        assert_eq!(map.keys().next_back(), Some(&"delta"));
+        assert_eq!(map.values().len(), 4);
+
+        for v in map.values_mut() {
+            *v *= 10;
+        }
+        let mut values = map.values_mut();
+        *values.next_back().unwrap() += 1;
+        assert_eq!(values.len(), 3);
+        assert_eq!(map.values().copied().collect::<Vec<_>>(), vec![10, 30, 20, 1]);
+    }
+
+    #[test]
+    fn test_btree_map_conversions() {
+        let btree: BTreeMap<i32, String> = (0..50).map(|k| (k, k.to_string())).collect();
+        let map = AVLMap::from(btree.clone());
+        assert_eq!(map.len(), 50);
+        assert_eq!(map.get(&42).map(String::as_str), Some("42"));
+        assert!(map.iter().eq(btree.iter()));
+        assert_eq!(BTreeMap::from(map), btree);
+    }
+}
@@ -0,0 +1,415 @@
+//! A read-only ordered set served from a file through `mmap`, enabled by the `mmap` feature on
+//! Unix.
+//!
+//! The file holds the elements in sorted order, the search tree over them being the implicit
+//! one of binary search: a lookup reads O(log n) elements, so a file much larger than memory
+//! stays on the disk but for the pages read often, which the OS keeps in its cache.
+//!
+//! The file is made of the magic bytes `AVLM` and a format version byte, then the elements
+//! encoded by their [`SnapshotElement`] impl, then the offsets in the file of the start of each
+//! element and of the end of the last one as little-endian `u64`s, then the number of elements
+//! as a little-endian `u64`.
+
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::iter::FusedIterator;
+use std::marker::PhantomData;
+use std::ops::{Bound, RangeBounds};
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::ptr;
+
+use crate::snapshot::invalid;
+use crate::SnapshotElement;
+
+const MAGIC: &[u8; 4] = b"AVLM";
+const VERSION: u8 = 1;
+/// The length of the header, up to the first element.
+const HEADER_LEN: usize = 5;
+
+/// A file mapped read-only in memory.
+struct Map {
+    ptr: *const u8,
+    len: usize,
+}
+
+// read-only, so shared between threads like a `&[u8]`
+unsafe impl Send for Map {}
+unsafe impl Sync for Map {}
+
+impl Map {
+
+    fn new(file: &File) -> io::Result<Self> {
+        let len = usize::try_from(file.metadata()?.len()).map_err(|_| invalid("file too large to map"))?;
+        if len == 0 {
+            return Err(invalid("not an AVLTree mapped file"));
+        }
+        let ptr = unsafe { libc::mmap(ptr::null_mut(), len, libc::PROT_READ, libc::MAP_SHARED, file.as_raw_fd(), 0) };
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Map { ptr: ptr as *const u8, len })
+    }
+
+    fn bytes(&self) -> &[u8] {
+        // SAFETY: the file is left as it is while mapped, as `AVLTreeMapped::open` requires
+        unsafe { std::slice::from_raw_parts(self.ptr, self.len) }
+    }
+}
+
+impl Drop for Map {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.ptr as *mut libc::c_void, self.len);
+        }
+    }
+}
+
+/// A read-only ordered set of the elements of a file mapped in memory, loaded as they are
+/// looked up.
+///
+/// Elements are decoded from the file on every read, so lookups return them by value. Opening
+/// a file checks its framing only: [`verify`](Self::verify) checks the elements, and reading a
+/// malformed one panics.
+///
+/// The set reads the file through a shared mapping, which sees the changes other processes
+/// make to the file: [`open`](Self::open) is `unsafe` for the file to stay as it is meanwhile.
+pub struct AVLTreeMapped<T> {
+    map: Map,
+    len: usize,
+    /// The offset of the table of element offsets.
+    table: usize,
+    _elements: PhantomData<fn() -> T>,
+}
+
+impl<T: Ord + SnapshotElement> AVLTreeMapped<T> {
+
+    /// Writes `elements`, which must come in strictly increasing order, to a new file at
+    /// `path`, then opens it.
+    ///
+    /// The elements are streamed to the file, making it possible to write more of them than fit
+    /// in memory. Fails with `InvalidInput` if they are out of order, no file being left.
+    ///
+    /// # Safety
+    ///
+    /// As for [`open`](Self::open).
+    pub unsafe fn create<B: Borrow<T>>(path: impl AsRef<Path>, elements: impl IntoIterator<Item = B>) -> io::Result<Self> {
+        let path = path.as_ref();
+        // the offsets go to a file of their own until the end of the elements is known
+        let mut offsets_path = path.as_os_str().to_owned();
+        offsets_path.push(".offsets");
+        let offsets_path = PathBuf::from(offsets_path);
+        let written = write_mapped::<T, B>(path, &offsets_path, elements);
+        let _ = fs::remove_file(&offsets_path);
+        if let Err(e) = written {
+            let _ = fs::remove_file(path);
+            return Err(e);
+        }
+        // SAFETY: passed on to the caller
+        unsafe { AVLTreeMapped::open(path) }
+    }
+
+    /// Maps the file at `path`, written by `create`.
+    ///
+    /// Fails with `InvalidData` if its header or its framing is wrong.
+    ///
+    /// # Safety
+    ///
+    /// The file must not be truncated or written to, by this process or another, for as long
+    /// as the set lives. The set reads it as memory: a truncation shows up as a `SIGBUS` on
+    /// the next read past the new end, and a write as bytes changing under a shared reference.
+    pub unsafe fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let map = Map::new(&File::open(path)?)?;
+        let bytes = map.bytes();
+        if bytes.len() < HEADER_LEN + 16 || &bytes[..4] != MAGIC {
+            return Err(invalid("not an AVLTree mapped file"));
+        }
+        if bytes[4] != VERSION {
+            return Err(invalid("unsupported mapped file version"));
+        }
+        let footer = bytes.len() - 8;
+        let len = read_u64(bytes, footer);
+        let table = len
+            .checked_add(1)
+            .and_then(|offsets| offsets.checked_mul(8))
+            .and_then(|table_len| (footer as u64).checked_sub(table_len))
+            .filter(|&table| table >= HEADER_LEN as u64)
+            .ok_or_else(|| invalid("mapped file element count out of range"))? as usize;
+        if read_u64(bytes, table) != HEADER_LEN as u64 || read_u64(bytes, footer - 8) != table as u64 {
+            return Err(invalid("mapped file offsets out of range"));
+        }
+        Ok(AVLTreeMapped { map, len: len as usize, table, _elements: PhantomData })
+    }
+
+    /// Returns the number of elements in the set.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the element at position `rank` in sorted order.
+    pub fn get_by_rank(&self, rank: usize) -> Option<T> {
+        (rank < self.len).then(|| self.element(rank))
+    }
+
+    /// Returns the smallest element.
+    pub fn first(&self) -> Option<T> {
+        self.get_by_rank(0)
+    }
+
+    /// Returns the largest element.
+    pub fn last(&self) -> Option<T> {
+        self.len.checked_sub(1).map(|rank| self.element(rank))
+    }
+
+    /// Returns `true` if the set holds an element equal to `value`.
+    pub fn contains<Q: ?Sized + Ord>(&self, value: &Q) -> bool where T: Borrow<Q> {
+        let rank = self.partition_point(|e| e.borrow() < value);
+        rank < self.len && self.element(rank).borrow().cmp(value) == Ordering::Equal
+    }
+
+    /// Returns an iterator over the elements falling in `range`, in sorted order.
+    pub fn range<Q: ?Sized + Ord, R: RangeBounds<Q>>(&self, range: R) -> Iter<'_, T> where T: Borrow<Q> {
+        let front = match range.start_bound() {
+            Bound::Included(s) => self.partition_point(|e| e.borrow() < s),
+            Bound::Excluded(s) => self.partition_point(|e| e.borrow() <= s),
+            Bound::Unbounded => 0,
+        };
+        let back = match range.end_bound() {
+            Bound::Included(e) => self.partition_point(|x| x.borrow() <= e),
+            Bound::Excluded(e) => self.partition_point(|x| x.borrow() < e),
+            Bound::Unbounded => self.len,
+        };
+        Iter { set: self, front, back: back.max(front) }
+    }
+
+    /// Returns an iterator over the elements, in sorted order.
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter { set: self, front: 0, back: self.len }
+    }
+
+    /// Reads all the elements, checking that they are well-formed and strictly increasing.
+    ///
+    /// Fails with `InvalidData` otherwise.
+    pub fn verify(&self) -> io::Result<()> {
+        let mut last = None;
+        for rank in 0..self.len {
+            let value = self.try_element(rank)?;
+            if last.is_some_and(|last| last >= value) {
+                return Err(invalid("mapped elements are not strictly increasing"));
+            }
+            last = Some(value);
+        }
+        Ok(())
+    }
+
+    /// Returns the number of elements for which `pred` holds, `pred` holding for the smaller
+    /// elements only.
+    fn partition_point(&self, mut pred: impl FnMut(&T) -> bool) -> usize {
+        let (mut low, mut high) = (0, self.len);
+        while low < high {
+            let mid = low + (high - low) / 2;
+            if pred(&self.element(mid)) {
+                low = mid + 1;
+            }
+            else {
+                high = mid;
+            }
+        }
+        low
+    }
+
+    fn element(&self, rank: usize) -> T {
+        self.try_element(rank).unwrap_or_else(|e| panic!("malformed element in mapped file: {e}"))
+    }
+
+    fn try_element(&self, rank: usize) -> io::Result<T> {
+        let bytes = self.map.bytes();
+        let start = read_u64(bytes, self.table + 8 * rank) as usize;
+        let end = read_u64(bytes, self.table + 8 * (rank + 1)) as usize;
+        let mut encoded = bytes.get(start..end.min(self.table)).ok_or_else(|| invalid("mapped file offsets out of range"))?;
+        let value = T::read_from(&mut encoded)?;
+        if !encoded.is_empty() {
+            return Err(invalid("trailing bytes after mapped element"));
+        }
+        Ok(value)
+    }
+}
+
+/// Writes the file of `AVLTreeMapped::create`, with the help of a temporary file of offsets.
+fn write_mapped<T: Ord + SnapshotElement, B: Borrow<T>>(
+    path: &Path,
+    offsets_path: &Path,
+    elements: impl IntoIterator<Item = B>,
+) -> io::Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+    let mut offsets = BufWriter::new(File::create(offsets_path)?);
+    file.write_all(MAGIC)?;
+    file.write_all(&[VERSION])?;
+    let mut at = HEADER_LEN as u64;
+    let mut len = 0u64;
+    let mut last: Option<B> = None;
+    for value in elements {
+        if last.as_ref().is_some_and(|last| last.borrow() >= value.borrow()) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "elements are not strictly increasing"));
+        }
+        at.write_to(&mut offsets)?;
+        let mut counted = Counted { inner: &mut file, count: 0 };
+        value.borrow().write_to(&mut counted)?;
+        at += counted.count;
+        len += 1;
+        last = Some(value);
+    }
+    at.write_to(&mut offsets)?;
+
+    let offsets = offsets.into_inner().map_err(|e| e.into_error())?;
+    offsets.sync_all()?;
+    drop(offsets);
+    io::copy(&mut File::open(offsets_path)?, &mut file)?;
+    len.write_to(&mut file)?;
+    file.into_inner().map_err(|e| e.into_error())?.sync_all()
+}
+
+/// A writer counting the bytes written through it.
+struct Counted<W> {
+    inner: W,
+    count: u64,
+}
+
+impl<W: Write> Write for Counted<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.count += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+fn read_u64(bytes: &[u8], at: usize) -> u64 {
+    u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap())
+}
+
+/// An iterator over elements of an [`AVLTreeMapped`], decoded as they are read.
+pub struct Iter<'a, T> {
+    set: &'a AVLTreeMapped<T>,
+    front: usize,
+    back: usize,
+}
+
+impl<T: Ord + SnapshotElement> Iterator for Iter<'_, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        if self.front == self.back {
+            return None;
+        }
+        self.front += 1;
+        Some(self.set.element(self.front - 1))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.back - self.front;
+        (len, Some(len))
+    }
+}
+
+impl<T: Ord + SnapshotElement> DoubleEndedIterator for Iter<'_, T> {
+    fn next_back(&mut self) -> Option<T> {
+        if self.front == self.back {
+            return None;
+        }
+        self.back -= 1;
+        Some(self.set.element(self.back))
+    }
+}
+
+impl<T: Ord + SnapshotElement> ExactSizeIterator for Iter<'_, T> {}
+
+impl<T: Ord + SnapshotElement> FusedIterator for Iter<'_, T> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AVLTree;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("avl_tree-mapped-{}-{name}", std::process::id()))
+    }
+
+    // SAFETY, for all the tests: every file is only rewritten once the set mapping it is
+    // dropped, by the test alone
+
+    #[test]
+    fn test_mapped() {
+        let path = temp_path("ints");
+        let tree: AVLTree<i64> = (0..1000).map(|v| v * 3).collect();
+        let set = unsafe { AVLTreeMapped::<i64>::create(&path, tree.iter()) }.unwrap();
+        assert_eq!(set.len(), 1000);
+        assert_eq!(set.verify().map_err(|e| e.kind()), Ok(()));
+        assert!(set.contains(&0) && set.contains(&999) && set.contains(&2997));
+        assert!(!set.contains(&1) && !set.contains(&-3) && !set.contains(&3000));
+        assert!(set.range(10..=21).eq([12, 15, 18, 21]));
+        assert!(set.range((Bound::Excluded(12), Bound::Excluded(21))).rev().eq([18, 15]));
+        assert_eq!(set.range(..).len(), 1000);
+        assert_eq!(set.range((Bound::Included(30), Bound::Included(3))).len(), 0);
+        assert_eq!((set.first(), set.last(), set.get_by_rank(2), set.get_by_rank(1000)), (Some(0), Some(2997), Some(6), None));
+        assert!(set.iter().eq(tree.iter().copied()));
+        drop(set);
+        assert_eq!(unsafe { AVLTreeMapped::<i64>::open(&path) }.unwrap().len(), 1000);
+        fs::remove_file(&path).unwrap();
+
+        let path = temp_path("words");
+        let words = unsafe { AVLTreeMapped::<String>::create(&path, ["apple", "fig", "pear"].map(String::from)) }.unwrap();
+        assert!(words.contains("fig"));
+        assert!(!words.contains("figs"));
+        assert!(words.range::<str, _>((Bound::Included("b"), Bound::Excluded("q"))).eq(["fig", "pear"]));
+        drop(words);
+        fs::remove_file(&path).unwrap();
+
+        let path = temp_path("empty");
+        let empty = unsafe { AVLTreeMapped::<u8>::create(&path, Vec::<u8>::new()) }.unwrap();
+        assert!(empty.is_empty() && empty.first().is_none() && !empty.contains(&0));
+        assert_eq!(empty.iter().next(), None);
+        drop(empty);
+        fs::remove_file(&path).unwrap();
+
+        let path = temp_path("unsorted");
+        let error = unsafe { AVLTreeMapped::<u8>::create(&path, [1, 3, 2]) }.err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_mapped_corrupted() {
+        // a file whose framing is broken is rejected on opening
+        let path = temp_path("framing");
+        drop(unsafe { AVLTreeMapped::<u32>::create(&path, [1, 2, 3]) }.unwrap());
+        let mut bytes = fs::read(&path).unwrap();
+        let count = bytes.len() - 8;
+        bytes[count] = 4;
+        fs::write(&path, &bytes).unwrap();
+        assert_eq!(unsafe { AVLTreeMapped::<u32>::open(&path) }.err().unwrap().kind(), io::ErrorKind::InvalidData);
+        fs::write(&path, b"AVLT\x01").unwrap();
+        assert_eq!(unsafe { AVLTreeMapped::<u32>::open(&path) }.err().unwrap().kind(), io::ErrorKind::InvalidData);
+        fs::remove_file(&path).unwrap();
+
+        // and one whose elements are out of order by `verify`
+        let path = temp_path("order");
+        drop(unsafe { AVLTreeMapped::<u32>::create(&path, [1, 2, 3]) }.unwrap());
+        let mut bytes = fs::read(&path).unwrap();
+        bytes[HEADER_LEN] = 5;
+        fs::write(&path, &bytes).unwrap();
+        let set = unsafe { AVLTreeMapped::<u32>::open(&path) }.unwrap();
+        assert_eq!(set.verify().unwrap_err().kind(), io::ErrorKind::InvalidData);
+        drop(set);
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,205 @@
+//! Iterators merging the elements of several trees into a single sorted sequence.
+
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::iter::Peekable;
+
+use crate::{AVLTree, Iter};
+
+impl<T: Ord> AVLTree<T> {
+
+    /// Visits the elements of both `self` and `other` in sorted order, without building another
+    /// tree. Elements present in both trees are visited twice, the one of `self` first, unless
+    /// the iterator is turned into a deduplicating one with [`Merge::dedup`].
+    pub fn merge_iter<'a>(&'a self, other: &'a AVLTree<T>) -> Merge<'a, T> {
+        Merge { a: self.iter().peekable(), b: other.iter().peekable(), dedup: false, last: None }
+    }
+
+    /// Visits the elements of all of `trees` in sorted order. Equal elements are visited in the
+    /// order of the trees they are in, unless the iterator is turned into a deduplicating one
+    /// with [`MergeAll::dedup`].
+    ///
+    /// A heap holds the next element of each tree, so stepping takes O(log k) on top of the
+    /// step in the tree, for k trees.
+    pub fn merge_all(trees: &[AVLTree<T>]) -> MergeAll<'_, T> {
+        let mut sources: Vec<_> = trees.iter().map(AVLTree::iter).collect();
+        let heads = sources.iter_mut().enumerate()
+            .filter_map(|(source, iter)| Some(Head { value: iter.next()?, source }))
+            .collect();
+        MergeAll { sources, heads, dedup: false, last: None }
+    }
+}
+
+/// A lazy iterator over the elements of two `AVLTree`s, in sorted order. Created by
+/// [`AVLTree::merge_iter`].
+pub struct Merge<'a, T: Ord> {
+    a: Peekable<Iter<'a, T>>,
+    b: Peekable<Iter<'a, T>>,
+    dedup: bool,
+    last: Option<&'a T>,
+}
+
+impl<'a, T: Ord> Merge<'a, T> {
+    /// Makes the iterator skip elements equal to the one it last yielded, so that each value
+    /// is visited once, even if it is in both trees or several times in one of them.
+    pub fn dedup(mut self) -> Self {
+        self.dedup = true;
+        self
+    }
+}
+
+impl<'a, T: Ord> Iterator for Merge<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        loop {
+            let next = match (self.a.peek(), self.b.peek()) {
+                (Some(x), Some(y)) if y < x => self.b.next(),
+                (Some(_), _) => self.a.next(),
+                (None, _) => self.b.next(),
+            }?;
+            if self.dedup && self.last == Some(next) {
+                continue;
+            }
+            self.last = Some(next);
+            return Some(next);
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.a.len() + self.b.len();
+        (if self.dedup { 0 } else { len }, Some(len))
+    }
+}
+
+/// A lazy iterator over the elements of any number of `AVLTree`s, in sorted order. Created by
+/// [`AVLTree::merge_all`].
+pub struct MergeAll<'a, T: Ord> {
+    sources: Vec<Iter<'a, T>>,
+    heads: BinaryHeap<Head<'a, T>>,
+    dedup: bool,
+    last: Option<&'a T>,
+}
+
+/// The next element of one of the trees a [`MergeAll`] merges, ordered so that the heap's
+/// greatest one is the smallest element, from the first tree among equal ones.
+struct Head<'a, T> {
+    value: &'a T,
+    source: usize,
+}
+
+impl<T: Ord> PartialEq for Head<'_, T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<T: Ord> Eq for Head<'_, T> {}
+
+impl<T: Ord> PartialOrd for Head<'_, T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T: Ord> Ord for Head<'_, T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.value.cmp(self.value).then(other.source.cmp(&self.source))
+    }
+}
+
+impl<'a, T: Ord> MergeAll<'a, T> {
+    /// Makes the iterator skip elements equal to the one it last yielded, so that each value
+    /// is visited once, however many times it is in the trees.
+    pub fn dedup(mut self) -> Self {
+        self.dedup = true;
+        self
+    }
+}
+
+impl<'a, T: Ord> Iterator for MergeAll<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        loop {
+            let Head { value, source } = self.heads.pop()?;
+            if let Some(next) = self.sources[source].next() {
+                self.heads.push(Head { value: next, source });
+            }
+            if self.dedup && self.last == Some(value) {
+                continue;
+            }
+            self.last = Some(value);
+            return Some(value);
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.heads.len() + self.sources.iter().map(Iter::len).sum::<usize>();
+        (if self.dedup { 0 } else { len }, Some(len))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DuplicatePolicy;
+
+    #[test]
+    fn test_merge_iter() {
+        let a: AVLTree<i32> = [1, 3, 5, 7, 9, 10].into_iter().collect();
+        let b: AVLTree<i32> = [2, 3, 4, 9, 10, 11, 12].into_iter().collect();
+        let empty = AVLTree::new();
+
+        assert!(a.merge_iter(&b).copied().eq([1, 2, 3, 3, 4, 5, 7, 9, 9, 10, 10, 11, 12]));
+        assert_eq!(a.merge_iter(&b).size_hint(), (13, Some(13)));
+        assert!(a.merge_iter(&b).dedup().eq(a.union(&b)));
+        assert!(a.merge_iter(&empty).eq(a.iter()));
+        assert!(empty.merge_iter(&b).dedup().eq(b.iter()));
+        assert_eq!(empty.merge_iter(&empty).next(), None);
+
+        let mut dups = AVLTree::new();
+        for v in [4, 4, 8, 4] {
+            dups.insert_with_policy(v, DuplicatePolicy::KeepBoth);
+        }
+        assert!(dups.merge_iter(&a).dedup().copied().eq([1, 3, 4, 5, 7, 8, 9, 10]));
+    }
+
+    #[test]
+    fn test_merge_all() {
+        let shards: Vec<AVLTree<i32>> = (0..5)
+            .map(|shard| (0..40).filter(|v| v % 5 == shard || v % 7 == 0).collect())
+            .collect();
+        let mut expected: Vec<_> = shards.iter().flatten().copied().collect();
+        expected.sort();
+        let merged = AVLTree::merge_all(&shards);
+        assert_eq!(merged.size_hint(), (expected.len(), Some(expected.len())));
+        assert!(merged.copied().eq(expected));
+        assert!(AVLTree::merge_all(&shards).dedup().copied().eq(0..40));
+        assert!(AVLTree::merge_all(&shards[..1]).eq(shards[0].iter()));
+        assert_eq!(AVLTree::<i32>::merge_all(&[]).next(), None);
+
+        // equal elements come in the order of their trees
+        #[derive(Debug)]
+        struct Tagged(i32, usize);
+        impl PartialEq for Tagged {
+            fn eq(&self, other: &Self) -> bool {
+                self.0 == other.0
+            }
+        }
+        impl Eq for Tagged {}
+        impl PartialOrd for Tagged {
+            fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+                Some(self.cmp(other))
+            }
+        }
+        impl Ord for Tagged {
+            fn cmp(&self, other: &Self) -> Ordering {
+                self.0.cmp(&other.0)
+            }
+        }
+        let trees: Vec<AVLTree<Tagged>> = (0..3).map(|t| (0..4).map(|v| Tagged(v, t)).collect()).collect();
+        let tags: Vec<_> = AVLTree::merge_all(&trees).map(|v| (v.0, v.1)).collect();
+        assert_eq!(&tags[..4], [(0, 0), (0, 1), (0, 2), (1, 0)]);
+    }
+}
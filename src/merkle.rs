@@ -0,0 +1,390 @@
+//! An ordered set whose subtrees each carry a cryptographic hash of their contents, enabled by
+//! the `merkle` feature.
+//!
+//! The hash of a node is the SHA-256 of the hash of its left subtree, the encoding of its
+//! element as written in a [snapshot](crate::snapshot), and the hash of its right subtree, an
+//! empty subtree hashing to 32 zero bytes. As with [`persistent`](crate::persistent) trees,
+//! nodes are never modified once built: changes copy the path down to where they happen, which
+//! is what gets rehashed, and share the rest.
+//!
+//! Hashes cover the shape of the tree along with its elements. Two trees built by the same
+//! sequence of operations, such as replicas applying the same log, have identical shapes, and
+//! compare in O(1) by [`root_hash`](AVLTreeMerkle::root_hash); [`diff`](AVLTreeMerkle::diff)
+//! then only descends into the subtrees that differ. Trees holding the same elements in
+//! different shapes hash differently, and diffing them falls back to a full walk.
+
+use std::borrow::Borrow;
+use std::cmp::{max, Ordering};
+use std::sync::Arc;
+
+use sha2::{Digest, Sha256};
+
+use crate::SnapshotElement;
+
+/// A SHA-256 hash, as computed for the subtrees of an [`AVLTreeMerkle`].
+pub type MerkleHash = [u8; 32];
+
+type Link<T> = Option<Arc<Node<T>>>;
+
+#[derive(Clone)]
+struct Node<T> {
+    value: T,
+    height: u8,
+    hash: MerkleHash,
+    left: Link<T>,
+    right: Link<T>,
+}
+
+fn height<T>(link: &Link<T>) -> u8 {
+    link.as_ref().map_or(0, |n| n.height)
+}
+
+fn hash<T>(link: &Link<T>) -> MerkleHash {
+    link.as_ref().map_or([0; 32], |n| n.hash)
+}
+
+fn node<T: SnapshotElement>(value: T, left: Link<T>, right: Link<T>) -> Arc<Node<T>> {
+    let mut encoded = Vec::new();
+    value.write_to(&mut encoded).expect("writing to a Vec can't fail");
+    let mut hasher = Sha256::new();
+    hasher.update(hash(&left));
+    hasher.update(&encoded);
+    hasher.update(hash(&right));
+    let height = 1 + max(height(&left), height(&right));
+    Arc::new(Node { value, height, hash: hasher.finalize().into(), left, right })
+}
+
+/// Takes the contents of `node`, cloning them only if another version shares it.
+fn open<T: Clone>(node: Arc<Node<T>>) -> Node<T> {
+    Arc::unwrap_or_clone(node)
+}
+
+/// Makes a node out of `value` and two subtrees whose heights differ by 2 at most, rotating
+/// if they do.
+fn balance<T: Clone + SnapshotElement>(value: T, left: Link<T>, right: Link<T>) -> Arc<Node<T>> {
+    let (hl, hr) = (height(&left), height(&right));
+    if hl > hr + 1 {
+        let l = open(left.unwrap());
+        if height(&l.left) >= height(&l.right) {
+            node(l.value, l.left, Some(node(value, l.right, right)))
+        }
+        else { // left-right case
+            let lr = open(l.right.unwrap());
+            node(lr.value, Some(node(l.value, l.left, lr.left)), Some(node(value, lr.right, right)))
+        }
+    }
+    else if hr > hl + 1 {
+        let r = open(right.unwrap());
+        if height(&r.right) >= height(&r.left) {
+            node(r.value, Some(node(value, left, r.left)), r.right)
+        }
+        else { // right-left case
+            let rl = open(r.left.unwrap());
+            node(rl.value, Some(node(value, left, rl.left)), Some(node(r.value, rl.right, r.right)))
+        }
+    }
+    else {
+        node(value, left, right)
+    }
+}
+
+/// Joins two subtrees of any heights with `value` in between, all elements of `left` being
+/// smaller than `value` and all elements of `right` larger.
+fn join<T: Clone + SnapshotElement>(left: Link<T>, value: T, right: Link<T>) -> Arc<Node<T>> {
+    let (hl, hr) = (height(&left), height(&right));
+    if hl > hr + 1 {
+        let l = open(left.unwrap());
+        balance(l.value, l.left, Some(join(l.right, value, right)))
+    }
+    else if hr > hl + 1 {
+        let r = open(right.unwrap());
+        balance(r.value, Some(join(left, value, r.left)), r.right)
+    }
+    else {
+        node(value, left, right)
+    }
+}
+
+/// Joins two subtrees of any heights, all elements of `left` being smaller than the ones of
+/// `right`.
+fn concat<T: Clone + SnapshotElement>(left: Link<T>, right: Link<T>) -> Link<T> {
+    let Some(right) = right else {
+        return left;
+    };
+    let (first, right) = remove_first(open(right));
+    Some(join(left, first, right))
+}
+
+/// Returns the smallest element of the subtree at `n` and the subtree without it.
+fn remove_first<T: Clone + SnapshotElement>(n: Node<T>) -> (T, Link<T>) {
+    match n.left {
+        None => (n.value, n.right),
+        Some(left) => {
+            let (first, left) = remove_first(open(left));
+            (first, Some(join(left, n.value, n.right)))
+        }
+    }
+}
+
+/// Splits the subtree at `link` into the elements smaller than `key` and the ones larger,
+/// telling whether it has an element equal to `key`. Splitting at the element of the root
+/// returns its subtrees as they are.
+fn split<T, Q>(link: &Link<T>, key: &Q) -> (Link<T>, bool, Link<T>)
+where T: Borrow<Q> + Clone + SnapshotElement, Q: ?Sized + Ord {
+    let Some(n) = link else {
+        return (None, false, None);
+    };
+    match key.cmp(n.value.borrow()) {
+        Ordering::Equal => (n.left.clone(), true, n.right.clone()),
+        Ordering::Less => {
+            let (left, found, right) = split(&n.left, key);
+            (left, found, Some(join(right, n.value.clone(), n.right.clone())))
+        }
+        Ordering::Greater => {
+            let (left, found, right) = split(&n.right, key);
+            (Some(join(n.left.clone(), n.value.clone(), left)), found, right)
+        }
+    }
+}
+
+fn push_all<T: Clone>(link: &Link<T>, out: &mut Vec<T>) {
+    if let Some(n) = link {
+        push_all(&n.left, out);
+        out.push(n.value.clone());
+        push_all(&n.right, out);
+    }
+}
+
+/// Appends the elements of `a` missing from `b` to `only_a`, and the ones of `b` missing from
+/// `a` to `only_b`, skipping the subtrees with identical hashes.
+fn diff<T>(a: &Link<T>, b: Link<T>, only_a: &mut Vec<T>, only_b: &mut Vec<T>) where T: Ord + Clone + SnapshotElement {
+    if hash(a) == hash(&b) {
+        return;
+    }
+    let Some(n) = a else {
+        return push_all(&b, only_b);
+    };
+    if b.is_none() {
+        return push_all(a, only_a);
+    }
+    // when the shapes match, this gives the subtrees of `b` without building any node
+    let (left, found, right) = split(&b, &n.value);
+    diff(&n.left, left, only_a, only_b);
+    if !found {
+        only_a.push(n.value.clone());
+    }
+    diff(&n.right, right, only_a, only_b);
+}
+
+/// An ordered set based on a persistent AVL tree whose nodes hash their subtrees, for checking
+/// and synchronizing replicas.
+///
+/// Changes take O(log n) time and allocate as many nodes, each hashing its element once; `clone`
+/// takes O(1).
+pub struct AVLTreeMerkle<T> {
+    root: Link<T>,
+    len: usize,
+}
+
+impl<T: Ord + Clone + SnapshotElement> AVLTreeMerkle<T> {
+
+    /// Creates an empty set.
+    pub const fn new() -> Self {
+        AVLTreeMerkle { root: None, len: 0 }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the hash of the whole tree, 32 zero bytes if it is empty.
+    pub fn root_hash(&self) -> MerkleHash {
+        hash(&self.root)
+    }
+
+    /// Returns `true` if the set contains `value`.
+    pub fn contains<Q: ?Sized + Ord>(&self, value: &Q) -> bool where T: Borrow<Q> {
+        let mut next = &self.root;
+        while let Some(n) = next {
+            next = match value.cmp(n.value.borrow()) {
+                Ordering::Equal => return true,
+                Ordering::Less => &n.left,
+                Ordering::Greater => &n.right,
+            };
+        }
+        false
+    }
+
+    /// Adds `value` to the set. Returns `false` if an equal element was already present, in
+    /// which case the set is left untouched.
+    pub fn insert(&mut self, value: T) -> bool {
+        let (left, found, right) = split(&self.root, &value);
+        if found {
+            return false;
+        }
+        self.root = Some(join(left, value, right));
+        self.len += 1;
+        true
+    }
+
+    /// Removes `value` from the set. Returns `false` if it wasn't present.
+    pub fn remove<Q: ?Sized + Ord>(&mut self, value: &Q) -> bool where T: Borrow<Q> {
+        let (left, found, right) = split(&self.root, value);
+        if !found {
+            return false;
+        }
+        self.root = concat(left, right);
+        self.len -= 1;
+        true
+    }
+
+    /// Returns the elements only in `self` and the elements only in `other`, both in sorted
+    /// order.
+    ///
+    /// Subtrees hashing the same are skipped whole, so that diffing trees of the same shape
+    /// costs O(d log n) for d differences. Diffing trees of unrelated shapes costs up to
+    /// O(n log n).
+    pub fn diff(&self, other: &AVLTreeMerkle<T>) -> (Vec<T>, Vec<T>) {
+        let (mut only_self, mut only_other) = (Vec::new(), Vec::new());
+        diff(&self.root, other.root.clone(), &mut only_self, &mut only_other);
+        (only_self, only_other)
+    }
+
+    /// Returns an iterator over the elements of the set, in sorted order.
+    pub fn iter(&self) -> Iter<'_, T> {
+        let mut iter = Iter { stack: Vec::new() };
+        iter.push_left(&self.root);
+        iter
+    }
+}
+
+impl<T> Clone for AVLTreeMerkle<T> {
+    fn clone(&self) -> Self {
+        AVLTreeMerkle { root: self.root.clone(), len: self.len }
+    }
+}
+
+impl<T: Ord + Clone + SnapshotElement> Default for AVLTreeMerkle<T> {
+    fn default() -> Self {
+        AVLTreeMerkle::new()
+    }
+}
+
+impl<T: Ord + Clone + SnapshotElement> FromIterator<T> for AVLTreeMerkle<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut tree = AVLTreeMerkle::new();
+        tree.extend(iter);
+        tree
+    }
+}
+
+impl<T: Ord + Clone + SnapshotElement> Extend<T> for AVLTreeMerkle<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for value in iter {
+            self.insert(value);
+        }
+    }
+}
+
+/// An iterator over the elements of an `AVLTreeMerkle`, in sorted order.
+pub struct Iter<'a, T> {
+    /// The next node, on top, and its ancestors whose element comes after.
+    stack: Vec<&'a Node<T>>,
+}
+
+impl<'a, T> Iter<'a, T> {
+
+    fn push_left(&mut self, mut link: &'a Link<T>) {
+        while let Some(n) = link {
+            self.stack.push(n);
+            link = &n.left;
+        }
+    }
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let n = self.stack.pop()?;
+        self.push_left(&n.right);
+        Some(&n.value)
+    }
+}
+
+impl<'a, T: Ord + Clone + SnapshotElement> IntoIterator for &'a AVLTreeMerkle<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check<T: Ord + Clone + SnapshotElement>(link: &Link<T>) -> u8 {
+        let Some(n) = link else {
+            return 0;
+        };
+        let (hl, hr) = (check(&n.left), check(&n.right));
+        assert!(hl.abs_diff(hr) <= 1);
+        assert_eq!(n.height, 1 + max(hl, hr));
+        assert_eq!(n.hash, node(n.value.clone(), n.left.clone(), n.right.clone()).hash);
+        n.height
+    }
+
+    #[test]
+    fn test_merkle() {
+        let mut tree = AVLTreeMerkle::new();
+        assert_eq!(tree.root_hash(), [0; 32]);
+        for i in 0..200u32 {
+            assert!(tree.insert((i * 37) % 200));
+            check(&tree.root);
+        }
+        assert!(!tree.insert(5));
+        assert!(tree.iter().copied().eq(0..200));
+        for i in (0..200).filter(|i| i % 3 != 0) {
+            assert!(tree.remove(&i));
+            check(&tree.root);
+        }
+        assert!(!tree.remove(&1));
+        assert!(tree.contains(&3) && !tree.contains(&1));
+        assert!(tree.iter().copied().eq((0..200).step_by(3)));
+        assert_eq!(tree.len(), 67);
+    }
+
+    #[test]
+    fn test_merkle_diff() {
+        let base: AVLTreeMerkle<u32> = (0..1000).collect();
+        let mut replica = base.clone();
+        assert_eq!(replica.root_hash(), base.root_hash());
+        // the same operations give the same shape, hence the same hash
+        let mut other = base.clone();
+        for tree in [&mut replica, &mut other] {
+            tree.remove(&500);
+            tree.insert(2000);
+        }
+        assert_eq!(replica.root_hash(), other.root_hash());
+        assert_ne!(replica.root_hash(), base.root_hash());
+
+        replica.remove(&10);
+        replica.insert(1500);
+        let (only_replica, only_base) = replica.diff(&base);
+        assert_eq!(only_replica, vec![1500, 2000]);
+        assert_eq!(only_base, vec![10, 500]);
+        assert_eq!(replica.diff(&replica), (vec![], vec![]));
+
+        // unrelated shapes still diff right
+        let evens: AVLTreeMerkle<u32> = (0..1000).step_by(2).rev().collect();
+        let (only_base, only_evens) = base.diff(&evens);
+        assert_eq!(only_base, (1..1000).step_by(2).collect::<Vec<_>>());
+        assert!(only_evens.is_empty());
+    }
+}
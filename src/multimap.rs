@@ -0,0 +1,288 @@
+//! An ordered map in which a key can be associated with several values.
+
+use std::borrow::Borrow;
+use std::slice;
+
+use crate::map::{self, AVLMap, Entry};
+
+/// An ordered multimap based on an AVL tree.
+///
+/// Each distinct key occupies a single node of the tree, its values are kept in insertion
+/// order.
+pub struct AVLMultiMap<K: Ord, V> {
+    map: AVLMap<K, Vec<V>>,
+    len: usize,
+}
+
+impl<K: Ord, V> AVLMultiMap<K, V> {
+
+    /// Creates an empty multimap.
+    pub fn new() -> Self {
+        AVLMultiMap { map: AVLMap::new(), len: 0 }
+    }
+
+    /// Returns the number of values in the multimap, counting every value of every key.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the number of distinct keys in the multimap.
+    pub fn keys_len(&self) -> usize {
+        self.map.len()
+    }
+
+    /// Returns `true` if at least one value is stored for `key`.
+    pub fn contains_key<Q: ?Sized + Ord>(&self, key: &Q) -> bool where K: Borrow<Q> {
+        self.map.contains_key(key)
+    }
+
+    /// Adds `value` to the values of `key`, after the ones already stored for it.
+    pub fn insert(&mut self, key: K, value: V) {
+        match self.map.entry(key) {
+            Entry::Occupied(mut entry) => entry.get_mut().push(value),
+            Entry::Vacant(entry) => { entry.insert(vec![value]); },
+        }
+        self.len += 1;
+    }
+
+    /// Returns an iterator over the values of `key`, in insertion order.
+    pub fn get_all<Q: ?Sized + Ord>(&self, key: &Q) -> GetAll<'_, V> where K: Borrow<Q> {
+        let values = self.map.get(key).map_or(&[][..], Vec::as_slice);
+        GetAll { inner: values.iter() }
+    }
+
+    /// Removes the first value of `key` equal to `value`, returning `true` if there was one.
+    ///
+    /// The key goes away along with its last value.
+    pub fn remove_one<Q: ?Sized + Ord>(&mut self, key: &Q, value: &V) -> bool where K: Borrow<Q>, V: PartialEq {
+        let Some(values) = self.map.get_mut(key)
+        else {
+            return false;
+        };
+        let Some(i) = values.iter().position(|v| v == value)
+        else {
+            return false;
+        };
+        values.remove(i);
+        if values.is_empty() {
+            self.map.remove(key);
+        }
+        self.len -= 1;
+        true
+    }
+
+    /// Removes `key` along with all of its values, returning the values in insertion order.
+    pub fn remove_all<Q: ?Sized + Ord>(&mut self, key: &Q) -> Vec<V> where K: Borrow<Q> {
+        let values = self.map.remove(key).unwrap_or_default();
+        self.len -= values.len();
+        values
+    }
+
+    /// Returns an iterator over the keys of the multimap, in sorted order and without
+    /// repetitions.
+    pub fn keys(&self) -> Keys<'_, K, V> {
+        Keys { inner: self.map.keys() }
+    }
+
+    /// Returns an iterator over all the key-value pairs, sorted by key; the values of a key
+    /// come in insertion order.
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter { entries: self.map.iter(), front: None, back: None, remaining: self.len }
+    }
+}
+
+impl<K: Ord, V> Default for AVLMultiMap<K, V> {
+    fn default() -> Self {
+        AVLMultiMap::new()
+    }
+}
+
+/// An iterator over the values of a key of an `AVLMultiMap`.
+///
+/// Created by [`AVLMultiMap::get_all`].
+pub struct GetAll<'a, V> {
+    inner: slice::Iter<'a, V>,
+}
+
+impl<'a, V> Iterator for GetAll<'a, V> {
+    type Item = &'a V;
+
+    fn next(&mut self) -> Option<&'a V> {
+        self.inner.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a, V> DoubleEndedIterator for GetAll<'a, V> {
+    fn next_back(&mut self) -> Option<&'a V> {
+        self.inner.next_back()
+    }
+}
+
+impl<V> ExactSizeIterator for GetAll<'_, V> {}
+
+/// An iterator over the distinct keys of an `AVLMultiMap`.
+///
+/// Created by [`AVLMultiMap::keys`].
+pub struct Keys<'a, K: Ord, V> {
+    inner: map::Keys<'a, K, Vec<V>>,
+}
+
+impl<'a, K: Ord, V> Iterator for Keys<'a, K, V> {
+    type Item = &'a K;
+
+    fn next(&mut self) -> Option<&'a K> {
+        self.inner.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a, K: Ord, V> DoubleEndedIterator for Keys<'a, K, V> {
+    fn next_back(&mut self) -> Option<&'a K> {
+        self.inner.next_back()
+    }
+}
+
+impl<K: Ord, V> ExactSizeIterator for Keys<'_, K, V> {}
+
+/// An iterator over the key-value pairs of an `AVLMultiMap`.
+///
+/// Created by [`AVLMultiMap::iter`].
+pub struct Iter<'a, K: Ord, V> {
+    entries: map::Iter<'a, K, Vec<V>>,
+    front: Option<(&'a K, slice::Iter<'a, V>)>,
+    back: Option<(&'a K, slice::Iter<'a, V>)>,
+    remaining: usize,
+}
+
+impl<'a, K: Ord, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<(&'a K, &'a V)> {
+        loop {
+            if let Some((key, values)) = &mut self.front {
+                if let Some(value) = values.next() {
+                    self.remaining -= 1;
+                    return Some((key, value));
+                }
+            }
+            match self.entries.next() {
+                Some((key, values)) => self.front = Some((key, values.iter())),
+                // the last key may already be partly consumed from the back
+                None => {
+                    let (key, values) = self.back.as_mut()?;
+                    let value = values.next()?;
+                    self.remaining -= 1;
+                    return Some((key, value));
+                }
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, K: Ord, V> DoubleEndedIterator for Iter<'a, K, V> {
+    fn next_back(&mut self) -> Option<(&'a K, &'a V)> {
+        loop {
+            if let Some((key, values)) = &mut self.back {
+                if let Some(value) = values.next_back() {
+                    self.remaining -= 1;
+                    return Some((key, value));
+                }
+            }
+            match self.entries.next_back() {
+                Some((key, values)) => self.back = Some((key, values.iter())),
+                None => {
+                    let (key, values) = self.front.as_mut()?;
+                    let value = values.next_back()?;
+                    self.remaining -= 1;
+                    return Some((key, value));
+                }
+            }
+        }
+    }
+}
+
+impl<K: Ord, V> ExactSizeIterator for Iter<'_, K, V> {}
+
+impl<'a, K: Ord, V> IntoIterator for &'a AVLMultiMap<K, V> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Iter<'a, K, V> {
+        self.iter()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_multimap() {
+        let mut map = AVLMultiMap::new();
+        map.insert("b", 1);
+        map.insert("a", 2);
+        map.insert("b", 3);
+        map.insert("c", 4);
+        map.insert("b", 1);
+        assert_eq!(map.len(), 5);
+        assert_eq!(map.keys_len(), 3);
+        assert_eq!(map.get_all("b").copied().collect::<Vec<_>>(), vec![1, 3, 1]);
+        assert_eq!(map.get_all("z").next(), None);
+
+        assert!(map.remove_one("b", &1));
+        assert_eq!(map.get_all("b").copied().collect::<Vec<_>>(), vec![3, 1]);
+        assert!(!map.remove_one("b", &7));
+        assert!(!map.remove_one("z", &1));
+        assert!(map.remove_one("a", &2));
+        assert!(!map.contains_key("a"));
+        assert_eq!(map.len(), 3);
+
+        assert_eq!(map.remove_all("b"), vec![3, 1]);
+        assert_eq!(map.remove_all("b"), Vec::<i32>::new());
+        assert_eq!(map.len(), 1);
+        assert_eq!(map.keys().copied().collect::<Vec<_>>(), vec!["c"]);
+    }
+
+    #[test]
+    fn test_multimap_iter() {
+        let mut map = AVLMultiMap::new();
+        for (k, v) in [(2, 'x'), (1, 'a'), (2, 'y'), (3, 'c'), (2, 'z')] {
+            map.insert(k, v);
+        }
+        let pairs: Vec<(i32, char)> = map.iter().map(|(k, v)| (*k, *v)).collect();
+        assert_eq!(pairs, vec![(1, 'a'), (2, 'x'), (2, 'y'), (2, 'z'), (3, 'c')]);
+        let reversed: Vec<(i32, char)> = map.iter().rev().map(|(k, v)| (*k, *v)).collect();
+        assert_eq!(reversed, pairs.iter().rev().copied().collect::<Vec<_>>());
+
+        // both ends meeting inside the values of a single key
+        let mut single = AVLMultiMap::new();
+        for v in 0..4 {
+            single.insert(0, v);
+        }
+        let mut iter = single.iter();
+        assert_eq!(iter.len(), 4);
+        assert_eq!(iter.next(), Some((&0, &0)));
+        assert_eq!(iter.next_back(), Some((&0, &3)));
+        assert_eq!(iter.next(), Some((&0, &1)));
+        assert_eq!(iter.next_back(), Some((&0, &2)));
+        assert_eq!(iter.len(), 0);
+        assert_eq!(iter.next(), None);
+        assert_eq!(iter.next_back(), None);
+    }
+}
@@ -0,0 +1,222 @@
+//! An ordered multiset keeping a count for each distinct element.
+
+use std::borrow::Borrow;
+
+use crate::map::{self, AVLMap, Entry};
+
+/// An ordered multiset based on an AVL tree.
+///
+/// Equal elements share a single node of the tree, which stores how many times the element
+/// was inserted; only the first inserted of the equal elements is kept.
+pub struct AVLMultiSet<T: Ord> {
+    map: AVLMap<T, usize>,
+    len: usize,
+}
+
+impl<T: Ord> AVLMultiSet<T> {
+
+    /// Creates an empty multiset.
+    pub fn new() -> Self {
+        AVLMultiSet { map: AVLMap::new(), len: 0 }
+    }
+
+    /// Returns the number of elements in the multiset, counting repetitions.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the number of distinct elements in the multiset.
+    pub fn distinct_len(&self) -> usize {
+        self.map.len()
+    }
+
+    /// Returns how many times `value` is in the multiset.
+    pub fn count<Q: ?Sized + Ord>(&self, value: &Q) -> usize where T: Borrow<Q> {
+        self.map.get(value).copied().unwrap_or(0)
+    }
+
+    /// Returns `true` if `value` is in the multiset at least once.
+    pub fn contains<Q: ?Sized + Ord>(&self, value: &Q) -> bool where T: Borrow<Q> {
+        self.map.contains_key(value)
+    }
+
+    /// Adds one occurrence of `value`, returning its count afterwards.
+    pub fn insert(&mut self, value: T) -> usize {
+        self.len += 1;
+        match self.map.entry(value) {
+            Entry::Occupied(mut entry) => {
+                *entry.get_mut() += 1;
+                *entry.get()
+            }
+            Entry::Vacant(entry) => *entry.insert(1),
+        }
+    }
+
+    /// Removes one occurrence of `value`, returning `true` if there was one.
+    pub fn remove_one<Q: ?Sized + Ord>(&mut self, value: &Q) -> bool where T: Borrow<Q> {
+        let Some(count) = self.map.get_mut(value)
+        else {
+            return false;
+        };
+        *count -= 1;
+        if *count == 0 {
+            self.map.remove(value);
+        }
+        self.len -= 1;
+        true
+    }
+
+    /// Removes every occurrence of `value`, returning how many there were.
+    pub fn remove_all<Q: ?Sized + Ord>(&mut self, value: &Q) -> usize where T: Borrow<Q> {
+        let count = self.map.remove(value).unwrap_or(0);
+        self.len -= count;
+        count
+    }
+
+    /// Returns an iterator over the elements in sorted order, each repeated as many times as
+    /// it is in the multiset.
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter { entries: self.map.iter(), front: None, back: None, remaining: self.len }
+    }
+
+    /// Returns an iterator over the distinct elements in sorted order, along with their counts.
+    pub fn iter_counts(&self) -> map::Iter<'_, T, usize> {
+        self.map.iter()
+    }
+}
+
+impl<T: Ord> Default for AVLMultiSet<T> {
+    fn default() -> Self {
+        AVLMultiSet::new()
+    }
+}
+
+/// An iterator over the elements of an `AVLMultiSet`, repeated by multiplicity.
+///
+/// Created by [`AVLMultiSet::iter`].
+pub struct Iter<'a, T: Ord> {
+    entries: map::Iter<'a, T, usize>,
+    front: Option<(&'a T, usize)>,
+    back: Option<(&'a T, usize)>,
+    remaining: usize,
+}
+
+/// Takes one repetition out of `run`, if any is left.
+fn take_one<'a, T>(run: &mut Option<(&'a T, usize)>) -> Option<&'a T> {
+    match run {
+        Some((value, count)) if *count > 0 => {
+            *count -= 1;
+            Some(*value)
+        }
+        _ => None,
+    }
+}
+
+impl<'a, T: Ord> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        if let Some(value) = take_one(&mut self.front) {
+            return Some(value);
+        }
+        // the last element may already be partly consumed from the back
+        match self.entries.next() {
+            Some((value, &count)) => {
+                self.front = Some((value, count - 1));
+                Some(value)
+            }
+            None => take_one(&mut self.back),
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, T: Ord> DoubleEndedIterator for Iter<'a, T> {
+    fn next_back(&mut self) -> Option<&'a T> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        if let Some(value) = take_one(&mut self.back) {
+            return Some(value);
+        }
+        match self.entries.next_back() {
+            Some((value, &count)) => {
+                self.back = Some((value, count - 1));
+                Some(value)
+            }
+            None => take_one(&mut self.front),
+        }
+    }
+}
+
+impl<T: Ord> ExactSizeIterator for Iter<'_, T> {}
+
+impl<'a, T: Ord> IntoIterator for &'a AVLMultiSet<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_multiset() {
+        let mut set = AVLMultiSet::new();
+        for v in [3, 1, 3, 2, 3, 1] {
+            set.insert(v);
+        }
+        assert_eq!(set.len(), 6);
+        assert_eq!(set.distinct_len(), 3);
+        assert_eq!(set.count(&3), 3);
+        assert_eq!(set.count(&4), 0);
+        assert_eq!(set.insert(2), 2);
+        assert_eq!(set.iter().copied().collect::<Vec<_>>(), vec![1, 1, 2, 2, 3, 3, 3]);
+        assert_eq!(set.iter_counts().map(|(v, c)| (*v, *c)).collect::<Vec<_>>(), vec![(1, 2), (2, 2), (3, 3)]);
+
+        assert!(set.remove_one(&1));
+        assert!(set.remove_one(&1));
+        assert!(!set.remove_one(&1));
+        assert!(!set.contains(&1));
+        assert_eq!(set.remove_all(&3), 3);
+        assert_eq!(set.remove_all(&3), 0);
+        assert_eq!(set.len(), 2);
+        assert_eq!(set.iter().copied().collect::<Vec<_>>(), vec![2, 2]);
+    }
+
+    #[test]
+    fn test_multiset_iter() {
+        let mut set = AVLMultiSet::new();
+        for v in [1, 2, 2, 2, 3] {
+            set.insert(v);
+        }
+        assert_eq!(set.iter().rev().copied().collect::<Vec<_>>(), vec![3, 2, 2, 2, 1]);
+
+        let mut iter = set.iter();
+        assert_eq!(iter.next(), Some(&1));
+        assert_eq!(iter.next(), Some(&2));
+        assert_eq!(iter.next_back(), Some(&3));
+        assert_eq!(iter.next_back(), Some(&2));
+        assert_eq!(iter.len(), 1);
+        assert_eq!(iter.next(), Some(&2));
+        assert_eq!(iter.next(), None);
+        assert_eq!(iter.next_back(), None);
+    }
+}
@@ -0,0 +1,202 @@
+//! Lookups of the elements closest to a value, for types with a distance.
+
+use std::cmp::Ordering;
+use std::time::{Duration, Instant};
+
+use crate::{neighbour, peek, AVLTree, Side, TotalOrd, TotalOrder};
+
+/// A distance between values, consistent with their order: the further apart two values are in
+/// sorted order, the larger their distance.
+pub trait Distance {
+    type Output: Ord;
+
+    fn distance(&self, other: &Self) -> Self::Output;
+}
+
+macro_rules! int_distance {
+    ($($t:ty => $d:ty),*) => {
+        $(impl Distance for $t {
+            type Output = $d;
+
+            fn distance(&self, other: &Self) -> $d {
+                self.abs_diff(*other)
+            }
+        })*
+    };
+}
+
+int_distance!(
+    i8 => u8, i16 => u16, i32 => u32, i64 => u64, i128 => u128, isize => usize,
+    u8 => u8, u16 => u16, u32 => u32, u64 => u64, u128 => u128, usize => usize
+);
+
+/// The distance between two floats is the absolute value of their difference, ordered as they
+/// are.
+impl<T: TotalOrder + Copy + std::ops::Sub<Output = T> + PartialOrd> Distance for TotalOrd<T> {
+    type Output = TotalOrd<T>;
+
+    fn distance(&self, other: &Self) -> TotalOrd<T> {
+        TotalOrd(if self.0 > other.0 { self.0 - other.0 } else { other.0 - self.0 })
+    }
+}
+
+impl Distance for Duration {
+    type Output = Duration;
+
+    fn distance(&self, other: &Self) -> Duration {
+        self.abs_diff(*other)
+    }
+}
+
+impl Distance for Instant {
+    type Output = Duration;
+
+    fn distance(&self, other: &Self) -> Duration {
+        self.max(other).duration_since(*self.min(other))
+    }
+}
+
+/// Which of two elements as close to a value [`AVLTree::nearest_with`] returns, and
+/// [`AVLTree::nearest_k_with`] returns first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tie {
+    /// The one smaller than the value.
+    Lower,
+    /// The one larger than the value.
+    Higher,
+}
+
+impl<T: Ord + Distance> AVLTree<T> {
+
+    /// Returns the element closest to `x`, the smaller one if two are as close, or `None` if the
+    /// tree is empty.
+    pub fn nearest(&self, x: &T) -> Option<&T> {
+        self.nearest_with(x, Tie::Lower)
+    }
+
+    /// Returns the element closest to `x`, settling ties as told by `tie`, or `None` if the
+    /// tree is empty.
+    ///
+    /// A single descent finds the largest element not greater than `x` and the smallest one not
+    /// smaller, between which the closest one is.
+    pub fn nearest_with(&self, x: &T, tie: Tie) -> Option<&T> {
+        let (mut below, mut above) = (None, None);
+        let mut next = self.root.as_ref();
+        while let Some(node) = next {
+            let n = peek(node);
+            match n.value.cmp(x) {
+                Ordering::Equal => return Some(&n.value),
+                Ordering::Less => {
+                    below = Some(&n.value);
+                    next = n.right.as_ref();
+                }
+                Ordering::Greater => {
+                    above = Some(&n.value);
+                    next = n.left.as_ref();
+                }
+            }
+        }
+        match (below, above) {
+            (Some(b), Some(a)) => Some(match closer(x, b, a, tie) {
+                Side::Left => b,
+                Side::Right => a,
+            }),
+            (b, a) => b.or(a),
+        }
+    }
+
+    /// Returns the `k` elements closest to `x`, or all of them if there are fewer, from the
+    /// closest to the furthest. Of two elements as close, the smaller one comes first.
+    pub fn nearest_k(&self, x: &T, k: usize) -> Vec<&T> {
+        self.nearest_k_with(x, k, Tie::Lower)
+    }
+
+    /// Returns the `k` elements closest to `x`, or all of them if there are fewer, from the
+    /// closest to the furthest, ordering elements as close as told by `tie`.
+    ///
+    /// Two cursors start on both sides of where `x` would be inserted and move away from it, so
+    /// this takes O(log n + k).
+    pub fn nearest_k_with(&self, x: &T, k: usize, tie: Tie) -> Vec<&T> {
+        let mut below = self.last_where(|v| v < x);
+        let mut above = self.first_where_not(|v| v < x);
+        let mut nearest = Vec::with_capacity(k.min(self.len()));
+        while nearest.len() < k {
+            let side = match (below, above) {
+                (Some(b), Some(a)) => closer(x, &peek(b).value, &peek(a).value, tie),
+                (Some(_), None) => Side::Left,
+                (None, Some(_)) => Side::Right,
+                (None, None) => break,
+            };
+            let cursor = match side {
+                Side::Left => &mut below,
+                Side::Right => &mut above,
+            };
+            let node = cursor.unwrap();
+            nearest.push(&peek(node).value);
+            *cursor = neighbour(&self.root, node, side);
+        }
+        nearest
+    }
+}
+
+/// Tells which of `below` and `above`, lying on either side of `x`, is closer to it.
+fn closer<T: Distance>(x: &T, below: &T, above: &T, tie: Tie) -> Side {
+    match below.distance(x).cmp(&above.distance(x)) {
+        Ordering::Less => Side::Left,
+        Ordering::Greater => Side::Right,
+        Ordering::Equal => match tie {
+            Tie::Lower => Side::Left,
+            Tie::Higher => Side::Right,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nearest() {
+        let tree: AVLTree<i32> = (0..50).map(|i| i * 10).collect();
+        assert_eq!(tree.nearest(&42), Some(&40));
+        assert_eq!(tree.nearest(&47), Some(&50));
+        assert_eq!(tree.nearest(&70), Some(&70));
+        assert_eq!(tree.nearest(&-100), Some(&0));
+        assert_eq!(tree.nearest(&1000), Some(&490));
+        assert_eq!(tree.nearest(&45), Some(&40));
+        assert_eq!(tree.nearest_with(&45, Tie::Higher), Some(&50));
+        assert_eq!(AVLTree::<u8>::new().nearest(&3), None);
+        for x in -20..520 {
+            let best = tree.iter().min_by_key(|v| v.abs_diff(x)).unwrap();
+            assert_eq!(tree.nearest(&x), Some(best));
+        }
+
+        let floats: AVLTree<_> = [0.5, 1.25, 3.0].into_iter().map(TotalOrd).collect();
+        assert_eq!(floats.nearest(&TotalOrd(2.0)), Some(&TotalOrd(1.25)));
+        assert_eq!(floats.nearest(&TotalOrd(2.25)), Some(&TotalOrd(3.0)));
+
+        let start = Instant::now();
+        let samples: AVLTree<_> = (0..10).map(|i| start + Duration::from_millis(i * 100)).collect();
+        let snapped = samples.nearest(&(start + Duration::from_millis(430)));
+        assert_eq!(snapped, Some(&(start + Duration::from_millis(400))));
+    }
+
+    #[test]
+    fn test_nearest_k() {
+        let tree: AVLTree<i32> = (0..50).map(|i| i * 10).collect();
+        assert_eq!(tree.nearest_k(&42, 4), [&40, &50, &30, &60]);
+        assert_eq!(tree.nearest_k(&45, 2), [&40, &50]);
+        assert_eq!(tree.nearest_k_with(&45, 2, Tie::Higher), [&50, &40]);
+        assert_eq!(tree.nearest_k(&30, 3), [&30, &20, &40]);
+        assert_eq!(tree.nearest_k(&-5, 3), [&0, &10, &20]);
+        assert_eq!(tree.nearest_k(&600, 2), [&490, &480]);
+        assert_eq!(tree.nearest_k(&42, 0), Vec::<&i32>::new());
+        assert_eq!(tree.nearest_k(&42, 100).len(), 50);
+        for x in [-20, 3, 255, 499, 700] {
+            let mut sorted: Vec<_> = tree.iter().collect();
+            sorted.sort_by_key(|v| (v.abs_diff(x), **v));
+            assert_eq!(tree.nearest_k(&x, 50), sorted);
+        }
+        assert!(AVLTree::<u8>::new().nearest_k(&3, 5).is_empty());
+    }
+}
@@ -0,0 +1,224 @@
+//! Logs of the changes made to a tree, to rebuild the states it went through by replaying
+//! them.
+//!
+//! The tree being deterministic, replaying a log on an empty tree gives back the same elements
+//! and the same shape, down to the balance factors, as the tree the log was recorded from.
+//!
+//! A log is written in the same manner as a snapshot: the magic bytes `AVLL` and a format
+//! version byte, then the number of operations as a little-endian `u64`, then the operations,
+//! each a tag byte followed by its element encoded by its [`SnapshotElement`] impl.
+
+use std::io::{self, Read, Write};
+
+use crate::snapshot::invalid;
+use crate::{AVLTree, DuplicatePolicy, SnapshotElement};
+
+const MAGIC: &[u8; 4] = b"AVLL";
+const VERSION: u8 = 1;
+
+/// The policies of `InsertWithPolicy` operations, tagged 1, 2 and 3.
+const POLICIES: [DuplicatePolicy; 3] = [DuplicatePolicy::Reject, DuplicatePolicy::Replace, DuplicatePolicy::KeepBoth];
+
+/// A change to an [`AVLTree`], as recorded in an [`OperationLog`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Operation<T> {
+    /// Inserts the value, unless an equal one is present, as `insert` does.
+    Insert(T),
+    /// Inserts the value as `insert_with_policy` does.
+    InsertWithPolicy(T, DuplicatePolicy),
+    /// Removes the element equal to the value, as `remove` does.
+    Remove(T),
+    /// Rebuilds the tree into a perfectly balanced one.
+    Rebuild,
+}
+
+impl<T: Ord> Operation<T> {
+
+    /// Runs the operation on `tree`. Returns the element left out of the tree, if any: a
+    /// rejected value, or a replaced or removed element.
+    pub fn apply(self, tree: &mut AVLTree<T>) -> Option<T> {
+        match self {
+            Operation::Insert(value) => tree.try_insert(value).err().map(|e| e.value),
+            Operation::InsertWithPolicy(value, policy) => tree.insert_with_policy(value, policy),
+            Operation::Remove(value) => tree.take(&value),
+            Operation::Rebuild => {
+                tree.rebuild();
+                None
+            }
+        }
+    }
+}
+
+impl<T: SnapshotElement> Operation<T> {
+
+    /// Writes the tag of the operation, then its element if it has one.
+    pub(crate) fn write_to<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let (tag, value) = match self {
+            Operation::Insert(value) => (0, Some(value)),
+            Operation::InsertWithPolicy(value, policy) => {
+                (1 + POLICIES.iter().position(|p| p == policy).unwrap() as u8, Some(value))
+            }
+            Operation::Remove(value) => (4, Some(value)),
+            Operation::Rebuild => (5, None),
+        };
+        tag.write_to(w)?;
+        value.map_or(Ok(()), |value| value.write_to(w))
+    }
+
+    /// Reads an operation written by `write_to`.
+    pub(crate) fn read_from<R: Read>(r: &mut R) -> io::Result<Self> {
+        Ok(match u8::read_from(r)? {
+            0 => Operation::Insert(T::read_from(r)?),
+            tag @ 1..=3 => Operation::InsertWithPolicy(T::read_from(r)?, POLICIES[tag as usize - 1]),
+            4 => Operation::Remove(T::read_from(r)?),
+            5 => Operation::Rebuild,
+            _ => return Err(invalid("unknown operation in log")),
+        })
+    }
+}
+
+/// A sequence of operations on a tree, made by recording them as they are run with
+/// [`record`](Self::record), and run again from scratch with [`AVLTree::replay`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OperationLog<T> {
+    operations: Vec<Operation<T>>,
+}
+
+impl<T: Ord> OperationLog<T> {
+
+    /// Creates an empty log.
+    pub fn new() -> Self {
+        OperationLog { operations: Vec::new() }
+    }
+
+    /// Runs `operation` on `tree` and appends it to the log. Returns what
+    /// [`Operation::apply`] returns.
+    pub fn record(&mut self, tree: &mut AVLTree<T>, operation: Operation<T>) -> Option<T> where T: Clone {
+        self.operations.push(operation.clone());
+        operation.apply(tree)
+    }
+
+    /// Appends `operation` to the log without running it.
+    pub fn push(&mut self, operation: Operation<T>) {
+        self.operations.push(operation);
+    }
+
+    /// Returns the operations in the order they were recorded.
+    pub fn operations(&self) -> &[Operation<T>] {
+        &self.operations
+    }
+
+    /// Returns the number of operations.
+    pub fn len(&self) -> usize {
+        self.operations.len()
+    }
+
+    /// Returns `true` if the log holds no operation.
+    pub fn is_empty(&self) -> bool {
+        self.operations.is_empty()
+    }
+
+    /// Writes the log to `w` in the format described in the [module docs](self).
+    pub fn write_to<W: Write>(&self, mut w: W) -> io::Result<()> where T: SnapshotElement {
+        w.write_all(MAGIC)?;
+        w.write_all(&[VERSION])?;
+        (self.len() as u64).write_to(&mut w)?;
+        for operation in &self.operations {
+            operation.write_to(&mut w)?;
+        }
+        w.flush()
+    }
+
+    /// Reads a log written by `write_to`.
+    ///
+    /// Fails with `InvalidData` if the header or a tag is wrong.
+    pub fn read_from<R: Read>(mut r: R) -> io::Result<Self> where T: SnapshotElement {
+        let mut header = [0; 5];
+        r.read_exact(&mut header)?;
+        if &header[..4] != MAGIC {
+            return Err(invalid("not an AVLTree operation log"));
+        }
+        if header[4] != VERSION {
+            return Err(invalid("unsupported operation log version"));
+        }
+        let len = usize::read_from(&mut r)?;
+        let mut operations = Vec::with_capacity(len.min(4096));
+        for _ in 0..len {
+            operations.push(Operation::read_from(&mut r)?);
+        }
+        Ok(OperationLog { operations })
+    }
+}
+
+impl<T: Ord> Default for OperationLog<T> {
+    fn default() -> Self {
+        OperationLog::new()
+    }
+}
+
+impl<T: Ord + Clone> AVLTree<T> {
+
+    /// Builds a tree by running the operations of `log` on an empty tree, giving back the tree
+    /// the log was recorded from if it started empty.
+    pub fn replay(log: &OperationLog<T>) -> Self {
+        let mut tree = AVLTree::new();
+        for operation in log.operations() {
+            operation.clone().apply(&mut tree);
+        }
+        tree
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replay() {
+        let mut tree = AVLTree::new();
+        let mut log = OperationLog::new();
+        for v in 0..200u32 {
+            log.record(&mut tree, Operation::Insert(v * 7 % 101));
+            if v % 3 == 0 {
+                log.record(&mut tree, Operation::Remove(v % 50));
+            }
+            if v % 17 == 0 {
+                log.record(&mut tree, Operation::InsertWithPolicy(v % 10, DuplicatePolicy::KeepBoth));
+            }
+        }
+        assert_eq!(log.record(&mut tree, Operation::Insert(1)), Some(1));
+        assert_eq!(log.record(&mut tree, Operation::InsertWithPolicy(500, DuplicatePolicy::Replace)), None);
+        let replayed = AVLTree::replay(&log);
+        // the same shape, not only the same elements
+        assert!(replayed == tree);
+        assert_eq!(replayed.to_json_structure(), tree.to_json_structure());
+
+        log.record(&mut tree, Operation::Rebuild);
+        assert_eq!(AVLTree::replay(&log).to_json_structure(), tree.to_json_structure());
+        assert!(AVLTree::replay(&OperationLog::<u32>::new()).is_empty());
+    }
+
+    #[test]
+    fn test_log_round_trip() {
+        let mut log = OperationLog::new();
+        log.push(Operation::Insert("b".to_string()));
+        log.push(Operation::InsertWithPolicy("a".to_string(), DuplicatePolicy::KeepBoth));
+        log.push(Operation::Remove("b".to_string()));
+        log.push(Operation::Rebuild);
+        let mut bytes = Vec::new();
+        log.write_to(&mut bytes).unwrap();
+        assert_eq!(&bytes[..5], b"AVLL\x01");
+        let back = OperationLog::<String>::read_from(&bytes[..]).unwrap();
+        assert_eq!(back, log);
+        assert!(AVLTree::replay(&back).iter().eq(["a"]));
+
+        let error = OperationLog::<String>::read_from(&bytes[..bytes.len() - 1]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+        let mut bad_tag = bytes.clone();
+        bad_tag[13] = 9;
+        assert_eq!(OperationLog::<String>::read_from(&bad_tag[..]).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        let mut bad_magic = bytes;
+        bad_magic[3] = b'T';
+        assert_eq!(OperationLog::<String>::read_from(&bad_magic[..]).unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+}
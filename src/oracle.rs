@@ -0,0 +1,147 @@
+//! Differential tests running random sequences of operations on an `AVLTree` and on a
+//! `BTreeSet` side by side, comparing their results and their contents after every step.
+//!
+//! A failure reports the seed of the sequence. Setting `AVL_ORACLE_SEED` to it runs that
+//! sequence alone, and `AVL_ORACLE_RUNS` sets how many sequences are run otherwise.
+
+use std::collections::BTreeSet;
+use std::env;
+use std::ops::Bound;
+
+use crate::AVLTree;
+
+/// The number of operations in each sequence.
+const STEPS: usize = 400;
+
+/// The SplitMix64 generator, small and good enough to pick operations.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n
+    }
+
+    fn value(&mut self, keys: u64) -> i32 {
+        self.below(keys) as i32
+    }
+}
+
+#[derive(Debug)]
+enum Op {
+    Insert(i32),
+    Remove(i32),
+    Take(i32),
+    Contains(i32),
+    GetByRank(usize),
+    CountRange(i32, i32),
+    IterFrom(i32),
+    SplitAppend(i32),
+    InsertBatch(Vec<i32>),
+    RemoveBatch(Vec<i32>),
+    RetainMultiples(i32),
+    ExtractMultiples(i32),
+    Drain,
+}
+
+impl Op {
+    /// Picks an operation on keys below `keys`, mostly insertions and removals so that the
+    /// trees grow and shrink.
+    fn random(rng: &mut Rng, keys: u64) -> Op {
+        match rng.below(100) {
+            0..=34 => Op::Insert(rng.value(keys)),
+            35..=59 => Op::Remove(rng.value(keys)),
+            60..=64 => Op::Take(rng.value(keys)),
+            65..=69 => Op::Contains(rng.value(keys)),
+            70..=74 => Op::GetByRank(rng.below(keys / 2) as usize),
+            75..=79 => Op::CountRange(rng.value(keys), rng.value(keys)),
+            80..=84 => Op::IterFrom(rng.value(keys)),
+            85..=88 => Op::SplitAppend(rng.value(keys)),
+            89..=91 => Op::InsertBatch((0..rng.below(20)).map(|_| rng.value(keys)).collect()),
+            92..=94 => Op::RemoveBatch((0..rng.below(20)).map(|_| rng.value(keys)).collect()),
+            95..=96 => Op::RetainMultiples(2 + rng.value(5)),
+            97..=98 => Op::ExtractMultiples(2 + rng.value(5)),
+            _ => Op::Drain,
+        }
+    }
+}
+
+/// Runs random operations from `seed` on both sets, panicking with the seed, the step and the
+/// operation at the first difference.
+fn run(seed: u64) {
+    let mut rng = Rng(seed);
+    let keys = 16 << rng.below(6);
+    let mut tree = AVLTree::new();
+    let mut model = BTreeSet::new();
+    for step in 0..STEPS {
+        let op = Op::random(&mut rng, keys);
+        let context = format!("seed {seed}, step {step}, {op:?}");
+        match &op {
+            Op::Insert(v) => assert_eq!(tree.insert(*v), model.insert(*v), "{context}"),
+            Op::Remove(v) => assert_eq!(tree.remove(v), model.remove(v), "{context}"),
+            Op::Take(v) => assert_eq!(tree.take(v), model.take(v), "{context}"),
+            Op::Contains(v) => assert_eq!(tree.contains(v), model.contains(v), "{context}"),
+            Op::GetByRank(rank) => assert_eq!(tree.get_by_rank(*rank), model.iter().nth(*rank), "{context}"),
+            Op::CountRange(a, b) => {
+                let range = (Bound::Included(*a.min(b)), Bound::Excluded(*a.max(b)));
+                assert_eq!(tree.count_range(range), model.range(range).count(), "{context}");
+            }
+            Op::IterFrom(v) => assert!(tree.iter_from(v).eq(model.range(*v..)), "{context}"),
+            Op::SplitAppend(v) => {
+                let mut high = tree.split_off(v);
+                let model_high = model.split_off(v);
+                assert!(high.iter().eq(&model_high), "{context}");
+                assert!(tree.iter().eq(&model), "{context}");
+                assert_eq!(high.check_invariants(), Ok(()), "{context}");
+                assert_eq!(tree.check_invariants(), Ok(()), "{context}");
+                tree.append(&mut high);
+                assert!(high.is_empty(), "{context}");
+                model.extend(model_high);
+            }
+            Op::InsertBatch(values) => {
+                tree.insert_batch(values.iter().copied());
+                model.extend(values);
+            }
+            Op::RemoveBatch(values) => {
+                let removed = values.iter().collect::<BTreeSet<_>>().into_iter().filter(|v| model.remove(v)).count();
+                assert_eq!(tree.remove_batch(values), removed, "{context}");
+            }
+            Op::RetainMultiples(m) => {
+                tree.retain(|v| v % m == 0);
+                model.retain(|v| v % m == 0);
+            }
+            Op::ExtractMultiples(m) => {
+                let extracted: Vec<_> = tree.extract_if(|v| v % m == 0).collect();
+                let expected: Vec<_> = model.iter().copied().filter(|v| v % m == 0).collect();
+                model.retain(|v| v % m != 0);
+                assert_eq!(extracted, expected, "{context}");
+            }
+            Op::Drain => {
+                assert!(tree.drain().eq(std::mem::take(&mut model)), "{context}");
+            }
+        }
+        assert_eq!(tree.len(), model.len(), "{context}");
+        assert!(tree.iter().eq(&model), "{context}");
+        assert!(tree.iter().rev().eq(model.iter().rev()), "{context}");
+        assert_eq!(tree.check_invariants(), Ok(()), "{context}");
+    }
+}
+
+#[test]
+fn test_against_btreeset() {
+    if let Ok(seed) = env::var("AVL_ORACLE_SEED") {
+        run(seed.parse().expect("AVL_ORACLE_SEED: not a number"));
+        return;
+    }
+    let runs = env::var("AVL_ORACLE_RUNS").map_or(100, |runs| runs.parse().expect("AVL_ORACLE_RUNS: not a number"));
+    for seed in 0..runs {
+        run(seed);
+    }
+}
@@ -0,0 +1,486 @@
+//! A persistent ordered set: insertions and removals leave the tree they are called on as it
+//! was and return a new one, which shares all the subtrees they don't touch with it.
+//!
+//! Nodes are never modified once built. A change copies the O(log n) nodes on the path from the
+//! root down to where it happens, rotations included, and links the copies to the untouched
+//! subtrees; cloning a tree only clones the pointer to its root. Nodes are reference-counted
+//! with `Arc`, so that versions can be shared between threads.
+
+use std::borrow::Borrow;
+use std::cmp::{max, Ordering};
+use std::fmt;
+use std::ops::RangeBounds;
+use std::ptr;
+use std::sync::{Arc, Weak};
+
+use crate::out_of_bounds;
+
+pub(crate) type Link<T> = Option<Arc<Node<T>>>;
+
+#[derive(Clone)]
+pub(crate) struct Node<T> {
+    pub(crate) value: T,
+    pub(crate) height: u8,
+    pub(crate) left: Link<T>,
+    pub(crate) right: Link<T>,
+}
+
+pub(crate) fn height<T>(link: &Link<T>) -> u8 {
+    link.as_ref().map_or(0, |n| n.height)
+}
+
+fn node<T>(value: T, left: Link<T>, right: Link<T>) -> Arc<Node<T>> {
+    let height = 1 + max(height(&left), height(&right));
+    Arc::new(Node { value, height, left, right })
+}
+
+/// Takes the contents of `node`, cloning them only if another version shares it.
+fn open<T: Clone>(node: Arc<Node<T>>) -> Node<T> {
+    Arc::unwrap_or_clone(node)
+}
+
+/// Makes a node out of `value` and two subtrees whose heights differ by 2 at most, rotating
+/// if they do.
+fn balance<T: Clone>(value: T, left: Link<T>, right: Link<T>) -> Arc<Node<T>> {
+    let (hl, hr) = (height(&left), height(&right));
+    if hl > hr + 1 {
+        let l = open(left.unwrap());
+        if height(&l.left) >= height(&l.right) {
+            node(l.value, l.left, Some(node(value, l.right, right)))
+        }
+        else { // left-right case
+            let lr = open(l.right.unwrap());
+            node(lr.value, Some(node(l.value, l.left, lr.left)), Some(node(value, lr.right, right)))
+        }
+    }
+    else if hr > hl + 1 {
+        let r = open(right.unwrap());
+        if height(&r.right) >= height(&r.left) {
+            node(r.value, Some(node(value, left, r.left)), r.right)
+        }
+        else { // right-left case
+            let rl = open(r.left.unwrap());
+            node(rl.value, Some(node(value, left, rl.left)), Some(node(r.value, rl.right, r.right)))
+        }
+    }
+    else {
+        node(value, left, right)
+    }
+}
+
+/// Returns a copy of the subtree at `link` with `value` added, or gives `value` back if an
+/// equal element is present.
+pub(crate) fn insert<T: Ord + Clone>(link: &Link<T>, value: T) -> Result<Arc<Node<T>>, T> {
+    let Some(n) = link else {
+        return Ok(node(value, None, None));
+    };
+    match value.cmp(&n.value) {
+        Ordering::Equal => Err(value),
+        Ordering::Less => Ok(balance(n.value.clone(), Some(insert(&n.left, value)?), n.right.clone())),
+        Ordering::Greater => Ok(balance(n.value.clone(), n.left.clone(), Some(insert(&n.right, value)?))),
+    }
+}
+
+/// Returns a copy of the subtree at `link` without `value`, or `None` if it isn't present.
+pub(crate) fn remove<T: Ord + Clone + Borrow<Q>, Q: ?Sized + Ord>(link: &Link<T>, value: &Q) -> Option<Link<T>> {
+    let n = link.as_ref()?;
+    let subtree = match value.cmp(n.value.borrow()) {
+        Ordering::Less => balance(n.value.clone(), remove(&n.left, value)?, n.right.clone()),
+        Ordering::Greater => balance(n.value.clone(), n.left.clone(), remove(&n.right, value)?),
+        Ordering::Equal => match (&n.left, &n.right) {
+            (left, None) => return Some(left.clone()),
+            (None, right) => return Some(right.clone()),
+            (left, Some(right)) => {
+                let (successor, right) = remove_first(right);
+                balance(successor, left.clone(), right)
+            }
+        },
+    };
+    Some(Some(subtree))
+}
+
+/// Returns the smallest element of the subtree at `n` and a copy of the subtree without it.
+fn remove_first<T: Clone>(n: &Arc<Node<T>>) -> (T, Link<T>) {
+    match &n.left {
+        None => (n.value.clone(), n.right.clone()),
+        Some(left) => {
+            let (first, left) = remove_first(left);
+            (first, Some(balance(n.value.clone(), left, n.right.clone())))
+        }
+    }
+}
+
+/// Builds a perfectly balanced subtree out of the next `len` elements of `values`, which must
+/// come in increasing order.
+pub(crate) fn build<T, I: Iterator<Item = T>>(values: &mut I, len: usize) -> Link<T> {
+    if len == 0 {
+        return None;
+    }
+    let left = build(values, len / 2);
+    let value = values.next().expect("iterator shorter than announced");
+    let right = build(values, len - len / 2 - 1);
+    Some(node(value, left, right))
+}
+
+pub(crate) fn get<'a, T: Borrow<Q>, Q: ?Sized + Ord>(link: &'a Link<T>, value: &Q) -> Option<&'a T> {
+    let mut next = link.as_deref();
+    while let Some(n) = next {
+        next = match value.cmp(n.value.borrow()) {
+            Ordering::Equal => return Some(&n.value),
+            Ordering::Less => n.left.as_deref(),
+            Ordering::Greater => n.right.as_deref(),
+        };
+    }
+    None
+}
+
+/// A read-only copy of an [`AVLTree`](crate::AVLTree), as returned by its
+/// [`snapshot`](crate::AVLTree::snapshot) method, sharing the nodes it has in common with the
+/// other snapshots of the same tree.
+pub type Snapshot<T> = AVLTree<T>;
+
+/// The persistent copy an [`AVLTree`](crate::AVLTree) keeps of itself once a snapshot was
+/// taken, which the tree's changes of one element are applied to, path-copying the nodes that
+/// earlier snapshots share.
+///
+/// The edits need `T: Clone`, which the tree's own methods don't require. They are filled in
+/// as function pointers by `new`, from `snapshot`, which does.
+pub(crate) struct Shadow<T> {
+    copy: Snapshot<T>,
+    /// The generation of the tree that `copy` holds the elements of.
+    pub(crate) generation: u64,
+    /// The root of the last snapshot handed out, which is alive as long as the snapshot is.
+    handed_out: Weak<Node<T>>,
+    insert: Edit<T>,
+    remove: Edit<T>,
+}
+
+/// Returns a copy of the subtree at `link` with the change made, or `None` if it can't be.
+type Edit<T> = fn(&Link<T>, &T) -> Option<Link<T>>;
+
+impl<T: Ord> Shadow<T> {
+
+    /// Takes `copy`, holding the elements of a tree at `generation`, none of them equal.
+    pub(crate) fn new(copy: Snapshot<T>, generation: u64) -> Self where T: Clone {
+        Shadow {
+            copy,
+            generation,
+            handed_out: Weak::new(),
+            insert: |link, value| insert(link, value.clone()).ok().map(Some),
+            remove: |link, value| remove(link, value),
+        }
+    }
+
+    /// Returns the copy, sharing all of its nodes.
+    pub(crate) fn snapshot(&mut self) -> Snapshot<T> {
+        self.handed_out = self.copy.root.as_ref().map_or_else(Weak::new, Arc::downgrade);
+        self.copy.clone()
+    }
+
+    /// Returns `true` if the last snapshot handed out is still alive, which is what keeping the
+    /// copy up to date is for.
+    pub(crate) fn is_shared(&self) -> bool {
+        let held = self.copy.root.as_ref().is_some_and(|root| ptr::eq(Arc::as_ptr(root), self.handed_out.as_ptr()));
+        self.handed_out.strong_count() > held as usize
+    }
+
+    /// Adds a clone of `value` to the copy. Returns `false` if an equal element is present,
+    /// which the copy can't hold both of.
+    pub(crate) fn insert(&mut self, value: &T) -> bool {
+        let Some(root) = (self.insert)(&self.copy.root, value) else { return false };
+        self.copy = AVLTree { root, len: self.copy.len + 1 };
+        true
+    }
+
+    /// Removes the element equal to `value` from the copy. Returns `false` if there is none.
+    pub(crate) fn remove(&mut self, value: &T) -> bool {
+        let Some(root) = (self.remove)(&self.copy.root, value) else { return false };
+        self.copy = AVLTree { root, len: self.copy.len - 1 };
+        true
+    }
+}
+
+/// A persistent ordered set based on an AVL tree.
+///
+/// [`insert`](Self::insert) and [`remove`](Self::remove) take `&self` and return the new
+/// version of the set, in O(log n) time and space; `clone` takes O(1). Elements are cloned
+/// along with the nodes copied, which makes `Arc<T>` a good choice of element when cloning `T`
+/// is costly.
+pub struct AVLTree<T> {
+    root: Link<T>,
+    len: usize,
+}
+
+impl<T: Ord> AVLTree<T> {
+
+    /// Creates an empty set.
+    pub const fn new() -> Self {
+        AVLTree { root: None, len: 0 }
+    }
+
+    /// Builds a balanced tree out of `len` elements, which must come in increasing order.
+    pub(crate) fn from_sorted<I: Iterator<Item = T>>(mut values: I, len: usize) -> Self {
+        AVLTree { root: build(&mut values, len), len }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns `true` if the set contains `value`.
+    pub fn contains<Q: ?Sized + Ord>(&self, value: &Q) -> bool where T: Borrow<Q> {
+        self.get(value).is_some()
+    }
+
+    /// Returns a reference to the element equal to `value`, if any.
+    pub fn get<Q: ?Sized + Ord>(&self, value: &Q) -> Option<&T> where T: Borrow<Q> {
+        get(&self.root, value)
+    }
+
+    /// Returns the set with `value` added. If an equal element is already present, the
+    /// returned set is the same as this one.
+    pub fn insert(&self, value: T) -> Self where T: Clone {
+        match insert(&self.root, value) {
+            Ok(root) => AVLTree { root: Some(root), len: self.len + 1 },
+            Err(_) => self.clone(),
+        }
+    }
+
+    /// Returns the set without `value`. If it isn't present, the returned set is the same as
+    /// this one.
+    pub fn remove<Q: ?Sized + Ord>(&self, value: &Q) -> Self where T: Borrow<Q> + Clone {
+        match remove(&self.root, value) {
+            Some(root) => AVLTree { root, len: self.len - 1 },
+            None => self.clone(),
+        }
+    }
+
+    /// Returns `true` if both sets are the same version, sharing their whole tree, in which
+    /// case they are equal without comparing any element.
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        match (&self.root, &other.root) {
+            (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+            (a, b) => a.is_none() && b.is_none(),
+        }
+    }
+
+    /// Returns an iterator over the elements of the set, in sorted order.
+    pub fn iter(&self) -> Range<'_, T> {
+        Range::new(&self.root, ..)
+    }
+
+    /// Returns an iterator over the elements falling in `range`, in sorted order.
+    pub fn range<Q: ?Sized + Ord, R: RangeBounds<Q>>(&self, range: R) -> Range<'_, T> where T: Borrow<Q> {
+        Range::new(&self.root, range)
+    }
+}
+
+impl<T> Clone for AVLTree<T> {
+    fn clone(&self) -> Self {
+        AVLTree { root: self.root.clone(), len: self.len }
+    }
+}
+
+impl<T: Ord> Default for AVLTree<T> {
+    fn default() -> Self {
+        AVLTree::new()
+    }
+}
+
+impl<T: Ord> FromIterator<T> for AVLTree<T> {
+    /// Builds a balanced tree in one go, keeping the first of equal elements.
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut values: Vec<T> = iter.into_iter().collect();
+        values.sort();
+        values.dedup();
+        let len = values.len();
+        AVLTree::from_sorted(values.into_iter(), len)
+    }
+}
+
+impl<T: Ord + Clone> Extend<T> for AVLTree<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for value in iter {
+            *self = self.insert(value);
+        }
+    }
+}
+
+impl<T: Ord> PartialEq for AVLTree<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.ptr_eq(other) || (self.len == other.len && self.iter().eq(other.iter()))
+    }
+}
+
+impl<T: Ord> Eq for AVLTree<T> {}
+
+impl<T: Ord + fmt::Debug> fmt::Debug for AVLTree<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+impl<'a, T: Ord> IntoIterator for &'a AVLTree<T> {
+    type Item = &'a T;
+    type IntoIter = Range<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// An iterator over the elements of a persistent tree falling in a range, in sorted order.
+///
+/// Created by [`AVLTree::iter`] and [`AVLTree::range`], as well as by the pinned views of an
+/// `AVLTreeEpoch`, which shares the node layout.
+pub struct Range<'a, T> {
+    /// The next node, on top, and its ancestors whose element comes after.
+    stack: Vec<&'a Node<T>>,
+    last: Option<&'a Node<T>>,
+}
+
+impl<'a, T> Range<'a, T> {
+
+    pub(crate) fn new<Q: ?Sized + Ord, R: RangeBounds<Q>>(root: &'a Link<T>, range: R) -> Self where T: Borrow<Q> + Ord {
+        let (below, above) = out_of_bounds(range.start_bound(), range.end_bound());
+
+        // the first element, and its ancestors at which the path to it turns left
+        let mut stack = Vec::new();
+        let mut next = root.as_deref();
+        while let Some(n) = next {
+            if below(n.value.borrow()) {
+                next = n.right.as_deref();
+            }
+            else {
+                stack.push(n);
+                next = n.left.as_deref();
+            }
+        }
+        let mut last = None;
+        let mut next = root.as_deref();
+        while let Some(n) = next {
+            if above(n.value.borrow()) {
+                next = n.left.as_deref();
+            }
+            else {
+                last = Some(n);
+                next = n.right.as_deref();
+            }
+        }
+        match (stack.last(), last) {
+            (Some(first), Some(l)) if first.value <= l.value => Range { stack, last },
+            _ => Range { stack: Vec::new(), last: None },
+        }
+    }
+}
+
+impl<'a, T> Iterator for Range<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let n = self.stack.pop()?;
+        if self.last.is_some_and(|last| ptr::eq(n, last)) {
+            self.stack.clear();
+            self.last = None;
+        }
+        else {
+            let mut next = n.right.as_deref();
+            while let Some(m) = next {
+                self.stack.push(m);
+                next = m.left.as_deref();
+            }
+        }
+        Some(&n.value)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use std::ops::Bound;
+
+    /// Checks the heights and the ordering of the subtree at `link`, returning its height.
+    pub(crate) fn check<T: Ord>(link: &Link<T>) -> u8 {
+        let Some(n) = link else {
+            return 0;
+        };
+        let (hl, hr) = (check(&n.left), check(&n.right));
+        assert!(hl.abs_diff(hr) <= 1);
+        assert_eq!(n.height, 1 + max(hl, hr));
+        assert!(n.left.as_ref().is_none_or(|l| l.value < n.value));
+        assert!(n.right.as_ref().is_none_or(|r| r.value > n.value));
+        n.height
+    }
+
+    #[test]
+    fn test_persistent() {
+        let mut versions = vec![AVLTree::new()];
+        for i in 0..100 {
+            let next = versions.last().unwrap().insert((i * 37) % 100);
+            check(&next.root);
+            versions.push(next);
+        }
+        // every version is still whole, and shares its nodes with the next one
+        for (i, v) in versions.iter().enumerate() {
+            assert_eq!(v.len(), i);
+            assert!(v.iter().eq((0..i as i32).map(|j| (j * 37) % 100).collect::<AVLTree<_>>().iter()));
+        }
+        let full = versions.pop().unwrap();
+        assert!(full.iter().copied().eq(0..100));
+        assert!(full.insert(5).ptr_eq(&full));
+        assert!(full.remove(&100).ptr_eq(&full));
+        assert!(full.range(10..20).copied().eq(10..20));
+        assert!(full.range((Bound::Excluded(98), Bound::Unbounded)).copied().eq([99]));
+        assert_eq!(full.range((Bound::Excluded(20), Bound::Excluded(21))).next(), None);
+
+        let mut fewer = full.clone();
+        for i in (0..100).filter(|i| i % 3 != 0) {
+            fewer = fewer.remove(&i);
+            check(&fewer.root);
+        }
+        assert!(fewer.iter().copied().eq((0..100).step_by(3)));
+        assert_eq!(full.len(), 100);
+        assert!(full.contains(&1) && !fewer.contains(&1));
+        assert_eq!(fewer, (0..100).step_by(3).collect());
+        assert_ne!(fewer, full);
+        assert_eq!(format!("{:?}", fewer.range(..7).collect::<AVLTree<_>>()), "{0, 3, 6}");
+    }
+
+    fn nodes<T>(link: &Link<T>, all: &mut HashSet<*const Node<T>>) {
+        if let Some(n) = link {
+            all.insert(Arc::as_ptr(n));
+            nodes(&n.left, all);
+            nodes(&n.right, all);
+        }
+    }
+
+    #[test]
+    fn test_snapshot_sharing() {
+        let mut tree: crate::AVLTree<i32> = (0..1000).step_by(2).collect();
+        let mut previous = tree.snapshot();
+        for i in 0..100 {
+            if i % 2 == 0 {
+                tree.insert(i * 10 + 1);
+            }
+            else {
+                tree.remove(&(i * 8));
+            }
+            let snapshot = tree.snapshot();
+            check(&snapshot.root);
+            assert!(snapshot.iter().eq(tree.iter()));
+
+            // only the path to the change is copied
+            let (mut old, mut new) = (HashSet::new(), HashSet::new());
+            nodes(&previous.root, &mut old);
+            nodes(&snapshot.root, &mut new);
+            assert!(new.difference(&old).count() <= 2 * snapshot.root.as_ref().unwrap().height as usize);
+            previous = snapshot;
+        }
+        assert!(previous.ptr_eq(&tree.snapshot()));
+    }
+}
@@ -0,0 +1,186 @@
+//! A double-ended priority queue which, unlike a binary heap, can remove or reprioritize any of
+//! its elements.
+
+use std::borrow::Borrow;
+use std::rc::Rc;
+
+use crate::{AVLTree, DuplicatePolicy, Iter, Side};
+
+/// A priority queue based on an [`AVLTree`] keeping equal elements side by side.
+///
+/// Pushing, popping at either end, and removing or changing any element all take O(log n);
+/// peeking takes O(log n) too, being a walk down the side of the tree. Equal elements are kept
+/// in the order they were pushed in: `pop_min` takes the first of them, `pop_max` the last.
+pub struct AVLPriorityQueue<T: Ord> {
+    tree: AVLTree<T>,
+}
+
+impl<T: Ord> AVLPriorityQueue<T> {
+
+    /// Creates an empty queue.
+    pub fn new() -> Self {
+        AVLPriorityQueue { tree: AVLTree::new() }
+    }
+
+    /// Returns the number of elements in the queue.
+    pub fn len(&self) -> usize {
+        self.tree.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    /// Adds `value` to the queue, after the elements equal to it.
+    pub fn push(&mut self, value: T) {
+        self.tree.insert_with_policy(value, DuplicatePolicy::KeepBoth);
+    }
+
+    /// Returns the smallest element.
+    pub fn peek(&self) -> Option<&T> {
+        self.peek_end(Side::Left)
+    }
+
+    /// Returns the largest element.
+    pub fn peek_max(&self) -> Option<&T> {
+        self.peek_end(Side::Right)
+    }
+
+    fn peek_end(&self, side: Side) -> Option<&T> {
+        self.tree.extreme(side).map(|node| &crate::peek(node).value)
+    }
+
+    /// Removes the smallest element and returns it, the first pushed of several equal ones.
+    pub fn pop_min(&mut self) -> Option<T> {
+        self.pop_end(Side::Left)
+    }
+
+    /// Removes the largest element and returns it, the last pushed of several equal ones.
+    pub fn pop_max(&mut self) -> Option<T> {
+        self.pop_end(Side::Right)
+    }
+
+    fn pop_end(&mut self, side: Side) -> Option<T> {
+        let node = Rc::clone(self.tree.extreme(side)?);
+        Some(self.tree.remove_node(node))
+    }
+
+    /// Returns `true` if the queue contains `value`.
+    pub fn contains<Q: ?Sized + Ord>(&self, value: &Q) -> bool where T: Borrow<Q> {
+        self.tree.contains(value)
+    }
+
+    /// Removes an element equal to `value` from the queue and returns it, or `None` if there
+    /// is none.
+    pub fn remove<Q: ?Sized + Ord>(&mut self, value: &Q) -> Option<T> where T: Borrow<Q> {
+        self.tree.take(value)
+    }
+
+    /// Replaces an element equal to `old` with `new`, which takes its place in the order of
+    /// priorities, and returns the element replaced. Returns `None`, leaving the queue untouched,
+    /// if there is none.
+    pub fn change_priority<Q: ?Sized + Ord>(&mut self, old: &Q, new: T) -> Option<T> where T: Borrow<Q> {
+        let old = self.tree.take(old)?;
+        self.push(new);
+        Some(old)
+    }
+
+    /// Returns an iterator over the elements from the smallest to the largest, in the order they
+    /// would be popped by `pop_min`.
+    pub fn iter(&self) -> Iter<'_, T> {
+        self.tree.iter()
+    }
+
+    /// Returns the elements from the smallest to the largest.
+    pub fn into_sorted_vec(self) -> Vec<T> {
+        self.tree.into_sorted_vec()
+    }
+}
+
+impl<T: Ord> Default for AVLPriorityQueue<T> {
+    fn default() -> Self {
+        AVLPriorityQueue::new()
+    }
+}
+
+impl<T: Ord> FromIterator<T> for AVLPriorityQueue<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut queue = AVLPriorityQueue::new();
+        queue.extend(iter);
+        queue
+    }
+}
+
+impl<T: Ord> Extend<T> for AVLPriorityQueue<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for value in iter {
+            self.push(value);
+        }
+    }
+}
+
+impl<'a, T: Ord> IntoIterator for &'a AVLPriorityQueue<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cmp::Ordering;
+
+    /// A task ordered by priority alone.
+    #[derive(Debug, PartialEq, Eq)]
+    struct Task(u32, &'static str);
+
+    impl PartialOrd for Task {
+        fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+            Some(self.cmp(other))
+        }
+    }
+
+    impl Ord for Task {
+        fn cmp(&self, other: &Self) -> Ordering {
+            self.0.cmp(&other.0)
+        }
+    }
+
+    #[test]
+    fn test_priority_queue() {
+        let mut queue: AVLPriorityQueue<_> = (0..100).map(|i| (i * 37) % 100).collect();
+        queue.extend([50, 50]);
+        assert_eq!(queue.len(), 102);
+        assert_eq!(queue.peek(), Some(&0));
+        assert_eq!(queue.peek_max(), Some(&99));
+        assert_eq!(queue.remove(&50), Some(50));
+        assert_eq!(queue.remove(&100), None);
+        assert_eq!(queue.change_priority(&10, 1000), Some(10));
+        assert_eq!(queue.change_priority(&10, 0), None);
+        assert!(queue.contains(&50) && !queue.contains(&10));
+        assert_eq!(queue.pop_max(), Some(1000));
+        assert_eq!(queue.pop_min(), Some(0));
+        assert!(queue.iter().zip(queue.iter().skip(1)).all(|(a, b)| a <= b));
+        let sorted = queue.into_sorted_vec();
+        assert_eq!(sorted.len(), 99);
+        assert_eq!(sorted.iter().filter(|&&v| v == 50).count(), 2);
+
+        let mut tasks = AVLPriorityQueue::new();
+        for (i, name) in ["a", "b", "c", "d", "e"].into_iter().enumerate() {
+            tasks.push(Task(i as u32 % 2, name));
+        }
+        tasks.push(Task(5, "f"));
+        assert_eq!(tasks.change_priority(&Task(5, ""), Task(1, "g")), Some(Task(5, "f")));
+        assert_eq!(tasks.pop_max(), Some(Task(1, "g")));
+        let mut popped = Vec::new();
+        while let Some(Task(_, name)) = tasks.pop_min() {
+            popped.push(name);
+        }
+        assert_eq!(popped, ["a", "c", "e", "b", "d"]);
+        assert_eq!(tasks.pop_max(), None);
+        assert!(tasks.peek().is_none());
+    }
+}
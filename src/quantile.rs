@@ -0,0 +1,112 @@
+//! Quantiles of the elements of a tree, found by rank.
+
+use crate::AVLTree;
+
+/// How [`AVLTree::quantile_with`] picks an element when the quantile falls between two.
+///
+/// The quantile `q` of n elements lies at position `q * (n - 1)` in sorted order, counting from
+/// 0, which is generally not a whole number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interpolation {
+    /// The element before the position.
+    Lower,
+    /// The element after the position.
+    Higher,
+    /// The element nearest to the position, the one at an even rank on ties.
+    Nearest,
+}
+
+/// Returns the position of quantile `q` among `len` elements.
+fn position(q: f64, len: usize) -> f64 {
+    assert!((0.0..=1.0).contains(&q), "quantile {q} out of range");
+    q * (len - 1) as f64
+}
+
+impl<T: Ord> AVLTree<T> {
+
+    /// Returns the element at quantile `q`, between 0 and 1, the lower one when it falls between
+    /// two: `quantile(0.5)` is the lower median. Takes O(log n), the element being looked up by
+    /// rank.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `q` is not between 0 and 1.
+    pub fn quantile(&self, q: f64) -> Option<&T> {
+        self.quantile_with(q, Interpolation::Lower)
+    }
+
+    /// Returns the element at quantile `q`, between 0 and 1, picking one of the two elements
+    /// around it as told by `interpolation`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `q` is not between 0 and 1.
+    pub fn quantile_with(&self, q: f64, interpolation: Interpolation) -> Option<&T> {
+        if self.is_empty() {
+            return None;
+        }
+        let position = position(q, self.len());
+        let rank = match interpolation {
+            Interpolation::Lower => position.floor(),
+            Interpolation::Higher => position.ceil(),
+            Interpolation::Nearest => position.round_ties_even(),
+        };
+        self.get_by_rank(rank as usize)
+    }
+
+    /// Returns the value at quantile `q`, between 0 and 1, interpolated linearly between the two
+    /// elements around it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `q` is not between 0 and 1.
+    pub fn quantile_linear(&self, q: f64) -> Option<f64> where T: Clone + Into<f64> {
+        if self.is_empty() {
+            return None;
+        }
+        let position = position(q, self.len());
+        let lower: f64 = self.get_by_rank(position.floor() as usize)?.clone().into();
+        let higher: f64 = self.get_by_rank(position.ceil() as usize)?.clone().into();
+        Some(lower + (higher - lower) * position.fract())
+    }
+
+    /// Returns the median element, the lower one of the two middle elements if the tree holds
+    /// an even number of them.
+    pub fn median(&self) -> Option<&T> {
+        self.get_by_rank(self.len().checked_sub(1)? / 2)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quantile() {
+        let tree: AVLTree<u32> = (1..=10).map(|i| i * 10).collect();
+        assert_eq!(tree.median(), Some(&50));
+        assert_eq!(tree.quantile(0.5), Some(&50));
+        assert_eq!(tree.quantile_with(0.5, Interpolation::Higher), Some(&60));
+        assert_eq!(tree.quantile(0.0), Some(&10));
+        assert_eq!(tree.quantile_with(1.0, Interpolation::Lower), Some(&100));
+        // 0.25 lies at position 2.25, 0.75 at 6.75, 0.5 at 4.5 which ties
+        assert_eq!(tree.quantile_with(0.25, Interpolation::Nearest), Some(&30));
+        assert_eq!(tree.quantile_with(0.75, Interpolation::Nearest), Some(&80));
+        assert_eq!(tree.quantile_with(0.5, Interpolation::Nearest), Some(&50));
+        assert_eq!(tree.quantile_linear(0.5), Some(55.0));
+        assert_eq!(tree.quantile_linear(0.25), Some(32.5));
+        assert_eq!(tree.quantile_linear(1.0), Some(100.0));
+
+        let single = AVLTree::from([7u32]);
+        assert_eq!(single.quantile_linear(0.3), Some(7.0));
+        assert_eq!(single.median(), Some(&7));
+        let empty = AVLTree::<u32>::new();
+        assert_eq!((empty.median(), empty.quantile(0.5), empty.quantile_linear(0.5)), (None, None, None));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_quantile_out_of_range() {
+        AVLTree::from([1]).quantile(1.5);
+    }
+}
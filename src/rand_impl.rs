@@ -0,0 +1,76 @@
+//! Random sampling, enabled by the `rand` feature.
+//!
+//! The size each node keeps of its subtree lets the tree find the element of any rank in
+//! O(log n): drawing a rank uniformly draws an element uniformly, without collecting the
+//! elements anywhere first.
+
+use std::collections::BTreeSet;
+
+use rand::Rng;
+
+use crate::AVLTree;
+
+impl<T: Ord> AVLTree<T> {
+
+    /// Returns an element drawn uniformly at random with `rng`, or `None` if the tree is empty.
+    /// Available with the `rand` feature.
+    pub fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Option<&T> {
+        if self.is_empty() {
+            return None;
+        }
+        self.get_by_rank(rng.random_range(0..self.len()))
+    }
+
+    /// Returns `k` distinct elements drawn uniformly at random with `rng`, in sorted order, or
+    /// all the elements if the tree has fewer than `k`. Available with the `rand` feature.
+    ///
+    /// Ranks are drawn with Floyd's algorithm, which takes exactly `k` draws, and the elements
+    /// looked up by rank, in O(k log n) overall.
+    pub fn sample_k<R: Rng + ?Sized>(&self, rng: &mut R, k: usize) -> Vec<&T> {
+        let n = self.len();
+        if k >= n {
+            return self.iter().collect();
+        }
+        let mut ranks = BTreeSet::new();
+        for j in n - k..n {
+            let rank = rng.random_range(0..=j);
+            if !ranks.insert(rank) {
+                ranks.insert(j);
+            }
+        }
+        ranks.into_iter().map(|rank| self.get_by_rank(rank).unwrap()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_sample() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let tree: AVLTree<u32> = (0..10).map(|i| i * 3).collect();
+        let mut counts = [0; 10];
+        for _ in 0..10_000 {
+            let v = tree.sample(&mut rng).unwrap();
+            counts[*v as usize / 3] += 1;
+        }
+        assert!(counts.iter().all(|&c| (800..1200).contains(&c)));
+        assert_eq!(AVLTree::<u32>::new().sample(&mut rng), None);
+
+        let mut counts = [0; 10];
+        for _ in 0..2000 {
+            let sample = tree.sample_k(&mut rng, 4);
+            assert_eq!(sample.len(), 4);
+            assert!(sample.windows(2).all(|w| w[0] < w[1]));
+            for v in sample {
+                counts[*v as usize / 3] += 1;
+            }
+        }
+        assert!(counts.iter().all(|&c| (650..950).contains(&c)));
+        assert!(tree.sample_k(&mut rng, 20).into_iter().eq(tree.iter()));
+        assert!(tree.sample_k(&mut rng, 0).is_empty());
+    }
+}
@@ -0,0 +1,475 @@
+//! An ordered set made of raw-pointer-linked nodes, enabled by the `raw` feature.
+//!
+//! # Safety
+//!
+//! All the unsafe code of the module relies on one invariant: every pointer stored in a tree,
+//! the root as well as the parent and child links, points at a live node allocated with
+//! `Box::new` and owned by that tree alone. Nodes are only freed by `take` and `Drop`, after
+//! being unlinked, and references to elements never outlive the borrow of the tree they are
+//! reached through, so the borrow checker of the tree stands in for the one of the nodes.
+
+use std::borrow::Borrow;
+use std::cmp::{max, Ordering};
+use std::marker::PhantomData;
+use std::mem;
+use std::ptr::NonNull;
+
+use crate::Side;
+
+type Link<T> = Option<NonNull<Node<T>>>;
+
+struct Node<T> {
+    value: T,
+    height: usize,
+    size: usize,
+    parent: Link<T>,
+    left: Link<T>,
+    right: Link<T>,
+}
+
+impl<T> Node<T> {
+
+    fn child(&self, side: Side) -> Link<T> {
+        match side {
+            Side::Left => self.left,
+            Side::Right => self.right,
+        }
+    }
+
+    fn child_mut(&mut self, side: Side) -> &mut Link<T> {
+        match side {
+            Side::Left => &mut self.left,
+            Side::Right => &mut self.right,
+        }
+    }
+}
+
+/// An ordered set based on an AVL tree whose nodes are linked by raw pointers, parents
+/// included, and freed by hand.
+///
+/// It behaves like [`AVLTreeBoxed`](crate::AVLTreeBoxed) but keeps parent links, so updates
+/// retrace without a stack and iteration steps without one.
+pub struct AVLTreeRaw<T: Ord> {
+    root: Link<T>,
+    // the tree owns its nodes, and so their elements
+    marker: PhantomData<Box<Node<T>>>,
+}
+
+// SAFETY: the nodes are owned by the tree alone, so it can be sent or shared whenever a
+// `Box<T>` could.
+unsafe impl<T: Ord + Send> Send for AVLTreeRaw<T> {}
+unsafe impl<T: Ord + Sync> Sync for AVLTreeRaw<T> {}
+
+/// Dereferences a node pointer of a tree.
+///
+/// # Safety
+///
+/// `node` must come from a tree borrowed for all of `'a`, see the module docs.
+unsafe fn node<'a, T>(node: NonNull<Node<T>>) -> &'a Node<T> {
+    &*node.as_ptr()
+}
+
+/// Mutably dereferences a node pointer of a tree.
+///
+/// # Safety
+///
+/// `node` must come from a tree mutably borrowed for all of `'a`, and no other reference to
+/// the node may be alive meanwhile.
+unsafe fn node_mut<'a, T>(node: NonNull<Node<T>>) -> &'a mut Node<T> {
+    &mut *node.as_ptr()
+}
+
+fn height<T>(link: Link<T>) -> usize {
+    // SAFETY: links are valid as long as the tree they come from, which the callers borrow
+    link.map_or(0, |n| unsafe { node(n) }.height)
+}
+
+fn size<T>(link: Link<T>) -> usize {
+    // SAFETY: see `height`
+    link.map_or(0, |n| unsafe { node(n) }.size)
+}
+
+impl<T: Ord> AVLTreeRaw<T> {
+
+    /// Creates an empty set.
+    pub fn new() -> Self {
+        AVLTreeRaw { root: None, marker: PhantomData }
+    }
+
+    /// Returns the number of elements in the set.
+    pub fn len(&self) -> usize {
+        size(self.root)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+
+    /// Returns `true` if the set contains `value`.
+    pub fn contains<Q: ?Sized + Ord>(&self, value: &Q) -> bool where T: Borrow<Q> {
+        self.find(value).is_some()
+    }
+
+    /// Returns the element equal to `value`.
+    pub fn get<Q: ?Sized + Ord>(&self, value: &Q) -> Option<&T> where T: Borrow<Q> {
+        // SAFETY: the node belongs to `self`, which stays borrowed for the returned lifetime
+        self.find(value).map(|n| &unsafe { node(n) }.value)
+    }
+
+    fn find<Q: ?Sized + Ord>(&self, value: &Q) -> Link<T> where T: Borrow<Q> {
+        let mut next = self.root;
+        while let Some(ptr) = next {
+            // SAFETY: the node belongs to `self`
+            let n = unsafe { node(ptr) };
+            next = match value.cmp(n.value.borrow()) {
+                Ordering::Equal => return Some(ptr),
+                Ordering::Less => n.left,
+                Ordering::Greater => n.right,
+            };
+        }
+        None
+    }
+
+    /// Adds `value` to the set. Returns `false` if an equal element was already present, in
+    /// which case the set is left untouched.
+    pub fn insert(&mut self, value: T) -> bool {
+        let mut parent = None;
+        let mut side = Side::Left;
+        let mut next = self.root;
+        while let Some(ptr) = next {
+            // SAFETY: the node belongs to `self`
+            let n = unsafe { node(ptr) };
+            side = match value.cmp(&n.value) {
+                Ordering::Equal => return false,
+                Ordering::Less => Side::Left,
+                Ordering::Greater => Side::Right,
+            };
+            parent = Some(ptr);
+            next = n.child(side);
+        }
+
+        let new = Box::new(Node { value, height: 1, size: 1, parent, left: None, right: None });
+        let new = NonNull::from(Box::leak(new));
+        match parent {
+            None => self.root = Some(new),
+            // SAFETY: `self` is mutably borrowed and no other node reference is alive
+            Some(p) => *unsafe { node_mut(p) }.child_mut(side) = Some(new),
+        }
+        self.rebalance(parent);
+        true
+    }
+
+    /// Removes `value` from the set. Returns `false` if it wasn't present.
+    pub fn remove<Q: ?Sized + Ord>(&mut self, value: &Q) -> bool where T: Borrow<Q> {
+        self.take(value).is_some()
+    }
+
+    /// Removes the element equal to `value` and returns it, or `None` if there was none.
+    pub fn take<Q: ?Sized + Ord>(&mut self, value: &Q) -> Option<T> where T: Borrow<Q> {
+        let mut target = self.find(value)?;
+        // SAFETY: all the nodes touched belong to `self`, which is mutably borrowed, and each
+        // reference is dead by the time another one to the same node is created
+        unsafe {
+            let n = node(target);
+            if let (Some(_), Some(right)) = (n.left, n.right) {
+                // trade elements with the successor, which has no left child
+                let mut s = right;
+                while let Some(left) = node(s).left {
+                    s = left;
+                }
+                mem::swap(&mut node_mut(target).value, &mut node_mut(s).value);
+                target = s;
+            }
+
+            let n = node(target);
+            let (parent, child) = (n.parent, n.left.or(n.right));
+            if let Some(c) = child {
+                node_mut(c).parent = parent;
+            }
+            self.replace_child(parent, target, child);
+            self.rebalance(parent);
+            Some(Box::from_raw(target.as_ptr()).value)
+        }
+    }
+
+    /// Returns the element at position `rank` in sorted order (starting from 0).
+    pub fn get_by_rank(&self, rank: usize) -> Option<&T> {
+        let mut next = self.root;
+        let mut rank = rank;
+        while let Some(ptr) = next {
+            // SAFETY: the node belongs to `self`, which stays borrowed for the returned lifetime
+            let n = unsafe { node(ptr) };
+            let left = size(n.left);
+            next = match rank.cmp(&left) {
+                Ordering::Less => n.left,
+                Ordering::Equal => return Some(&n.value),
+                Ordering::Greater => {
+                    rank -= left + 1;
+                    n.right
+                }
+            };
+        }
+        None
+    }
+
+    /// Returns an iterator over the elements in sorted order.
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            front: self.root.map(|root| outermost(root, Side::Left)),
+            back: self.root.map(|root| outermost(root, Side::Right)),
+            remaining: self.len(),
+            marker: PhantomData,
+        }
+    }
+
+    /// Makes `parent` (the root if `None`) point at `new` where it pointed at `old`.
+    fn replace_child(&mut self, parent: Link<T>, old: NonNull<Node<T>>, new: Link<T>) {
+        match parent {
+            None => self.root = new,
+            Some(p) => {
+                // SAFETY: `p` belongs to `self`, which is mutably borrowed
+                let p = unsafe { node_mut(p) };
+                if p.left == Some(old) {
+                    p.left = new;
+                }
+                else {
+                    p.right = new;
+                }
+            }
+        }
+    }
+
+    /// Rotates the subtree rooted at `x` towards `side`, returning the new root of the subtree.
+    fn rotate(&mut self, x: NonNull<Node<T>>, side: Side) -> NonNull<Node<T>> {
+        // SAFETY: `x`, its child `z` and the inner grandchild are distinct nodes of `self`,
+        // which is mutably borrowed, and each reference is used before the next is created
+        unsafe {
+            let z = node(x).child(!side).unwrap();
+            let inner = node(z).child(side);
+            *node_mut(x).child_mut(!side) = inner;
+            if let Some(i) = inner {
+                node_mut(i).parent = Some(x);
+            }
+            let parent = node(x).parent;
+            node_mut(z).parent = parent;
+            self.replace_child(parent, x, Some(z));
+            *node_mut(z).child_mut(side) = Some(x);
+            node_mut(x).parent = Some(z);
+            update(x);
+            update(z);
+            z
+        }
+    }
+
+    /// Retraces from `next` up to the root, refreshing heights and sizes and rotating
+    /// wherever a balance factor has reached +2 or -2.
+    fn rebalance(&mut self, mut next: Link<T>) {
+        while let Some(mut ptr) = next {
+            // SAFETY: the nodes belong to `self`, which is mutably borrowed
+            unsafe {
+                update(ptr);
+                let b = balance_factor(ptr);
+                if b.abs() > 1 {
+                    let side = if b > 1 { Side::Left } else { Side::Right };
+                    let z = node(ptr).child(!side).unwrap();
+                    let zb = balance_factor(z);
+                    if (b > 1 && zb < 0) || (b < -1 && zb > 0) {
+                        self.rotate(z, !side);
+                    }
+                    ptr = self.rotate(ptr, side);
+                }
+                next = node(ptr).parent;
+            }
+        }
+    }
+}
+
+/// Recomputes the height and size of a node.
+///
+/// # Safety
+///
+/// As for `node_mut`.
+unsafe fn update<T>(ptr: NonNull<Node<T>>) {
+    let n = node_mut(ptr);
+    n.height = 1 + max(height(n.left), height(n.right));
+    n.size = 1 + size(n.left) + size(n.right);
+}
+
+/// # Safety
+///
+/// As for `node`.
+unsafe fn balance_factor<T>(ptr: NonNull<Node<T>>) -> isize {
+    let n = node(ptr);
+    height(n.right) as isize - height(n.left) as isize
+}
+
+/// Follows `side` children from `ptr` as far as they go.
+fn outermost<T>(mut ptr: NonNull<Node<T>>, side: Side) -> NonNull<Node<T>> {
+    // SAFETY: the callers hold a borrow of the tree owning the nodes
+    while let Some(next) = unsafe { node(ptr) }.child(side) {
+        ptr = next;
+    }
+    ptr
+}
+
+/// Returns the in-order neighbour of `ptr` on `side`.
+fn neighbour<T>(ptr: NonNull<Node<T>>, side: Side) -> Link<T> {
+    // SAFETY: see `outermost`
+    unsafe {
+        if let Some(child) = node(ptr).child(side) {
+            return Some(outermost(child, !side));
+        }
+        let mut ptr = ptr;
+        while let Some(p) = node(ptr).parent {
+            if node(p).child(!side) == Some(ptr) {
+                return Some(p);
+            }
+            ptr = p;
+        }
+        None
+    }
+}
+
+impl<T: Ord> Default for AVLTreeRaw<T> {
+    fn default() -> Self {
+        AVLTreeRaw::new()
+    }
+}
+
+/// Frees the nodes one by one, from the leaves up, without recursing.
+impl<T: Ord> Drop for AVLTreeRaw<T> {
+    fn drop(&mut self) {
+        let mut next = self.root.take();
+        // SAFETY: the tree is being dropped, so every node is visited by this loop only; a
+        // node is freed once both of its children are, after which its parent is visited
+        unsafe {
+            while let Some(ptr) = next {
+                let n = node_mut(ptr);
+                if let Some(child) = n.left.take().or_else(|| n.right.take()) {
+                    next = Some(child);
+                }
+                else {
+                    next = n.parent;
+                    drop(Box::from_raw(ptr.as_ptr()));
+                }
+            }
+        }
+    }
+}
+
+impl<T: Ord> FromIterator<T> for AVLTreeRaw<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut tree = AVLTreeRaw::new();
+        tree.extend(iter);
+        tree
+    }
+}
+
+impl<T: Ord> Extend<T> for AVLTreeRaw<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for value in iter {
+            self.insert(value);
+        }
+    }
+}
+
+/// An iterator over the elements of an `AVLTreeRaw`, in sorted order.
+///
+/// Created by [`AVLTreeRaw::iter`].
+pub struct Iter<'a, T> {
+    front: Link<T>,
+    back: Link<T>,
+    remaining: usize,
+    marker: PhantomData<&'a T>,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        let ptr = self.front?;
+        self.front = neighbour(ptr, Side::Right);
+        // SAFETY: the tree is borrowed for `'a`
+        Some(&unsafe { node(ptr) }.value)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, T> DoubleEndedIterator for Iter<'a, T> {
+    fn next_back(&mut self) -> Option<&'a T> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        let ptr = self.back?;
+        self.back = neighbour(ptr, Side::Left);
+        // SAFETY: the tree is borrowed for `'a`
+        Some(&unsafe { node(ptr) }.value)
+    }
+}
+
+impl<T> ExactSizeIterator for Iter<'_, T> {}
+
+impl<'a, T: Ord> IntoIterator for &'a AVLTreeRaw<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Checks links, heights, sizes and balance of a subtree, returning its height.
+    fn check<T>(link: Link<T>, parent: Link<T>) -> usize {
+        let Some(ptr) = link
+        else {
+            return 0;
+        };
+        let n = unsafe { node(ptr) };
+        assert_eq!(n.parent, parent);
+        let (hl, hr) = (check(n.left, link), check(n.right, link));
+        assert!(hl.abs_diff(hr) <= 1);
+        assert_eq!(n.height, 1 + max(hl, hr));
+        assert_eq!(n.size, 1 + size(n.left) + size(n.right));
+        n.height
+    }
+
+    // run under Miri as well: `cargo +nightly miri test --features raw raw::`
+    #[test]
+    fn test_raw() {
+        let mut tree = AVLTreeRaw::new();
+        for i in 0..64 {
+            assert!(tree.insert(((i * 29) % 64).to_string()));
+        }
+        assert!(!tree.insert("7".to_string()));
+        check(tree.root, None);
+        assert_eq!(tree.len(), 64);
+        let mut sorted: Vec<String> = (0..64).map(|i| i.to_string()).collect();
+        sorted.sort();
+        assert!(tree.iter().eq(sorted.iter()));
+        assert!(tree.iter().rev().eq(sorted.iter().rev()));
+        assert_eq!(tree.get_by_rank(10), Some(&sorted[10]));
+
+        for i in (0..64).filter(|i| i % 3 != 0) {
+            assert_eq!(tree.take(i.to_string().as_str()), Some(i.to_string()));
+            check(tree.root, None);
+        }
+        assert!(!tree.remove("1"));
+        assert!(tree.contains("63"));
+        assert_eq!(tree.get("9").map(String::as_str), Some("9"));
+        assert_eq!(tree.len(), 22);
+        // the remaining nodes, `String`s included, are freed by `Drop`
+    }
+}
@@ -0,0 +1,456 @@
+//! Rayon support, enabled by the `rayon` feature.
+//!
+//! `&AVLTree<T>` implements `IntoParallelIterator`, so `par_iter()` comes with rayon's prelude.
+//! The resulting iterator is indexed and yields the elements in sorted order: it is split by
+//! rank, each half narrowing down to the smallest subtree holding its range, so that every
+//! thread ends up walking a subtree of its own.
+//!
+//! A tree can't be shared between threads as such, its nodes being reference counted without
+//! atomics. The parallel iterator only ever reads the elements and the child links and sizes
+//! of the nodes, which nothing writes to while the tree is borrowed: neither the reference
+//! counts nor the borrow flags, which the owning thread may still touch, are read.
+//!
+//! [`AVLTree::from_sorted_slice`] builds a tree with its subtrees built on several threads,
+//! each of them being moved to the thread joining it only once complete. The same goes for
+//! the set operations `par_union`, `par_intersection` and `par_difference`, which split and
+//! join standalone subtrees once the handles to their nodes are cut.
+
+use rayon::iter::plumbing::{bridge, Consumer, Producer, ProducerCallback, UnindexedConsumer};
+use rayon::iter::{IndexedParallelIterator, IntoParallelIterator, IntoParallelRefIterator, ParallelIterator};
+use rayon::slice::ParallelSlice;
+
+use std::cell::RefCell;
+use std::rc::{Rc, Weak};
+
+use crate::{balanced_height, build_balanced, concat, detach, join, split, AVLNode, AVLTree, Link, Side};
+
+/// Below this many elements, subtrees are built on the current thread.
+const SEQUENTIAL_BUILD: usize = 1 << 12;
+
+/// Below this many elements in both subtrees, set operations go on on the current thread.
+const SEQUENTIAL_MERGE: usize = 1 << 12;
+
+/// A node of a borrowed tree, readable from any thread.
+struct Shared<'a, T: Ord>(&'a AVLNode<T>);
+
+// SAFETY: see the module documentation, only the fields written through `&mut AVLTree` are
+// read, and the elements only through shared references, hence `T: Sync`.
+unsafe impl<T: Ord + Sync> Send for Shared<'_, T> {}
+unsafe impl<T: Ord + Sync> Sync for Shared<'_, T> {}
+
+impl<T: Ord> Clone for Shared<'_, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T: Ord> Copy for Shared<'_, T> {}
+
+impl<'a, T: Ord> Shared<'a, T> {
+
+    fn new(link: &'a Link<T>) -> Option<Self> {
+        // SAFETY: the tree is borrowed for `'a`, so the node isn't written meanwhile, and the
+        // borrow flag isn't read
+        link.as_ref().map(|node| Shared(unsafe { &*node.as_ptr() }))
+    }
+
+    fn child(self, side: Side) -> Option<Self> {
+        Shared::new(self.0.child(side))
+    }
+
+    fn child_size(self, side: Side) -> usize {
+        self.child(side).map_or(0, |c| c.0.size)
+    }
+}
+
+impl<T: Ord + Clone + Send + Sync> AVLTree<T> {
+
+    /// Builds a tree out of clones of sorted elements, available with the `rayon` feature. Of
+    /// several equal elements in a row, the first one is kept, as `insert` would.
+    ///
+    /// Like [`from_sorted_iter`](AVLTree::from_sorted_iter), the tree is built perfectly
+    /// balanced in O(n), but the left and right subtrees of large enough subtrees are built in
+    /// parallel with `rayon::join`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if an element is smaller than the one before it.
+    pub fn from_sorted_slice(values: &[T]) -> Self {
+        if values.par_windows(2).any(|pair| pair[1] < pair[0]) {
+            panic!("from_sorted_slice: elements out of order");
+        }
+        let kept: Vec<&T> = values.par_iter().enumerate()
+            .filter(|&(i, v)| i == 0 || values[i - 1] < *v)
+            .map(|(_, v)| v)
+            .collect();
+        AVLTree::from_root(build_in_parallel(&kept).0)
+    }
+}
+
+impl<T: Ord + Send> AVLTree<T> {
+
+    /// Merges `self` and `other` into a tree holding the elements present in either of them,
+    /// available with the `rayon` feature. Where both trees hold equal elements, the one from
+    /// `self` is kept.
+    ///
+    /// The root of `self` splits `other` in two and both halves are merged in parallel, in
+    /// O(m log(n/m + 1)) work overall for trees of sizes m <= n. Both trees are consumed, clone
+    /// them first to keep them.
+    ///
+    /// The handles to the elements of both trees stop resolving, cutting them taking a walk
+    /// over the trees on the calling thread.
+    pub fn par_union(self, other: AVLTree<T>) -> AVLTree<T> {
+        combine(self, other, SetOperation::Union)
+    }
+
+    /// Same as `par_union`, but keeps only the elements present in both trees, as held by
+    /// `self`.
+    pub fn par_intersection(self, other: AVLTree<T>) -> AVLTree<T> {
+        combine(self, other, SetOperation::Intersection)
+    }
+
+    /// Same as `par_union`, but keeps only the elements of `self` missing from `other`.
+    pub fn par_difference(self, other: AVLTree<T>) -> AVLTree<T> {
+        combine(self, other, SetOperation::Difference)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum SetOperation {
+    Union,
+    Intersection,
+    Difference,
+}
+
+fn combine<T: Ord + Send>(mut a: AVLTree<T>, mut b: AVLTree<T>, operation: SetOperation) -> AVLTree<T> {
+    release_handles(&a);
+    release_handles(&b);
+    let root = combine_subtrees(Detached(a.root.take()), Detached(b.root.take()), operation);
+    AVLTree::from_root(root.0)
+}
+
+/// Cuts the links between the nodes of `tree` and their handles, which stop resolving, as
+/// after `retain`. Handles hold the only links to the nodes from outside the tree.
+fn release_handles<T: Ord>(tree: &AVLTree<T>) {
+    for node in tree.nodes() {
+        if let Some(anchor) = node.borrow_mut().anchor.take() {
+            *anchor.borrow_mut() = Weak::new();
+        }
+    }
+}
+
+/// Applies `operation` to two standalone subtrees: the root of `a` splits `b` in two, the
+/// halves of each are combined recursively, in parallel unless they are small, and the
+/// results are joined back, along with the root of `a` if `operation` keeps it.
+fn combine_subtrees<T: Ord + Send>(a: Detached<T>, b: Detached<T>, operation: SetOperation) -> Detached<T> {
+    let (a, b) = (a.0, b.0);
+    let pivot = match a {
+        None if operation == SetOperation::Union => return Detached(b),
+        None => return Detached(None),
+        Some(pivot) => pivot,
+    };
+    let b_size = match &b {
+        None if operation == SetOperation::Intersection => return Detached(None),
+        None => return Detached(Some(pivot)),
+        Some(b) => b.borrow().size,
+    };
+
+    let small = pivot.borrow().size + b_size <= SEQUENTIAL_MERGE;
+    let (la, ra) = detach(&pivot);
+    let (lb, found, rb) = {
+        let p = pivot.borrow();
+        let (lb, rest) = split(b, &|v: &T| *v >= p.value);
+        let (found, rb) = split(rest, &|v: &T| *v > p.value);
+        (lb, found, rb)
+    };
+    let (la, lb, ra, rb) = (Detached(la), Detached(lb), Detached(ra), Detached(rb));
+    let (left, right) = if small {
+        (combine_subtrees(la, lb, operation), combine_subtrees(ra, rb, operation))
+    }
+    else {
+        rayon::join(move || combine_subtrees(la, lb, operation), move || combine_subtrees(ra, rb, operation))
+    };
+
+    let keep = match operation {
+        SetOperation::Union => true,
+        SetOperation::Intersection => found.is_some(),
+        SetOperation::Difference => found.is_none(),
+    };
+    if keep {
+        Detached(Some(join(left.0, pivot, right.0)))
+    }
+    else {
+        Detached(concat(left.0, right.0))
+    }
+}
+
+/// A complete subtree, which can be handed over to another thread.
+struct Detached<T: Ord>(Link<T>);
+
+// SAFETY: all the links to the nodes of the subtree, strong and weak, are held by the subtree
+// itself, so the reference counts all move together with it. That takes subtrees of trees whose
+// handles were cut by `release_handles`, or of brand new ones: the anchors of the nodes are
+// shared with the handles, and hold links to the nodes.
+unsafe impl<T: Ord + Send> Send for Detached<T> {}
+
+/// Builds a perfectly balanced subtree out of clones of `values`, strictly increasing, of the
+/// same shape as `build_balanced` gives.
+fn build_in_parallel<T: Ord + Clone + Send + Sync>(values: &[&T]) -> Detached<T> {
+    let len = values.len();
+    if len <= SEQUENTIAL_BUILD {
+        return Detached(build_balanced(&mut values.iter().map(|&v| v.clone()), len));
+    }
+    let mid = len / 2;
+    let (left, right) = rayon::join(|| build_in_parallel(&values[..mid]), || build_in_parallel(&values[mid + 1..]));
+    let (left, right) = (left.0, right.0);
+
+    let node = Rc::new(RefCell::new(AVLNode::new(values[mid].clone(), &None)));
+    let mut n = node.borrow_mut();
+    for child in [&left, &right].into_iter().flatten() {
+        child.borrow_mut().parent = Rc::downgrade(&node);
+    }
+    n.left = left;
+    n.right = right;
+    n.balance = balanced_height(len - mid - 1) as i8 - balanced_height(mid) as i8;
+    n.update_size();
+    drop(n);
+    Detached(Some(node))
+}
+
+/// A parallel iterator over the elements of an `AVLTree`, in sorted order.
+///
+/// Created by `par_iter()` or `into_par_iter()` on `&AVLTree`.
+pub struct ParIter<'a, T: Ord> {
+    root: Option<Shared<'a, T>>,
+}
+
+impl<'a, T: Ord + Sync> IntoParallelIterator for &'a AVLTree<T> {
+    type Item = &'a T;
+    type Iter = ParIter<'a, T>;
+
+    fn into_par_iter(self) -> ParIter<'a, T> {
+        ParIter { root: Shared::new(&self.root) }
+    }
+}
+
+impl<'a, T: Ord + Sync> ParallelIterator for ParIter<'a, T> {
+    type Item = &'a T;
+
+    fn drive_unindexed<C: UnindexedConsumer<&'a T>>(self, consumer: C) -> C::Result {
+        bridge(self, consumer)
+    }
+
+    fn opt_len(&self) -> Option<usize> {
+        Some(self.len())
+    }
+}
+
+impl<T: Ord + Sync> IndexedParallelIterator for ParIter<'_, T> {
+    fn len(&self) -> usize {
+        self.root.map_or(0, |root| root.0.size)
+    }
+
+    fn drive<C: Consumer<Self::Item>>(self, consumer: C) -> C::Result {
+        bridge(self, consumer)
+    }
+
+    fn with_producer<CB: ProducerCallback<Self::Item>>(self, callback: CB) -> CB::Output {
+        let end = self.len();
+        callback.callback(RankProducer { root: self.root, start: 0, end })
+    }
+}
+
+/// Produces the elements of ranks `start..end` in the subtree of `root`.
+struct RankProducer<'a, T: Ord> {
+    root: Option<Shared<'a, T>>,
+    start: usize,
+    end: usize,
+}
+
+impl<T: Ord> RankProducer<'_, T> {
+
+    /// Moves down to the smallest subtree holding all of the range.
+    fn narrow(mut self) -> Self {
+        while let Some(root) = self.root {
+            let left = root.child_size(Side::Left);
+            if self.end <= left {
+                self.root = root.child(Side::Left);
+            }
+            else if self.start > left {
+                self.root = root.child(Side::Right);
+                self.start -= left + 1;
+                self.end -= left + 1;
+            }
+            else {
+                break;
+            }
+        }
+        self
+    }
+}
+
+impl<'a, T: Ord + Sync> Producer for RankProducer<'a, T> {
+    type Item = &'a T;
+    type IntoIter = RangeIter<'a, T>;
+
+    fn into_iter(self) -> RangeIter<'a, T> {
+        let mut iter = RangeIter { front: Vec::new(), back: Vec::new(), remaining: self.end - self.start };
+        if let Some(root) = self.root.filter(|_| self.start < self.end) {
+            iter.seek(root, Side::Left, self.start);
+            iter.seek(root, Side::Right, root.0.size - self.end);
+        }
+        iter
+    }
+
+    fn split_at(self, index: usize) -> (Self, Self) {
+        let mid = self.start + index;
+        let low = RankProducer { root: self.root, start: self.start, end: mid };
+        let high = RankProducer { root: self.root, start: mid, end: self.end };
+        (low.narrow(), high.narrow())
+    }
+}
+
+/// Walks the elements of a range of ranks from both ends, keeping a stack of the nodes still
+/// to visit at each end.
+struct RangeIter<'a, T: Ord> {
+    front: Vec<Shared<'a, T>>,
+    back: Vec<Shared<'a, T>>,
+    remaining: usize,
+}
+
+impl<'a, T: Ord> RangeIter<'a, T> {
+
+    fn stack(&mut self, side: Side) -> &mut Vec<Shared<'a, T>> {
+        match side {
+            Side::Left => &mut self.front,
+            Side::Right => &mut self.back,
+        }
+    }
+
+    /// Stacks the path to the element `rank` places away from the `side` end of the subtree of
+    /// `root`, keeping only the nodes still to visit from that end.
+    fn seek(&mut self, root: Shared<'a, T>, side: Side, mut rank: usize) {
+        let stack = self.stack(side);
+        let mut next = Some(root);
+        while let Some(n) = next {
+            let near = n.child_size(side);
+            if rank <= near {
+                stack.push(n);
+                if rank == near {
+                    return;
+                }
+                next = n.child(side);
+            }
+            else {
+                rank -= near + 1;
+                next = n.child(!side);
+            }
+        }
+    }
+
+    fn step(&mut self, side: Side) -> Option<&'a T> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        let stack = self.stack(side);
+        let n = stack.pop()?;
+        let mut next = n.child(!side);
+        while let Some(c) = next {
+            stack.push(c);
+            next = c.child(side);
+        }
+        Some(&n.0.value)
+    }
+}
+
+impl<'a, T: Ord> Iterator for RangeIter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        self.step(Side::Left)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, T: Ord> DoubleEndedIterator for RangeIter<'a, T> {
+    fn next_back(&mut self) -> Option<&'a T> {
+        self.step(Side::Right)
+    }
+}
+
+impl<T: Ord> ExactSizeIterator for RangeIter<'_, T> {}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::height_of;
+
+    #[test]
+    fn test_par_iter() {
+        let mut tree: AVLTree<u64> = (0..10_000).collect();
+        for v in (0..10_000).filter(|v| v % 7 == 0) {
+            tree.remove(&v);
+        }
+        let expected: Vec<u64> = tree.iter().copied().collect();
+
+        assert_eq!(tree.par_iter().with_max_len(1).sum::<u64>(), expected.iter().sum::<u64>());
+        let collected: Vec<u64> = tree.par_iter().with_max_len(3).copied().collect();
+        assert_eq!(collected, expected);
+        let reversed: Vec<u64> = tree.par_iter().rev().copied().collect();
+        assert!(reversed.iter().eq(expected.iter().rev()));
+        let middle: Vec<u64> = tree.par_iter().skip(100).take(5000).copied().collect();
+        assert_eq!(middle, expected[100..5100]);
+        assert!(tree.par_iter().enumerate().all(|(i, v)| expected[i] == *v));
+        assert_eq!(tree.par_iter().len(), expected.len());
+
+        let empty = AVLTree::<u64>::new();
+        assert_eq!(empty.par_iter().count(), 0);
+    }
+
+    #[test]
+    fn test_from_sorted_slice() {
+        let values: Vec<u32> = (0..50_000).map(|v| v / 3 * 2).collect();
+        let tree = AVLTree::from_sorted_slice(&values);
+        assert_eq!(tree.len(), 16_667);
+        assert!(tree.iter().copied().eq((0..16_667).map(|v| v * 2)));
+        // the same shape as when built on a single thread
+        let sequential = AVLTree::from_sorted_iter(values.iter().copied());
+        assert_eq!(tree.to_json_structure(), sequential.to_json_structure());
+        assert!(AVLTree::<u32>::from_sorted_slice(&[]).is_empty());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_from_unsorted_slice() {
+        AVLTree::from_sorted_slice(&[1, 3, 2]);
+    }
+
+    #[test]
+    fn test_par_set_operations() {
+        let a: AVLTree<u32> = (0..30_000).filter(|v| v % 2 == 0).collect();
+        let b: AVLTree<u32> = (0..30_000).filter(|v| v % 3 == 0).collect();
+        let check = |tree: AVLTree<u32>, expected: Vec<u32>| {
+            assert!(tree.iter().eq(expected.iter()));
+            assert_eq!(tree.len(), expected.len());
+            assert!((height_of(&tree.root) as f64) < 1.45 * (expected.len() as f64 + 2.0).log2());
+        };
+        check(a.clone().par_union(b.clone()), a.union(&b).copied().collect());
+        check(a.clone().par_intersection(b.clone()), a.intersection(&b).copied().collect());
+        check(a.clone().par_difference(b.clone()), a.difference(&b).copied().collect());
+        check(b.clone().par_difference(a.clone()), b.difference(&a).copied().collect());
+        check(a.clone().par_union(AVLTree::new()), a.to_vec());
+        check(AVLTree::new().par_intersection(b.clone()), Vec::new());
+
+        // the handles to the nodes stay on this thread, cut from them
+        let mut c = a.clone();
+        let handle = c.insert_with_handle(1).unwrap();
+        let c = c.par_intersection(b.clone());
+        assert_eq!(c.get_by_handle(&handle), None);
+        assert_eq!(c.len(), a.intersection(&b).count());
+    }
+}
@@ -0,0 +1,240 @@
+//! Exports of the tree shape to formats meant for visualization tools.
+
+use std::fmt::{self, Write};
+#[cfg(feature = "svg")]
+use std::io;
+
+use crate::{height_of, peek, AVLTree, NodeRef, Side};
+
+impl<T: Ord + fmt::Display> AVLTree<T> {
+
+    /// Returns a Mermaid flowchart of the tree, ready to be pasted in a Markdown ` ```mermaid `
+    /// block.
+    ///
+    /// Nodes are numbered in preorder and labeled with their elements, edges are labeled `L` or
+    /// `R` so that a lone child shows on which side it hangs.
+    ///
+    /// ```text
+    /// flowchart TD
+    ///     n0["2"]
+    ///     n0 -->|L| n1["1"]
+    ///     n0 -->|R| n2["3"]
+    /// ```
+    pub fn to_mermaid(&self) -> String {
+        let mut out = String::from("flowchart TD\n");
+        if let Some(root) = &self.root {
+            writeln!(out, "    n0[\"{}\"]", mermaid_label(&peek(root).value)).unwrap();
+            let mut next_id = 1;
+            write_mermaid_edges(&mut out, root, 0, &mut next_id);
+        }
+        out
+    }
+}
+
+/// Writes the edges below `node`, known as `n{id}`, numbering its descendants from `next_id`.
+fn write_mermaid_edges<T: Ord + fmt::Display>(out: &mut String, node: &NodeRef<T>, id: usize, next_id: &mut usize) {
+    for (side, tag) in [(Side::Left, 'L'), (Side::Right, 'R')] {
+        if let Some(child) = peek(node).child(side) {
+            let child_id = *next_id;
+            *next_id += 1;
+            writeln!(out, "    n{} -->|{}| n{}[\"{}\"]", id, tag, child_id, mermaid_label(&peek(child).value)).unwrap();
+            write_mermaid_edges(out, child, child_id, next_id);
+        }
+    }
+}
+
+/// Renders `value` for use inside a quoted Mermaid label, where `"` has to be an entity.
+fn mermaid_label<T: fmt::Display>(value: &T) -> String {
+    value.to_string().replace('"', "#quot;")
+}
+
+impl<T: Ord + fmt::Display> AVLTree<T> {
+
+    /// Returns the structure of the tree as nested JSON objects, for tree visualizations such as
+    /// d3's hierarchy layouts.
+    ///
+    /// Each node is an object `{"value": ..., "height": ..., "left": ..., "right": ...}` whose
+    /// children are node objects or `null`; an empty tree gives `null`. The value is the
+    /// element's `Display` output as a JSON string.
+    pub fn to_json_structure(&self) -> String {
+        let mut out = String::new();
+        write_json_node(&mut out, self.root.as_ref(), height_of(&self.root));
+        out
+    }
+}
+
+fn write_json_node<T: Ord + fmt::Display>(out: &mut String, node: Option<&NodeRef<T>>, height: usize) {
+    let Some(node) = node
+    else {
+        out.push_str("null");
+        return;
+    };
+    let n = peek(node);
+    write!(out, "{{\"value\":\"{}\",\"height\":{},\"left\":", json_escape(&n.value.to_string()), height).unwrap();
+    write_json_node(out, n.left.as_ref(), n.child_height(Side::Left, height));
+    out.push_str(",\"right\":");
+    write_json_node(out, n.right.as_ref(), n.child_height(Side::Right, height));
+    out.push('}');
+}
+
+/// Escapes `text` for use inside a JSON string.
+pub(crate) fn json_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(escaped, "\\u{:04x}", c as u32).unwrap(),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Horizontal distance between consecutive elements, and vertical distance between levels.
+#[cfg(feature = "svg")]
+const SVG_STEP: (f64, f64) = (48.0, 64.0);
+#[cfg(feature = "svg")]
+const SVG_RADIUS: f64 = 18.0;
+
+#[cfg(feature = "svg")]
+impl<T: Ord + fmt::Display> AVLTree<T> {
+
+    /// Returns an SVG drawing of the tree, available with the `svg` feature.
+    ///
+    /// Each element gets its own column, in sorted order, and each level its own row. Nodes are
+    /// colored by balance factor: green when balanced, yellow when leaning by one, red beyond,
+    /// which a valid AVL tree never shows.
+    pub fn to_svg(&self) -> String {
+        let mut placed = Vec::with_capacity(self.len());
+        if let Some(root) = &self.root {
+            place(root, 0, &mut 0, None, &mut placed);
+        }
+        let levels = height_of(&self.root);
+        let width = SVG_STEP.0 * self.len().max(1) as f64;
+        let height = SVG_STEP.1 * levels.max(1) as f64;
+
+        let mut out = String::new();
+        writeln!(out, r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" viewBox="0 0 {} {}">"#, width, height, width, height).unwrap();
+        for p in &placed {
+            if let Some((px, py)) = p.parent {
+                writeln!(out, r#"  <line x1="{}" y1="{}" x2="{}" y2="{}" stroke="black"/>"#, px, py, p.x, p.y).unwrap();
+            }
+        }
+        for p in &placed {
+            let fill = match p.balance.abs() {
+                0 => "#b8e6b8",
+                1 => "#f5e49c",
+                _ => "#f08080",
+            };
+            writeln!(out, r#"  <circle cx="{}" cy="{}" r="{}" fill="{}" stroke="black"/>"#, p.x, p.y, SVG_RADIUS, fill).unwrap();
+            writeln!(out, r#"  <text x="{}" y="{}" text-anchor="middle" dominant-baseline="central" font-family="sans-serif" font-size="12">{}</text>"#, p.x, p.y, p.label).unwrap();
+        }
+        out.push_str("</svg>\n");
+        out
+    }
+
+    /// Writes the drawing made by `to_svg` to `w`, for instance a freshly created `.svg` file.
+    pub fn write_svg<W: io::Write>(&self, mut w: W) -> io::Result<()> {
+        w.write_all(self.to_svg().as_bytes())
+    }
+}
+
+/// A node laid out for the SVG drawing.
+#[cfg(feature = "svg")]
+struct Placed {
+    x: f64,
+    y: f64,
+    parent: Option<(f64, f64)>,
+    balance: i8,
+    label: String,
+}
+
+/// Lays out the subtree of `node`, whose elements start at column `next_column`, and returns
+/// the position of `node`.
+#[cfg(feature = "svg")]
+fn place<T: Ord + fmt::Display>(node: &NodeRef<T>, depth: usize, next_column: &mut usize, parent: Option<(f64, f64)>, placed: &mut Vec<Placed>) {
+    let n = peek(node);
+    // the column is only known once the left subtree is laid out, so the node is pushed first
+    // and positioned afterwards
+    let index = placed.len();
+    let y = SVG_STEP.1 * (depth as f64 + 0.5);
+    placed.push(Placed { x: 0.0, y, parent, balance: n.balance_factor(), label: xml_escape(&n.value.to_string()) });
+    let x_of = |column: usize| SVG_STEP.0 * (column as f64 + 0.5);
+    if let Some(left) = &n.left {
+        // the left child's edge starts from this node, whose column is not known yet
+        let first = placed.len();
+        place(left, depth + 1, next_column, None, placed);
+        placed[first].parent = Some((x_of(*next_column), y));
+    }
+    let x = x_of(*next_column);
+    *next_column += 1;
+    placed[index].x = x;
+    if let Some(right) = &n.right {
+        place(right, depth + 1, next_column, Some((x, y)), placed);
+    }
+}
+
+#[cfg(feature = "svg")]
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mermaid() {
+        assert_eq!(AVLTree::<i32>::new().to_mermaid(), "flowchart TD\n");
+        let mut tree = AVLTree::new();
+        tree.extend([2, 1, 3, 4]);
+        let expected = "flowchart TD\n    n0[\"2\"]\n    n0 -->|L| n1[\"1\"]\n    n0 -->|R| n2[\"3\"]\n    n2 -->|R| n3[\"4\"]\n";
+        assert_eq!(tree.to_mermaid(), expected);
+
+        let quoted = AVLTree::from(["say \"hi\"".to_string()]);
+        assert_eq!(quoted.to_mermaid(), "flowchart TD\n    n0[\"say #quot;hi#quot;\"]\n");
+    }
+
+    #[test]
+    fn test_json_structure() {
+        assert_eq!(AVLTree::<i32>::new().to_json_structure(), "null");
+        let mut tree = AVLTree::new();
+        tree.extend([2, 1, 3, 4]);
+        let json = tree.to_json_structure();
+        let leaf = |v: i32| format!(r#"{{"value":"{}","height":1,"left":null,"right":null}}"#, v);
+        let expected = format!(r#"{{"value":"2","height":3,"left":{},"right":{{"value":"3","height":2,"left":null,"right":{}}}}}"#, leaf(1), leaf(4));
+        assert_eq!(json, expected);
+        let parsed: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed["right"]["right"]["value"], "4");
+
+        let quoted = AVLTree::from(["a\"b\\c\n".to_string()]).to_json_structure();
+        let parsed: serde_json::Value = serde_json::from_str(&quoted).unwrap();
+        assert_eq!(parsed["value"], "a\"b\\c\n");
+    }
+
+    #[cfg(feature = "svg")]
+    #[test]
+    fn test_svg() {
+        let mut tree = AVLTree::new();
+        tree.extend([2, 1, 3, 4]);
+        let svg = tree.to_svg();
+        assert!(svg.starts_with(r#"<svg xmlns="http://www.w3.org/2000/svg" width="192" height="192""#));
+        assert_eq!(svg.matches("<circle").count(), 4);
+        assert_eq!(svg.matches("<line").count(), 3);
+        // the root in the second column, leaning right; 1 at its lower left
+        assert!(svg.contains(r##"<circle cx="72" cy="32" r="18" fill="#f5e49c""##));
+        assert!(svg.contains(r#"<line x1="72" y1="32" x2="24" y2="96" stroke="black"/>"#));
+        assert!(svg.contains(r#"<line x1="120" y1="96" x2="168" y2="160" stroke="black"/>"#));
+
+        let escaped = AVLTree::from(["a<b".to_string()]).to_svg();
+        assert!(escaped.contains(">a&lt;b</text>"));
+        let mut file = Vec::new();
+        tree.write_svg(&mut file).unwrap();
+        assert_eq!(file, svg.into_bytes());
+    }
+}
@@ -0,0 +1,162 @@
+//! rkyv support, enabled by the `rkyv` feature.
+//!
+//! An `AVLTree` is archived as the sorted array of its elements, [`ArchivedAVLTree`], which
+//! answers lookups by binary search right from the archive bytes, memory-mapped ones included.
+
+use std::cmp::Ordering;
+use std::error::Error;
+use std::fmt;
+use std::ops::{Bound, RangeBounds};
+
+use rkyv::bytecheck::{CheckBytes, Verify};
+use rkyv::rancor::{fail, Fallible, Source};
+use rkyv::ser::{Allocator, Writer};
+use rkyv::vec::{ArchivedVec, VecResolver};
+use rkyv::{Archive, Deserialize, Place, Portable, Serialize};
+
+use crate::{build_balanced, AVLTree};
+
+/// The archived form of an `AVLTree`: its elements in strictly increasing order.
+///
+/// Validating an archive (as `rkyv::access` does) checks that order, so the lookups can rely on
+/// it.
+#[derive(Portable, CheckBytes)]
+#[bytecheck(crate = rkyv::bytecheck, verify)]
+#[rkyv(crate = rkyv)]
+#[repr(transparent)]
+pub struct ArchivedAVLTree<T> {
+    elements: ArchivedVec<T>,
+}
+
+impl<T> ArchivedAVLTree<T> {
+
+    /// Returns the number of elements in the archived tree.
+    pub fn len(&self) -> usize {
+        self.elements.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.elements.is_empty()
+    }
+
+    /// Returns the elements, in sorted order.
+    pub fn as_slice(&self) -> &[T] {
+        self.elements.as_slice()
+    }
+
+    /// Returns an iterator over the elements, in sorted order.
+    pub fn iter(&self) -> std::slice::Iter<'_, T> {
+        self.as_slice().iter()
+    }
+
+    /// Returns the element at position `rank` in sorted order.
+    pub fn get_by_rank(&self, rank: usize) -> Option<&T> {
+        self.as_slice().get(rank)
+    }
+
+    /// Returns `true` if the archived tree holds an element equal to `value`.
+    ///
+    /// `value` can be of the unarchived type, as for `Archived<i32>` compared with `i32`.
+    pub fn contains<Q: ?Sized>(&self, value: &Q) -> bool where T: PartialOrd<Q> {
+        self.as_slice().binary_search_by(|e| e.partial_cmp(value).unwrap_or(Ordering::Less)).is_ok()
+    }
+
+    /// Returns the elements falling in `range`, in sorted order.
+    pub fn range<Q, R: RangeBounds<Q>>(&self, range: R) -> &[T] where T: PartialOrd<Q> {
+        let elements = self.as_slice();
+        let start = match range.start_bound() {
+            Bound::Included(s) => elements.partition_point(|e| e < s),
+            Bound::Excluded(s) => elements.partition_point(|e| e <= s),
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(e) => elements.partition_point(|x| x <= e),
+            Bound::Excluded(e) => elements.partition_point(|x| x < e),
+            Bound::Unbounded => elements.len(),
+        };
+        &elements[start..end.max(start)]
+    }
+}
+
+#[derive(Debug)]
+struct UnsortedError;
+
+impl fmt::Display for UnsortedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "archived tree elements are not strictly increasing")
+    }
+}
+
+impl Error for UnsortedError {}
+
+// SAFETY: only succeeds when the elements are in strictly increasing order, the one invariant
+// of the type
+unsafe impl<T: Ord, C: Fallible + ?Sized> Verify<C> for ArchivedAVLTree<T> where C::Error: Source {
+    fn verify(&self, _: &mut C) -> Result<(), C::Error> {
+        if !self.as_slice().windows(2).all(|w| w[0] < w[1]) {
+            fail!(UnsortedError);
+        }
+        Ok(())
+    }
+}
+
+impl<T: Ord + Archive> Archive for AVLTree<T> {
+    type Archived = ArchivedAVLTree<T::Archived>;
+    type Resolver = VecResolver;
+
+    fn resolve(&self, resolver: VecResolver, out: Place<Self::Archived>) {
+        rkyv::munge::munge!(let ArchivedAVLTree { elements } = out);
+        ArchivedVec::resolve_from_len(self.len(), resolver, elements);
+    }
+}
+
+impl<T: Ord + Serialize<S>, S: Fallible + Allocator + Writer + ?Sized> Serialize<S> for AVLTree<T> {
+    fn serialize(&self, serializer: &mut S) -> Result<VecResolver, S::Error> {
+        ArchivedVec::<T::Archived>::serialize_from_iter::<T, _, _>(self.iter(), serializer)
+    }
+}
+
+/// Rebuilds a balanced tree from the sorted elements in O(n).
+impl<T, D> Deserialize<AVLTree<T>, D> for ArchivedAVLTree<T::Archived>
+where
+    T: Ord + Archive,
+    T::Archived: Deserialize<T, D>,
+    D: Fallible + ?Sized,
+{
+    fn deserialize(&self, deserializer: &mut D) -> Result<AVLTree<T>, D::Error> {
+        let values = self.iter().map(|e| e.deserialize(deserializer)).collect::<Result<Vec<T>, _>>()?;
+        let len = values.len();
+        Ok(AVLTree::from_root(build_balanced(&mut values.into_iter(), len)))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rkyv::rancor;
+
+    #[test]
+    fn test_archive() {
+        let tree: AVLTree<i32> = (0..100).map(|v| v * 3).collect();
+        let bytes = rkyv::to_bytes::<rancor::Error>(&tree).unwrap();
+        let archived = rkyv::access::<ArchivedAVLTree<rkyv::Archived<i32>>, rancor::Error>(&bytes).unwrap();
+        assert_eq!(archived.len(), 100);
+        assert!(archived.contains(&30));
+        assert!(!archived.contains(&31));
+        assert_eq!(archived.range(10..=21).iter().map(|v| v.to_native()).collect::<Vec<_>>(), vec![12, 15, 18, 21]);
+        assert!(archived.range((Bound::Included(50), Bound::Excluded(40))).is_empty());
+        assert_eq!(archived.get_by_rank(99).map(|v| v.to_native()), Some(297));
+
+        let back: AVLTree<i32> = rkyv::deserialize::<AVLTree<i32>, rancor::Error>(archived).unwrap();
+        assert!(back == tree);
+    }
+
+    #[test]
+    fn test_archive_rejects_unsorted() {
+        let bytes = rkyv::to_bytes::<rancor::Error>(&vec![1, 3, 2]).unwrap();
+        assert!(rkyv::access::<ArchivedAVLTree<rkyv::Archived<i32>>, rancor::Error>(&bytes).is_err());
+        let bytes = rkyv::to_bytes::<rancor::Error>(&vec![1, 2, 3]).unwrap();
+        assert!(rkyv::access::<ArchivedAVLTree<rkyv::Archived<i32>>, rancor::Error>(&bytes).is_ok());
+    }
+}
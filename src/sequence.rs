@@ -0,0 +1,397 @@
+//! A list based on an AVL tree, whose elements are ordered by position instead of by value.
+//!
+//! Nodes keep the size of their subtree, so that the position of an element is the number of
+//! elements on its left: it serves as the key, without being stored. Inserting or removing at
+//! any position, splitting and concatenating are all done by splitting and joining subtrees,
+//! which takes O(log n).
+
+use std::fmt;
+use std::ops::{Index, IndexMut};
+
+type Link<T> = Option<Box<Node<T>>>;
+
+struct Node<T> {
+    value: T,
+    height: u8,
+    size: usize,
+    left: Link<T>,
+    right: Link<T>,
+}
+
+fn height<T>(link: &Link<T>) -> u8 {
+    link.as_ref().map_or(0, |n| n.height)
+}
+
+fn size<T>(link: &Link<T>) -> usize {
+    link.as_ref().map_or(0, |n| n.size)
+}
+
+fn node<T>(value: T, left: Link<T>, right: Link<T>) -> Box<Node<T>> {
+    let height = 1 + height(&left).max(height(&right));
+    let size = 1 + size(&left) + size(&right);
+    Box::new(Node { value, height, size, left, right })
+}
+
+/// Makes a node of `value` between `left` and `right`, rotating if their heights differ by 2.
+fn balance<T>(value: T, left: Link<T>, right: Link<T>) -> Box<Node<T>> {
+    let (hl, hr) = (height(&left), height(&right));
+    if hl > hr + 1 {
+        let l = *left.unwrap();
+        if height(&l.left) >= height(&l.right) {
+            node(l.value, l.left, Some(node(value, l.right, right)))
+        }
+        else { // left-right case
+            let lr = *l.right.unwrap();
+            node(lr.value, Some(node(l.value, l.left, lr.left)), Some(node(value, lr.right, right)))
+        }
+    }
+    else if hr > hl + 1 {
+        let r = *right.unwrap();
+        if height(&r.right) >= height(&r.left) {
+            node(r.value, Some(node(value, left, r.left)), r.right)
+        }
+        else { // right-left case
+            let rl = *r.left.unwrap();
+            node(rl.value, Some(node(value, left, rl.left)), Some(node(r.value, rl.right, r.right)))
+        }
+    }
+    else {
+        node(value, left, right)
+    }
+}
+
+/// Joins two subtrees of any heights with `value` in between.
+fn join<T>(left: Link<T>, value: T, right: Link<T>) -> Box<Node<T>> {
+    let (hl, hr) = (height(&left), height(&right));
+    if hl > hr + 1 {
+        let l = *left.unwrap();
+        balance(l.value, l.left, Some(join(l.right, value, right)))
+    }
+    else if hr > hl + 1 {
+        let r = *right.unwrap();
+        balance(r.value, Some(join(left, value, r.left)), r.right)
+    }
+    else {
+        node(value, left, right)
+    }
+}
+
+/// Joins two subtrees of any heights, `left` coming first.
+fn concat<T>(left: Link<T>, right: Link<T>) -> Link<T> {
+    let Some(right) = right else {
+        return left;
+    };
+    let (first, right) = remove_first(*right);
+    Some(join(left, first, right))
+}
+
+/// Returns the first element of the subtree at `n` and the subtree without it.
+fn remove_first<T>(n: Node<T>) -> (T, Link<T>) {
+    match n.left {
+        None => (n.value, n.right),
+        Some(left) => {
+            let (first, left) = remove_first(*left);
+            (first, Some(join(left, n.value, n.right)))
+        }
+    }
+}
+
+/// Splits the subtree at `link` into its first `index` elements and the others.
+fn split<T>(link: Link<T>, index: usize) -> (Link<T>, Link<T>) {
+    let Some(n) = link else {
+        return (None, None);
+    };
+    let n = *n;
+    let left_size = size(&n.left);
+    if index <= left_size {
+        let (left, right) = split(n.left, index);
+        (left, Some(join(right, n.value, n.right)))
+    }
+    else {
+        let (left, right) = split(n.right, index - left_size - 1);
+        (Some(join(n.left, n.value, left)), right)
+    }
+}
+
+/// Builds a perfectly balanced subtree out of the next `len` elements of `values`.
+fn build<T, I: Iterator<Item = T>>(values: &mut I, len: usize) -> Link<T> {
+    if len == 0 {
+        return None;
+    }
+    let left = build(values, len / 2);
+    let value = values.next().expect("iterator shorter than announced");
+    let right = build(values, len - len / 2 - 1);
+    Some(node(value, left, right))
+}
+
+/// A list of elements with O(log n) access, insertion and removal at any position, and O(log n)
+/// splitting and concatenation.
+///
+/// Positions are counted from 0, as for a `Vec`, which it can replace where elements get
+/// inserted and removed in the middle, or lists get cut and pasted.
+pub struct Sequence<T> {
+    root: Link<T>,
+}
+
+impl<T> Sequence<T> {
+
+    /// Creates an empty sequence.
+    pub const fn new() -> Self {
+        Sequence { root: None }
+    }
+
+    /// Returns the number of elements in the sequence.
+    pub fn len(&self) -> usize {
+        size(&self.root)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+
+    /// Returns the element at `index`, or `None` if it is out of bounds.
+    pub fn get(&self, index: usize) -> Option<&T> {
+        let mut index = index;
+        let mut next = self.root.as_deref();
+        while let Some(n) = next {
+            let left_size = size(&n.left);
+            if index == left_size {
+                return Some(&n.value);
+            }
+            if index < left_size {
+                next = n.left.as_deref();
+            }
+            else {
+                index -= left_size + 1;
+                next = n.right.as_deref();
+            }
+        }
+        None
+    }
+
+    /// Returns the element at `index` mutably, or `None` if it is out of bounds.
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        let mut index = index;
+        let mut next = self.root.as_deref_mut();
+        while let Some(n) = next {
+            let left_size = size(&n.left);
+            if index == left_size {
+                return Some(&mut n.value);
+            }
+            if index < left_size {
+                next = n.left.as_deref_mut();
+            }
+            else {
+                index -= left_size + 1;
+                next = n.right.as_deref_mut();
+            }
+        }
+        None
+    }
+
+    /// Inserts `value` at `index`, shifting the elements from there one position further.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is larger than the length.
+    pub fn insert_at(&mut self, index: usize, value: T) {
+        assert!(index <= self.len(), "Sequence::insert_at: index {index} out of bounds");
+        let (left, right) = split(self.root.take(), index);
+        self.root = Some(join(left, value, right));
+    }
+
+    /// Adds `value` at the end of the sequence.
+    pub fn push(&mut self, value: T) {
+        self.insert_at(self.len(), value);
+    }
+
+    /// Removes the element at `index` and returns it, shifting the elements after it one
+    /// position back. Returns `None` if `index` is out of bounds.
+    pub fn remove_at(&mut self, index: usize) -> Option<T> {
+        if index >= self.len() {
+            return None;
+        }
+        let (left, right) = split(self.root.take(), index);
+        let (value, right) = remove_first(*right.unwrap());
+        self.root = concat(left, right);
+        Some(value)
+    }
+
+    /// Splits the sequence into its first `index` elements and the others.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is larger than the length.
+    pub fn split_at(mut self, index: usize) -> (Self, Self) {
+        assert!(index <= self.len(), "Sequence::split_at: index {index} out of bounds");
+        let (left, right) = split(self.root.take(), index);
+        (Sequence { root: left }, Sequence { root: right })
+    }
+
+    /// Returns the sequence made of the elements of `self`, then the ones of `other`.
+    pub fn concat(mut self, mut other: Self) -> Self {
+        Sequence { root: concat(self.root.take(), other.root.take()) }
+    }
+
+    /// Returns an iterator over the elements, in order.
+    pub fn iter(&self) -> Iter<'_, T> {
+        let mut iter = Iter { stack: Vec::new(), remaining: self.len() };
+        iter.descend(self.root.as_deref());
+        iter
+    }
+}
+
+impl<T> Default for Sequence<T> {
+    fn default() -> Self {
+        Sequence::new()
+    }
+}
+
+/// Drops the nodes one by one, as dropping the root `Box` would recurse down the tree.
+impl<T> Drop for Sequence<T> {
+    fn drop(&mut self) {
+        let mut stack: Vec<Box<Node<T>>> = self.root.take().into_iter().collect();
+        while let Some(mut node) = stack.pop() {
+            stack.extend(node.left.take());
+            stack.extend(node.right.take());
+        }
+    }
+}
+
+impl<T> Index<usize> for Sequence<T> {
+    type Output = T;
+
+    fn index(&self, index: usize) -> &T {
+        self.get(index).expect("Sequence: index out of bounds")
+    }
+}
+
+impl<T> IndexMut<usize> for Sequence<T> {
+    fn index_mut(&mut self, index: usize) -> &mut T {
+        self.get_mut(index).expect("Sequence: index out of bounds")
+    }
+}
+
+/// Builds a balanced tree at once, in O(n).
+impl<T> FromIterator<T> for Sequence<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let values: Vec<T> = iter.into_iter().collect();
+        let len = values.len();
+        Sequence { root: build(&mut values.into_iter(), len) }
+    }
+}
+
+impl<T> Extend<T> for Sequence<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        let mut tail: Sequence<T> = iter.into_iter().collect();
+        self.root = concat(self.root.take(), tail.root.take());
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Sequence<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+/// An iterator over the elements of a `Sequence`, in order.
+pub struct Iter<'a, T> {
+    stack: Vec<&'a Node<T>>,
+    remaining: usize,
+}
+
+impl<'a, T> Iter<'a, T> {
+
+    /// Stacks `node` and its left children as far as they go.
+    fn descend(&mut self, mut next: Option<&'a Node<T>>) {
+        while let Some(n) = next {
+            self.stack.push(n);
+            next = n.left.as_deref();
+        }
+    }
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let n = self.stack.pop()?;
+        self.remaining -= 1;
+        self.descend(n.right.as_deref());
+        Some(&n.value)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<T> ExactSizeIterator for Iter<'_, T> {}
+
+impl<'a, T> IntoIterator for &'a Sequence<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Checks heights, sizes and balance of the whole tree, returning its height.
+    fn check<T>(link: &Link<T>) -> u8 {
+        let Some(n) = link else {
+            return 0;
+        };
+        let (hl, hr) = (check(&n.left), check(&n.right));
+        assert!(hl.abs_diff(hr) <= 1);
+        assert_eq!(n.height, 1 + hl.max(hr));
+        assert_eq!(n.size, 1 + size(&n.left) + size(&n.right));
+        n.height
+    }
+
+    #[test]
+    fn test_sequence() {
+        let mut seq = Sequence::new();
+        let mut expected = Vec::new();
+        for i in 0..300i64 {
+            let index = (i as usize * 37) % (expected.len() + 1);
+            seq.insert_at(index, i);
+            expected.insert(index, i);
+            check(&seq.root);
+        }
+        assert!(seq.iter().eq(expected.iter()));
+        assert_eq!(seq.get(300), None);
+        assert_eq!(seq[42], expected[42]);
+        seq[42] += 1000;
+        expected[42] += 1000;
+        for i in 0..100 {
+            let index = (i * 53) % expected.len();
+            assert_eq!(seq.remove_at(index), Some(expected.remove(index)));
+            check(&seq.root);
+        }
+        assert_eq!(seq.remove_at(200), None);
+        assert_eq!(seq.len(), 200);
+        assert!((0..200).all(|i| seq.get(i) == Some(&expected[i])));
+
+        let (front, back) = seq.split_at(70);
+        check(&front.root);
+        check(&back.root);
+        assert!(front.iter().eq(&expected[..70]));
+        assert!(back.iter().eq(&expected[70..]));
+        // pasting the back before the front: the heights differ
+        let (small, _) = Sequence::from_iter(0..10).split_at(3);
+        let mut seq = back.concat(small).concat(front);
+        check(&seq.root);
+        seq.extend([-1, -2]);
+        seq.push(-3);
+        check(&seq.root);
+        assert!(seq.iter().copied().eq(expected[70..].iter().copied().chain(0..3).chain(expected[..70].iter().copied()).chain([-1, -2, -3])));
+        assert_eq!(format!("{:?}", Sequence::from_iter(['a', 'b'])), "['a', 'b']");
+        let (empty, all) = seq.split_at(0);
+        assert!(empty.is_empty() && all.len() == 206);
+    }
+}
@@ -0,0 +1,155 @@
+//! Serde support, enabled by the `serde` feature. An `AVLTree` is represented as a sequence of
+//! its elements in sorted order, an `AVLMap` as a map in key order.
+//!
+//! Deserializing builds the tree bottom-up in O(n), which requires the input to be in that same
+//! order: elements (or keys) that aren't strictly increasing are rejected with an error, so a
+//! corrupted payload can't produce an invalid tree.
+
+use std::fmt;
+use std::marker::PhantomData;
+
+use serde::de::{Deserialize, Deserializer, Error, MapAccess, SeqAccess, Visitor};
+use serde::ser::{Serialize, Serializer};
+
+use crate::{build_balanced, AVLMap, AVLTree};
+
+impl<T: Ord + Serialize> Serialize for AVLTree<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+impl<'de, T: Ord + Deserialize<'de>> Deserialize<'de> for AVLTree<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_seq(TreeVisitor(PhantomData))
+    }
+}
+
+struct TreeVisitor<T>(PhantomData<T>);
+
+impl<'de, T: Ord + Deserialize<'de>> Visitor<'de> for TreeVisitor<T> {
+    type Value = AVLTree<T>;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "a sequence")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<AVLTree<T>, A::Error> {
+        let mut values: Vec<T> = Vec::with_capacity(capacity(seq.size_hint()));
+        while let Some(value) = seq.next_element()? {
+            check_order(values.last(), &value, values.len())?;
+            values.push(value);
+        }
+        let len = values.len();
+        Ok(AVLTree::from_root(build_balanced(&mut values.into_iter(), len)))
+    }
+}
+
+impl<K: Ord + Serialize, V: Serialize> Serialize for AVLMap<K, V> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(self.iter())
+    }
+}
+
+impl<'de, K: Ord + Deserialize<'de>, V: Deserialize<'de>> Deserialize<'de> for AVLMap<K, V> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_map(MapVisitor(PhantomData))
+    }
+}
+
+struct MapVisitor<K, V>(PhantomData<(K, V)>);
+
+impl<'de, K: Ord + Deserialize<'de>, V: Deserialize<'de>> Visitor<'de> for MapVisitor<K, V> {
+    type Value = AVLMap<K, V>;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "a map")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut access: A) -> Result<AVLMap<K, V>, A::Error> {
+        let mut entries: Vec<(K, V)> = Vec::with_capacity(capacity(access.size_hint()));
+        while let Some((key, value)) = access.next_entry()? {
+            check_order(entries.last().map(|(k, _)| k), &key, entries.len())?;
+            entries.push((key, value));
+        }
+        Ok(AVLMap::from_sorted_entries(entries.into_iter()))
+    }
+}
+
+/// Returns the capacity to reserve for the elements the input announces, at most 4096 of them
+/// ahead of reading them, as a tiny forged input could announce billions.
+fn capacity(size_hint: Option<usize>) -> usize {
+    size_hint.unwrap_or(0).min(4096)
+}
+
+/// Fails unless `value`, found at `index` in the input, comes strictly after `previous`.
+fn check_order<T: Ord, E: Error>(previous: Option<&T>, value: &T, index: usize) -> Result<(), E> {
+    match previous {
+        Some(previous) if previous >= value => {
+            Err(E::custom(format_args!("element at index {} is not greater than the previous one", index)))
+        }
+        _ => Ok(()),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::height_of;
+
+    #[test]
+    fn test_serde_tree() {
+        let tree = AVLTree::from([3, 1, 2]);
+        let json = serde_json::to_string(&tree).unwrap();
+        assert_eq!(json, "[1,2,3]");
+        let back: AVLTree<i32> = serde_json::from_str(&json).unwrap();
+        assert!(back == tree);
+        assert!(serde_json::from_str::<AVLTree<i32>>("{}").is_err());
+
+        let big: AVLTree<i32> = serde_json::from_str(&serde_json::to_string(&(0..1000).collect::<Vec<_>>()).unwrap()).unwrap();
+        assert!(big.iter().copied().eq(0..1000));
+        assert!(height_of(&big.root) <= 10);
+        assert_eq!(big.count_range(100..200), 100);
+    }
+
+    #[test]
+    fn test_serde_rejects_unsorted() {
+        let error = serde_json::from_str::<AVLTree<i32>>("[1,3,2]").unwrap_err();
+        assert!(error.to_string().contains("element at index 2 is not greater than the previous one"));
+        assert!(serde_json::from_str::<AVLTree<i32>>("[1,1]").is_err());
+        assert!(serde_json::from_str::<AVLMap<String, i32>>(r#"{"b":1,"a":2}"#).is_err());
+    }
+
+    /// A sequence announcing more elements than it holds.
+    struct Forged;
+
+    impl<'de> SeqAccess<'de> for Forged {
+        type Error = serde::de::value::Error;
+
+        fn next_element_seed<S: serde::de::DeserializeSeed<'de>>(&mut self, _: S) -> Result<Option<S::Value>, Self::Error> {
+            Ok(None)
+        }
+
+        fn size_hint(&self) -> Option<usize> {
+            Some(usize::MAX)
+        }
+    }
+
+    #[test]
+    fn test_serde_forged_size_hint() {
+        let tree: AVLTree<u64> = TreeVisitor(PhantomData).visit_seq(Forged).unwrap();
+        assert!(tree.is_empty());
+    }
+
+    #[test]
+    fn test_serde_map() {
+        let mut map = AVLMap::new();
+        map.insert("b".to_string(), vec![2]);
+        map.insert("a".to_string(), vec![1, 1]);
+        let json = serde_json::to_string(&map).unwrap();
+        assert_eq!(json, r#"{"a":[1,1],"b":[2]}"#);
+        let back: AVLMap<String, Vec<i32>> = serde_json::from_str(&json).unwrap();
+        assert_eq!(back.iter().collect::<Vec<_>>(), map.iter().collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,310 @@
+use std::cmp::Ordering;
+use std::iter::Peekable;
+
+use crate::{AVLTree, Iter};
+
+impl<T: Ord> AVLTree<T> {
+
+    /// Visits the elements present in `self` or in `other` (or both), in sorted order and
+    /// without duplicates.
+    pub fn union<'a>(&'a self, other: &'a AVLTree<T>) -> Union<'a, T> {
+        Union { a: self.iter().peekable(), b: other.iter().peekable() }
+    }
+
+    /// Visits the elements present in both `self` and `other`, in sorted order.
+    pub fn intersection<'a>(&'a self, other: &'a AVLTree<T>) -> Intersection<'a, T> {
+        Intersection { a: self.iter().peekable(), b: other.iter().peekable() }
+    }
+
+    /// Visits the elements present in `self` but not in `other`, in sorted order.
+    pub fn difference<'a>(&'a self, other: &'a AVLTree<T>) -> Difference<'a, T> {
+        Difference { a: self.iter().peekable(), b: other.iter().peekable() }
+    }
+
+    /// Visits the elements present in exactly one of `self` and `other`, in sorted order.
+    pub fn symmetric_difference<'a>(&'a self, other: &'a AVLTree<T>) -> SymmetricDifference<'a, T> {
+        SymmetricDifference { a: self.iter().peekable(), b: other.iter().peekable() }
+    }
+
+    /// Visits the elements present in exactly one of `self` and `other`, in sorted order, each
+    /// telling which of the two it is in: what `other` lacks to become a copy of `self`, and
+    /// what it has in excess.
+    pub fn diff<'a>(&'a self, other: &'a AVLTree<T>) -> Diff<'a, T> {
+        Diff { a: self.iter().peekable(), b: other.iter().peekable() }
+    }
+
+    /// Returns `true` if `self` and `other` have no element in common.
+    pub fn is_disjoint(&self, other: &AVLTree<T>) -> bool {
+        self.intersection(other).next().is_none()
+    }
+
+    /// Returns `true` if every element of `self` is also in `other`.
+    pub fn is_subset(&self, other: &AVLTree<T>) -> bool {
+        if self.len() > other.len() {
+            return false;
+        }
+        let mut others = other.iter();
+        'outer: for x in self.iter() {
+            for y in others.by_ref() {
+                match y.cmp(x) {
+                    Ordering::Less => continue,
+                    Ordering::Equal => continue 'outer,
+                    Ordering::Greater => return false,
+                }
+            }
+            return false; // ran out of elements in `other`
+        }
+        true
+    }
+
+    /// Returns `true` if every element of `other` is also in `self`.
+    pub fn is_superset(&self, other: &AVLTree<T>) -> bool {
+        other.is_subset(self)
+    }
+}
+
+/// A lazy iterator over the union of two `AVLTree`s. Created by [`AVLTree::union`].
+pub struct Union<'a, T: Ord> {
+    a: Peekable<Iter<'a, T>>,
+    b: Peekable<Iter<'a, T>>,
+}
+
+/// A lazy iterator over the intersection of two `AVLTree`s. Created by
+/// [`AVLTree::intersection`].
+pub struct Intersection<'a, T: Ord> {
+    a: Peekable<Iter<'a, T>>,
+    b: Peekable<Iter<'a, T>>,
+}
+
+/// A lazy iterator over the difference of two `AVLTree`s. Created by [`AVLTree::difference`].
+pub struct Difference<'a, T: Ord> {
+    a: Peekable<Iter<'a, T>>,
+    b: Peekable<Iter<'a, T>>,
+}
+
+/// A lazy iterator over the symmetric difference of two `AVLTree`s. Created by
+/// [`AVLTree::symmetric_difference`].
+pub struct SymmetricDifference<'a, T: Ord> {
+    a: Peekable<Iter<'a, T>>,
+    b: Peekable<Iter<'a, T>>,
+}
+
+/// A lazy iterator over the elements telling two `AVLTree`s apart. Created by
+/// [`AVLTree::diff`].
+pub struct Diff<'a, T: Ord> {
+    a: Peekable<Iter<'a, T>>,
+    b: Peekable<Iter<'a, T>>,
+}
+
+/// An element yielded by [`Diff`], along with the tree it is in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DiffItem<'a, T> {
+    /// The element is in the tree `diff` was called on, not in the other one.
+    OnlyInSelf(&'a T),
+    /// The element is in the tree passed to `diff`, not in the one it was called on.
+    OnlyInOther(&'a T),
+}
+
+impl<'a, T: Ord> Iterator for Union<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        let order = match (self.a.peek(), self.b.peek()) {
+            (None, _) => return self.b.next(),
+            (_, None) => return self.a.next(),
+            (Some(x), Some(y)) => x.cmp(y),
+        };
+        match order {
+            Ordering::Less => self.a.next(),
+            Ordering::Greater => self.b.next(),
+            Ordering::Equal => {
+                self.b.next();
+                self.a.next()
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (a, b) = (self.a.len(), self.b.len());
+        (a.max(b), Some(a + b))
+    }
+}
+
+impl<'a, T: Ord> Iterator for Intersection<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        loop {
+            let order = self.a.peek()?.cmp(self.b.peek()?);
+            match order {
+                Ordering::Less => { self.a.next(); },
+                Ordering::Greater => { self.b.next(); },
+                Ordering::Equal => {
+                    self.b.next();
+                    return self.a.next();
+                }
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.a.len().min(self.b.len())))
+    }
+}
+
+impl<'a, T: Ord> Iterator for Difference<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        loop {
+            let order = match self.b.peek() {
+                None => return self.a.next(),
+                Some(y) => self.a.peek()?.cmp(y),
+            };
+            match order {
+                Ordering::Less => return self.a.next(),
+                Ordering::Greater => { self.b.next(); },
+                Ordering::Equal => {
+                    self.a.next();
+                    self.b.next();
+                }
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (a, b) = (self.a.len(), self.b.len());
+        (a.saturating_sub(b), Some(a))
+    }
+}
+
+impl<'a, T: Ord> Iterator for SymmetricDifference<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        loop {
+            let order = match (self.a.peek(), self.b.peek()) {
+                (None, _) => return self.b.next(),
+                (_, None) => return self.a.next(),
+                (Some(x), Some(y)) => x.cmp(y),
+            };
+            match order {
+                Ordering::Less => return self.a.next(),
+                Ordering::Greater => return self.b.next(),
+                Ordering::Equal => {
+                    self.a.next();
+                    self.b.next();
+                }
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.a.len() + self.b.len()))
+    }
+}
+
+impl<'a, T: Ord> Iterator for Diff<'a, T> {
+    type Item = DiffItem<'a, T>;
+
+    fn next(&mut self) -> Option<DiffItem<'a, T>> {
+        loop {
+            let order = match (self.a.peek(), self.b.peek()) {
+                (None, _) => return self.b.next().map(DiffItem::OnlyInOther),
+                (_, None) => return self.a.next().map(DiffItem::OnlyInSelf),
+                (Some(x), Some(y)) => x.cmp(y),
+            };
+            match order {
+                Ordering::Less => return self.a.next().map(DiffItem::OnlyInSelf),
+                Ordering::Greater => return self.b.next().map(DiffItem::OnlyInOther),
+                Ordering::Equal => {
+                    self.a.next();
+                    self.b.next();
+                }
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.a.len() + self.b.len()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tree_of(values: &[i32]) -> AVLTree<i32> {
+        let mut tree = AVLTree::new();
+        for &v in values {
+            tree.insert(v);
+        }
+        tree
+    }
+
+    #[test]
+    fn test_set_operations() {
+        let a = tree_of(&[1, 3, 5, 7, 9, 10]);
+        let b = tree_of(&[2, 3, 4, 9, 10, 11, 12]);
+        let empty = tree_of(&[]);
+
+        assert_eq!(a.union(&b).copied().collect::<Vec<_>>(), vec![1, 2, 3, 4, 5, 7, 9, 10, 11, 12]);
+        assert_eq!(a.intersection(&b).copied().collect::<Vec<_>>(), vec![3, 9, 10]);
+        assert_eq!(a.difference(&b).copied().collect::<Vec<_>>(), vec![1, 5, 7]);
+        assert_eq!(b.difference(&a).copied().collect::<Vec<_>>(), vec![2, 4, 11, 12]);
+        assert_eq!(a.symmetric_difference(&b).copied().collect::<Vec<_>>(), vec![1, 2, 4, 5, 7, 11, 12]);
+
+        assert_eq!(a.union(&empty).count(), 6);
+        assert_eq!(empty.union(&b).count(), 7);
+        assert_eq!(a.intersection(&empty).next(), None);
+        assert_eq!(a.difference(&empty).count(), 6);
+        assert_eq!(empty.difference(&a).next(), None);
+        assert_eq!(empty.symmetric_difference(&b).count(), 7);
+    }
+
+    #[test]
+    fn test_diff() {
+        let a = tree_of(&[1, 3, 5, 7]);
+        let b = tree_of(&[2, 3, 7, 8]);
+        assert_eq!(a.diff(&b).collect::<Vec<_>>(), vec![
+            DiffItem::OnlyInSelf(&1),
+            DiffItem::OnlyInOther(&2),
+            DiffItem::OnlyInSelf(&5),
+            DiffItem::OnlyInOther(&8),
+        ]);
+        assert_eq!(a.diff(&a).next(), None);
+        assert_eq!(a.diff(&tree_of(&[])).count(), 4);
+
+        // applying the diff to `b` turns it into `a`
+        let mut synced = b.clone();
+        for item in a.diff(&b) {
+            match item {
+                DiffItem::OnlyInSelf(v) => synced.insert(*v),
+                DiffItem::OnlyInOther(v) => synced.remove(v),
+            };
+        }
+        assert!(synced.iter().eq(a.iter()));
+    }
+
+    #[test]
+    fn test_set_relations() {
+        let a = tree_of(&[1, 3, 5]);
+        let b = tree_of(&[0, 1, 2, 3, 4, 5]);
+        let c = tree_of(&[1, 3, 6]);
+        let d = tree_of(&[6, 7]);
+        let empty = tree_of(&[]);
+
+        assert!(a.is_subset(&b));
+        assert!(b.is_superset(&a));
+        assert!(!b.is_subset(&a));
+        assert!(!c.is_subset(&b));
+        assert!(!a.is_superset(&c));
+        assert!(a.is_subset(&a));
+        assert!(empty.is_subset(&a));
+        assert!(!a.is_subset(&empty));
+
+        assert!(a.is_disjoint(&d));
+        assert!(!c.is_disjoint(&d));
+        assert!(empty.is_disjoint(&empty));
+    }
+}
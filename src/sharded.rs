@@ -0,0 +1,220 @@
+//! An ordered set split across several independently locked trees, so that writes to different
+//! shards don't contend.
+//!
+//! Elements are assigned to shards by hash, which spreads any workload evenly whatever the
+//! distribution of the keys, at the cost of having every ordered traversal visit all shards:
+//! [`ShardedView::iter`] merges their sorted sequences on the fly.
+
+use std::borrow::Borrow;
+use std::cmp::Reverse;
+use std::collections::hash_map::RandomState;
+use std::collections::BinaryHeap;
+use std::hash::{BuildHasher, Hash};
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use crate::boxed::{self, AVLTreeBoxed};
+
+/// An ordered set made of a fixed number of [`AVLTreeBoxed`], each behind its own lock.
+///
+/// Insertions, removals and lookups only lock the shard the element hashes to.
+pub struct ShardedAVLSet<T: Ord, S = RandomState> {
+    shards: Box<[RwLock<AVLTreeBoxed<T>>]>,
+    hasher: S,
+}
+
+impl<T: Ord + Hash> ShardedAVLSet<T> {
+
+    /// Creates an empty set split across `shards` trees.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `shards` is 0.
+    pub fn new(shards: usize) -> Self {
+        Self::with_hasher(shards, RandomState::new())
+    }
+}
+
+impl<T: Ord + Hash, S: BuildHasher> ShardedAVLSet<T, S> {
+
+    /// Creates an empty set split across `shards` trees, which assigns elements to shards
+    /// with `hasher`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `shards` is 0.
+    pub fn with_hasher(shards: usize, hasher: S) -> Self {
+        assert!(shards > 0, "ShardedAVLSet: no shard");
+        ShardedAVLSet { shards: (0..shards).map(|_| RwLock::default()).collect(), hasher }
+    }
+
+    /// Returns the number of shards.
+    pub fn shard_count(&self) -> usize {
+        self.shards.len()
+    }
+
+    fn shard<Q: ?Sized + Hash>(&self, value: &Q) -> &RwLock<AVLTreeBoxed<T>> {
+        let i = self.hasher.hash_one(value) % self.shards.len() as u64;
+        &self.shards[i as usize]
+    }
+
+    fn read_shard<Q: ?Sized + Hash>(&self, value: &Q) -> RwLockReadGuard<'_, AVLTreeBoxed<T>> {
+        self.shard(value).read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn write_shard<Q: ?Sized + Hash>(&self, value: &Q) -> RwLockWriteGuard<'_, AVLTreeBoxed<T>> {
+        self.shard(value).write().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Returns the number of elements in the set.
+    ///
+    /// Shards are counted one after the other, so that the total may be off while other
+    /// threads write to the set; [`read`](Self::read) gives an exact count.
+    pub fn len(&self) -> usize {
+        self.shards.iter().map(|s| s.read().unwrap_or_else(|e| e.into_inner()).len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns `true` if the set contains `value`.
+    pub fn contains<Q: ?Sized + Ord + Hash>(&self, value: &Q) -> bool where T: Borrow<Q> {
+        self.read_shard(value).contains(value)
+    }
+
+    /// Adds `value` to the set. Returns `false` if an equal element was already present, in
+    /// which case the set is left untouched.
+    pub fn insert(&self, value: T) -> bool {
+        self.write_shard(&value).insert(value)
+    }
+
+    /// Removes `value` from the set. Returns `false` if it wasn't present.
+    pub fn remove<Q: ?Sized + Ord + Hash>(&self, value: &Q) -> bool where T: Borrow<Q> {
+        self.write_shard(value).remove(value)
+    }
+
+    /// Removes the element equal to `value` from the set and returns it.
+    pub fn take<Q: ?Sized + Ord + Hash>(&self, value: &Q) -> Option<T> where T: Borrow<Q> {
+        self.write_shard(value).take(value)
+    }
+
+    /// Locks all shards for reading, giving a consistent view of the whole set until it is
+    /// dropped. Writers wait meanwhile.
+    pub fn read(&self) -> ShardedView<'_, T> {
+        // shards are always locked in the same order, and writers never hold more than one
+        let guards = self.shards.iter().map(|s| s.read().unwrap_or_else(|e| e.into_inner())).collect();
+        ShardedView { guards }
+    }
+}
+
+impl<T: Ord + Hash, S: BuildHasher + Default> Default for ShardedAVLSet<T, S> {
+    /// Creates an empty set with one shard per available CPU.
+    fn default() -> Self {
+        let shards = std::thread::available_parallelism().map_or(1, |n| n.get());
+        Self::with_hasher(shards, S::default())
+    }
+}
+
+/// All the shards of a [`ShardedAVLSet`], locked for reading.
+pub struct ShardedView<'a, T: Ord> {
+    guards: Vec<RwLockReadGuard<'a, AVLTreeBoxed<T>>>,
+}
+
+impl<T: Ord> ShardedView<'_, T> {
+
+    /// Returns the number of elements in the set.
+    pub fn len(&self) -> usize {
+        self.guards.iter().map(|g| g.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns an iterator over the elements of all shards, in sorted order.
+    pub fn iter(&self) -> Iter<'_, T> {
+        let mut shards: Vec<_> = self.guards.iter().map(|g| g.iter()).collect();
+        let heads = shards.iter_mut().enumerate()
+            .filter_map(|(i, s)| s.next().map(|v| Reverse((v, i))))
+            .collect();
+        Iter { shards, heads, remaining: self.len() }
+    }
+}
+
+/// An iterator merging the elements of the shards of a [`ShardedAVLSet`], in sorted order.
+pub struct Iter<'a, T> {
+    shards: Vec<boxed::Iter<'a, T>>,
+    /// The next element of each shard not exhausted yet, the smallest on top.
+    heads: BinaryHeap<Reverse<(&'a T, usize)>>,
+    remaining: usize,
+}
+
+impl<'a, T: Ord> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // an element only ever lives in one shard, so there is no tie to break
+        let Reverse((value, i)) = self.heads.pop()?;
+        if let Some(next) = self.shards[i].next() {
+            self.heads.push(Reverse((next, i)));
+        }
+        self.remaining -= 1;
+        Some(value)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<T: Ord> ExactSizeIterator for Iter<'_, T> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sharded() {
+        let set = ShardedAVLSet::new(5);
+        assert_eq!(set.shard_count(), 5);
+        for i in 0..300 {
+            assert!(set.insert((i * 37) % 300));
+        }
+        assert!(!set.insert(5));
+        assert_eq!(set.len(), 300);
+        assert!(set.read().iter().copied().eq(0..300));
+        // hashing spreads the elements over all shards
+        assert!(set.read().guards.iter().all(|g| !g.is_empty()));
+
+        for i in (0..300).filter(|i| i % 3 != 0) {
+            assert!(set.remove(&i));
+        }
+        assert!(!set.remove(&1));
+        assert_eq!(set.take(&3), Some(3));
+        assert!(set.contains(&6) && !set.contains(&3));
+        let view = set.read();
+        assert_eq!(view.iter().len(), 99);
+        assert!(view.iter().copied().eq([0].into_iter().chain((6..300).step_by(3))));
+    }
+
+    #[test]
+    fn test_sharded_threads() {
+        let set: ShardedAVLSet<u32> = ShardedAVLSet::default();
+        std::thread::scope(|s| {
+            for t in 0..4 {
+                let set = &set;
+                s.spawn(move || {
+                    for i in (t..4000).step_by(4) {
+                        assert!(set.insert(i));
+                    }
+                    for i in (t..4000).step_by(8) {
+                        assert!(set.remove(&i));
+                    }
+                    let view = set.read();
+                    assert!(view.iter().zip(view.iter().skip(1)).all(|(a, b)| a < b));
+                });
+            }
+        });
+        assert!(set.read().iter().copied().eq((0..4000).filter(|i| i % 8 >= 4)));
+    }
+}
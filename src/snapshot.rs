@@ -0,0 +1,206 @@
+//! A compact binary format to persist trees without serde.
+//!
+//! A snapshot is made of a header, the magic bytes `AVLT` followed by a format version byte,
+//! then the number of elements as a little-endian `u64`, then the elements in sorted order,
+//! each encoded by its [`SnapshotElement`] impl.
+
+use std::io::{self, Read, Write};
+
+use crate::{build_balanced, AVLTree};
+
+const MAGIC: &[u8; 4] = b"AVLT";
+const VERSION: u8 = 1;
+
+/// An element type that can be written to and read back from a snapshot.
+///
+/// Integers are encoded in little-endian order over their full width (`usize` and `isize` over
+/// 64 bits), strings and byte vectors as their length as a `u64` followed by their bytes.
+pub trait SnapshotElement: Sized {
+    fn write_to<W: Write>(&self, w: &mut W) -> io::Result<()>;
+    fn read_from<R: Read>(r: &mut R) -> io::Result<Self>;
+}
+
+macro_rules! snapshot_int {
+    ($($t:ty),*) => {$(
+        impl SnapshotElement for $t {
+            fn write_to<W: Write>(&self, w: &mut W) -> io::Result<()> {
+                w.write_all(&self.to_le_bytes())
+            }
+
+            fn read_from<R: Read>(r: &mut R) -> io::Result<Self> {
+                let mut bytes = [0; std::mem::size_of::<$t>()];
+                r.read_exact(&mut bytes)?;
+                Ok(<$t>::from_le_bytes(bytes))
+            }
+        }
+    )*};
+}
+
+snapshot_int!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128);
+
+impl SnapshotElement for usize {
+    fn write_to<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        (*self as u64).write_to(w)
+    }
+
+    fn read_from<R: Read>(r: &mut R) -> io::Result<Self> {
+        usize::try_from(u64::read_from(r)?).map_err(|_| invalid("usize element out of range"))
+    }
+}
+
+impl SnapshotElement for isize {
+    fn write_to<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        (*self as i64).write_to(w)
+    }
+
+    fn read_from<R: Read>(r: &mut R) -> io::Result<Self> {
+        isize::try_from(i64::read_from(r)?).map_err(|_| invalid("isize element out of range"))
+    }
+}
+
+impl SnapshotElement for bool {
+    fn write_to<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        (*self as u8).write_to(w)
+    }
+
+    fn read_from<R: Read>(r: &mut R) -> io::Result<Self> {
+        match u8::read_from(r)? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(invalid("invalid bool element")),
+        }
+    }
+}
+
+impl SnapshotElement for char {
+    fn write_to<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        (*self as u32).write_to(w)
+    }
+
+    fn read_from<R: Read>(r: &mut R) -> io::Result<Self> {
+        char::from_u32(u32::read_from(r)?).ok_or_else(|| invalid("invalid char element"))
+    }
+}
+
+impl SnapshotElement for Vec<u8> {
+    fn write_to<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        (self.len() as u64).write_to(w)?;
+        w.write_all(self)
+    }
+
+    fn read_from<R: Read>(r: &mut R) -> io::Result<Self> {
+        let len = u64::read_from(r)?;
+        let mut bytes = Vec::new();
+        // reading through `take` rather than into a buffer of the announced size, so that a
+        // corrupted length can't trigger a huge allocation
+        r.take(len).read_to_end(&mut bytes)?;
+        if bytes.len() as u64 != len {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        Ok(bytes)
+    }
+}
+
+impl SnapshotElement for String {
+    fn write_to<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        (self.len() as u64).write_to(w)?;
+        w.write_all(self.as_bytes())
+    }
+
+    fn read_from<R: Read>(r: &mut R) -> io::Result<Self> {
+        String::from_utf8(Vec::read_from(r)?).map_err(|_| invalid("invalid UTF-8 in string element"))
+    }
+}
+
+pub(crate) fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+impl<T: Ord + SnapshotElement> AVLTree<T> {
+
+    /// Writes the tree to `w` in the snapshot format described in the [module docs](self).
+    pub fn write_snapshot<W: Write>(&self, mut w: W) -> io::Result<()> {
+        w.write_all(MAGIC)?;
+        w.write_all(&[VERSION])?;
+        (self.len() as u64).write_to(&mut w)?;
+        for value in self.iter() {
+            value.write_to(&mut w)?;
+        }
+        w.flush()
+    }
+
+    /// Reads a tree written by `write_snapshot`, rebuilding it balanced in O(n).
+    ///
+    /// Fails with `InvalidData` if the header is wrong or the elements are not strictly
+    /// increasing.
+    pub fn read_snapshot<R: Read>(mut r: R) -> io::Result<Self> {
+        let mut header = [0; 5];
+        r.read_exact(&mut header)?;
+        if &header[..4] != MAGIC {
+            return Err(invalid("not an AVLTree snapshot"));
+        }
+        if header[4] != VERSION {
+            return Err(invalid("unsupported snapshot version"));
+        }
+        let len = usize::read_from(&mut r)?;
+        let mut values: Vec<T> = Vec::with_capacity(len.min(4096));
+        for _ in 0..len {
+            let value = T::read_from(&mut r)?;
+            if values.last().is_some_and(|last| *last >= value) {
+                return Err(invalid("snapshot elements are not strictly increasing"));
+            }
+            values.push(value);
+        }
+        Ok(AVLTree::from_root(build_balanced(&mut values.into_iter(), len)))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::height_of;
+
+    #[test]
+    fn test_snapshot_round_trip() {
+        let tree: AVLTree<i64> = (-500..500).map(|v| v * 7).collect();
+        let mut bytes = Vec::new();
+        tree.write_snapshot(&mut bytes).unwrap();
+        assert_eq!(&bytes[..5], b"AVLT\x01");
+        assert_eq!(bytes.len(), 5 + 8 + 1000 * 8);
+        let back = AVLTree::<i64>::read_snapshot(&bytes[..]).unwrap();
+        assert!(back == tree);
+        assert!(height_of(&back.root) <= 10);
+
+        let words: AVLTree<String> = ["pear", "apple", "fig"].into_iter().map(String::from).collect();
+        let mut bytes = Vec::new();
+        words.write_snapshot(&mut bytes).unwrap();
+        assert!(AVLTree::<String>::read_snapshot(&bytes[..]).unwrap() == words);
+
+        let mut bytes = Vec::new();
+        AVLTree::<u8>::new().write_snapshot(&mut bytes).unwrap();
+        assert!(AVLTree::<u8>::read_snapshot(&bytes[..]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_snapshot_rejects_corruption() {
+        let mut bytes = Vec::new();
+        AVLTree::from([1u32, 2, 3]).write_snapshot(&mut bytes).unwrap();
+
+        let error = AVLTree::<u32>::read_snapshot(&bytes[..bytes.len() - 1]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+
+        let mut unsorted = bytes.clone();
+        unsorted[13..17].copy_from_slice(&5u32.to_le_bytes());
+        let error = AVLTree::<u32>::read_snapshot(&unsorted[..]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        let mut bad_magic = bytes.clone();
+        bad_magic[0] = b'X';
+        assert_eq!(AVLTree::<u32>::read_snapshot(&bad_magic[..]).unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+        let mut huge_len = bytes;
+        huge_len[5..13].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(AVLTree::<u32>::read_snapshot(&huge_len[..]).is_err());
+    }
+}
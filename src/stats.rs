@@ -0,0 +1,150 @@
+//! Counters of the work done inside a tree, for studying how it balances itself.
+//!
+//! Enabled by the `stats` feature. Without it, the counters take no room and counting compiles
+//! to nothing.
+
+#[cfg(feature = "stats")]
+use std::cell::Cell;
+
+#[cfg(feature = "stats")]
+use crate::{peek, AVLTree};
+
+/// Where the counting happens: the searches and retracings of a tree report to its own
+/// counters, kept in cells so that lookups through `&self` count too.
+#[derive(Default)]
+pub(crate) struct Counters {
+    #[cfg(feature = "stats")]
+    single_rotations: Cell<u64>,
+    #[cfg(feature = "stats")]
+    double_rotations: Cell<u64>,
+    #[cfg(feature = "stats")]
+    comparisons: Cell<u64>,
+    #[cfg(feature = "stats")]
+    retrace_steps: Cell<u64>,
+}
+
+#[cfg(feature = "stats")]
+fn bump(counter: &Cell<u64>) {
+    counter.set(counter.get() + 1);
+}
+
+impl Counters {
+
+    #[inline]
+    pub(crate) fn rotation(&self, _double: bool) {
+        #[cfg(feature = "stats")]
+        bump(if _double { &self.double_rotations } else { &self.single_rotations });
+    }
+
+    #[inline]
+    pub(crate) fn comparison(&self) {
+        #[cfg(feature = "stats")]
+        bump(&self.comparisons);
+    }
+
+    #[inline]
+    pub(crate) fn retrace_step(&self) {
+        #[cfg(feature = "stats")]
+        bump(&self.retrace_steps);
+    }
+}
+
+/// What [`AVLTree::stats`] reports.
+///
+/// The counters add up since the tree was created or since the last
+/// [`reset_stats`](AVLTree::reset_stats). They cover inserting, removing and looking up
+/// elements one at a time, cursors included. Operations joining whole subtrees together, such
+/// as `split_off`, `append`, `insert_batch` and the set operations, and rebuilds, are left out.
+#[cfg(feature = "stats")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Stats {
+    /// Single rotations, a double rotation not counting as two of them.
+    pub single_rotations: u64,
+    /// Double rotations, right-left or left-right.
+    pub double_rotations: u64,
+    /// Calls to the comparison of elements, or to the predicate standing in for it, made by
+    /// searches.
+    pub comparisons: u64,
+    /// Nodes whose balance factor was refreshed while retracing after an insertion or a
+    /// removal. The walk up to the root going on beyond them, to refresh subtree sizes, is not
+    /// counted.
+    pub retrace_steps: u64,
+    /// The number of nodes at each depth, the root being at depth 0, as the tree is now.
+    pub depths: Vec<usize>,
+}
+
+#[cfg(feature = "stats")]
+impl<T: Ord> AVLTree<T> {
+
+    /// Returns the counters of the work done by the tree, and the histogram of the depths of
+    /// its nodes, which takes O(n) to make.
+    pub fn stats(&self) -> Stats {
+        let mut depths = Vec::new();
+        let mut stack: Vec<_> = self.root.iter().map(|root| (root, 0)).collect();
+        while let Some((node, depth)) = stack.pop() {
+            if depths.len() == depth {
+                depths.push(0);
+            }
+            depths[depth] += 1;
+            let n = peek(node);
+            stack.extend([&n.left, &n.right].into_iter().flatten().map(|child| (child, depth + 1)));
+        }
+        let c = &self.counters;
+        Stats {
+            single_rotations: c.single_rotations.get(),
+            double_rotations: c.double_rotations.get(),
+            comparisons: c.comparisons.get(),
+            retrace_steps: c.retrace_steps.get(),
+            depths,
+        }
+    }
+
+    /// Sets the counters back to 0.
+    pub fn reset_stats(&mut self) {
+        self.counters = Counters::default();
+    }
+}
+
+#[cfg(all(test, feature = "stats"))]
+mod tests {
+    use super::*;
+    use crate::DuplicatePolicy;
+
+    #[test]
+    fn test_stats() {
+        let mut tree = AVLTree::new();
+        for v in 0..7 {
+            tree.insert(v);
+        }
+        let stats = tree.stats();
+        // sorted insertions only ever need single rotations
+        assert_eq!((stats.single_rotations, stats.double_rotations), (4, 0));
+        assert_eq!(stats.depths, [1, 2, 4]);
+        assert_eq!(stats.comparisons, 1 + 2 + 2 + 3 + 3 + 3);
+
+        tree.reset_stats();
+        assert!(tree.contains(&6));
+        assert_eq!(tree.stats().comparisons, 3);
+
+        // 0, 2, 1 makes one double rotation
+        let mut zigzag = AVLTree::new();
+        for v in [0, 2, 1] {
+            zigzag.insert_with_policy(v, DuplicatePolicy::KeepBoth);
+        }
+        let stats = zigzag.stats();
+        assert_eq!((stats.single_rotations, stats.double_rotations), (0, 1));
+        assert_eq!(stats.retrace_steps, 3);
+        assert_eq!(stats.depths, [1, 2]);
+
+        // removing a leaf of a perfect tree retraces its parent only
+        zigzag.reset_stats();
+        zigzag.remove(&0);
+        assert_eq!(zigzag.stats().retrace_steps, 1);
+
+        let mut cursor = zigzag.cursor_back_mut();
+        cursor.insert_after(4).unwrap();
+        cursor.insert_after(3).unwrap();
+        assert_eq!(zigzag.stats().single_rotations, 1);
+        assert_eq!(AVLTree::<i32>::new().stats().depths, Vec::<usize>::new());
+    }
+}
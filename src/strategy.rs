@@ -0,0 +1,116 @@
+//! proptest strategies generating trees by running random sequences of operations, so that
+//! their shapes are those left behind by removals as well as by insertions.
+//!
+//! Enabled by the `proptest` feature. [`AVLTree`] implements `Arbitrary` with [`trees`], which
+//! shrinks along with the sequence of operations.
+
+use std::fmt::Debug;
+use std::ops::Range;
+use std::rc::Rc;
+
+use proptest::arbitrary::{any_with, Arbitrary};
+use proptest::collection::vec;
+use proptest::prelude::*;
+use proptest::sample::Index;
+
+use crate::{AVLTree, DuplicatePolicy};
+
+/// An operation on an [`AVLTree`], as generated by [`ops`].
+#[derive(Debug, Clone)]
+pub enum TreeOp<T> {
+    /// Inserts the value, unless an equal one is present.
+    Insert(T),
+    /// Inserts the value, after the equal ones present.
+    InsertDuplicate(T),
+    /// Removes the element at the rank the index picks, if the tree isn't empty.
+    Remove(Index),
+    /// Rebuilds the tree into a perfectly balanced one.
+    Rebuild,
+}
+
+impl<T: Ord> TreeOp<T> {
+    /// Runs the operation on `tree`.
+    pub fn apply(self, tree: &mut AVLTree<T>) {
+        match self {
+            TreeOp::Insert(value) => {
+                tree.insert(value);
+            }
+            TreeOp::InsertDuplicate(value) => {
+                tree.insert_with_policy(value, DuplicatePolicy::KeepBoth);
+            }
+            TreeOp::Remove(index) => {
+                if !tree.is_empty() {
+                    let node = Rc::clone(tree.node_by_rank(index.index(tree.len())).unwrap());
+                    tree.remove_node(node);
+                }
+            }
+            TreeOp::Rebuild => tree.rebuild(),
+        }
+    }
+}
+
+/// Generates sequences of `len` operations on elements from `element`: mostly insertions of
+/// distinct elements and removals, some insertions of duplicates and a few rebuilds.
+pub fn ops<T: Debug + Clone, S: Strategy<Value = T> + Clone>(element: S, len: Range<usize>) -> impl Strategy<Value = Vec<TreeOp<T>>> {
+    let op = prop_oneof![
+        6 => element.clone().prop_map(TreeOp::Insert),
+        1 => element.prop_map(TreeOp::InsertDuplicate),
+        3 => any::<Index>().prop_map(TreeOp::Remove),
+        1 => Just(TreeOp::Rebuild),
+    ];
+    vec(op, len)
+}
+
+/// Generates trees by running [`ops`] on an empty tree.
+pub fn trees<T: Ord + Debug + Clone, S: Strategy<Value = T> + Clone>(element: S, len: Range<usize>) -> impl Strategy<Value = AVLTree<T>> {
+    ops(element, len).prop_map(|ops| {
+        let mut tree = AVLTree::new();
+        for op in ops {
+            op.apply(&mut tree);
+        }
+        tree
+    })
+}
+
+impl<T: Arbitrary + Ord + Clone + 'static> Arbitrary for AVLTree<T> where T::Strategy: Clone {
+    type Parameters = T::Parameters;
+    type Strategy = BoxedStrategy<AVLTree<T>>;
+
+    /// Trees made by up to 100 operations.
+    fn arbitrary_with(params: T::Parameters) -> Self::Strategy {
+        trees(any_with::<T>(params), 0..100).boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    proptest! {
+        #[test]
+        fn test_arbitrary_trees(tree in any::<AVLTree<u8>>()) {
+            prop_assert_eq!(tree.check_invariants(), Ok(()));
+            prop_assert!(tree.iter().zip(tree.iter().skip(1)).all(|(a, b)| a <= b));
+        }
+
+        #[test]
+        fn test_ops_replay(ops in ops(0..50i32, 0..200)) {
+            let mut tree = AVLTree::new();
+            let mut model = Vec::new();
+            for op in ops {
+                match &op {
+                    TreeOp::Insert(v) => if !model.contains(v) { model.push(*v) },
+                    TreeOp::InsertDuplicate(v) => model.push(*v),
+                    TreeOp::Remove(index) => if !model.is_empty() {
+                        model.sort();
+                        model.remove(index.index(model.len()));
+                    },
+                    TreeOp::Rebuild => {}
+                }
+                op.apply(&mut tree);
+            }
+            model.sort();
+            prop_assert!(tree.iter().eq(&model));
+        }
+    }
+}
@@ -0,0 +1,105 @@
+use std::cmp::Ordering;
+
+/// A total order for a type whose natural order is only partial.
+///
+/// Implemented for `f32` and `f64` with their `total_cmp`; other `PartialOrd` types can
+/// implement it to be stored in a tree through [`TotalOrd`].
+pub trait TotalOrder {
+    fn total_cmp(&self, other: &Self) -> Ordering;
+}
+
+impl TotalOrder for f32 {
+    fn total_cmp(&self, other: &Self) -> Ordering {
+        f32::total_cmp(self, other)
+    }
+}
+
+impl TotalOrder for f64 {
+    fn total_cmp(&self, other: &Self) -> Ordering {
+        f64::total_cmp(self, other)
+    }
+}
+
+/// Wraps a value to order it by its [`TotalOrder`], so that it can be stored in an `AVLTree`
+/// (or used as an `AVLMap` key) despite not being `Ord`.
+///
+/// For floats, this is the IEEE 754 total order: NaNs are accepted and sorted to the ends,
+/// positive ones after `+inf` and negative ones before `-inf`, and `-0.0 < +0.0`. Equality
+/// follows the same order, so a NaN equals itself and `-0.0` differs from `+0.0`. Use
+/// [`TotalOrd::non_nan`] to keep NaNs out instead.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TotalOrd<T>(pub T);
+
+impl<T: TotalOrder> TotalOrd<T> {
+
+    pub fn new(value: T) -> Self {
+        TotalOrd(value)
+    }
+
+    /// Returns the wrapped value.
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T: TotalOrder + PartialOrd> TotalOrd<T> {
+
+    /// Wraps `value` unless it is unordered with itself, which is how NaNs behave.
+    pub fn non_nan(value: T) -> Option<Self> {
+        #[allow(clippy::eq_op)]
+        (value == value).then_some(TotalOrd(value))
+    }
+}
+
+impl<T: TotalOrder> From<T> for TotalOrd<T> {
+    fn from(value: T) -> Self {
+        TotalOrd(value)
+    }
+}
+
+impl<T: TotalOrder> PartialEq for TotalOrd<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<T: TotalOrder> Eq for TotalOrd<T> {}
+
+impl<T: TotalOrder> PartialOrd for TotalOrd<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T: TotalOrder> Ord for TotalOrd<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AVLTree;
+
+    #[test]
+    fn test_float_tree() {
+        let mut tree = AVLTree::new();
+        for v in [2.5, -1.0, f64::NAN, 0.0, -0.0, f64::INFINITY, -f64::NAN, 2.5] {
+            tree.insert(TotalOrd(v));
+        }
+        assert_eq!(tree.len(), 7);
+        let values: Vec<f64> = tree.iter().map(|v| v.0).collect();
+        assert!(values[0].is_nan() && values[0].is_sign_negative());
+        assert_eq!(&values[1..6], &[-1.0, -0.0, 0.0, 2.5, f64::INFINITY]);
+        assert!(values[2].is_sign_negative() && values[3].is_sign_positive());
+        assert!(values[6].is_nan() && values[6].is_sign_positive());
+        assert!(tree.contains(&TotalOrd(f64::NAN)));
+        assert!(tree.remove(&TotalOrd::from(2.5)));
+
+        assert_eq!(TotalOrd::non_nan(1.5f32), Some(TotalOrd(1.5)));
+        assert_eq!(TotalOrd::non_nan(f32::NAN), None);
+        assert_eq!(TotalOrd::new(3.0f32).into_inner(), 3.0);
+    }
+}
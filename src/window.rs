@@ -0,0 +1,160 @@
+//! A sliding window over a stream of values, answering order statistics such as the median of
+//! the last n values.
+
+use std::collections::VecDeque;
+
+use crate::{AVLTree, DuplicatePolicy, Iter};
+
+/// The last values pushed into a stream, up to a given number, in an [`AVLTree`] sorting them
+/// and in a FIFO remembering their order of arrival.
+///
+/// Pushing, expiring and every order statistic take O(log n). Values are cloned once, to be
+/// kept in both.
+pub struct SlidingWindow<T: Ord + Clone> {
+    tree: AVLTree<T>,
+    arrivals: VecDeque<T>,
+    size: usize,
+}
+
+impl<T: Ord + Clone> SlidingWindow<T> {
+
+    /// Creates an empty window keeping up to `size` values.
+    ///
+    /// Windows over a duration rather than a number of values can be given `usize::MAX`, and
+    /// have their values expired by hand with [`expire`](Self::expire).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `size` is 0.
+    pub fn new(size: usize) -> Self {
+        assert!(size > 0, "SlidingWindow: empty window");
+        SlidingWindow { tree: AVLTree::new(), arrivals: VecDeque::new(), size }
+    }
+
+    /// Returns the maximum number of values in the window.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Returns the number of values in the window.
+    pub fn len(&self) -> usize {
+        self.arrivals.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.arrivals.is_empty()
+    }
+
+    /// Adds `value` to the window, expiring the oldest value if the window was full. Returns the
+    /// expired value.
+    pub fn push(&mut self, value: T) -> Option<T> {
+        let expired = if self.arrivals.len() == self.size { self.expire() } else { None };
+        self.tree.insert_with_policy(value.clone(), DuplicatePolicy::KeepBoth);
+        self.arrivals.push_back(value);
+        expired
+    }
+
+    /// Removes the oldest value from the window and returns it.
+    pub fn expire(&mut self) -> Option<T> {
+        let oldest = self.arrivals.pop_front()?;
+        // any of the equal values can go, they are alike
+        self.tree.remove(&oldest);
+        Some(oldest)
+    }
+
+    /// Returns the oldest value in the window.
+    pub fn oldest(&self) -> Option<&T> {
+        self.arrivals.front()
+    }
+
+    /// Returns the newest value in the window.
+    pub fn newest(&self) -> Option<&T> {
+        self.arrivals.back()
+    }
+
+    /// Returns the value at position `rank` in sorted order, starting from 0.
+    pub fn get_by_rank(&self, rank: usize) -> Option<&T> {
+        self.tree.get_by_rank(rank)
+    }
+
+    /// Returns the smallest value in the window.
+    pub fn min(&self) -> Option<&T> {
+        self.get_by_rank(0)
+    }
+
+    /// Returns the largest value in the window.
+    pub fn max(&self) -> Option<&T> {
+        self.get_by_rank(self.len().checked_sub(1)?)
+    }
+
+    /// Returns the median of the window, the lower one of the two middle values if the window
+    /// holds an even number of them.
+    pub fn median(&self) -> Option<&T> {
+        self.tree.median()
+    }
+
+    /// Returns the `p`-th percentile of the window, by the nearest-rank method: the smallest
+    /// value that at least `p` percent of the values are lower than or equal to.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `p` is not between 0 and 100.
+    pub fn percentile(&self, p: f64) -> Option<&T> {
+        assert!((0.0..=100.0).contains(&p), "SlidingWindow::percentile: {p} out of range");
+        let rank = (p / 100.0 * self.len() as f64).ceil() as usize;
+        self.get_by_rank(rank.saturating_sub(1))
+    }
+
+    /// Returns an iterator over the values in sorted order.
+    pub fn iter(&self) -> Iter<'_, T> {
+        self.tree.iter()
+    }
+
+    /// Returns an iterator over the values from the oldest to the newest.
+    pub fn arrivals(&self) -> std::collections::vec_deque::Iter<'_, T> {
+        self.arrivals.iter()
+    }
+}
+
+impl<T: Ord + Clone> Extend<T> for SlidingWindow<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for value in iter {
+            self.push(value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sliding_window() {
+        let mut window = SlidingWindow::new(25);
+        let stream: Vec<i32> = (0..200).map(|i| (i * 37) % 50).collect();
+        for (i, &v) in stream.iter().enumerate() {
+            let expired = window.push(v);
+            assert_eq!(expired, i.checked_sub(25).map(|j| stream[j]));
+            let mut sorted = stream[i.saturating_sub(24)..=i].to_vec();
+            sorted.sort();
+            assert!(window.iter().eq(sorted.iter()));
+            assert_eq!(window.median(), Some(&sorted[(sorted.len() - 1) / 2]));
+            assert_eq!(window.percentile(90.0), Some(&sorted[(sorted.len() * 9).div_ceil(10) - 1]));
+            assert_eq!((window.min(), window.max()), (sorted.first(), sorted.last()));
+        }
+        assert_eq!(window.percentile(0.0), window.min());
+        assert_eq!(window.percentile(100.0), window.max());
+        assert!(window.arrivals().eq(&stream[175..]));
+        assert_eq!(window.oldest(), Some(&stream[175]));
+
+        while window.len() > 1 {
+            window.expire();
+        }
+        assert_eq!(window.oldest(), window.newest());
+        assert_eq!(window.median(), Some(&stream[199]));
+        window.expire();
+        assert!(window.is_empty() && window.median().is_none() && window.max().is_none());
+        window.extend([3, 3, 1]);
+        assert_eq!(window.median(), Some(&3));
+    }
+}